@@ -33,6 +33,10 @@ use darkfi::{
         path::{expand_path, get_config_path},
     },
     validator::ValidatorConfig,
+    zk::{
+        verification_cache::DEFAULT_VERIFICATION_CACHE_SIZE,
+        verification_limit::DEFAULT_MAX_CONCURRENT_VERIFICATIONS,
+    },
     Error, Result,
 };
 use darkfi_serial::deserialize_async;
@@ -134,6 +138,14 @@ pub struct BlockchainNetwork {
     /// Garbage collection task transactions batch size
     txs_batch_size: Option<usize>,
 
+    #[structopt(long)]
+    /// Amount of ZK proof verification results to cache (0 disables it)
+    verification_cache_size: Option<usize>,
+
+    #[structopt(long)]
+    /// Amount of ZK proofs verified concurrently (0 disables the limit)
+    max_concurrent_verifications: Option<usize>,
+
     /// P2P network settings
     #[structopt(flatten)]
     net: SettingsOpt,
@@ -188,6 +200,12 @@ async fn realmain(args: Args, ex: Arc<smol::Executor<'static>>) -> Result<()> {
         pow_fixed_difficulty,
         genesis_block,
         verify_fees: !blockchain_config.skip_fees,
+        verification_cache_size: blockchain_config
+            .verification_cache_size
+            .unwrap_or(DEFAULT_VERIFICATION_CACHE_SIZE),
+        max_concurrent_verifications: blockchain_config
+            .max_concurrent_verifications
+            .unwrap_or(DEFAULT_MAX_CONCURRENT_VERIFICATIONS),
     };
 
     // Generate the daemon
@@ -45,7 +45,7 @@ use crate::proto::{ForkSyncRequest, ForkSyncResponse};
 #[derive(Clone, Debug, SerialEncodable, SerialDecodable)]
 pub struct ProposalMessage(pub Proposal);
 
-impl_p2p_message!(ProposalMessage, "proposal");
+impl_p2p_message!(ProposalMessage, "proposal", dedup);
 
 /// Atomic pointer to the `ProtocolProposal` handler.
 pub type ProtocolProposalHandlerPtr = Arc<ProtocolProposalHandler>;
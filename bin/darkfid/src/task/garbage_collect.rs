@@ -102,6 +102,8 @@ pub async fn garbage_collect_task(node: DarkfiNodePtr) -> Result<()> {
                     &tx_vec,
                     &mut MerkleTree::new(1),
                     false,
+                    &node.validator.verification_cache,
+                    &node.validator.verification_limit,
                 )
                 .await
                 {
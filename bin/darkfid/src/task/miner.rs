@@ -26,7 +26,7 @@ use darkfi::{
         consensus::{Fork, Proposal},
         utils::best_fork_index,
     },
-    zk::{empty_witnesses, ProvingKey, ZkCircuit},
+    zk::{empty_witnesses, ProvingKey, VerificationCache, VerificationLimit, ZkCircuit},
     zkas::ZkBinary,
     Error, Result,
 };
@@ -320,6 +320,8 @@ async fn mine_next_block(
         pk,
         node.validator.consensus.module.read().await.target,
         node.validator.verify_fees,
+        &node.validator.verification_cache,
+        &node.validator.verification_limit,
     )
     .await?;
 
@@ -353,6 +355,7 @@ async fn mine_next_block(
 }
 
 /// Auxiliary function to generate next block in an atomic manner.
+#[allow(clippy::too_many_arguments)]
 async fn generate_next_block(
     extended_fork: &Fork,
     secret: &mut SecretKey,
@@ -361,6 +364,8 @@ async fn generate_next_block(
     pk: &ProvingKey,
     block_target: u32,
     verify_fees: bool,
+    verification_cache: &VerificationCache,
+    verification_limit: &VerificationLimit,
 ) -> Result<(BigUint, BlockInfo)> {
     // Grab forks' last block proposal(previous)
     let last_proposal = extended_fork.last_proposal()?;
@@ -370,7 +375,14 @@ async fn generate_next_block(
 
     // Grab forks' unproposed transactions
     let (mut txs, _, fees) = extended_fork
-        .unproposed_txs(&extended_fork.blockchain, next_block_height, block_target, verify_fees)
+        .unproposed_txs(
+            &extended_fork.blockchain,
+            next_block_height,
+            block_target,
+            verify_fees,
+            verification_cache,
+            verification_limit,
+        )
         .await?;
 
     // We are deriving the next secret key for optimization.
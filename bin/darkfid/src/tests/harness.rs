@@ -25,7 +25,10 @@ use darkfi::{
     system::sleep,
     tx::{ContractCallLeaf, TransactionBuilder},
     validator::{consensus::Proposal, Validator, ValidatorConfig},
-    zk::{empty_witnesses, ProvingKey, ZkCircuit},
+    zk::{
+        empty_witnesses, verification_cache::DEFAULT_VERIFICATION_CACHE_SIZE,
+        verification_limit::DEFAULT_MAX_CONCURRENT_VERIFICATIONS, ProvingKey, ZkCircuit,
+    },
     Result,
 };
 use darkfi_contract_test_harness::vks;
@@ -87,6 +90,8 @@ impl Harness {
             pow_fixed_difficulty: config.pow_fixed_difficulty.clone(),
             genesis_block,
             verify_fees,
+            verification_cache_size: DEFAULT_VERIFICATION_CACHE_SIZE,
+            max_concurrent_verifications: DEFAULT_MAX_CONCURRENT_VERIFICATIONS,
         };
 
         // Generate validators using pregenerated vks
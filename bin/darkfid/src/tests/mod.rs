@@ -235,6 +235,9 @@ fn darkfid_programmatic_control() -> Result<()> {
         pow_fixed_difficulty: Some(BigUint::one()),
         genesis_block,
         verify_fees: false,
+        verification_cache_size: darkfi::zk::verification_cache::DEFAULT_VERIFICATION_CACHE_SIZE,
+        max_concurrent_verifications:
+            darkfi::zk::verification_limit::DEFAULT_MAX_CONCURRENT_VERIFICATIONS,
     };
     let consensus_config = crate::ConsensusInitTaskConfig {
         skip_sync: true,
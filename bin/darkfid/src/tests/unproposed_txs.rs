@@ -101,6 +101,8 @@ async fn simulate_unproposed_txs(
             current_block_height,
             validator.consensus.module.read().await.target,
             false,
+            &validator.verification_cache,
+            &validator.verification_limit,
         )
         .await?;
 
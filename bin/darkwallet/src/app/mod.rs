@@ -36,11 +36,11 @@ use crate::{
     prop::{Property, PropertyBool, PropertyStr, PropertySubType, PropertyType, Role},
     scene::{Pimpl, SceneNode as SceneNode3, SceneNodePtr, SceneNodeType as SceneNodeType3},
     text::TextShaperPtr,
-    ui::{chatview, Window},
+    ui::{chatview, Focus, FocusPtr, Window},
     ExecutorPtr,
 };
 
-mod node;
+pub(crate) mod node;
 mod schema;
 
 //fn print_type_of<T>(_: &T) {
@@ -121,6 +121,7 @@ pub struct App {
     pub render_api: RenderApiPtr,
     pub event_pub: GraphicsEventPublisherPtr,
     pub text_shaper: TextShaperPtr,
+    pub focus: FocusPtr,
     pub darkirc_evgr: SyncMutex<Option<LocalDarkIRCPtr>>,
     pub tasks: SyncMutex<Vec<Task<()>>>,
     pub ex: ExecutorPtr,
@@ -140,6 +141,7 @@ impl App {
             render_api,
             event_pub,
             text_shaper,
+            focus: Focus::new(),
             darkirc_evgr: SyncMutex::new(None),
             tasks: SyncMutex::new(vec![]),
         })
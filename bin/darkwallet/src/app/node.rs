@@ -175,6 +175,17 @@ pub fn create_editbox(name: &str) -> SceneNode {
     prop.set_range_f32(0., 1.);
     node.add_property(prop).unwrap();
 
+    let mut prop = Property::new("placeholder", PropertyType::Str, PropertySubType::Null);
+    prop.set_ui_text("Placeholder", "Hint shown while the EditBox is empty and unfocused");
+    node.add_property(prop).unwrap();
+
+    let mut prop =
+        Property::new("placeholder_color", PropertyType::Float32, PropertySubType::Color);
+    prop.set_array_len(4);
+    prop.set_range_f32(0., 1.);
+    prop.set_defaults_f32(vec![0.5, 0.5, 0.5, 1.]).unwrap();
+    node.add_property(prop).unwrap();
+
     let mut prop = Property::new("cursor_color", PropertyType::Float32, PropertySubType::Color);
     prop.set_array_len(4);
     prop.set_range_f32(0., 1.);
@@ -214,6 +225,17 @@ pub fn create_editbox(name: &str) -> SceneNode {
     prop.set_range_u32(0, u32::MAX);
     node.add_property(prop).unwrap();
 
+    let mut prop = Property::new("undo_depth", PropertyType::Uint32, PropertySubType::Null);
+    prop.set_ui_text("Undo depth", "Maximum number of edits that can be undone");
+    prop.set_defaults_u32(vec![100]).unwrap();
+    prop.set_range_u32(0, u32::MAX);
+    node.add_property(prop).unwrap();
+
+    let mut prop = Property::new("max_length", PropertyType::Uint32, PropertySubType::Null);
+    prop.set_ui_text("Max length", "Maximum number of glyphs in the text, or 0 for no limit");
+    prop.set_range_u32(0, u32::MAX);
+    node.add_property(prop).unwrap();
+
     let prop = Property::new("z_index", PropertyType::Uint32, PropertySubType::Null);
     node.add_property(prop).unwrap();
 
@@ -221,6 +243,12 @@ pub fn create_editbox(name: &str) -> SceneNode {
     node.add_property(prop).unwrap();
 
     node.add_signal("enter_pressed", "Enter key pressed", vec![]).unwrap();
+    node.add_signal(
+        "text_changed",
+        "Text edited by the user",
+        vec![("text", "New text", CallArgType::Str)],
+    )
+    .unwrap();
 
     node
 }
@@ -365,6 +365,7 @@ pub(super) async fn make_test(app: &App, window: SceneNodePtr) {
                 window_scale.clone(),
                 app.render_api.clone(),
                 app.text_shaper.clone(),
+                app.focus.clone(),
                 app.ex.clone(),
             )
         })
@@ -856,6 +857,7 @@ pub(super) async fn make(app: &App, window: SceneNodePtr) {
                 window_scale.clone(),
                 app.render_api.clone(),
                 app.text_shaper.clone(),
+                app.focus.clone(),
                 app.ex.clone(),
             )
         })
@@ -19,10 +19,11 @@
 use async_trait::async_trait;
 use atomic_float::AtomicF32;
 use darkfi::system::msleep;
+use darkfi_serial::serialize;
 use miniquad::{window, KeyCode, KeyMods, MouseButton, TouchPhase};
 use rand::{rngs::OsRng, Rng};
 use std::{
-    collections::HashMap,
+    collections::{HashMap, VecDeque},
     sync::{
         atomic::{AtomicBool, Ordering},
         Arc, Mutex as SyncMutex, Weak,
@@ -42,9 +43,9 @@ use crate::{
         PropertyUint32, Role,
     },
     pubsub::Subscription,
-    scene::{Pimpl, SceneNodePtr, SceneNodeWeak},
+    scene::{Pimpl, SceneNode, SceneNodePtr, SceneNodeWeak},
     text::{self, Glyph, GlyphPositionIter, TextShaperPtr},
-    ui::FreedData,
+    ui::{FocusPtr, FreedData},
     util::is_whitespace,
     ExecutorPtr,
 };
@@ -64,6 +65,130 @@ fn eol_nudge(font_size: f32, glyphs: &Vec<Glyph>) -> f32 {
     }
 }
 
+/// Notify slots registered on `text_changed` with the edited text
+async fn emit_text_changed(node: &SceneNode, text: &str) {
+    node.trigger("text_changed", serialize(&text.to_string())).await.unwrap();
+}
+
+/// The placeholder is only shown in place of an empty text, and gives way to
+/// the cursor once the editbox is focused.
+fn show_placeholder(text: &str, placeholder: &str, is_focused: bool) -> bool {
+    text.is_empty() && !placeholder.is_empty() && !is_focused
+}
+
+/// Cap the cursor to the glyph count of the text, so the caret stays
+/// visible when the text shrinks under it.
+fn clamp_cursor(cursor_pos: u32, glyph_count: usize) -> u32 {
+    cursor_pos.min(glyph_count as u32)
+}
+
+/// Selection span covering all glyphs, or `None` if there is no text
+fn select_all_span(glyph_count: usize) -> Option<(u32, u32)> {
+    if glyph_count == 0 {
+        return None
+    }
+    Some((0, glyph_count as u32))
+}
+
+/// Whitespace and punctuation separate words for word-wise navigation
+fn is_word_sep(substr: &str) -> bool {
+    substr.chars().all(|c| c.is_whitespace() || c.is_ascii_punctuation())
+}
+
+/// Find the start of the word before `pos`, where `substrs` are the glyph
+/// substrs of the text. Separators right before `pos` are skipped first,
+/// so consecutive whitespace is jumped over in one go.
+fn prev_word_boundary(substrs: &[&str], pos: usize) -> usize {
+    let mut pos = std::cmp::min(pos, substrs.len());
+    while pos > 0 && is_word_sep(substrs[pos - 1]) {
+        pos -= 1;
+    }
+    while pos > 0 && !is_word_sep(substrs[pos - 1]) {
+        pos -= 1;
+    }
+    pos
+}
+
+/// Find the end of the word after `pos`, where `substrs` are the glyph
+/// substrs of the text. Separators right after `pos` are skipped first,
+/// so consecutive whitespace is jumped over in one go.
+fn next_word_boundary(substrs: &[&str], pos: usize) -> usize {
+    let mut pos = std::cmp::min(pos, substrs.len());
+    while pos < substrs.len() && is_word_sep(substrs[pos]) {
+        pos += 1;
+    }
+    while pos < substrs.len() && !is_word_sep(substrs[pos]) {
+        pos += 1;
+    }
+    pos
+}
+
+/// State of the editbox text, saved in the undo history
+#[derive(Debug, Clone, PartialEq)]
+struct TextSnapshot {
+    text: String,
+    cursor_pos: u32,
+}
+
+/// Bounded undo/redo history of text edits
+struct UndoHistory {
+    /// States before each edit, the most recent at the back
+    undo: VecDeque<TextSnapshot>,
+    /// States undone, the most recent at the back
+    redo: Vec<TextSnapshot>,
+    /// Cursor position after the last single char insert. The next insert
+    /// continuing from there is coalesced into the same undo step.
+    last_insert_cursor: Option<u32>,
+}
+
+impl UndoHistory {
+    fn new() -> Self {
+        Self { undo: VecDeque::new(), redo: vec![], last_insert_cursor: None }
+    }
+
+    /// Record an edit changing the text from `before` to `after`, keeping
+    /// at most `max_depth` undo steps.
+    fn record(
+        &mut self,
+        before: TextSnapshot,
+        after: &TextSnapshot,
+        is_char_insert: bool,
+        max_depth: usize,
+    ) {
+        self.redo.clear();
+
+        let coalesce = is_char_insert && self.last_insert_cursor == Some(before.cursor_pos);
+        self.last_insert_cursor = if is_char_insert { Some(after.cursor_pos) } else { None };
+        if coalesce {
+            return
+        }
+
+        self.undo.push_back(before);
+        while self.undo.len() > max_depth {
+            self.undo.pop_front();
+        }
+    }
+
+    /// Returns the state to restore, if any. `current` can be redone later.
+    fn undo(&mut self, current: TextSnapshot) -> Option<TextSnapshot> {
+        let prev = self.undo.pop_back()?;
+        self.redo.push(current);
+        self.last_insert_cursor = None;
+        Some(prev)
+    }
+
+    /// Returns the state to restore, if any. `current` can be undone again.
+    fn redo(&mut self, current: TextSnapshot, max_depth: usize) -> Option<TextSnapshot> {
+        let next = self.redo.pop()?;
+        self.undo.push_back(current);
+        while self.undo.len() > max_depth {
+            self.undo.pop_front();
+        }
+        self.last_insert_cursor = None;
+        Some(next)
+    }
+}
+
 #[derive(Debug, Clone, Eq, Hash, PartialEq)]
 enum PressedKey {
     Char(char),
@@ -148,10 +273,13 @@ pub struct EditBox {
     tasks: Vec<smol::Task<()>>,
     render_api: RenderApiPtr,
     text_shaper: TextShaperPtr,
+    focus: FocusPtr,
     key_repeat: SyncMutex<PressedKeysSmoothRepeat>,
+    undo_history: SyncMutex<UndoHistory>,
 
     text_mesh: SyncMutex<Option<GfxDrawMesh>>,
     glyphs: SyncMutex<Vec<Glyph>>,
+    placeholder_glyphs: SyncMutex<Vec<Glyph>>,
     /// DC key for the text
     text_dc_key: u64,
     cursor_mesh: SyncMutex<Option<GfxDrawMesh>>,
@@ -168,6 +296,8 @@ pub struct EditBox {
     font_size: PropertyFloat32,
     text: PropertyStr,
     text_color: PropertyColor,
+    placeholder: PropertyStr,
+    placeholder_color: PropertyColor,
     cursor_color: PropertyColor,
     cursor_width: PropertyFloat32,
     cursor_ascent: PropertyFloat32,
@@ -176,6 +306,8 @@ pub struct EditBox {
     selected: PropertyPtr,
     z_index: PropertyUint32,
     debug: PropertyBool,
+    undo_depth: PropertyUint32,
+    max_length: PropertyUint32,
 
     mouse_btn_held: AtomicBool,
     cursor_is_visible: AtomicBool,
@@ -192,6 +324,7 @@ impl EditBox {
         window_scale: PropertyFloat32,
         render_api: RenderApiPtr,
         text_shaper: TextShaperPtr,
+        focus: FocusPtr,
         ex: ExecutorPtr,
     ) -> Pimpl {
         debug!(target: "ui::editbox", "EditBox::new()");
@@ -206,6 +339,9 @@ impl EditBox {
         let font_size = PropertyFloat32::wrap(node_ref, Role::Internal, "font_size", 0).unwrap();
         let text = PropertyStr::wrap(node_ref, Role::Internal, "text", 0).unwrap();
         let text_color = PropertyColor::wrap(node_ref, Role::Internal, "text_color").unwrap();
+        let placeholder = PropertyStr::wrap(node_ref, Role::Internal, "placeholder", 0).unwrap();
+        let placeholder_color =
+            PropertyColor::wrap(node_ref, Role::Internal, "placeholder_color").unwrap();
         let cursor_color = PropertyColor::wrap(node_ref, Role::Internal, "cursor_color").unwrap();
         let cursor_width =
             PropertyFloat32::wrap(node_ref, Role::Internal, "cursor_width", 0).unwrap();
@@ -221,12 +357,21 @@ impl EditBox {
             PropertyUint32::wrap(node_ref, Role::Internal, "cursor_idle_time", 0).unwrap();
         let z_index = PropertyUint32::wrap(node_ref, Role::Internal, "z_index", 0).unwrap();
         let debug = PropertyBool::wrap(node_ref, Role::Internal, "debug", 0).unwrap();
+        let undo_depth = PropertyUint32::wrap(node_ref, Role::Internal, "undo_depth", 0).unwrap();
+        let max_length = PropertyUint32::wrap(node_ref, Role::Internal, "max_length", 0).unwrap();
 
         let node_name = node_ref.name.clone();
         let node_id = node_ref.id;
 
+        // Boxes focused by the app from the start
+        if is_focused.get() {
+            focus.acquire(node_ref);
+        }
+
         // Must do this whenever the text changes
         let glyphs = text_shaper.shape(text.get(), font_size.get(), window_scale.get()).await;
+        let placeholder_glyphs =
+            text_shaper.shape(placeholder.get(), font_size.get(), window_scale.get()).await;
 
         let self_ = Arc::new_cyclic(|me: &Weak<Self>| {
             let mut on_modify = OnModify::new(ex.clone(), node_name, node_id, me.clone());
@@ -235,11 +380,11 @@ impl EditBox {
             // When text has been changed.
             // Cursor and selection might be invalidated.
             async fn reset(self_: Arc<EditBox>) {
-                self_.cursor_pos.set(0);
                 self_.selected.set_null(Role::Internal, 0).unwrap();
                 self_.selected.set_null(Role::Internal, 1).unwrap();
                 self_.scroll.set(0.);
                 self_.regen_glyphs().await;
+                self_.clamp_cursor_pos();
                 self_.redraw().await;
             }
             async fn redraw(self_: Arc<EditBox>) {
@@ -256,6 +401,12 @@ impl EditBox {
             // We must also reshape text
             on_modify.when_change(text.prop(), reset);
             on_modify.when_change(text_color.prop(), redraw);
+            async fn reshape_placeholder(self_: Arc<EditBox>) {
+                self_.regen_glyphs().await;
+                self_.redraw().await;
+            }
+            on_modify.when_change(placeholder.prop(), reshape_placeholder);
+            on_modify.when_change(placeholder_color.prop(), redraw);
             on_modify.when_change(hi_bg_color.prop(), redraw);
             //on_modify.when_change(selected.clone(), redraw);
             on_modify.when_change(z_index.prop(), redraw);
@@ -299,10 +450,13 @@ impl EditBox {
                 tasks,
                 render_api,
                 text_shaper,
+                focus,
                 key_repeat: SyncMutex::new(PressedKeysSmoothRepeat::new(400, 50)),
+                undo_history: SyncMutex::new(UndoHistory::new()),
 
                 text_mesh: SyncMutex::new(None),
                 glyphs: SyncMutex::new(glyphs),
+                placeholder_glyphs: SyncMutex::new(placeholder_glyphs),
                 text_dc_key: OsRng.gen(),
                 cursor_mesh: SyncMutex::new(None),
                 cursor_dc_key: OsRng.gen(),
@@ -317,6 +471,8 @@ impl EditBox {
                 font_size,
                 text,
                 text_color,
+                placeholder,
+                placeholder_color,
                 cursor_color,
                 cursor_width,
                 cursor_ascent,
@@ -325,6 +481,8 @@ impl EditBox {
                 selected,
                 z_index,
                 debug,
+                undo_depth,
+                max_length,
 
                 mouse_btn_held: AtomicBool::new(false),
                 cursor_is_visible: AtomicBool::new(true),
@@ -348,11 +506,88 @@ impl EditBox {
         let font_size = self.font_size.get();
         let window_scale = self.window_scale.get();
         let glyphs = self.text_shaper.shape(self.text.get(), font_size, window_scale).await;
+        let placeholder_glyphs =
+            self.text_shaper.shape(self.placeholder.get(), font_size, window_scale).await;
         // TODO: we aren't freeing textures
         *self.glyphs.lock().unwrap() = glyphs;
+        *self.placeholder_glyphs.lock().unwrap() = placeholder_glyphs;
+    }
+
+    /// Set the text and reshape it, placing the cursor at `cursor_pos`
+    /// capped to the new text length. Edits go through here so the
+    /// cursor can't end up past the end of the text.
+    async fn set_text(&self, text: String, cursor_pos: u32) {
+        self.text.set(text);
+        self.regen_glyphs().await;
+        self.cursor_pos.set(cursor_pos);
+        self.clamp_cursor_pos();
+    }
+
+    /// Cap the cursor to the current text length
+    fn clamp_cursor_pos(&self) {
+        let glyph_count = self.glyphs.lock().unwrap().len();
+        self.cursor_pos.set(clamp_cursor(self.cursor_pos.get(), glyph_count));
+    }
+
+    /// Build the text with `insert` placed at the cursor, returning it
+    /// along with the cursor position after the inserted glyphs.
+    /// Inserted glyphs past `max_length` are dropped, and `None` is
+    /// returned if none of them fit.
+    async fn text_with_insert(&self, insert: &str) -> Option<(String, u32)> {
+        let glyphs = self.glyphs.lock().unwrap().clone();
+        let cursor_pos = (self.cursor_pos.get() as usize).min(glyphs.len());
+
+        // We rebuild the string but insert our substr at cursor_pos.
+        // We can't use String::insert() because sometimes multiple chars are combined
+        // into a single glyph. We treat the cursor pos as acting on the substrs
+        // themselves.
+        let mut text = String::new();
+        for glyph in &glyphs[..cursor_pos] {
+            text.push_str(&glyph.substr);
+        }
+        text.push_str(insert);
+        for glyph in &glyphs[cursor_pos..] {
+            text.push_str(&glyph.substr);
+        }
+
+        // The limit is in glyphs, so shape the result to count them
+        let font_size = self.font_size.get();
+        let window_scale = self.window_scale.get();
+        let new_glyphs = self.text_shaper.shape(text.clone(), font_size, window_scale).await;
+        let inserted = new_glyphs.len().saturating_sub(glyphs.len());
+
+        let max_length = self.max_length.get() as usize;
+        let excess = match max_length {
+            0 => 0,
+            _ => new_glyphs.len().saturating_sub(max_length).min(inserted),
+        };
+        if inserted == excess {
+            return None
+        }
+
+        if excess > 0 {
+            let dropped = cursor_pos + inserted - excess..cursor_pos + inserted;
+            text = new_glyphs
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| !dropped.contains(i))
+                .map(|(_, glyph)| glyph.substr.as_str())
+                .collect();
+        }
+
+        Some((text, (cursor_pos + inserted - excess) as u32))
     }
 
     /// Called whenever the text or any text property changes.
+    /// Glyphs to render, and whether they are the placeholder's.
+    /// The placeholder is never part of the text so has no cursor or selection.
+    fn render_glyphs(&self) -> (Vec<Glyph>, bool) {
+        if show_placeholder(&self.text.get(), &self.placeholder.get(), self.is_focused.get()) {
+            return (self.placeholder_glyphs.lock().unwrap().clone(), true)
+        }
+        (self.glyphs.lock().unwrap().clone(), false)
+    }
+
     fn regen_text_mesh(&self, mut clip: Rectangle) -> GfxDrawMesh {
         clip.x = 0.;
         clip.y = 0.;
@@ -370,11 +605,15 @@ impl EditBox {
         //debug!(target: "ui::editbox", "Rendering text '{text}' clip={clip:?}");
         //debug!(target: "ui::editbox", "    cursor_pos={cursor_pos}, is_focused={is_focused}");
 
-        let glyphs = self.glyphs.lock().unwrap().clone();
+        let (glyphs, is_placeholder) = self.render_glyphs();
+        let (text_color, scroll) =
+            if is_placeholder { (self.placeholder_color.get(), 0.) } else { (text_color, scroll) };
         let atlas = text::make_texture_atlas(&self.render_api, &glyphs);
 
         let mut mesh = MeshBuilder::with_clip(clip.clone());
-        self.draw_selected(&mut mesh, &glyphs, clip.h).unwrap();
+        if !is_placeholder {
+            self.draw_selected(&mut mesh, &glyphs, clip.h).unwrap();
+        }
 
         let glyph_pos_iter = GlyphPositionIter::new(font_size, window_scale, &glyphs, baseline);
 
@@ -513,6 +752,13 @@ impl EditBox {
     }
 
     async fn change_focus(self: Arc<Self>) {
+        // Only one box can be focused at a time
+        if self.is_focused.get() {
+            self.focus.acquire(&self.node());
+        } else {
+            self.focus.release(self.node().id);
+        }
+
         if !self.is_active.get() {
             return
         }
@@ -553,6 +799,8 @@ impl EditBox {
         } else {
             debug!(target: "ui::editbox", "EditBox focused");
             self.is_focused.set(true);
+            // Unfocuses whichever box had the focus before
+            self.focus.acquire(&self.node());
         }
 
         let cpos = self.find_closest_glyph_idx(mouse_pos.x, &rect);
@@ -655,42 +903,89 @@ impl EditBox {
     }
 
     async fn insert_char(&self, key: char) {
+        let before = self.snapshot();
+        // Replacing a selection isn't coalesced with other inserts
+        let is_char_insert = self.selected.is_null(0).unwrap();
+
         if !self.selected.is_null(0).unwrap() {
-            self.delete_highlighted();
-            self.regen_glyphs().await;
+            self.delete_highlighted().await;
         };
 
-        let mut text = String::new();
+        // The editbox is full. Any replaced selection is still deleted.
+        let Some((text, cursor_pos)) = self.text_with_insert(&key.to_string()).await else {
+            self.record_edit(before, false).await;
+            self.redraw().await;
+            return
+        };
 
-        let cursor_pos = self.cursor_pos.get();
+        self.set_text(text, cursor_pos).await;
+        self.record_edit(before, is_char_insert).await;
 
-        let glyphs = self.glyphs.lock().unwrap().clone();
+        self.pause_blinking();
+        self.apply_cursor_scrolling();
+        self.redraw().await;
+    }
 
-        // We rebuild the string but insert our substr at cursor_pos.
-        // The substr is inserted before cursor_pos, and appending to the end
-        // of the string is when cursor_pos = len(str).
-        // We can't use String::insert() because sometimes multiple chars are combined
-        // into a single glyph. We treat the cursor pos as acting on the substrs
-        // themselves.
-        for (i, glyph) in glyphs.iter().enumerate() {
-            if cursor_pos == i as u32 {
-                text.push(key);
-            }
-            text.push_str(&glyph.substr);
-        }
-        // Append to the end
-        if cursor_pos == glyphs.len() as u32 {
-            text.push(key);
+    /// Current text and cursor position, used for the undo history
+    fn snapshot(&self) -> TextSnapshot {
+        TextSnapshot { text: self.text.get(), cursor_pos: self.cursor_pos.get() }
+    }
+
+    /// Record an edit in the undo history and emit `text_changed`.
+    /// Does nothing if the text didn't actually change.
+    async fn record_edit(&self, before: TextSnapshot, is_char_insert: bool) {
+        let after = self.snapshot();
+        if after.text == before.text {
+            return
         }
 
-        self.text.set(text);
-        // Not always true lol
-        // If glyphs are recombined, this could get messed up
-        // meh lets pretend it doesn't exist for now.
-        self.cursor_pos.set(cursor_pos + 1);
+        let max_depth = self.undo_depth.get() as usize;
+        self.undo_history.lock().unwrap().record(before, &after, is_char_insert, max_depth);
+
+        let node = self.node.upgrade().unwrap();
+        emit_text_changed(&node, &after.text).await;
+    }
+
+    /// Restore the text and cursor from the undo history
+    async fn restore_snapshot(&self, snapshot: TextSnapshot) {
+        self.selected.set_null(Role::Internal, 0).unwrap();
+        self.selected.set_null(Role::Internal, 1).unwrap();
+        self.set_text(snapshot.text.clone(), snapshot.cursor_pos).await;
+
+        let node = self.node.upgrade().unwrap();
+        emit_text_changed(&node, &snapshot.text).await;
+
+        self.pause_blinking();
+        self.apply_cursor_scrolling();
+        self.redraw().await;
+    }
+
+    async fn undo(&self) {
+        let Some(prev) = self.undo_history.lock().unwrap().undo(self.snapshot()) else { return };
+        debug!(target: "ui::editbox", "undo() text=\"{}\"", prev.text);
+        self.restore_snapshot(prev).await;
+    }
+
+    async fn redo(&self) {
+        let max_depth = self.undo_depth.get() as usize;
+        let Some(next) = self.undo_history.lock().unwrap().redo(self.snapshot(), max_depth) else {
+            return
+        };
+        debug!(target: "ui::editbox", "redo() text=\"{}\"", next.text);
+        self.restore_snapshot(next).await;
+    }
+
+    /// Select the entire text and move the cursor to its end.
+    /// Typing afterwards replaces the selection.
+    async fn select_all(&self) {
+        let glyph_count = self.glyphs.lock().unwrap().len();
+        let Some((start, end)) = select_all_span(glyph_count) else { return };
+
+        self.selected.set_u32(Role::Internal, 0, start).unwrap();
+        self.selected.set_u32(Role::Internal, 1, end).unwrap();
+        self.cursor_pos.set(end);
 
         self.pause_blinking();
-        self.regen_glyphs().await;
         self.apply_cursor_scrolling();
         self.redraw().await;
     }
@@ -699,6 +994,11 @@ impl EditBox {
         debug!(target: "ui::editbox", "handle_shortcut({:?}, {:?})", key, mods);
 
         match key {
+            'a' => {
+                if mods.ctrl {
+                    self.select_all().await;
+                }
+            }
             'c' => {
                 if mods.ctrl {
                     self.copy_highlighted().unwrap();
@@ -711,6 +1011,18 @@ impl EditBox {
                     }
                 }
             }
+            'z' | 'Z' => {
+                if mods.ctrl && mods.shift {
+                    self.redo().await;
+                } else if mods.ctrl {
+                    self.undo().await;
+                }
+            }
+            'y' => {
+                if mods.ctrl {
+                    self.redo().await;
+                }
+            }
             _ => {}
         }
     }
@@ -731,7 +1043,14 @@ impl EditBox {
                 }
 
                 if cursor_pos > 0 {
-                    cursor_pos -= 1;
+                    // Jump to the start of the previous word if ctrl is held
+                    if mods.ctrl {
+                        let glyphs = self.glyphs.lock().unwrap().clone();
+                        let substrs: Vec<&str> = glyphs.iter().map(|g| g.substr.as_str()).collect();
+                        cursor_pos = prev_word_boundary(&substrs, cursor_pos as usize) as u32;
+                    } else {
+                        cursor_pos -= 1;
+                    }
                     debug!(target: "ui::editbox", "Left cursor_pos={}", cursor_pos);
                     self.cursor_pos.set(cursor_pos);
                 }
@@ -757,9 +1076,15 @@ impl EditBox {
                     self.selected.set_u32(Role::Internal, 0, cursor_pos).unwrap();
                 }
 
-                let glyphs_len = self.glyphs.lock().unwrap().len() as u32;
-                if cursor_pos < glyphs_len {
-                    cursor_pos += 1;
+                let glyphs = self.glyphs.lock().unwrap().clone();
+                if cursor_pos < glyphs.len() as u32 {
+                    // Jump to the end of the next word if ctrl is held
+                    if mods.ctrl {
+                        let substrs: Vec<&str> = glyphs.iter().map(|g| g.substr.as_str()).collect();
+                        cursor_pos = next_word_boundary(&substrs, cursor_pos as usize) as u32;
+                    } else {
+                        cursor_pos += 1;
+                    }
                     debug!(target: "ui::editbox", "Right cursor_pos={}", cursor_pos);
                     self.cursor_pos.set(cursor_pos);
                 }
@@ -792,8 +1117,9 @@ impl EditBox {
                 node.trigger("enter_pressed", vec![]).await.unwrap();
             }
             KeyCode::Delete => {
+                let before = self.snapshot();
                 if !self.selected.is_null(0).unwrap() {
-                    self.delete_highlighted();
+                    self.delete_highlighted().await;
                 } else {
                     let glyphs = self.glyphs.lock().unwrap().clone();
 
@@ -814,17 +1140,37 @@ impl EditBox {
                         }
                         text.push_str(&substr);
                     }
-                    self.text.set(text);
+                    self.set_text(text, cursor_pos).await;
                 };
+                self.record_edit(before, false).await;
 
                 self.pause_blinking();
-                self.regen_glyphs().await;
                 self.apply_cursor_scrolling();
                 self.redraw().await;
             }
             KeyCode::Backspace => {
+                let before = self.snapshot();
                 if !self.selected.is_null(0).unwrap() {
-                    self.delete_highlighted();
+                    self.delete_highlighted().await;
+                } else if mods.ctrl {
+                    // Delete the previous word
+                    let glyphs = self.glyphs.lock().unwrap().clone();
+                    let substrs: Vec<&str> = glyphs.iter().map(|g| g.substr.as_str()).collect();
+
+                    let cursor_pos = self.cursor_pos.get() as usize;
+                    let word_start = prev_word_boundary(&substrs, cursor_pos);
+                    if word_start == cursor_pos {
+                        return;
+                    }
+
+                    let mut text = String::new();
+                    for (i, substr) in substrs.iter().enumerate() {
+                        if word_start <= i && i < cursor_pos {
+                            continue
+                        }
+                        text.push_str(substr);
+                    }
+                    self.set_text(text, word_start as u32).await;
                 } else {
                     let glyphs = self.glyphs.lock().unwrap().clone();
 
@@ -841,12 +1187,11 @@ impl EditBox {
                         }
                         text.push_str(&substr);
                     }
-                    self.text.set(text);
-                    self.cursor_pos.set(cursor_pos - 1);
+                    self.set_text(text, cursor_pos - 1).await;
                 };
+                self.record_edit(before, false).await;
 
                 self.pause_blinking();
-                self.regen_glyphs().await;
                 self.apply_cursor_scrolling();
                 self.redraw().await;
             }
@@ -899,7 +1244,7 @@ impl EditBox {
         }
     }
 
-    fn delete_highlighted(&self) {
+    async fn delete_highlighted(&self) {
         assert!(!self.selected.is_null(0).unwrap());
         assert!(!self.selected.is_null(1).unwrap());
 
@@ -925,11 +1270,9 @@ impl EditBox {
             "delete_highlighted() text=\"{}\", cursor_pos={}",
             text, sel_start
         );
-        self.text.set(text);
-
         self.selected.set_null(Role::Internal, 0).unwrap();
         self.selected.set_null(Role::Internal, 1).unwrap();
-        self.cursor_pos.set(sel_start as u32);
+        self.set_text(text, sel_start as u32).await;
     }
 
     fn copy_highlighted(&self) -> Result<()> {
@@ -954,25 +1297,13 @@ impl EditBox {
     }
 
     async fn paste_text(&self, key: String) {
-        let mut text = String::new();
+        let before = self.snapshot();
 
-        let cursor_pos = self.cursor_pos.get();
+        // The pasted text is truncated so it fits within max_length
+        let Some((text, cursor_pos)) = self.text_with_insert(&key).await else { return };
 
-        if cursor_pos == 0 {
-            text = key.clone();
-        }
-
-        let glyphs = self.glyphs.lock().unwrap().clone();
-        for (glyph_idx, glyph) in glyphs.iter().enumerate() {
-            text.push_str(&glyph.substr);
-            if cursor_pos == glyph_idx as u32 + 1 {
-                text.push_str(&key);
-            }
-        }
-
-        self.text.set(text);
-        // Not always true lol
-        self.cursor_pos.set(cursor_pos + 1);
+        self.set_text(text, cursor_pos).await;
+        self.record_edit(before, false).await;
 
         self.apply_cursor_scrolling();
         self.redraw().await;
@@ -1316,3 +1647,339 @@ impl Stoppable for EditBox {
     }
 }
 */
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        app::node::create_editbox,
+        gfx::RenderApi,
+        prop::{Property, PropertySubType, PropertyType},
+        scene::{SceneNodeType, Slot},
+        text::TextShaper,
+        ui::Focus,
+    };
+    use darkfi_serial::deserialize;
+    use std::sync::mpsc;
+
+    fn substrs(text: &str) -> Vec<String> {
+        text.chars().map(|c| c.to_string()).collect()
+    }
+
+    /// Create an editbox shaping text with the real fonts. The property
+    /// hooks only run when `ex` is ticked.
+    async fn make_editbox(ex: ExecutorPtr) -> (SceneNodePtr, Arc<EditBox>) {
+        let mut window = SceneNode::new("window", SceneNodeType::Window);
+        let mut prop = Property::new("scale", PropertyType::Float32, PropertySubType::Pixel);
+        prop.set_defaults_f32(vec![1.]).unwrap();
+        window.add_property(prop).unwrap();
+        let window_scale = PropertyFloat32::wrap(&window, Role::Internal, "scale", 0).unwrap();
+
+        let node = create_editbox("editbox");
+        node.set_property_f32(Role::App, "font_size", 20.).unwrap();
+
+        let (method_req, _) = mpsc::channel();
+        let render_api = RenderApi::new(method_req);
+        let node = node
+            .setup(|me| {
+                EditBox::new(me, window_scale, render_api, TextShaper::new(), Focus::new(), ex)
+            })
+            .await;
+
+        let Pimpl::EditBox(editbox) = &node.pimpl else { unreachable!() };
+        let editbox = editbox.clone();
+        (node, editbox)
+    }
+
+    /// Run the property hooks until they're all done
+    fn settle(ex: &ExecutorPtr) {
+        while ex.try_tick() {}
+    }
+
+    fn glyphs_text(glyphs: &[Glyph]) -> String {
+        glyphs.iter().map(|glyph| glyph.substr.as_str()).collect()
+    }
+
+    const CTRL: KeyMods = KeyMods { shift: false, ctrl: true, alt: false, logo: false };
+
+    #[test]
+    fn text_changed_signal() {
+        let ex = Arc::new(smol::Executor::new());
+        smol::block_on(async {
+            let (node, editbox) = make_editbox(ex).await;
+            let (slot, recvr) = Slot::new("text_changed_test");
+            node.register("text_changed", slot).unwrap();
+
+            // Inserting 'o' at the end of "hell"
+            editbox.set_text("hell".to_string(), 4).await;
+            editbox.insert_char('o').await;
+            let text: String = deserialize(&recvr.try_recv().unwrap()).unwrap();
+            assert_eq!(text, "hello");
+
+            // Pasting too
+            editbox.paste_text(" world".to_string()).await;
+            let text: String = deserialize(&recvr.try_recv().unwrap()).unwrap();
+            assert_eq!(text, "hello world");
+
+            // Edits which leave the text as it was don't emit anything
+            editbox.max_length.set(11);
+            editbox.insert_char('!').await;
+            assert!(recvr.try_recv().is_err());
+        });
+    }
+
+    #[test]
+    fn cursor_clamped_to_text() {
+        let ex = Arc::new(smol::Executor::new());
+        smol::block_on(async {
+            let (node, editbox) = make_editbox(ex.clone()).await;
+            editbox.set_text("hello world".to_string(), 11).await;
+            assert_eq!(editbox.cursor_pos.get(), 11);
+
+            // Another component shrinking the text moves the cursor to the new end
+            node.set_property_str(Role::App, "text", "hi").unwrap();
+            settle(&ex);
+            assert_eq!(editbox.cursor_pos.get(), 2);
+
+            // Otherwise it stays where it was
+            editbox.set_text("hello world".to_string(), 3).await;
+            node.set_property_str(Role::App, "text", "hello world!").unwrap();
+            settle(&ex);
+            assert_eq!(editbox.cursor_pos.get(), 3);
+
+            // Edits can't place it past the end either
+            editbox.set_text(String::new(), 5).await;
+            assert_eq!(editbox.cursor_pos.get(), 0);
+        });
+    }
+
+    #[test]
+    fn placeholder_visibility() {
+        let ex = Arc::new(smol::Executor::new());
+        smol::block_on(async {
+            let (node, editbox) = make_editbox(ex.clone()).await;
+
+            // Nothing to show without a placeholder
+            let (glyphs, is_placeholder) = editbox.render_glyphs();
+            assert!(glyphs.is_empty());
+            assert!(!is_placeholder);
+
+            // Shown for an empty text, without becoming part of it
+            node.set_property_str(Role::App, "placeholder", "Send a message").unwrap();
+            settle(&ex);
+            let (glyphs, is_placeholder) = editbox.render_glyphs();
+            assert!(is_placeholder);
+            assert_eq!(glyphs_text(&glyphs), "Send a message");
+            assert_eq!(editbox.text.get(), "");
+
+            // Typing hides it
+            editbox.insert_char('h').await;
+            let (glyphs, is_placeholder) = editbox.render_glyphs();
+            assert!(!is_placeholder);
+            assert_eq!(glyphs_text(&glyphs), "h");
+            assert_eq!(editbox.text.get(), "h");
+
+            // Focusing hides it so the cursor is visible
+            editbox.set_text(String::new(), 0).await;
+            assert!(editbox.render_glyphs().1);
+            editbox.is_focused.set(true);
+            assert!(!editbox.render_glyphs().1);
+        });
+    }
+
+    #[test]
+    fn select_all() {
+        let ex = Arc::new(smol::Executor::new());
+        smol::block_on(async {
+            let (_node, editbox) = make_editbox(ex).await;
+            editbox.set_text("hello world".to_string(), 3).await;
+
+            editbox.handle_shortcut('a', &CTRL).await;
+            assert_eq!(editbox.selected.get_u32(0).unwrap(), 0);
+            assert_eq!(editbox.selected.get_u32(1).unwrap(), 11);
+            assert_eq!(editbox.cursor_pos.get(), 11);
+
+            // Typing replaces the selection
+            editbox.insert_char('x').await;
+            assert_eq!(editbox.text.get(), "x");
+            assert_eq!(editbox.cursor_pos.get(), 1);
+            assert!(editbox.selected.is_null(0).unwrap());
+
+            // Nothing to select in an empty editbox
+            editbox.set_text(String::new(), 0).await;
+            editbox.handle_shortcut('a', &CTRL).await;
+            assert!(editbox.selected.is_null(0).unwrap());
+            assert_eq!(editbox.cursor_pos.get(), 0);
+        });
+    }
+
+    #[test]
+    fn word_boundaries() {
+        let text = substrs("hello world");
+        let text: Vec<&str> = text.iter().map(|s| s.as_str()).collect();
+        assert_eq!(prev_word_boundary(&text, 11), 6);
+        assert_eq!(prev_word_boundary(&text, 6), 0);
+        assert_eq!(prev_word_boundary(&text, 3), 0);
+        assert_eq!(prev_word_boundary(&text, 0), 0);
+        assert_eq!(next_word_boundary(&text, 0), 5);
+        assert_eq!(next_word_boundary(&text, 5), 11);
+        assert_eq!(next_word_boundary(&text, 11), 11);
+
+        // Leading, trailing and consecutive whitespace
+        let text = substrs("  foo   bar  ");
+        let text: Vec<&str> = text.iter().map(|s| s.as_str()).collect();
+        assert_eq!(prev_word_boundary(&text, 13), 8);
+        assert_eq!(prev_word_boundary(&text, 8), 2);
+        assert_eq!(prev_word_boundary(&text, 2), 0);
+        assert_eq!(next_word_boundary(&text, 0), 5);
+        assert_eq!(next_word_boundary(&text, 5), 11);
+        assert_eq!(next_word_boundary(&text, 11), 13);
+
+        // Punctuation separates words too
+        let text = substrs("send 1.5 DRK, now!");
+        let text: Vec<&str> = text.iter().map(|s| s.as_str()).collect();
+        assert_eq!(prev_word_boundary(&text, 18), 14);
+        assert_eq!(prev_word_boundary(&text, 14), 9);
+        assert_eq!(prev_word_boundary(&text, 9), 7);
+        assert_eq!(next_word_boundary(&text, 5), 6);
+        assert_eq!(next_word_boundary(&text, 8), 12);
+
+        // Only whitespace, and empty text
+        let text = substrs("    ");
+        let text: Vec<&str> = text.iter().map(|s| s.as_str()).collect();
+        assert_eq!(prev_word_boundary(&text, 4), 0);
+        assert_eq!(next_word_boundary(&text, 0), 4);
+        assert_eq!(prev_word_boundary(&[], 0), 0);
+        assert_eq!(next_word_boundary(&[], 0), 0);
+    }
+
+    fn snap(text: &str, cursor_pos: u32) -> TextSnapshot {
+        TextSnapshot { text: text.to_string(), cursor_pos }
+    }
+
+    /// Simulate typing `chars` at the end of `current`
+    fn type_chars(history: &mut UndoHistory, current: &mut TextSnapshot, chars: &str) {
+        for c in chars.chars() {
+            let before = current.clone();
+            current.text.push(c);
+            current.cursor_pos += 1;
+            history.record(before, current, true, 100);
+        }
+    }
+
+    #[test]
+    fn undo_redo() {
+        let mut history = UndoHistory::new();
+        let mut current = snap("", 0);
+
+        // Consecutive inserts are coalesced into a single undo step
+        type_chars(&mut history, &mut current, "hello");
+        assert_eq!(history.undo.len(), 1);
+
+        // Moving the cursor starts a new step
+        current.cursor_pos = 0;
+        type_chars(&mut history, &mut current, ">");
+        assert_eq!(current, snap("hello>", 1));
+        assert_eq!(history.undo.len(), 2);
+
+        // Other edits are always their own step
+        let before = current.clone();
+        current = snap("hello", 0);
+        history.record(before, &current, false, 100);
+        assert_eq!(history.undo.len(), 3);
+
+        let state = history.undo(current.clone()).unwrap();
+        assert_eq!(state, snap("hello>", 1));
+        let state = history.undo(state).unwrap();
+        assert_eq!(state, snap("hello", 0));
+        let state = history.undo(state).unwrap();
+        assert_eq!(state, snap("", 0));
+        assert!(history.undo(state.clone()).is_none());
+
+        // Redo re-applies the edits in order
+        let state = history.redo(state, 100).unwrap();
+        assert_eq!(state, snap("hello", 0));
+        let state = history.redo(state, 100).unwrap();
+        assert_eq!(state, snap("hello>", 1));
+        let state = history.redo(state, 100).unwrap();
+        assert_eq!(state, current);
+        assert!(history.redo(state.clone(), 100).is_none());
+
+        // A new edit clears the redo stack
+        let state = history.undo(state).unwrap();
+        let before = state.clone();
+        history.record(before, &snap("hello>!", 7), false, 100);
+        assert!(history.redo(snap("hello>!", 7), 100).is_none());
+    }
+
+    #[test]
+    fn undo_depth_is_bounded() {
+        let mut history = UndoHistory::new();
+        for i in 0..10 {
+            let before = snap(&"a".repeat(i), 0);
+            let after = snap(&"a".repeat(i + 1), 0);
+            history.record(before, &after, false, 3);
+        }
+        assert_eq!(history.undo.len(), 3);
+
+        // Only the most recent edits can be undone
+        let state = history.undo(snap(&"a".repeat(10), 0)).unwrap();
+        assert_eq!(state.text, "a".repeat(9));
+        let state = history.undo(state).unwrap();
+        let state = history.undo(state).unwrap();
+        assert_eq!(state.text, "a".repeat(7));
+        assert!(history.undo(state).is_none());
+    }
+
+    #[test]
+    fn typing_past_max_length_is_noop() {
+        let ex = Arc::new(smol::Executor::new());
+        smol::block_on(async {
+            let (_node, editbox) = make_editbox(ex).await;
+            editbox.max_length.set(5);
+
+            for key in "hello world".chars() {
+                editbox.insert_char(key).await;
+            }
+            assert_eq!(editbox.text.get(), "hello");
+            assert_eq!(editbox.cursor_pos.get(), 5);
+
+            // Typing in the middle is ignored too
+            editbox.cursor_pos.set(2);
+            editbox.insert_char('x').await;
+            assert_eq!(editbox.text.get(), "hello");
+
+            // Unset max_length never limits input
+            editbox.max_length.set(0);
+            editbox.cursor_pos.set(5);
+            editbox.insert_char('!').await;
+            assert_eq!(editbox.text.get(), "hello!");
+        });
+    }
+
+    #[test]
+    fn paste_truncates_to_max_length() {
+        let ex = Arc::new(smol::Executor::new());
+        smol::block_on(async {
+            let (_node, editbox) = make_editbox(ex).await;
+            editbox.max_length.set(5);
+
+            // Only the start of the pasted text fits
+            editbox.set_text("ad".to_string(), 1).await;
+            editbox.paste_text("bcXYZ".to_string()).await;
+            assert_eq!(editbox.text.get(), "abcXd");
+            assert_eq!(editbox.cursor_pos.get(), 4);
+
+            // Pasting into a full editbox does nothing
+            editbox.paste_text("e".to_string()).await;
+            assert_eq!(editbox.text.get(), "abcXd");
+
+            // The limit is in glyphs. A flag is two chars shaped into one glyph.
+            editbox.max_length.set(3);
+            editbox.set_text("ab".to_string(), 2).await;
+            editbox.paste_text("🇮🇸🇮🇸".to_string()).await;
+            assert_eq!(editbox.text.get(), "ab🇮🇸");
+            assert_eq!(editbox.cursor_pos.get(), 3);
+        });
+    }
+}
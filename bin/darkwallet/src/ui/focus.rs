@@ -0,0 +1,148 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2024 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::sync::{Arc, Mutex as SyncMutex};
+
+use crate::{
+    prop::{PropertyBool, Role},
+    scene::{SceneNode as SceneNode3, SceneNodeId},
+};
+
+pub type FocusPtr = Arc<Focus>;
+
+/// Keyboard focus shared by all the widgets of the scene. At most one node
+/// owns the focus, so key events only mutate that node.
+pub struct Focus {
+    /// The owner with its `is_focused` property
+    owner: SyncMutex<Option<(SceneNodeId, PropertyBool)>>,
+}
+
+impl Focus {
+    pub fn new() -> FocusPtr {
+        Arc::new(Self { owner: SyncMutex::new(None) })
+    }
+
+    /// Make `node` the owner of the focus. The previous owner gets its
+    /// `is_focused` property cleared, which triggers its focus change hook.
+    pub fn acquire(&self, node: &SceneNode3) {
+        let is_focused = PropertyBool::wrap(node, Role::App, "is_focused", 0).unwrap();
+        let prev = self.owner.lock().unwrap().replace((node.id, is_focused));
+
+        if let Some((prev_id, prev_is_focused)) = prev {
+            if prev_id != node.id {
+                prev_is_focused.set(false);
+            }
+        }
+    }
+
+    /// Give up the focus if `node_id` owns it
+    pub fn release(&self, node_id: SceneNodeId) {
+        let mut owner = self.owner.lock().unwrap();
+        if owner.as_ref().map(|(id, _)| *id) == Some(node_id) {
+            *owner = None;
+        }
+    }
+
+    pub fn owner(&self) -> Option<SceneNodeId> {
+        self.owner.lock().unwrap().as_ref().map(|(id, _)| *id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        app::node::create_editbox,
+        gfx::RenderApi,
+        prop::{Property, PropertyFloat32, PropertySubType, PropertyType},
+        scene::{Pimpl, SceneNodePtr, SceneNodeType},
+        text::TextShaper,
+        ui::{EditBox, EditBoxPtr, UIObject},
+        ExecutorPtr,
+    };
+    use miniquad::KeyMods;
+    use std::sync::mpsc;
+
+    const NO_MODS: KeyMods = KeyMods { shift: false, ctrl: false, alt: false, logo: false };
+
+    async fn make_editbox(
+        name: &str,
+        focus: FocusPtr,
+        ex: ExecutorPtr,
+    ) -> (SceneNodePtr, EditBoxPtr) {
+        let mut window = SceneNode3::new("window", SceneNodeType::Window);
+        let mut prop = Property::new("scale", PropertyType::Float32, PropertySubType::Pixel);
+        prop.set_defaults_f32(vec![1.]).unwrap();
+        window.add_property(prop).unwrap();
+        let window_scale = PropertyFloat32::wrap(&window, Role::Internal, "scale", 0).unwrap();
+
+        let (method_req, _) = mpsc::channel();
+        let render_api = RenderApi::new(method_req);
+        let node = create_editbox(name)
+            .setup(|me| EditBox::new(me, window_scale, render_api, TextShaper::new(), focus, ex))
+            .await;
+
+        let Pimpl::EditBox(editbox) = &node.pimpl else { unreachable!() };
+        let editbox = editbox.clone();
+        (node, editbox)
+    }
+
+    /// Run the property hooks until they're all done
+    fn settle(ex: &ExecutorPtr) {
+        while ex.try_tick() {}
+    }
+
+    #[test]
+    fn keys_route_to_focused_node() {
+        let ex = Arc::new(smol::Executor::new());
+        smol::block_on(async {
+            let focus = Focus::new();
+            let (a, editbox_a) = make_editbox("a", focus.clone(), ex.clone()).await;
+            let (b, editbox_b) = make_editbox("b", focus.clone(), ex.clone()).await;
+
+            // Clicking a
+            a.set_property_bool(Role::App, "is_focused", true).unwrap();
+            settle(&ex);
+            assert_eq!(focus.owner(), Some(a.id));
+
+            // Only the focused box takes the key
+            assert!(editbox_a.handle_char('x', NO_MODS, false).await);
+            assert!(!editbox_b.handle_char('y', NO_MODS, false).await);
+            assert_eq!(a.get_property_str("text").unwrap(), "x");
+            assert_eq!(b.get_property_str("text").unwrap(), "");
+
+            // Clicking b takes the focus away from a
+            b.set_property_bool(Role::App, "is_focused", true).unwrap();
+            settle(&ex);
+            assert_eq!(focus.owner(), Some(b.id));
+            assert!(!a.get_property_bool("is_focused").unwrap());
+
+            assert!(!editbox_a.handle_char('x', NO_MODS, false).await);
+            assert!(editbox_b.handle_char('y', NO_MODS, false).await);
+            assert_eq!(a.get_property_str("text").unwrap(), "x");
+            assert_eq!(b.get_property_str("text").unwrap(), "y");
+
+            // Only the owner can release the focus
+            focus.release(a.id);
+            assert_eq!(focus.owner(), Some(b.id));
+            b.set_property_bool(Role::App, "is_focused", false).unwrap();
+            settle(&ex);
+            assert_eq!(focus.owner(), None);
+        });
+    }
+}
@@ -35,6 +35,8 @@ pub mod chatview;
 pub use chatview::{ChatView, ChatViewPtr};
 mod editbox;
 pub use editbox::{EditBox, EditBoxPtr};
+mod focus;
+pub use focus::{Focus, FocusPtr};
 mod image;
 pub use image::{Image, ImagePtr};
 pub mod vector_art;
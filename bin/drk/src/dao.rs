@@ -1897,7 +1897,7 @@ impl Drk {
         let signature_secret = SecretKey::random(&mut OsRng);
         let mut inputs = Vec::with_capacity(gov_owncoins_to_use.len());
         for gov_owncoin in gov_owncoins_to_use {
-            let nullifier = poseidon_hash([gov_owncoin.secret.inner(), gov_owncoin.coin.inner()]);
+            let nullifier = gov_owncoin.nullifier().inner();
             let vote_nullifier =
                 poseidon_hash([nullifier, gov_owncoin.secret.inner(), proposal_bulla.inner()]);
             if votes_nullifiers.contains(&vote_nullifier.into()) {
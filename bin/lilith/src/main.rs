@@ -201,7 +201,11 @@ impl Lilith {
                         debug!(target: "net::refinery:::whitelist_refinery",
                        "Host {} is not responsive. Downgrading from whitelist", url);
 
-                        hosts.greylist_host(url, *last_seen)?;
+                        // The peer may have been banned meanwhile
+                        if let Err(e) = hosts.greylist_host(url, *last_seen) {
+                            debug!(target: "net::refinery::whitelist_refinery",
+                           "Unable to downgrade addr={}, err={}", url, e);
+                        }
 
                         continue
                     }
@@ -212,7 +216,12 @@ impl Lilith {
                     // This node is active. Update the last seen field.
                     let last_seen = UNIX_EPOCH.elapsed().unwrap().as_secs();
 
-                    hosts.whitelist_host(url, last_seen)?;
+                    // The peer may have been banned meanwhile
+                    if let Err(e) = hosts.whitelist_host(url, last_seen) {
+                        debug!(target: "net::refinery::whitelist_refinery",
+                       "Unable to update addr={}, err={}", url, e);
+                        continue
+                    }
                 }
                 None => {
                     debug!(target: "net::refinery::whitelist_refinery",
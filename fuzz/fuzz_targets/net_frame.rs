@@ -0,0 +1,30 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2024 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+use darkfi::net::message::Frame;
+use darkfi_serial::{deserialize_partial, serialize};
+
+fuzz_target!(|data: &[u8]| {
+    // Anything that decodes must encode back to the bytes it was read from
+    if let Ok((frame, consumed)) = deserialize_partial::<Frame>(data) {
+        assert_eq!(&data[..consumed], &serialize(&frame)[..]);
+    }
+});
@@ -19,12 +19,11 @@ use darkfi::net;
 use darkfi_serial::{AsyncDecodable, VarInt};
 use smol::io::AsyncReadExt;
 use std::sync::Arc;
-use url::Url;
 
 const ENDPOINT: &str = "tcp+tls://lilith1.dark.fi:5262";
 
 async fn ping(endpoint: &str) {
-    let Ok(endpoint) = Url::parse(endpoint) else {
+    let Ok(endpoint) = net::transport::parse_endpoint(endpoint) else {
         println!("Invalid endpoint {endpoint}");
         return
     };
@@ -30,7 +30,7 @@ use darkfi::{
     tx::{ContractCallLeaf, Transaction, TransactionBuilder},
     util::{encoding::base64, parse::decode_base10, path::expand_path, time::Timestamp},
     validator::{utils::deploy_native_contracts, verification::verify_genesis_block},
-    zk::{empty_witnesses, ProvingKey, ZkCircuit},
+    zk::{empty_witnesses, ProvingKey, VerificationCache, VerificationLimit, ZkCircuit},
     zkas::ZkBinary,
     Result,
 };
@@ -164,7 +164,16 @@ async fn main() -> Result<()> {
             let overlay = BlockchainOverlay::new(&blockchain)?;
             deploy_native_contracts(&overlay, 0).await?;
 
-            verify_genesis_block(&overlay, &genesis_block, 0).await?;
+            let verification_cache = VerificationCache::new(0);
+            let verification_limit = VerificationLimit::new(0);
+            verify_genesis_block(
+                &overlay,
+                &genesis_block,
+                0,
+                &verification_cache,
+                &verification_limit,
+            )
+            .await?;
 
             println!("Genesis block {hash} verified successfully!");
         }
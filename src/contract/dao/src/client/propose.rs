@@ -16,7 +16,7 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
-use darkfi_money_contract::model::CoinAttributes;
+use darkfi_money_contract::model::{CoinAttributes, Nullifier};
 use darkfi_sdk::{
     bridgetree,
     bridgetree::Hashable,
@@ -101,7 +101,7 @@ impl<'a, T: StorageAdapter<Value = pallas::Base>> DaoProposeCall<'a, T> {
                 blind: note.coin_blind,
             }
             .to_coin();
-            let nullifier = poseidon_hash([input.secret.inner(), coin.inner()]);
+            let nullifier = Nullifier::derive(&input.secret, &coin).inner();
 
             let smt_null_path = self.money_null_smt.prove_membership(&nullifier);
             if !smt_null_path.verify(&smt_null_root, &pallas::Base::ZERO, &nullifier) {
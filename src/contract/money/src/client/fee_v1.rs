@@ -159,7 +159,7 @@ pub fn create_fee_proof(
         poseidon_hash([input.coin.note.user_data, input.user_data_blind.inner()]);
     let input_value_commit = pedersen_commitment_u64(input.coin.note.value, input_value_blind);
     let output_value_commit = pedersen_commitment_u64(output.value, output_value_blind);
-    let token_commit = poseidon_hash([input.coin.note.token_id.inner(), token_blind.inner()]);
+    let token_commit = input.coin.note.token_id.commit(token_blind);
 
     // Create output coin
     let output_coin = CoinAttributes {
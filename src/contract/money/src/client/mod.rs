@@ -27,6 +27,8 @@
 //! are able to abstract away any wallet interfaces to client implementations.
 
 use std::hash::{Hash, Hasher};
+#[cfg(debug_assertions)]
+use std::sync::Mutex;
 
 use darkfi_sdk::{
     bridgetree,
@@ -37,6 +39,7 @@ use darkfi_sdk::{
     pasta::pallas,
 };
 use darkfi_serial::{async_trait, SerialDecodable, SerialEncodable};
+use rand::{CryptoRng, RngCore};
 
 use crate::model::{Coin, Nullifier, TokenId};
 
@@ -110,7 +113,7 @@ pub struct OwnCoin {
 impl OwnCoin {
     /// Derive the [`Nullifier`] for this [`OwnCoin`]
     pub fn nullifier(&self) -> Nullifier {
-        Nullifier::from(poseidon_hash([self.secret.inner(), self.coin.inner()]))
+        Nullifier::derive(&self.secret, &self.coin)
     }
 }
 
@@ -136,3 +139,94 @@ pub fn compute_remainder_blind(
 
     Blind(total)
 }
+
+/// Fresh blinds for minting a single coin
+#[derive(Debug, Clone, Copy)]
+pub struct Blinds {
+    /// Blind for the value commitment
+    pub value_blind: ScalarBlind,
+    /// Blind for the token commitment
+    pub token_blind: BaseBlind,
+    /// Blind for the coin itself
+    pub coin_blind: BaseBlind,
+}
+
+/// Generate a fresh set of blinds for minting a coin. Blinds must never
+/// be reused across coins, since that links them, so prefer this over
+/// picking blinds by hand.
+pub fn random_blinds(rng: &mut (impl CryptoRng + RngCore)) -> Blinds {
+    Blinds {
+        value_blind: Blind::random(rng),
+        token_blind: Blind::random(rng),
+        coin_blind: Blind::random(rng),
+    }
+}
+
+/// Size in bits of the Bloom filter remembering the coin blinds
+/// used within this process
+#[cfg(debug_assertions)]
+const COIN_BLIND_FILTER_BITS: usize = 1 << 16;
+
+/// Amount of filter bits set for every coin blind
+#[cfg(debug_assertions)]
+const COIN_BLIND_FILTER_HASHES: usize = 4;
+
+#[cfg(debug_assertions)]
+static COIN_BLIND_FILTER: Mutex<[u64; COIN_BLIND_FILTER_BITS / 64]> =
+    Mutex::new([0; COIN_BLIND_FILTER_BITS / 64]);
+
+/// Remember `coin_blind` in a process-wide Bloom filter, warning and
+/// returning `true` if it was probably used before. This is a debugging
+/// aid only compiled into debug builds, and false positives get more
+/// likely as more coins are minted.
+#[cfg(debug_assertions)]
+pub(crate) fn check_coin_blind_reuse(coin_blind: &BaseBlind) -> bool {
+    // Blinds are random field elements, so their bytes can be used as
+    // the filter hashes directly.
+    let repr = coin_blind.inner().to_repr();
+    let mut filter = COIN_BLIND_FILTER.lock().unwrap();
+
+    let mut seen = true;
+    for chunk in repr.chunks_exact(4).take(COIN_BLIND_FILTER_HASHES) {
+        let bit = u32::from_le_bytes(chunk.try_into().unwrap()) as usize % COIN_BLIND_FILTER_BITS;
+        let (word, mask) = (bit / 64, 1 << (bit % 64));
+        seen &= filter[word] & mask != 0;
+        filter[word] |= mask;
+    }
+
+    if seen {
+        log::warn!(
+            target: "contract::money::client",
+            "coin_blind {:?} was probably used before, blinds must not be reused across coins",
+            coin_blind.inner(),
+        );
+    }
+
+    seen
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::OsRng;
+
+    use super::*;
+
+    #[test]
+    fn random_blinds_are_distinct() {
+        let a = random_blinds(&mut OsRng);
+        let b = random_blinds(&mut OsRng);
+
+        assert_ne!(a.value_blind, b.value_blind);
+        assert_ne!(a.token_blind, b.token_blind);
+        assert_ne!(a.coin_blind, b.coin_blind);
+        assert_ne!(a.token_blind, a.coin_blind);
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    fn coin_blind_reuse_detected() {
+        let blinds = random_blinds(&mut OsRng);
+        assert!(!check_coin_blind_reuse(&blinds.coin_blind));
+        assert!(check_coin_blind_reuse(&blinds.coin_blind));
+    }
+}
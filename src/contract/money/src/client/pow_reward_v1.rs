@@ -31,8 +31,9 @@ use rand::rngs::OsRng;
 
 use crate::{
     client::{
+        random_blinds,
         transfer_v1::{proof::create_transfer_mint_proof, TransferCallOutput},
-        MoneyNote,
+        Blinds, MoneyNote,
     },
     model::{ClearInput, Coin, MoneyPoWRewardParamsV1, Output, DARK_TOKEN_ID},
 };
@@ -87,9 +88,7 @@ impl PoWRewardCallBuilder {
         let token_id = *DARK_TOKEN_ID;
 
         // Building the clear input using random blinds
-        let value_blind = Blind::random(&mut OsRng);
-        let token_blind = Blind::random(&mut OsRng);
-        let coin_blind = Blind::random(&mut OsRng);
+        let Blinds { value_blind, token_blind, coin_blind } = random_blinds(&mut OsRng);
         let c_input = ClearInput {
             value,
             token_id,
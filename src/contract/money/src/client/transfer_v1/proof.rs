@@ -24,16 +24,45 @@ use darkfi::{
 use darkfi_sdk::{
     bridgetree::Hashable,
     crypto::{
-        pasta_prelude::*, pedersen_commitment_u64, poseidon_hash, BaseBlind, FuncId, MerkleNode,
-        PublicKey, ScalarBlind, SecretKey,
+        pasta_prelude::*, pedersen_commitment_u64, pedersen_commitment_u64_opens, poseidon_hash,
+        BaseBlind, FuncId, MerkleNode, PublicKey, ScalarBlind, SecretKey,
     },
     pasta::pallas,
 };
 use log::debug;
-use rand::rngs::OsRng;
+use rand::RngCore;
 
 use super::{TransferCallInput, TransferCallOutput};
-use crate::model::{Coin, CoinAttributes, Nullifier};
+use crate::{
+    client::Blinds,
+    model::{Coin, Nullifier, DARK_TOKEN_ID},
+};
+
+/// Seed used for proving when the `test-det-proofs` feature is enabled
+#[cfg(feature = "test-det-proofs")]
+const DET_PROOF_SEED: u64 = 0x6461726b6669;
+
+/// RNG used for proving. With the `test-det-proofs` feature, this is a
+/// fixed-seed RNG so that proofs are byte-identical across runs and can
+/// be committed as golden files. Note that `StdRng` output is only stable
+/// within a `rand` release.
+fn proof_rng() -> impl RngCore {
+    #[cfg(feature = "test-det-proofs")]
+    {
+        use rand::SeedableRng;
+        rand::rngs::StdRng::seed_from_u64(DET_PROOF_SEED)
+    }
+
+    #[cfg(not(feature = "test-det-proofs"))]
+    rand::rngs::OsRng
+}
+
+/// Affine `(x, y)` coordinates of a value commitment, in the order the
+/// `Mint_V1` and `Burn_V1` circuits constrain them as public inputs.
+pub fn commitment_coords(commit: &pallas::Point) -> (pallas::Base, pallas::Base) {
+    let coords = commit.to_affine().coordinates().unwrap();
+    (*coords.x(), *coords.y())
+}
 
 pub struct TransferMintRevealed {
     pub coin: Coin,
@@ -42,12 +71,45 @@ pub struct TransferMintRevealed {
 }
 
 impl TransferMintRevealed {
+    /// Compute the public inputs of the `Mint_V1` circuit for `output`
+    pub fn compute(
+        output: &TransferCallOutput,
+        value_blind: ScalarBlind,
+        token_blind: BaseBlind,
+        spend_hook: FuncId,
+        user_data: pallas::Base,
+        coin_blind: BaseBlind,
+    ) -> Self {
+        let value_commit = pedersen_commitment_u64(output.value, value_blind);
+        let token_commit = output.token_id.commit(token_blind);
+
+        let (pub_x, pub_y) = output.public_key.xy();
+        let coin = Coin::compute(
+            pub_x,
+            pub_y,
+            output.value,
+            output.token_id,
+            spend_hook,
+            user_data,
+            coin_blind,
+        );
+        debug!(target: "contract::money::client::transfer::proof", "Created coin: {:?}", coin);
+
+        Self { coin, value_commit, token_commit }
+    }
+
     pub fn to_vec(&self) -> Vec<pallas::Base> {
-        let valcom_coords = self.value_commit.to_affine().coordinates().unwrap();
+        let (valcom_x, valcom_y) = commitment_coords(&self.value_commit);
 
         // NOTE: It's important to keep these in the same order
         // as the `constrain_instance` calls in the zkas code.
-        vec![self.coin.inner(), *valcom_coords.x(), *valcom_coords.y(), self.token_commit]
+        vec![self.coin.inner(), valcom_x, valcom_y, self.token_commit]
+    }
+
+    /// Check that the revealed value commitment opens to the given
+    /// value and blind. Intended as a sanity check before proving.
+    pub fn value_commit_opens(&self, value: u64, value_blind: ScalarBlind) -> bool {
+        pedersen_commitment_u64_opens(&self.value_commit, value, value_blind)
     }
 }
 
@@ -62,15 +124,63 @@ pub struct TransferBurnRevealed {
 }
 
 impl TransferBurnRevealed {
+    /// Compute the public inputs of the `Burn_V1` circuit for `input`
+    pub fn compute(
+        input: &TransferCallInput,
+        value_blind: ScalarBlind,
+        token_blind: BaseBlind,
+        signature_secret: SecretKey,
+    ) -> Self {
+        let (pub_x, pub_y) = PublicKey::from_secret(input.coin.secret).xy();
+        let coin = Coin::compute(
+            pub_x,
+            pub_y,
+            input.coin.note.value,
+            input.coin.note.token_id,
+            input.coin.note.spend_hook,
+            input.coin.note.user_data,
+            input.coin.note.coin_blind,
+        );
+
+        let merkle_root = {
+            let position: u64 = input.coin.leaf_position.into();
+            let mut current = MerkleNode::from(coin.inner());
+            for (level, sibling) in input.merkle_path.iter().enumerate() {
+                let level = level as u8;
+                current = if position & (1 << level) == 0 {
+                    MerkleNode::combine(level.into(), &current, sibling)
+                } else {
+                    MerkleNode::combine(level.into(), sibling, &current)
+                };
+            }
+            current
+        };
+
+        let user_data_enc =
+            poseidon_hash([input.coin.note.user_data, input.user_data_blind.inner()]);
+        let value_commit = pedersen_commitment_u64(input.coin.note.value, value_blind);
+        let token_commit = input.coin.note.token_id.commit(token_blind);
+
+        Self {
+            value_commit,
+            token_commit,
+            nullifier: input.coin.nullifier(),
+            merkle_root,
+            spend_hook: input.coin.note.spend_hook,
+            user_data_enc,
+            signature_public: PublicKey::from_secret(signature_secret),
+        }
+    }
+
     pub fn to_vec(&self) -> Vec<pallas::Base> {
-        let valcom_coords = self.value_commit.to_affine().coordinates().unwrap();
+        let (valcom_x, valcom_y) = commitment_coords(&self.value_commit);
 
         // NOTE: It's important to keep these in the same order
         // as the `constrain_instance` calls in the zkas code.
         vec![
             self.nullifier.inner(),
-            *valcom_coords.x(),
-            *valcom_coords.y(),
+            valcom_x,
+            valcom_y,
             self.token_commit,
             self.merkle_root.inner(),
             self.user_data_enc,
@@ -79,6 +189,12 @@ impl TransferBurnRevealed {
             self.signature_public.y(),
         ]
     }
+
+    /// Check that the revealed value commitment opens to the given
+    /// value and blind. Intended as a sanity check before proving.
+    pub fn value_commit_opens(&self, value: u64, value_blind: ScalarBlind) -> bool {
+        pedersen_commitment_u64_opens(&self.value_commit, value, value_blind)
+    }
 }
 
 pub fn create_transfer_burn_proof(
@@ -89,46 +205,8 @@ pub fn create_transfer_burn_proof(
     token_blind: BaseBlind,
     signature_secret: SecretKey,
 ) -> Result<(Proof, TransferBurnRevealed)> {
-    let public_key = PublicKey::from_secret(input.coin.secret);
-    let signature_public = PublicKey::from_secret(signature_secret);
-
-    let coin = CoinAttributes {
-        public_key,
-        value: input.coin.note.value,
-        token_id: input.coin.note.token_id,
-        spend_hook: input.coin.note.spend_hook,
-        user_data: input.coin.note.user_data,
-        blind: input.coin.note.coin_blind,
-    }
-    .to_coin();
-
-    let merkle_root = {
-        let position: u64 = input.coin.leaf_position.into();
-        let mut current = MerkleNode::from(coin.inner());
-        for (level, sibling) in input.merkle_path.iter().enumerate() {
-            let level = level as u8;
-            current = if position & (1 << level) == 0 {
-                MerkleNode::combine(level.into(), &current, sibling)
-            } else {
-                MerkleNode::combine(level.into(), sibling, &current)
-            };
-        }
-        current
-    };
-
-    let user_data_enc = poseidon_hash([input.coin.note.user_data, input.user_data_blind.inner()]);
-    let value_commit = pedersen_commitment_u64(input.coin.note.value, value_blind);
-    let token_commit = poseidon_hash([input.coin.note.token_id.inner(), token_blind.inner()]);
-
-    let public_inputs = TransferBurnRevealed {
-        value_commit,
-        token_commit,
-        nullifier: input.coin.nullifier(),
-        merkle_root,
-        spend_hook: input.coin.note.spend_hook,
-        user_data_enc,
-        signature_public,
-    };
+    let public_inputs =
+        TransferBurnRevealed::compute(input, value_blind, token_blind, signature_secret);
 
     let prover_witnesses = vec![
         Witness::Base(Value::known(input.coin.secret.inner())),
@@ -147,7 +225,7 @@ pub fn create_transfer_burn_proof(
 
     //darkfi::zk::export_witness_json("proof/witness/burn_v1.json", &prover_witnesses, &public_inputs.to_vec());
     let circuit = ZkCircuit::new(prover_witnesses, zkbin);
-    let proof = Proof::create(pk, &[circuit], &public_inputs.to_vec(), &mut OsRng)?;
+    let proof = Proof::create(pk, &[circuit], &public_inputs.to_vec(), proof_rng())?;
 
     Ok((proof, public_inputs))
 }
@@ -163,22 +241,18 @@ pub fn create_transfer_mint_proof(
     user_data: pallas::Base,
     coin_blind: BaseBlind,
 ) -> Result<(Proof, TransferMintRevealed)> {
-    let value_commit = pedersen_commitment_u64(output.value, value_blind);
-    let token_commit = poseidon_hash([output.token_id.inner(), token_blind.inner()]);
-    let (pub_x, pub_y) = output.public_key.xy();
+    #[cfg(debug_assertions)]
+    crate::client::check_coin_blind_reuse(&coin_blind);
 
-    let coin = CoinAttributes {
-        public_key: output.public_key,
-        value: output.value,
-        token_id: output.token_id,
+    let public_inputs = TransferMintRevealed::compute(
+        output,
+        value_blind,
+        token_blind,
         spend_hook,
         user_data,
-        blind: coin_blind,
-    };
-    debug!(target: "contract::money::client::transfer::proof", "Created coin: {:?}", coin);
-    let coin = coin.to_coin();
-
-    let public_inputs = TransferMintRevealed { coin, value_commit, token_commit };
+        coin_blind,
+    );
+    let (pub_x, pub_y) = output.public_key.xy();
 
     let prover_witnesses = vec![
         Witness::Base(Value::known(pub_x)),
@@ -194,7 +268,354 @@ pub fn create_transfer_mint_proof(
 
     //darkfi::zk::export_witness_json("proof/witness/mint_v1.json", &prover_witnesses, &public_inputs.to_vec());
     let circuit = ZkCircuit::new(prover_witnesses, zkbin);
-    let proof = Proof::create(pk, &[circuit], &public_inputs.to_vec(), &mut OsRng)?;
+    let proof = Proof::create(pk, &[circuit], &public_inputs.to_vec(), proof_rng())?;
 
     Ok((proof, public_inputs))
 }
+
+/// Create a `Mint_V1` proof for a fee output of `value` native tokens
+/// paid to `public_key`. Fee outputs follow fixed conventions, so they
+/// can't be told apart from each other:
+/// * the token is always the native `DARK_TOKEN_ID`
+/// * `spend_hook` is `FuncId::none()`, so no contract controls the coin
+/// * `user_data` is zero
+pub fn create_fee_output_proof(
+    zkbin: &ZkBinary,
+    pk: &ProvingKey,
+    value: u64,
+    blinds: &Blinds,
+    public_key: PublicKey,
+) -> Result<(Proof, TransferMintRevealed)> {
+    let output = TransferCallOutput {
+        public_key,
+        value,
+        token_id: *DARK_TOKEN_ID,
+        spend_hook: FuncId::none(),
+        user_data: pallas::Base::ZERO,
+        blind: blinds.coin_blind,
+    };
+
+    create_transfer_mint_proof(
+        zkbin,
+        pk,
+        &output,
+        blinds.value_blind,
+        blinds.token_blind,
+        output.spend_hook,
+        output.user_data,
+        output.blind,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use darkfi_sdk::crypto::{Blind, Keypair, MerkleTree};
+    use rand::rngs::OsRng;
+
+    use super::*;
+    use crate::{
+        client::{MoneyNote, OwnCoin},
+        model::CoinAttributes,
+    };
+
+    #[test]
+    fn coin_compute_matches_revealed() {
+        let keypair = Keypair::random(&mut OsRng);
+        let (pub_x, pub_y) = keypair.public.xy();
+        let attrs = CoinAttributes {
+            public_key: keypair.public,
+            value: 42,
+            token_id: *DARK_TOKEN_ID,
+            spend_hook: FuncId::none(),
+            user_data: pallas::Base::from(7),
+            blind: Blind::random(&mut OsRng),
+        };
+        let coin = Coin::compute(
+            pub_x,
+            pub_y,
+            attrs.value,
+            attrs.token_id,
+            attrs.spend_hook,
+            attrs.user_data,
+            attrs.blind,
+        );
+        assert_eq!(coin, attrs.to_coin());
+
+        let mint = TransferMintRevealed::compute(
+            &attrs,
+            Blind::random(&mut OsRng),
+            Blind::random(&mut OsRng),
+            attrs.spend_hook,
+            attrs.user_data,
+            attrs.blind,
+        );
+        assert_eq!(mint.coin, coin);
+
+        // The burn side doesn't reveal the coin, but the Merkle root it
+        // computes only matches the tree if it derived the same coin.
+        let mut tree = MerkleTree::new(1);
+        tree.append(MerkleNode::from(coin.inner()));
+        let leaf_position = tree.mark().unwrap();
+
+        let note = MoneyNote {
+            value: attrs.value,
+            token_id: attrs.token_id,
+            spend_hook: attrs.spend_hook,
+            user_data: attrs.user_data,
+            coin_blind: attrs.blind,
+            value_blind: Blind::random(&mut OsRng),
+            token_blind: Blind::random(&mut OsRng),
+            memo: vec![],
+        };
+        let input = TransferCallInput {
+            coin: OwnCoin { coin, note, secret: keypair.secret, leaf_position },
+            merkle_path: tree.witness(leaf_position, 0).unwrap(),
+            user_data_blind: Blind::random(&mut OsRng),
+        };
+
+        let burn = TransferBurnRevealed::compute(
+            &input,
+            Blind::random(&mut OsRng),
+            Blind::random(&mut OsRng),
+            keypair.secret,
+        );
+        assert_eq!(burn.merkle_root, tree.root(0).unwrap());
+    }
+
+    #[test]
+    fn nullifier_derive_edge_cases() {
+        let keypair = Keypair::random(&mut OsRng);
+        let attrs = CoinAttributes {
+            public_key: keypair.public,
+            value: 42,
+            token_id: *DARK_TOKEN_ID,
+            spend_hook: FuncId::none(),
+            user_data: pallas::Base::ZERO,
+            blind: Blind::random(&mut OsRng),
+        };
+        let coin = attrs.to_coin();
+        let nullifier = Nullifier::derive(&keypair.secret, &coin);
+
+        // Derivation is deterministic, and bound to both the secret and
+        // the coin, so coins differing in their blind only don't collide
+        assert_eq!(nullifier, Nullifier::derive(&keypair.secret, &coin));
+        let other_secret = Keypair::random(&mut OsRng).secret;
+        assert_ne!(nullifier, Nullifier::derive(&other_secret, &coin));
+        let twin = CoinAttributes { blind: Blind::random(&mut OsRng), ..attrs.clone() }.to_coin();
+        assert_ne!(nullifier, Nullifier::derive(&keypair.secret, &twin));
+
+        // Zero value coins still get a nullifier, so they can't be
+        // spent twice either
+        let dust = CoinAttributes { value: 0, ..attrs.clone() }.to_coin();
+        assert_ne!(Nullifier::derive(&keypair.secret, &dust), nullifier);
+
+        // Wallets, OwnCoin and the burn public inputs all agree
+        let mut tree = MerkleTree::new(1);
+        tree.append(MerkleNode::from(coin.inner()));
+        let leaf_position = tree.mark().unwrap();
+        let note = MoneyNote {
+            value: attrs.value,
+            token_id: attrs.token_id,
+            spend_hook: attrs.spend_hook,
+            user_data: attrs.user_data,
+            coin_blind: attrs.blind,
+            value_blind: Blind::random(&mut OsRng),
+            token_blind: Blind::random(&mut OsRng),
+            memo: vec![],
+        };
+        let input = TransferCallInput {
+            coin: OwnCoin { coin, note, secret: keypair.secret, leaf_position },
+            merkle_path: tree.witness(leaf_position, 0).unwrap(),
+            user_data_blind: Blind::random(&mut OsRng),
+        };
+        assert_eq!(input.coin.nullifier(), nullifier);
+
+        let burn = TransferBurnRevealed::compute(
+            &input,
+            Blind::random(&mut OsRng),
+            Blind::random(&mut OsRng),
+            keypair.secret,
+        );
+        assert_eq!(burn.nullifier, nullifier);
+    }
+
+    #[test]
+    fn burn_proof_from_membership_witness() -> Result<()> {
+        use darkfi::zk::{empty_witnesses, VerifyingKey};
+        use darkfi_sdk::crypto::membership_witness;
+
+        let zkbin = ZkBinary::decode(include_bytes!("../../../proof/burn_v1.zk.bin"))?;
+        let circuit = ZkCircuit::new(empty_witnesses(&zkbin)?, &zkbin);
+        let pk = ProvingKey::build(zkbin.k, &circuit);
+        let vk = VerifyingKey::build(zkbin.k, &circuit);
+
+        let keypair = Keypair::random(&mut OsRng);
+        let note = MoneyNote {
+            value: 42,
+            token_id: *DARK_TOKEN_ID,
+            spend_hook: FuncId::none(),
+            user_data: pallas::Base::ZERO,
+            coin_blind: Blind::random(&mut OsRng),
+            value_blind: Blind::random(&mut OsRng),
+            token_blind: Blind::random(&mut OsRng),
+            memo: vec![],
+        };
+        let attrs = CoinAttributes {
+            public_key: keypair.public,
+            value: note.value,
+            token_id: note.token_id,
+            spend_hook: note.spend_hook,
+            user_data: note.user_data,
+            blind: note.coin_blind,
+        };
+        let coin = attrs.to_coin();
+
+        // Bury our coin among others, only marking the one we own
+        let mut tree = MerkleTree::new(1);
+        for i in 0..5 {
+            if i == 3 {
+                tree.append(MerkleNode::from(coin.inner()));
+                tree.mark().unwrap();
+            } else {
+                tree.append(MerkleNode::from(pallas::Base::random(&mut OsRng)));
+            }
+        }
+
+        let (leaf_position, merkle_path) =
+            membership_witness(&tree, &MerkleNode::from(coin.inner())).unwrap();
+        assert_eq!(u64::from(leaf_position), 3);
+
+        let input = TransferCallInput {
+            coin: OwnCoin { coin, note, secret: keypair.secret, leaf_position },
+            merkle_path,
+            user_data_blind: Blind::random(&mut OsRng),
+        };
+
+        let (proof, revealed) = create_transfer_burn_proof(
+            &zkbin,
+            &pk,
+            &input,
+            Blind::random(&mut OsRng),
+            Blind::random(&mut OsRng),
+            keypair.secret,
+        )?;
+        assert_eq!(revealed.merkle_root, tree.root(0).unwrap());
+        assert!(proof.verify(&vk, &revealed.to_vec()).is_ok());
+
+        // The circuit enforces the same nullifier wallets derive, so one
+        // derived with another key doesn't verify
+        assert_eq!(revealed.nullifier, Nullifier::derive(&keypair.secret, &coin));
+        let mut forged = revealed;
+        forged.nullifier = Nullifier::derive(&Keypair::random(&mut OsRng).secret, &coin);
+        assert!(proof.verify(&vk, &forged.to_vec()).is_err());
+
+        // Coins missing from the tree have no witness
+        let other = MerkleNode::from(pallas::Base::random(&mut OsRng));
+        assert!(membership_witness(&tree, &other).is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn fee_output_proof() -> Result<()> {
+        use darkfi::zk::{empty_witnesses, VerifyingKey};
+
+        use crate::client::random_blinds;
+
+        let zkbin = ZkBinary::decode(include_bytes!("../../../proof/mint_v1.zk.bin"))?;
+        let circuit = ZkCircuit::new(empty_witnesses(&zkbin)?, &zkbin);
+        let pk = ProvingKey::build(zkbin.k, &circuit);
+        let vk = VerifyingKey::build(zkbin.k, &circuit);
+
+        let keypair = Keypair::random(&mut OsRng);
+        let blinds = random_blinds(&mut OsRng);
+        let (proof, revealed) = create_fee_output_proof(&zkbin, &pk, 42, &blinds, keypair.public)?;
+        assert!(proof.verify(&vk, &revealed.to_vec()).is_ok());
+
+        // The revealed coin commits to the fee conventions
+        let attrs = CoinAttributes {
+            public_key: keypair.public,
+            value: 42,
+            token_id: *DARK_TOKEN_ID,
+            spend_hook: FuncId::none(),
+            user_data: pallas::Base::ZERO,
+            blind: blinds.coin_blind,
+        };
+        assert_eq!(revealed.coin, attrs.to_coin());
+        assert_eq!(revealed.token_commit, DARK_TOKEN_ID.commit(blinds.token_blind));
+        assert!(pedersen_commitment_u64_opens(&revealed.value_commit, 42, blinds.value_blind));
+
+        // Any other spend hook would give a different coin
+        let hooked = CoinAttributes { spend_hook: FuncId::from(pallas::Base::ONE), ..attrs };
+        assert_ne!(revealed.coin, hooked.to_coin());
+
+        Ok(())
+    }
+
+    #[test]
+    fn commitment_coords_match_affine() {
+        use darkfi_sdk::crypto::util::FieldElemAsStr;
+
+        // Coordinates of 42 * V + 7 * R, with the value commitment
+        // fixed bases V and R
+        let value_commit = pedersen_commitment_u64(42, ScalarBlind::from(7));
+        let x = pallas::Base::from_str(
+            "0x2ab4971fdd4c0bd82a2095dfce6ea40e1d28f6d54d2f6b5c75fb9a1cbfa89d2b",
+        )
+        .unwrap();
+        let y = pallas::Base::from_str(
+            "0x0b205638cb87e24d0d392c61158d52891b636d44c88ce1de809f4560ddc2e6df",
+        )
+        .unwrap();
+        assert_eq!(commitment_coords(&value_commit), (x, y));
+
+        let mint = TransferMintRevealed {
+            coin: Coin::from(pallas::Base::from(1)),
+            value_commit,
+            token_commit: pallas::Base::from(2),
+        };
+        assert_eq!(mint.to_vec()[1..3], [x, y]);
+    }
+
+    #[cfg(feature = "test-det-proofs")]
+    #[test]
+    fn deterministic_mint_proof() -> Result<()> {
+        use darkfi::zk::empty_witnesses;
+        use darkfi_serial::serialize;
+        use rand::SeedableRng;
+
+        let zkbin = ZkBinary::decode(include_bytes!("../../../proof/mint_v1.zk.bin"))?;
+        let circuit = ZkCircuit::new(empty_witnesses(&zkbin)?, &zkbin);
+        let pk = ProvingKey::build(zkbin.k, &circuit);
+
+        let mut rng = rand::rngs::StdRng::seed_from_u64(42);
+        let output = TransferCallOutput {
+            public_key: Keypair::random(&mut rng).public,
+            value: 42,
+            token_id: *DARK_TOKEN_ID,
+            spend_hook: FuncId::none(),
+            user_data: pallas::Base::ZERO,
+            blind: Blind::from(1),
+        };
+
+        let prove = || {
+            create_transfer_mint_proof(
+                &zkbin,
+                &pk,
+                &output,
+                Blind::from(2),
+                Blind::from(3),
+                FuncId::none(),
+                pallas::Base::ZERO,
+                Blind::from(4),
+            )
+        };
+
+        let (proof_a, revealed_a) = prove()?;
+        let (proof_b, revealed_b) = prove()?;
+        assert_eq!(serialize(&proof_a), serialize(&proof_b));
+        assert_eq!(revealed_a.to_vec(), revealed_b.to_vec());
+
+        Ok(())
+    }
+}
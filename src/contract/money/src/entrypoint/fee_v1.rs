@@ -19,7 +19,7 @@
 use darkfi_sdk::{
     crypto::{
         pasta_prelude::*,
-        pedersen_commitment_u64, poseidon_hash,
+        pedersen_commitment_u64,
         smt::{
             wasmdb::{SmtWasmDbStorage, SmtWasmFp},
             PoseidonFp, EMPTY_NODES_FP,
@@ -115,7 +115,7 @@ pub(crate) fn money_fee_process_instruction_v1(
 
     // Fees can only be paid using the native token, so we'll compare
     // the token commitments with this one:
-    let native_token_commit = poseidon_hash([DARK_TOKEN_ID.inner(), params.token_blind.inner()]);
+    let native_token_commit = DARK_TOKEN_ID.commit(params.token_blind);
 
     // ===================================
     // Perform the actual state transition
@@ -68,6 +68,10 @@ pub mod entrypoint;
 /// Client API for interaction with this smart contract
 pub mod client;
 
+// Deterministic proofs leak the witnesses, so they must never ship.
+#[cfg(all(feature = "test-det-proofs", not(debug_assertions)))]
+compile_error!("The `test-det-proofs` feature must not be enabled in release builds");
+
 // These are the different sled trees that will be created
 pub const MONEY_CONTRACT_INFO_TREE: &str = "info";
 pub const MONEY_CONTRACT_COINS_TREE: &str = "coins";
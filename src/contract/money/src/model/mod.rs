@@ -35,13 +35,36 @@ pub use nullifier::Nullifier;
 
 /// Token ID definitions and methods
 pub mod token_id;
-pub use token_id::{TokenId, DARK_TOKEN_ID};
+pub use token_id::{verify_token_commitment, TokenId, DARK_TOKEN_ID};
 
 /// A `Coin` represented in the Money state
 #[derive(Debug, Clone, Copy, Eq, PartialEq, SerialEncodable, SerialDecodable)]
 pub struct Coin(pallas::Base);
 
 impl Coin {
+    /// Compute the coin commitment from its attributes. This is the same
+    /// hash the mint and burn circuits constrain, so wallets can derive
+    /// coins to index outputs without creating proofs.
+    pub fn compute(
+        pub_x: pallas::Base,
+        pub_y: pallas::Base,
+        value: u64,
+        token_id: TokenId,
+        spend_hook: FuncId,
+        user_data: pallas::Base,
+        blind: BaseBlind,
+    ) -> Self {
+        Self(poseidon_hash([
+            pub_x,
+            pub_y,
+            pallas::Base::from(value),
+            token_id.inner(),
+            spend_hook.inner(),
+            user_data,
+            blind.inner(),
+        ]))
+    }
+
     /// Reference the raw inner base field element
     pub fn inner(&self) -> pallas::Base {
         self.0
@@ -85,16 +108,15 @@ pub struct CoinAttributes {
 impl CoinAttributes {
     pub fn to_coin(&self) -> Coin {
         let (pub_x, pub_y) = self.public_key.xy();
-        let coin = poseidon_hash([
+        Coin::compute(
             pub_x,
             pub_y,
-            pallas::Base::from(self.value),
-            self.token_id.inner(),
-            self.spend_hook.inner(),
+            self.value,
+            self.token_id,
+            self.spend_hook,
             self.user_data,
-            self.blind.inner(),
-        ]);
-        Coin(coin)
+            self.blind,
+        )
     }
 }
 
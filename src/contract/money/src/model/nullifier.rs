@@ -16,18 +16,30 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
-use darkfi_sdk::{crypto::pasta_prelude::PrimeField, error::ContractError, pasta::pallas};
+use darkfi_sdk::{
+    crypto::{pasta_prelude::PrimeField, poseidon_hash, SecretKey},
+    error::ContractError,
+    pasta::pallas,
+};
 use darkfi_serial::{SerialDecodable, SerialEncodable};
 
 #[cfg(feature = "client")]
 use darkfi_serial::async_trait;
 
+use super::Coin;
+
 /// The `Nullifier` is represented as a base field element.
 #[repr(C)]
 #[derive(Debug, Clone, Copy, Eq, PartialEq, SerialEncodable, SerialDecodable)]
 pub struct Nullifier(pallas::Base);
 
 impl Nullifier {
+    /// Derive the `Nullifier` of a [`Coin`] owned by the given secret key.
+    /// This is the same derivation enforced by the burn circuits.
+    pub fn derive(secret: &SecretKey, coin: &Coin) -> Self {
+        Self(poseidon_hash([secret.inner(), coin.inner()]))
+    }
+
     /// Reference the raw inner base field element
     pub fn inner(&self) -> pallas::Base {
         self.0
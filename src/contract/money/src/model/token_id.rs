@@ -19,6 +19,7 @@
 use darkfi_sdk::{
     crypto::{
         constants::DRK_TOKEN_ID_PERSONALIZATION, pasta_prelude::PrimeField, util::hash_to_base,
+        BaseBlind,
     },
     error::ContractError,
     pasta::pallas,
@@ -79,6 +80,12 @@ impl TokenId {
         self.0
     }
 
+    /// Compute the token commitment revealed by the mint and burn
+    /// circuits, hiding this token ID behind the given blind.
+    pub fn commit(&self, blind: BaseBlind) -> pallas::Base {
+        poseidon_hash([self.0, blind.inner()])
+    }
+
     /// Create a `TokenId` object from given bytes, erroring if the input
     /// bytes are noncanonical.
     pub fn from_bytes(x: [u8; 32]) -> Result<Self, ContractError> {
@@ -96,7 +103,37 @@ impl TokenId {
     }
 }
 
+/// Check that a published token commitment opens to the claimed token
+/// ID and blind. Auditors can use this to verify a revealed token without
+/// access to the proof witnesses.
+pub fn verify_token_commitment(commit: pallas::Base, token_id: TokenId, blind: BaseBlind) -> bool {
+    token_id.commit(blind) == commit
+}
+
 use core::str::FromStr;
 darkfi_sdk::fp_from_bs58!(TokenId);
 darkfi_sdk::fp_to_bs58!(TokenId);
 darkfi_sdk::ty_from_fp!(TokenId);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn token_commitment_opening() {
+        let token_id = TokenId::derive_from(
+            pallas::Base::from(1),
+            pallas::Base::from(2),
+            pallas::Base::from(3),
+        );
+        let blind = BaseBlind::from(1337);
+        let commit = poseidon_hash([token_id.inner(), blind.inner()]);
+
+        assert_eq!(token_id.commit(blind), commit);
+        assert!(verify_token_commitment(commit, token_id, blind));
+
+        // A mismatched token ID or blind must not open the commitment
+        assert!(!verify_token_commitment(commit, *DARK_TOKEN_ID, blind));
+        assert!(!verify_token_commitment(commit, token_id, BaseBlind::from(1338)));
+    }
+}
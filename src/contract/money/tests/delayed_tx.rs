@@ -29,7 +29,7 @@ use darkfi_money_contract::{
         transfer_v1::make_transfer_call,
         MoneyNote, OwnCoin,
     },
-    model::{Input, MoneyFeeParamsV1, Output},
+    model::{Input, MoneyFeeParamsV1, Nullifier, Output},
     MoneyFunction, MONEY_CONTRACT_ZKAS_BURN_NS_V1, MONEY_CONTRACT_ZKAS_FEE_NS_V1,
     MONEY_CONTRACT_ZKAS_MINT_NS_V1,
 };
@@ -192,6 +192,9 @@ fn delayed_tx() -> Result<()> {
             signature_secret,
         )?;
 
+        // The standalone derivation must match what went into the proof
+        assert_eq!(public_inputs.nullifier, Nullifier::derive(&coin.secret, &coin.coin));
+
         // Encrypted note for the output
         let note = MoneyNote {
             coin_blind: output.blind,
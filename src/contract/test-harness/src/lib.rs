@@ -27,7 +27,10 @@ use darkfi::{
     tx::Transaction,
     util::{pcg::Pcg32, time::Timestamp},
     validator::{Validator, ValidatorConfig, ValidatorPtr},
-    zk::{empty_witnesses, halo2::Field, ProvingKey, ZkCircuit},
+    zk::{
+        empty_witnesses, halo2::Field, verification_cache::DEFAULT_VERIFICATION_CACHE_SIZE,
+        verification_limit::DEFAULT_MAX_CONCURRENT_VERIFICATIONS, ProvingKey, ZkCircuit,
+    },
     zkas::ZkBinary,
     Result,
 };
@@ -169,6 +172,8 @@ impl Wallet {
             pow_fixed_difficulty: Some(BigUint::from(1_u8)),
             genesis_block,
             verify_fees,
+            verification_cache_size: DEFAULT_VERIFICATION_CACHE_SIZE,
+            max_concurrent_verifications: DEFAULT_MAX_CONCURRENT_VERIFICATIONS,
         };
         let validator = Validator::new(&sled_db, &validator_config).await?;
 
@@ -115,6 +115,9 @@ pub enum Error {
     #[error("Transport request exceeds number of accepted transports")]
     InvalidTransportRequest,
 
+    #[error("Invalid P2P settings: {0}")]
+    InvalidSettings(String),
+
     #[error("Connection failed")]
     ConnectFailed,
 
@@ -131,6 +134,9 @@ pub enum Error {
     #[error("Channel timed out")]
     ChannelTimeout,
 
+    #[error("Channel send queue is full")]
+    ChannelBusy,
+
     #[error("Failed to reach any seeds")]
     SeedFailed,
 
@@ -149,6 +155,9 @@ pub enum Error {
     #[error("Connector stopped")]
     ConnectorStopped,
 
+    #[error("Peer is banned")]
+    PeerBanned,
+
     #[error("Network operation failed")]
     NetworkOperationFailed,
 
@@ -162,6 +171,9 @@ pub enum Error {
     #[error("Malformed packet")]
     MalformedPacket,
 
+    #[error("Message payload exceeds the size limit")]
+    PayloadTooLarge,
+
     #[error("Error decoding packet: {0}")]
     DecodePacket(String),
 
@@ -186,6 +198,9 @@ pub enum Error {
     #[error("No such host color exists")]
     InvalidHostColor,
 
+    #[error("Host can't be moved to this hostlist")]
+    InvalidHostTransition,
+
     #[error("No matching hostlist entry")]
     HostDoesNotExist,
 
@@ -504,6 +519,10 @@ pub enum Error {
     #[error(transparent)]
     TxVerifyFailed(#[from] TxVerifyFailed),
 
+    #[cfg(feature = "net")]
+    #[error(transparent)]
+    HandshakeFailed(#[from] HandshakeFailed),
+
     //=============
     // clock
     //=============
@@ -587,6 +606,23 @@ pub enum TxVerifyFailed {
     ErroneousTxs(Vec<crate::tx::Transaction>),
 }
 
+#[cfg(feature = "net")]
+/// P2P version handshake errors
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum HandshakeFailed {
+    #[error("Incompatible peer version: ours={0}, theirs={1}")]
+    IncompatibleVersion(String, String),
+
+    #[error("Version handshake timed out")]
+    Timeout,
+
+    #[error("Malformed version message: {0}")]
+    MalformedVersion(String),
+
+    #[error("Peer clock skewed by {0}s")]
+    ClockSkew(i64),
+}
+
 /// Client module errors
 #[derive(Debug, Clone, thiserror::Error)]
 pub enum ClientFailed {
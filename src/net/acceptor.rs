@@ -17,10 +17,11 @@
  */
 
 use std::{
+    collections::HashMap,
     io::ErrorKind,
     sync::{
         atomic::{AtomicUsize, Ordering::SeqCst},
-        Arc,
+        Arc, Mutex as SyncMutex,
     },
 };
 
@@ -32,7 +33,8 @@ use super::{
     channel::{Channel, ChannelPtr},
     hosts::HostColor,
     session::SessionWeakPtr,
-    transport::{Listener, PtListener},
+    settings::is_tor_scheme,
+    transport::{Listener, MultiListener, PtListener, TorBridgeConfig},
 };
 use crate::{
     system::{CondVar, Publisher, PublisherPtr, StoppableTask, StoppableTaskPtr, Subscription},
@@ -42,12 +44,36 @@ use crate::{
 /// Atomic pointer to Acceptor
 pub type AcceptorPtr = Arc<Acceptor>;
 
+/// Decides whether an inbound connection is accepted, before a channel
+/// is created for it. Operators can install their own (e.g. an address
+/// allowlist) with [`P2p::set_accept_filter()`].
+///
+/// [`P2p::set_accept_filter()`]: super::P2p::set_accept_filter
+pub trait AcceptFilter: Send + Sync {
+    /// Return `false` to drop the connection from `peer`
+    fn accept(&self, peer: &Url) -> bool;
+}
+
+/// Atomic pointer to an accept filter
+pub type AcceptFilterPtr = Arc<dyn AcceptFilter>;
+
+/// Filter used when none is configured, accepting every connection
+pub struct AcceptAll;
+
+impl AcceptFilter for AcceptAll {
+    fn accept(&self, _peer: &Url) -> bool {
+        true
+    }
+}
+
 /// Create inbound socket connections
 pub struct Acceptor {
     channel_publisher: PublisherPtr<Result<ChannelPtr>>,
     task: StoppableTaskPtr,
     session: SessionWeakPtr,
     conn_count: AtomicUsize,
+    /// Live inbound channels per remote host
+    conn_per_host: SyncMutex<HashMap<String, usize>>,
 }
 
 impl Acceptor {
@@ -58,16 +84,82 @@ impl Acceptor {
             task: StoppableTask::new(),
             session,
             conn_count: AtomicUsize::new(0),
+            conn_per_host: SyncMutex::new(HashMap::new()),
         })
     }
 
     /// Start accepting inbound socket connections
     pub async fn start(self: Arc<Self>, endpoint: Url, ex: Arc<Executor<'_>>) -> Result<()> {
-        let datastore =
-            self.session.upgrade().unwrap().p2p().settings().read().await.p2p_datastore.clone();
+        let ptlistener = self.bind(endpoint).await?;
+        self.accept(ptlistener, ex);
+        Ok(())
+    }
+
+    /// Start accepting inbound socket connections on all of the given
+    /// endpoints, which may use different transports, with a single
+    /// accept loop. The inbound connection limit applies to their total.
+    pub async fn start_multi(
+        self: Arc<Self>,
+        endpoints: Vec<Url>,
+        ex: Arc<Executor<'_>>,
+    ) -> Result<()> {
+        let mut listeners = Vec::with_capacity(endpoints.len());
+        for endpoint in endpoints {
+            listeners.push(self.bind(endpoint).await?);
+        }
+
+        let ptlistener = MultiListener::new(listeners, ex.clone());
+        self.accept(Box::new(ptlistener), ex);
+        Ok(())
+    }
+
+    /// Open a listening socket on `endpoint`
+    async fn bind(&self, endpoint: Url) -> Result<Box<dyn PtListener>> {
+        let p2p = self.session.upgrade().unwrap().p2p();
+        let settings = p2p.settings();
+        let settings = settings.read().await;
+        let datastore = settings.p2p_datastore.clone();
+        let tor_bridges = TorBridgeConfig {
+            bridges: settings.tor_bridges.clone(),
+            pt_path: settings.tor_pt_path.clone(),
+        };
+        #[cfg(feature = "p2p-tor")]
+        let tor_handshake_limit = settings.tor_handshake_limit;
+        #[cfg(feature = "p2p-tor")]
+        let tor_onion_services = settings.tor_onion_services.clone();
+        let tor_dial_only = settings.tor_dial_only;
+        drop(settings);
+
+        // Dial-only nodes never launch an onion service
+        if tor_dial_only && is_tor_scheme(&endpoint) {
+            return Err(Error::InvalidSettings(format!(
+                "Can't listen on {} with tor_dial_only set",
+                endpoint
+            )))
+        }
 
         // Initialize listener
-        let listener = Listener::new(endpoint.clone(), datastore).await?;
+        let listener =
+            Listener::new_with_tor_bridges(endpoint.clone(), datastore, tor_bridges).await?;
+
+        #[cfg(feature = "p2p-tor")]
+        let listener = listener
+            .with_tor_handshake_limit(tor_handshake_limit)
+            .with_tor_onion_services(tor_onion_services);
+
+        #[cfg(feature = "p2p-tor")]
+        let listener = match p2p.tor_client() {
+            Some(client) => listener.with_tor_client(client),
+            None => listener,
+        };
+
+        #[cfg(feature = "p2p-noise")]
+        let listener = match p2p.noise_keypair() {
+            Some(keypair) => listener.with_noise_keypair(keypair),
+            None => listener,
+        };
+        #[cfg(feature = "p2p-noise")]
+        let listener = listener.with_noise_remote_keys(p2p.noise_remote_keys());
 
         // Open socket
         let ptlistener = listener.listen().await?;
@@ -76,19 +168,10 @@ impl Acceptor {
         if endpoint.scheme() == "tor" {
             let onion_addr = listener.endpoint().await;
             info!("[P2P] Adding {} to external_addrs", onion_addr);
-            self.session
-                .upgrade()
-                .unwrap()
-                .p2p()
-                .settings()
-                .write()
-                .await
-                .external_addrs
-                .push(onion_addr);
+            p2p.settings().write().await.external_addrs.push(onion_addr);
         }
 
-        self.accept(ptlistener, ex);
-        Ok(())
+        Ok(ptlistener)
     }
 
     /// Stop accepting inbound socket connections
@@ -113,6 +196,39 @@ impl Acceptor {
         );
     }
 
+    /// Remote host an inbound connection is counted against for the
+    /// per-address limit. Inbound Tor connections all come from the
+    /// local Tor client, so they aren't counted.
+    fn limited_host(url: &Url) -> Option<String> {
+        if url.scheme().starts_with("tor") {
+            return None
+        }
+        url.host_str().map(|host| host.to_string())
+    }
+
+    /// Count a new connection from `host`, unless it already has `limit`
+    /// live connections. A limit of 0 is unlimited.
+    fn try_acquire_host(&self, host: &str, limit: usize) -> bool {
+        let mut conn_per_host = self.conn_per_host.lock().unwrap();
+        let count = conn_per_host.entry(host.to_string()).or_default();
+        if limit > 0 && *count >= limit {
+            return false
+        }
+        *count += 1;
+        true
+    }
+
+    /// Forget a connection from `host` once its channel stopped
+    fn release_host(&self, host: &str) {
+        let mut conn_per_host = self.conn_per_host.lock().unwrap();
+        if let Some(count) = conn_per_host.get_mut(host) {
+            *count -= 1;
+            if *count == 0 {
+                conn_per_host.remove(host);
+            }
+        }
+    }
+
     /// Run the accept loop.
     async fn run_accept_loop(
         self: Arc<Self>,
@@ -151,6 +267,22 @@ impl Acceptor {
                         continue
                     }
 
+                    if !self.session.upgrade().unwrap().p2p().accept_filter().accept(&url) {
+                        warn!(target: "net::acceptor::run_accept_loop()", "Peer {} rejected by accept filter", url);
+                        continue
+                    }
+
+                    // Refuse the connection if its host is up to its own limit
+                    let host = Self::limited_host(&url);
+                    if let Some(host) = &host {
+                        let p2p = self.session.upgrade().unwrap().p2p();
+                        let limit = p2p.settings().read().await.inbound_connections_per_address;
+                        if !self.try_acquire_host(host, limit) {
+                            warn!(target: "net::acceptor::run_accept_loop()", "Peer {} reached the per-address conn limit", url);
+                            continue
+                        }
+                    }
+
                     // Create the new Channel.
                     let session = self.session.clone();
                     let channel = Channel::new(stream, None, url, session).await;
@@ -168,6 +300,9 @@ impl Acceptor {
                         let stop_sub = channel_.subscribe_stop().await?;
                         stop_sub.receive().await;
                         self_.conn_count.fetch_sub(1, SeqCst);
+                        if let Some(host) = host {
+                            self_.release_host(&host);
+                        }
                         cv_.notify();
                         Ok::<(), crate::Error>(())
                     })
@@ -272,3 +407,221 @@ impl Acceptor {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use async_trait::async_trait;
+    use smol::channel::Receiver;
+
+    use super::*;
+    use crate::{
+        net::{
+            dnet::DnetEvent,
+            session::Session,
+            transport::{memory, PtStream},
+            P2p, Settings,
+        },
+        system::timeout::timeout,
+    };
+
+    /// Listener handing out in-memory connections from the given peers
+    struct StubListener {
+        peers: Receiver<Url>,
+    }
+
+    #[async_trait]
+    impl PtListener for StubListener {
+        async fn next(&self) -> std::io::Result<(Box<dyn PtStream>, Url)> {
+            let url = self.peers.recv().await.unwrap();
+            let (stream, _) = memory::duplex(64);
+            Ok((Box::new(stream), url))
+        }
+    }
+
+    /// Rejects a single address
+    struct RejectOne(Url);
+
+    impl AcceptFilter for RejectOne {
+        fn accept(&self, peer: &Url) -> bool {
+            peer != &self.0
+        }
+    }
+
+    #[test]
+    fn acceptor_filter_rejects_peer() {
+        let executor = Arc::new(Executor::new());
+        let executor_ = executor.clone();
+        smol::block_on(executor.run(async move {
+            let p2p = P2p::new(Settings::default(), executor_.clone()).await.unwrap();
+            let rejected = Url::parse("tcp://10.0.0.1:26661").unwrap();
+            let accepted = Url::parse("tcp://10.0.0.2:26661").unwrap();
+            p2p.set_accept_filter(Arc::new(RejectOne(rejected.clone())));
+
+            let session: Arc<dyn Session + Send + Sync> = p2p.session_inbound();
+            let acceptor = Acceptor::new(Arc::downgrade(&session));
+            let sub = acceptor.clone().subscribe().await;
+
+            let (peers, recv) = smol::channel::unbounded();
+            acceptor.clone().accept(Box::new(StubListener { peers: recv }), executor_.clone());
+
+            peers.send(rejected).await.unwrap();
+            peers.send(accepted.clone()).await.unwrap();
+
+            // Only the accepted peer gets a channel
+            let channel = sub.receive().await.unwrap();
+            assert_eq!(channel.address(), &accepted);
+
+            acceptor.stop().await;
+        }));
+    }
+
+    #[cfg(feature = "p2p-tor")]
+    #[test]
+    fn acceptor_tor_dial_only() {
+        let executor = Arc::new(Executor::new());
+        let executor_ = executor.clone();
+        smol::block_on(executor.run(async move {
+            let settings = Settings {
+                allowed_transports: vec!["tor".to_string()],
+                tor_dial_only: true,
+                ..Default::default()
+            };
+            let p2p = P2p::new(settings, executor_.clone()).await.unwrap();
+
+            // The endpoint is refused before any onion service is launched,
+            // so this doesn't need a working Tor network.
+            let session: Arc<dyn Session + Send + Sync> = p2p.session_inbound();
+            let acceptor = Acceptor::new(Arc::downgrade(&session));
+            for endpoint in ["tor://127.0.0.1:26661", "tor+tls://127.0.0.1:26661"] {
+                let res = acceptor.bind(Url::parse(endpoint).unwrap()).await;
+                assert!(matches!(res, Err(Error::InvalidSettings(_))));
+            }
+            assert!(p2p.settings().read().await.external_addrs.is_empty());
+        }));
+    }
+
+    #[test]
+    fn acceptor_per_address_limit() {
+        let executor = Arc::new(Executor::new());
+        let executor_ = executor.clone();
+        smol::block_on(executor.run(async move {
+            let settings = Settings { inbound_connections_per_address: 2, ..Default::default() };
+            let p2p = P2p::new(settings, executor_.clone()).await.unwrap();
+
+            let session: Arc<dyn Session + Send + Sync> = p2p.session_inbound();
+            let acceptor = Acceptor::new(Arc::downgrade(&session));
+            let sub = acceptor.clone().subscribe().await;
+
+            let (peers, recv) = smol::channel::unbounded();
+            acceptor.clone().accept(Box::new(StubListener { peers: recv }), executor_.clone());
+
+            // Three connections from one address, then one from another
+            let flooder: Vec<Url> = (1..=3)
+                .map(|port| Url::parse(&format!("tcp://10.0.0.1:{}", port)).unwrap())
+                .collect();
+            let other = Url::parse("tcp://10.0.0.2:26661").unwrap();
+            for url in flooder.iter().chain([&other]) {
+                peers.send(url.clone()).await.unwrap();
+            }
+
+            // The third connection from the flooding address is refused
+            let first = sub.receive().await.unwrap();
+            assert_eq!(first.address(), &flooder[0]);
+            assert_eq!(sub.receive().await.unwrap().address(), &flooder[1]);
+            assert_eq!(sub.receive().await.unwrap().address(), &other);
+
+            // Once one of its channels stops, the address may connect again.
+            // The stub streams have no peer, so a started channel stops.
+            first.clone().start(executor_.clone());
+            while acceptor.conn_per_host.lock().unwrap().get("10.0.0.1") != Some(&1) {
+                smol::future::yield_now().await;
+            }
+            peers.send(flooder[2].clone()).await.unwrap();
+            assert_eq!(sub.receive().await.unwrap().address(), &flooder[2]);
+
+            acceptor.stop().await;
+        }));
+    }
+
+    #[test]
+    fn acceptor_merges_listeners() {
+        let executor = Arc::new(Executor::new());
+        let executor_ = executor.clone();
+        smol::block_on(executor.run(async move {
+            let p2p = P2p::new(Settings::default(), executor_.clone()).await.unwrap();
+            let tcp_peer = Url::parse("tcp://10.0.0.1:26661").unwrap();
+            let tor_peer = Url::parse("tor://abcdefghij.onion:26661").unwrap();
+
+            let session: Arc<dyn Session + Send + Sync> = p2p.session_inbound();
+            let acceptor = Acceptor::new(Arc::downgrade(&session));
+            let sub = acceptor.clone().subscribe().await;
+
+            let (tcp_peers, tcp_recv) = smol::channel::unbounded();
+            let (tor_peers, tor_recv) = smol::channel::unbounded();
+            let listener = MultiListener::new(
+                vec![
+                    Box::new(StubListener { peers: tcp_recv }),
+                    Box::new(StubListener { peers: tor_recv }),
+                ],
+                executor_.clone(),
+            );
+            acceptor.clone().accept(Box::new(listener), executor_.clone());
+
+            tcp_peers.send(tcp_peer.clone()).await.unwrap();
+            let channel = sub.receive().await.unwrap();
+            assert_eq!(channel.address(), &tcp_peer);
+
+            tor_peers.send(tor_peer.clone()).await.unwrap();
+            let channel = sub.receive().await.unwrap();
+            assert_eq!(channel.address(), &tor_peer);
+
+            acceptor.stop().await;
+        }));
+    }
+
+    #[test]
+    fn inbound_session_listens_on_all_addrs() {
+        let executor = Arc::new(Executor::new());
+        let executor_ = executor.clone();
+        smol::block_on(executor.run(async move {
+            let mut inbound_addrs = vec![];
+            for _ in 0..2 {
+                let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+                let addr = listener.local_addr().unwrap();
+                inbound_addrs.push(Url::parse(&format!("tcp://{}", addr)).unwrap());
+            }
+
+            let settings = Settings {
+                localnet: true,
+                outbound_connections: 0,
+                allowed_transports: vec!["tcp".to_string()],
+                inbound_addrs: inbound_addrs.clone(),
+                ..Default::default()
+            };
+            let p2p = P2p::new(settings, executor_.clone()).await.unwrap();
+            p2p.dnet_enable();
+            let dnet_sub = p2p.dnet_subscribe().await;
+            p2p.clone().start().await.unwrap();
+
+            // Connections to either address reach the inbound session
+            let mut streams = vec![];
+            for addr in &inbound_addrs {
+                let addr = format!("{}:{}", addr.host_str().unwrap(), addr.port().unwrap());
+                streams.push(smol::net::TcpStream::connect(addr).await.unwrap());
+
+                let connected = async {
+                    loop {
+                        if let DnetEvent::InboundConnected(_) = dnet_sub.receive().await {
+                            break
+                        }
+                    }
+                };
+                timeout(Duration::from_secs(5), connected).await.unwrap();
+            }
+
+            p2p.stop().await;
+        }));
+    }
+}
@@ -17,22 +17,28 @@
  */
 
 use std::{
+    collections::{HashMap, HashSet, VecDeque},
     fmt,
+    future::Future,
     sync::{
-        atomic::{AtomicBool, Ordering::SeqCst},
-        Arc,
+        atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering::SeqCst},
+        Arc, Mutex as SyncMutex,
     },
-    time::UNIX_EPOCH,
+    time::{Duration, Instant, UNIX_EPOCH},
 };
 
 use darkfi_serial::{
-    async_trait, AsyncDecodable, AsyncEncodable, SerialDecodable, SerialEncodable, VarInt,
+    async_trait, serialize, AsyncDecodable, AsyncEncodable, SerialDecodable, SerialEncodable,
+    VarInt,
 };
-use log::{debug, error, info, trace};
+use futures::future::Either;
+use log::{debug, error, info, log_enabled, trace, warn, Level};
 use rand::{rngs::OsRng, Rng};
 use smol::{
-    io::{self, AsyncRead, AsyncReadExt, AsyncWriteExt, ReadHalf, WriteHalf},
-    lock::Mutex,
+    channel::{Receiver, Sender},
+    io::{self, AsyncRead, AsyncWriteExt, Cursor, ReadHalf, WriteHalf},
+    lock::{Mutex, Semaphore, SemaphoreGuardArc},
+    stream::Stream,
     Executor,
 };
 use url::Url;
@@ -41,17 +47,26 @@ use super::{
     dnet::{self, dnetev, DnetEvent},
     hosts::HostColor,
     message,
-    message::{SerializedMessage, VersionMessage, MAGIC_BYTES},
-    message_publisher::{MessageSubscription, MessageSubsystem},
+    message::{
+        Frame, Message, PingMessage, PongMessage, SerializedMessage, ServiceFlags, VersionMessage,
+        MAGIC_BYTES,
+    },
+    message_publisher::{MessageSubscription, MessageSubsystem, SubscriptionSnapshot},
     p2p::P2pPtr,
     session::{
         Session, SessionBitFlag, SessionWeakPtr, SESSION_ALL, SESSION_INBOUND, SESSION_REFINE,
+        SESSION_SEED,
     },
+    settings::{Settings, UnknownCommandAction},
+    stats::CountingReader,
     transport::PtStream,
 };
 use crate::{
     net::BanPolicy,
-    system::{Publisher, PublisherPtr, StoppableTask, StoppableTaskPtr, Subscription},
+    system::{
+        sleep, timeout::timeout, CondVar, Instrument, LogSpan, Publisher, PublisherPtr,
+        StoppableTask, StoppableTaskPtr, Subscription,
+    },
     util::time::NanoTimestamp,
     Error, Result,
 };
@@ -59,6 +74,246 @@ use crate::{
 /// Atomic pointer to async channel
 pub type ChannelPtr = Arc<Channel>;
 
+/// Window within which identical channel errors from the same peer are
+/// only logged once
+pub(in crate::net) const ERROR_LOG_WINDOW: Duration = Duration::from_secs(60);
+
+/// Deduplicates a channel's error logs so a rapidly flapping peer can't
+/// flood the logs. The first occurrence of an error is logged, and
+/// identical errors within the window are suppressed and counted. The
+/// count is reported with the next occurrence logged after the window,
+/// or on its own once the entry expires.
+pub(in crate::net) struct ErrorLogThrottle {
+    window: Duration,
+    /// Maps an error to the time it was last logged and the number of
+    /// occurrences suppressed since then
+    entries: SyncMutex<HashMap<String, (Instant, usize)>>,
+}
+
+impl ErrorLogThrottle {
+    pub(in crate::net) fn new(window: Duration) -> Self {
+        Self { window, entries: SyncMutex::new(HashMap::new()) }
+    }
+
+    /// Returns `None` if this error from `peer` should be suppressed,
+    /// otherwise `Some(n)` where `n` is the amount of identical errors
+    /// that were suppressed since it was last logged.
+    pub(in crate::net) fn check(&self, peer: &Url, err: &str) -> Option<usize> {
+        self.check_at(peer, err, Instant::now())
+    }
+
+    fn check_at(&self, peer: &Url, err: &str, now: Instant) -> Option<usize> {
+        let mut entries = self.entries.lock().unwrap();
+
+        let result = match entries.get_mut(err) {
+            Some((logged, suppressed)) if now.duration_since(*logged) < self.window => {
+                *suppressed += 1;
+                None
+            }
+            Some(entry) => {
+                let suppressed = entry.1;
+                *entry = (now, 0);
+                Some(suppressed)
+            }
+            None => {
+                entries.insert(err.to_string(), (now, 0));
+                Some(0)
+            }
+        };
+
+        // Forget other expired entries. Their suppressed occurrences are
+        // reported now, since the error may never come up again.
+        entries.retain(|err, (logged, suppressed)| {
+            if now.duration_since(*logged) < self.window {
+                return true
+            }
+
+            if *suppressed > 0 {
+                error!(
+                    target: "net::channel::error_log_throttle",
+                    "[P2P] Channel {}: {} ({} identical errors suppressed)",
+                    peer, err, suppressed,
+                );
+            }
+            false
+        });
+
+        result
+    }
+}
+
+/// Remembers the hashes of recently received messages across all
+/// channels, so gossip arriving over several channels is only dispatched
+/// once. Only messages opting in with [`Message::DEDUP`] are checked.
+/// Entries expire after the TTL, and the oldest one is evicted if the
+/// cache is full.
+pub(in crate::net) struct SeenCache {
+    enabled: AtomicBool,
+    capacity: usize,
+    ttl: Duration,
+    entries: SyncMutex<SeenEntries>,
+}
+
+/// Hashes of the messages seen, along with when they were first seen in
+/// insertion order. Insertion times only grow, so the front of the queue
+/// always holds the oldest entry.
+#[derive(Default)]
+struct SeenEntries {
+    seen: HashSet<[u8; 32]>,
+    order: VecDeque<([u8; 32], Instant)>,
+}
+
+impl SeenEntries {
+    fn pop_oldest(&mut self) {
+        if let Some((hash, _)) = self.order.pop_front() {
+            self.seen.remove(&hash);
+        }
+    }
+}
+
+impl SeenCache {
+    pub(in crate::net) fn new(enabled: bool, capacity: usize, ttl: Duration) -> Self {
+        Self {
+            enabled: AtomicBool::new(enabled && capacity > 0),
+            capacity,
+            ttl,
+            entries: SyncMutex::new(SeenEntries::default()),
+        }
+    }
+
+    pub(in crate::net) fn is_enabled(&self) -> bool {
+        self.enabled.load(SeqCst)
+    }
+
+    pub(in crate::net) fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled && self.capacity > 0, SeqCst);
+        if !enabled {
+            *self.entries.lock().unwrap() = SeenEntries::default();
+        }
+    }
+
+    /// Hash covering both the command and the payload of a message
+    fn message_hash(command: &str, payload: &[u8]) -> [u8; 32] {
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(&(command.len() as u64).to_le_bytes());
+        hasher.update(command.as_bytes());
+        hasher.update(payload);
+        *hasher.finalize().as_bytes()
+    }
+
+    /// Returns `false` if the message was already seen within the TTL,
+    /// otherwise remembers it and returns `true`. A disabled cache always
+    /// returns `true`.
+    pub(in crate::net) fn insert(&self, command: &str, payload: &[u8]) -> bool {
+        if !self.is_enabled() {
+            return true
+        }
+
+        let now = Instant::now();
+        let hash = Self::message_hash(command, payload);
+        let mut entries = self.entries.lock().unwrap();
+
+        // Expired entries are all at the front of the queue
+        while entries.order.front().is_some_and(|(_, seen)| now.duration_since(*seen) >= self.ttl) {
+            entries.pop_oldest();
+        }
+
+        if entries.seen.contains(&hash) {
+            return false
+        }
+
+        while entries.seen.len() >= self.capacity {
+            entries.pop_oldest();
+        }
+
+        entries.seen.insert(hash);
+        entries.order.push_back((hash, now));
+        true
+    }
+}
+
+/// Network location of a peer, as reported by a [`PeerResolver`]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PeerLocation {
+    /// Autonomous system number the peer address belongs to
+    pub asn: Option<u32>,
+    /// ISO 3166-1 alpha-2 country code of the peer address
+    pub country: Option<String>,
+}
+
+/// Resolves peer addresses to their network location. The crate doesn't
+/// bundle any GeoIP data, so applications wanting peer diversity metrics
+/// provide their own implementation with [`P2p::set_peer_resolver()`].
+///
+/// [`P2p::set_peer_resolver()`]: super::P2p::set_peer_resolver
+pub trait PeerResolver: Send + Sync {
+    /// Look up the location of the given peer address. Unknown fields
+    /// should be left as `None`.
+    fn resolve(&self, addr: &Url) -> PeerLocation;
+}
+
+/// Atomic pointer to a peer location resolver
+pub type PeerResolverPtr = Arc<dyn PeerResolver>;
+
+/// Kinds of peer misbehaviour a channel can observe
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ViolationKind {
+    /// The peer sent a message no protocol is subscribed to
+    MissingDispatcher,
+    /// The peer sent a message payload that doesn't decode into its
+    /// message, or declared one over the size limit
+    MalformedMessage,
+    /// Application-defined misbehaviour reported with
+    /// [`Channel::report_violation()`]
+    Misbehaviour,
+}
+
+/// Action taken on a peer after a violation
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BanDecision {
+    /// Log a warning and keep the channel open
+    Warn,
+    /// Stop the channel without blacklisting the peer
+    Disconnect,
+    /// Blacklist the peer and stop the channel
+    Ban,
+}
+
+/// Decides how to handle misbehaving peers. Applications can implement
+/// their own (e.g. score decay or tiered penalties) and install it with
+/// [`P2p::set_violation_policy()`].
+///
+/// [`P2p::set_violation_policy()`]: super::P2p::set_violation_policy
+pub trait ViolationPolicy: Send + Sync {
+    /// Called every time `peer` commits a violation of the given kind
+    fn on_violation(&self, peer: &Url, kind: ViolationKind) -> BanDecision;
+}
+
+/// Atomic pointer to a violation policy
+pub type ViolationPolicyPtr = Arc<dyn ViolationPolicy>;
+
+/// Policy used when the application doesn't provide one. Misbehaving
+/// peers are banned, unless the configured [`BanPolicy`] is `Relaxed`
+/// in which case they are only disconnected.
+pub struct DefaultViolationPolicy(pub BanPolicy);
+
+impl ViolationPolicy for DefaultViolationPolicy {
+    fn on_violation(&self, _peer: &Url, _kind: ViolationKind) -> BanDecision {
+        match self.0 {
+            BanPolicy::Strict => BanDecision::Ban,
+            BanPolicy::Relaxed => BanDecision::Disconnect,
+        }
+    }
+}
+
+/// Hook observing messages passing through a channel. It's called with
+/// the message command and the byte length of its payload.
+pub type MessageHook = Box<dyn Fn(&str, usize) + Send + Sync>;
+
+/// Redactor masking sensitive bytes of a payload before it's logged. It's
+/// called with the message command and a copy of the payload to modify.
+pub type PayloadRedactor = Box<dyn Fn(&str, &mut [u8]) + Send + Sync>;
+
 /// Channel debug info
 #[derive(Clone, Debug, SerialEncodable, SerialDecodable)]
 pub struct ChannelInfo {
@@ -66,14 +321,54 @@ pub struct ChannelInfo {
     pub connect_addr: Url,
     pub start_time: u64,
     pub id: u32,
+    /// Autonomous system number of the peer, if a resolver is set.
+    /// Kept local, it's not part of the serialized info.
+    #[skip_serialize]
+    pub asn: Option<u32>,
+    /// Country code of the peer, if a resolver is set.
+    /// Kept local, it's not part of the serialized info.
+    #[skip_serialize]
+    pub country: Option<String>,
 }
 
 impl ChannelInfo {
-    fn new(resolve_addr: Option<Url>, connect_addr: Url, start_time: u64) -> Self {
-        Self { resolve_addr, connect_addr, start_time, id: OsRng.gen() }
+    fn new(resolve_addr: Option<Url>, connect_addr: Url, start_time: u64, id: u32) -> Self {
+        Self { resolve_addr, connect_addr, start_time, id, asn: None, country: None }
+    }
+
+    /// Derive a channel id from the connection tuple, so reconnections
+    /// of the same logical connection keep their id. Used when
+    /// `Settings::deterministic_channel_ids` is set.
+    pub fn deterministic_id(connect_addr: &Url, resolve_addr: Option<&Url>, inbound: bool) -> u32 {
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(b"darkfi_channel_id");
+        hasher.update(connect_addr.as_str().as_bytes());
+        hasher.update(&[0]);
+        hasher.update(resolve_addr.map_or("", |addr| addr.as_str()).as_bytes());
+        hasher.update(&[0, inbound as u8]);
+        let hash = hasher.finalize();
+        u32::from_le_bytes(hash.as_bytes()[..4].try_into().unwrap())
+    }
+
+    /// Tag this info with the peer location returned by a resolver
+    fn tag(&mut self, location: PeerLocation) {
+        self.asn = location.asn;
+        self.country = location.country;
     }
 }
 
+/// New reading half handed to the receive loop by `rotate_stream()`,
+/// along with a sender acknowledging the swap
+type StreamRotation = (ReadHalf<Box<dyn PtStream>>, Sender<()>);
+
+/// Bound on the amount of sends in flight on a channel
+struct SendLimit {
+    /// One permit per send allowed to wait on the writer
+    permits: Arc<Semaphore>,
+    /// Fail with `Error::ChannelBusy` instead of waiting for a permit
+    nonblocking: bool,
+}
+
 /// Async channel for communication between nodes.
 pub struct Channel {
     /// The reading half of the transport stream
@@ -86,14 +381,54 @@ pub struct Channel {
     stop_publisher: PublisherPtr<Error>,
     /// Task that is listening for the stop signal
     receive_task: StoppableTaskPtr,
+    /// Task periodically probing the peer for half-open connections
+    probe_task: StoppableTaskPtr,
+    /// Nonce of the last ping sent by the probe, or `u32::MAX` if none
+    probe_nonce: AtomicU32,
+    /// Queue of stream rotations for the receive loop to pick up
+    rotate_send: Sender<StreamRotation>,
+    rotate_recv: Receiver<StreamRotation>,
+    /// Senders feeding the raw streams handed out by `message_stream()`
+    message_streams: SyncMutex<Vec<Sender<(String, Vec<u8>)>>>,
+    /// Hooks called for every message sent, in registration order
+    send_hooks: SyncMutex<Vec<MessageHook>>,
+    /// Hooks called for every message received, in registration order
+    recv_hooks: SyncMutex<Vec<MessageHook>>,
+    /// Hex-dump message payloads at trace level
+    log_payloads: AtomicBool,
+    /// Maximum length of a received message payload, 0 if unbounded
+    max_payload_len: u64,
+    /// Redactors applied to payloads before they're logged
+    payload_redactors: SyncMutex<Vec<PayloadRedactor>>,
     /// A boolean marking if this channel is stopped
     stopped: AtomicBool,
+    /// A boolean marking if reading from the peer is paused
+    paused: AtomicBool,
+    /// Gate the receive loop waits on before every read. Notified
+    /// while the channel isn't paused.
+    resume_gate: CondVar,
     /// Weak pointer to respective session
     pub(in crate::net) session: SessionWeakPtr,
     /// The version message of the node we are connected to.
     /// Some if the version exchange has already occurred, None
     /// otherwise.
     pub version: Mutex<Option<Arc<VersionMessage>>>,
+    /// Service bitflags advertised by the node we are connected to.
+    /// Set along with the version message on handshake.
+    services: AtomicU32,
+    /// Deduplicates this channel's error logs
+    error_log_throttle: ErrorLogThrottle,
+    /// Maximum time to wait for the next message from the peer.
+    /// `None` waits indefinitely.
+    read_deadline: SyncMutex<Option<Duration>>,
+    /// Maximum time a single message send may take.
+    /// `None` waits indefinitely.
+    write_deadline: SyncMutex<Option<Duration>>,
+    /// Maximum amount of concurrent sends, `None` if unbounded
+    send_limit: SyncMutex<Option<SendLimit>>,
+    /// UNIX timestamp in nanoseconds of the last message sent or
+    /// received. Starts at the channel creation time.
+    last_activity: AtomicU64,
     /// Channel debug info
     pub info: ChannelInfo,
 }
@@ -107,6 +442,19 @@ impl Channel {
         resolve_addr: Option<Url>,
         connect_addr: Url,
         session: SessionWeakPtr,
+    ) -> Arc<Self> {
+        Self::new_with_task_name(stream, resolve_addr, connect_addr, session, None).await
+    }
+
+    /// Same as `new()`, but allows labeling the receive loop task with a
+    /// custom name. If `task_name` is `None`, a name is derived from the
+    /// channel id and peer address.
+    pub async fn new_with_task_name(
+        stream: Box<dyn PtStream>,
+        resolve_addr: Option<Url>,
+        connect_addr: Url,
+        session: SessionWeakPtr,
+        task_name: Option<String>,
     ) -> Arc<Self> {
         let (reader, writer) = io::split(stream);
         let reader = Mutex::new(reader);
@@ -117,21 +465,74 @@ impl Channel {
 
         let version = Mutex::new(None);
         let start_time = UNIX_EPOCH.elapsed().unwrap().as_secs();
-        let info = ChannelInfo::new(resolve_addr, connect_addr.clone(), start_time);
+        let log_payloads = match session.upgrade() {
+            Some(s) => s.p2p().settings().read().await.log_payloads,
+            None => false,
+        };
+        let max_payload_len = match session.upgrade() {
+            Some(s) => s.p2p().settings().read().await.max_payload_len,
+            None => 0,
+        };
+        let id = match session.upgrade() {
+            Some(s) if s.p2p().settings().read().await.deterministic_channel_ids => {
+                let inbound = s.type_id() & SESSION_INBOUND != 0;
+                ChannelInfo::deterministic_id(&connect_addr, resolve_addr.as_ref(), inbound)
+            }
+            _ => OsRng.gen(),
+        };
+        let mut info = ChannelInfo::new(resolve_addr, connect_addr.clone(), start_time, id);
+        if let Some(resolver) = session.upgrade().and_then(|s| s.p2p().peer_resolver()) {
+            info.tag(resolver.resolve(&connect_addr));
+        }
+
+        let task_name =
+            task_name.unwrap_or_else(|| format!("channel-{}-{}", info.id, connect_addr.as_str()));
+        let (rotate_send, rotate_recv) = smol::channel::bounded(1);
+        let resume_gate = CondVar::new();
+        resume_gate.notify();
 
         Arc::new(Self {
             reader,
             writer,
             message_subsystem,
             stop_publisher: Publisher::new(),
-            receive_task: StoppableTask::new(),
+            probe_task: StoppableTask::new_named(format!("{}-probe", task_name)),
+            receive_task: StoppableTask::new_named(task_name),
+            probe_nonce: AtomicU32::new(u32::MAX),
+            rotate_send,
+            rotate_recv,
+            message_streams: SyncMutex::new(vec![]),
+            send_hooks: SyncMutex::new(vec![]),
+            recv_hooks: SyncMutex::new(vec![]),
+            log_payloads: AtomicBool::new(log_payloads),
+            max_payload_len,
+            payload_redactors: SyncMutex::new(vec![]),
             stopped: AtomicBool::new(false),
+            paused: AtomicBool::new(false),
+            resume_gate,
             session,
             version,
+            services: AtomicU32::new(0),
+            error_log_throttle: ErrorLogThrottle::new(ERROR_LOG_WINDOW),
+            read_deadline: SyncMutex::new(None),
+            write_deadline: SyncMutex::new(None),
+            send_limit: SyncMutex::new(None),
+            last_activity: AtomicU64::new(Self::now_nanos()),
             info,
         })
     }
 
+    /// Create a channel over `stream` which isn't attached to any session
+    /// or P2P instance, so framing and handshakes can be tested in
+    /// isolation. Anything relying on the P2P instance (dnet events,
+    /// probing, error log throttling, banning) is skipped.
+    #[cfg(test)]
+    pub async fn new_detached(stream: Box<dyn PtStream>) -> Arc<Self> {
+        let session: SessionWeakPtr = std::sync::Weak::<DetachedSession>::new();
+        let connect_addr = Url::parse("memory://detached").unwrap();
+        Self::new(stream, None, connect_addr, session).await
+    }
+
     /// Perform network handshake for message subsystem dispatchers.
     async fn setup_dispatchers(subsystem: &MessageSubsystem) {
         subsystem.add_dispatch::<message::VersionMessage>().await;
@@ -140,6 +541,8 @@ impl Channel {
         subsystem.add_dispatch::<message::PongMessage>().await;
         subsystem.add_dispatch::<message::GetAddrsMessage>().await;
         subsystem.add_dispatch::<message::AddrsMessage>().await;
+        subsystem.add_dispatch::<message::SignedAddrsMessage>().await;
+        subsystem.add_dispatch::<message::AckMessage>().await;
     }
 
     /// Starts the channel. Runs a receive loop to start receiving messages
@@ -149,15 +552,29 @@ impl Channel {
 
         let self_ = self.clone();
         self.receive_task.clone().start(
-            self.clone().main_receive_loop(),
+            self.clone().main_receive_loop().instrument(self.log_span()),
             |result| self_.handle_stop(result),
             Error::ChannelStopped,
+            executor.clone(),
+        );
+
+        let self_ = self.clone();
+        self.probe_task.clone().start(
+            self.clone().probe_loop().instrument(self.log_span()),
+            |result| self_.handle_probe_stop(result),
+            Error::ChannelStopped,
             executor,
         );
 
         debug!(target: "net::channel::start()", "END {:?}", self);
     }
 
+    /// Logging span tagging log lines with the channel id and peer address.
+    /// The receive loop runs within it, and so does the handshake.
+    pub fn log_span(&self) -> LogSpan {
+        LogSpan::new("channel", &[("id", &self.info.id), ("addr", self.address())])
+    }
+
     /// Stops the channel.
     /// Notifies all publishers that the channel has been closed in `handle_stop()`.
     pub async fn stop(&self) {
@@ -166,6 +583,29 @@ impl Channel {
         debug!(target: "net::channel::stop()", "END {:?}", self);
     }
 
+    /// Gracefully closes the channel. Unlike `stop()`, this first acquires
+    /// the writer lock so any in-flight write finishes, then flushes and
+    /// closes the writing half before stopping the receive task. This
+    /// guarantees the peer never sees a half-written frame from our side.
+    pub async fn close(&self) {
+        debug!(target: "net::channel::close()", "START {:?}", self);
+
+        // Hold the writer lock until the channel is stopped, so pending
+        // sends waiting on it bail out instead of writing to a closed stream.
+        let writer = &mut *self.writer.lock().await;
+
+        if let Err(e) = writer.flush().await {
+            debug!(target: "net::channel::close()", "Failed flushing {:?}: {}", self, e);
+        }
+
+        if let Err(e) = writer.close().await {
+            debug!(target: "net::channel::close()", "Failed closing {:?}: {}", self, e);
+        }
+
+        self.stop().await;
+        debug!(target: "net::channel::close()", "END {:?}", self);
+    }
+
     /// Creates a subscription to a stopped signal.
     /// If the channel is stopped then this will return a ChannelStopped error.
     pub async fn subscribe_stop(&self) -> Result<Subscription<Error>> {
@@ -186,6 +626,140 @@ impl Channel {
         self.stopped.load(SeqCst)
     }
 
+    /// Stop reading from the peer without dropping it, e.g. while the
+    /// application is busy processing. The message being read when this
+    /// is called is still delivered. Unread data then backs up in the
+    /// transport, so the peer gets throttled instead of messages piling
+    /// up in memory. The channel can still be stopped while paused.
+    ///
+    /// Keepalive pings aren't answered while paused, so long pauses can
+    /// make the peer drop the connection.
+    pub fn pause(&self) {
+        self.paused.store(true, SeqCst);
+        self.resume_gate.reset();
+    }
+
+    /// Continue reading from the peer after `pause()`
+    pub fn resume(&self) {
+        self.paused.store(false, SeqCst);
+        self.resume_gate.notify();
+    }
+
+    /// Returns true if reading from the peer is paused
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(SeqCst)
+    }
+
+    /// Set the maximum time to wait for the next message from the peer.
+    /// If it elapses, the channel is stopped. `None` disables the deadline.
+    /// The receive loop consults this before every read, so a change
+    /// applies starting with the next message.
+    pub fn set_read_deadline(&self, deadline: Option<Duration>) {
+        *self.read_deadline.lock().unwrap() = deadline;
+    }
+
+    /// Set the maximum time a single message send may take. If it elapses,
+    /// the send fails and the channel is stopped. `None` disables the deadline.
+    pub fn set_write_deadline(&self, deadline: Option<Duration>) {
+        *self.write_deadline.lock().unwrap() = deadline;
+    }
+
+    /// Limit the amount of sends which may be in flight on this channel
+    /// at once, to apply backpressure on producers. Once `limit` sends
+    /// are pending, further sends wait for one of them to finish, or fail
+    /// with `Error::ChannelBusy` if `nonblocking` is set. `None` removes
+    /// the limit. Sends already in flight are not affected by a change.
+    pub fn set_send_limit(&self, limit: Option<usize>, nonblocking: bool) {
+        *self.send_limit.lock().unwrap() =
+            limit.map(|n| SendLimit { permits: Arc::new(Semaphore::new(n)), nonblocking });
+    }
+
+    /// Take a slot from the send limit, if one is set. The slot is held
+    /// until the returned guard is dropped.
+    async fn acquire_send_slot(&self) -> Result<Option<SemaphoreGuardArc>> {
+        let Some((permits, nonblocking)) = self
+            .send_limit
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map(|limit| (limit.permits.clone(), limit.nonblocking))
+        else {
+            return Ok(None)
+        };
+
+        if nonblocking {
+            return permits.try_acquire_arc().map(Some).ok_or(Error::ChannelBusy)
+        }
+
+        Ok(Some(permits.acquire_arc().await))
+    }
+
+    /// Returns the current read deadline
+    pub fn read_deadline(&self) -> Option<Duration> {
+        *self.read_deadline.lock().unwrap()
+    }
+
+    /// Returns the current write deadline
+    pub fn write_deadline(&self) -> Option<Duration> {
+        *self.write_deadline.lock().unwrap()
+    }
+
+    /// Replace the underlying transport stream, e.g. when the connection
+    /// migrates to a new path, while keeping subscriptions, hooks and
+    /// protocols attached to the channel. Sends in flight finish on the
+    /// old stream first, and the receive loop switches over before
+    /// waiting for its next message. A frame the peer only partially
+    /// sent on the old stream is lost, so the peer should switch at a
+    /// message boundary.
+    pub async fn rotate_stream(&self, stream: Box<dyn PtStream>) -> Result<()> {
+        if self.is_stopped() {
+            return Err(Error::ChannelStopped)
+        }
+
+        let (reader, writer) = io::split(stream);
+
+        // Hold the writer so no further sends start on the old stream
+        let mut old_writer = self.writer.lock().await;
+        if let Err(e) = old_writer.flush().await {
+            debug!(
+                target: "net::channel::rotate_stream()",
+                "Failed flushing old stream of {:?}: {}", self, e,
+            );
+        }
+
+        match self.reader.try_lock() {
+            // The receive loop isn't running, so swap the reader directly
+            Some(mut old_reader) => *old_reader = reader,
+            None => {
+                let (ack_send, ack_recv) = smol::channel::bounded(1);
+                if self.rotate_send.send((reader, ack_send)).await.is_err() {
+                    return Err(Error::ChannelStopped)
+                }
+
+                // Pairs with the drain in `handle_stop()`, so a rotation
+                // is never left queued after the receive loop is gone.
+                if self.is_stopped() {
+                    while self.rotate_recv.try_recv().is_ok() {}
+                    return Err(Error::ChannelStopped)
+                }
+
+                if ack_recv.recv().await.is_err() {
+                    return Err(Error::ChannelStopped)
+                }
+            }
+        }
+
+        *old_writer = writer;
+        debug!(target: "net::channel::rotate_stream()", "Rotated stream of {:?}", self);
+
+        Ok(())
+    }
+
+    /// Returns the name of the task running the receive loop
+    pub fn task_name(&self) -> &str {
+        self.receive_task.name()
+    }
+
     /// Sends a message across a channel. First it converts the message
     /// into a `SerializedMessage` and then calls `send_serialized` to send it.
     /// Returns an error if something goes wrong.
@@ -195,7 +769,8 @@ impl Channel {
 
     /// Sends the encoded payload of provided `SerializedMessage` across the channel.
     /// Calls `send_message` that creates a new payload and sends it over the
-    /// network transport as a packet. Returns an error if something goes wrong.
+    /// network transport as a packet. Returns an error if something goes wrong,
+    /// or `Error::ChannelBusy` if the send limit is saturated in nonblocking mode.
     pub async fn send_serialized(&self, message: &SerializedMessage) -> Result<()> {
         debug!(
              target: "net::channel::send()", "[START] command={} {:?}",
@@ -206,15 +781,12 @@ impl Channel {
             return Err(Error::ChannelStopped)
         }
 
+        let _slot = self.acquire_send_slot().await?;
+
         // Catch failure and stop channel, return a net error
-        if let Err(e) = self.send_message(message).await {
-            if self.session.upgrade().unwrap().type_id() & (SESSION_ALL & !SESSION_REFINE) != 0 {
-                error!(
-                    target: "net::channel::send()", "[P2P] Channel send error for [{:?}]: {}",
-                    self, e
-                );
-            }
-            self.stop().await;
+        if let Err(e) = Self::with_deadline(self.write_deadline(), self.send_message(message)).await
+        {
+            self.handle_send_error(e).await;
             return Err(Error::ChannelStopped)
         }
 
@@ -226,39 +798,107 @@ impl Channel {
         Ok(())
     }
 
+    /// Like `send()`, but doesn't wait if another send is currently writing
+    /// to the channel. Returns `Ok(false)` without sending anything if the
+    /// writer is busy, and `Ok(true)` once the message has been written.
+    /// This lets broadcasters skip busy peers instead of blocking on them.
+    pub async fn try_send<M: message::Message>(&self, message: &M) -> Result<bool> {
+        if self.is_stopped() {
+            return Err(Error::ChannelStopped)
+        }
+
+        let Some(mut stream) = self.writer.try_lock() else {
+            debug!(
+                target: "net::channel::try_send()", "Writer busy, skipping command={} {:?}",
+                M::NAME, self,
+            );
+            return Ok(false)
+        };
+
+        let message = SerializedMessage::new(message).await;
+        let result =
+            Self::with_deadline(self.write_deadline(), self.write_message(&mut stream, &message))
+                .await;
+        drop(stream);
+
+        // Catch failure and stop channel, return a net error
+        if let Err(e) = result {
+            self.handle_send_error(e).await;
+            return Err(Error::ChannelStopped)
+        }
+
+        Ok(true)
+    }
+
+    /// Log a failed send and stop the channel.
+    async fn handle_send_error(&self, e: Error) {
+        if self.session_type_id() & (SESSION_ALL & !SESSION_REFINE) != 0 {
+            if let Some(suppressed) = self.error_log_throttle.check(self.address(), &e.to_string())
+            {
+                error!(
+                    target: "net::channel::send()",
+                    "[P2P] Channel send error for [{:?}]: {}{}",
+                    self, e, Self::suppressed_suffix(suppressed),
+                );
+            }
+        }
+        self.stop().await;
+    }
+
     /// Sends the encoded payload of provided `SerializedMessage` by writing
     /// the data to the channel async stream.
     async fn send_message(&self, message: &SerializedMessage) -> Result<()> {
-        assert!(!message.command.is_empty());
-
         let stream = &mut *self.writer.lock().await;
-        let mut written: usize = 0;
+        self.write_message(stream, message).await
+    }
 
-        dnetev!(self, SendMessage, {
-            chan: self.info.clone(),
-            cmd: message.command.clone(),
-            time: NanoTimestamp::current_time(),
-        });
+    /// Writes the encoded payload of provided `SerializedMessage` to the
+    /// given writing half of the channel stream, which must be locked by
+    /// the caller.
+    async fn write_message(
+        &self,
+        stream: &mut WriteHalf<Box<dyn PtStream>>,
+        message: &SerializedMessage,
+    ) -> Result<()> {
+        assert!(!message.command.is_empty());
 
-        trace!(target: "net::channel::send_message()", "Sending magic...");
-        written += MAGIC_BYTES.encode_async(stream).await?;
-        trace!(target: "net::channel::send_message()", "Sent magic");
+        let mut written: usize = 0;
 
-        trace!(target: "net::channel::send_message()", "Sending command...");
-        written += message.command.encode_async(stream).await?;
-        trace!(target: "net::channel::send_message()", "Sent command: {}", message.command);
+        // The channel might have been closed while we were waiting on the lock
+        if self.is_stopped() {
+            return Err(Error::ChannelStopped)
+        }
 
-        trace!(target: "net::channel::send_message()", "Sending payload...");
-        // First extract the length of the payload as a VarInt and write it to the stream.
-        written += VarInt(message.payload.len() as u64).encode_async(stream).await?;
-        // Then write the encoded payload itself to the stream.
-        stream.write_all(&message.payload).await?;
-        written += message.payload.len();
+        if !self.is_detached() {
+            dnetev!(self, SendMessage, {
+                chan: self.info.clone(),
+                cmd: message.command.clone(),
+                time: NanoTimestamp::current_time(),
+            });
+        }
 
+        trace!(target: "net::channel::send_message()", "Sending command: {}", message.command);
+        written += Frame::write(stream, &message.command, &message.payload).await?;
         trace!(target: "net::channel::send_message()", "Sent payload {} bytes, total bytes {}",
             message.payload.len(), written);
 
         stream.flush().await?;
+        self.touch();
+
+        if let Some(p2p) = self.try_p2p() {
+            p2p.traffic_stats().record_send(written);
+        }
+
+        for hook in self.send_hooks.lock().unwrap().iter() {
+            hook(&message.command, message.payload.len());
+        }
+
+        if self.logs_payloads() {
+            trace!(
+                target: "net::channel::send_message()", "Sent {}",
+                self.payload_log_line(&message.command, &message.payload),
+            );
+        }
 
         Ok(())
     }
@@ -268,34 +908,17 @@ impl Channel {
     /// using stream.take(). This manual deserialization provides a basic
     /// DDOS protection, since it prevents nodes from sending an arbitarily
     /// large payload.
+    ///
+    /// All reads go through `read_exact()`, including the VarInt and
+    /// integer decoders, so transports returning short reads (e.g. Tor)
+    /// are handled. Only a stream ending mid-frame is an error.
     pub async fn read_command<R: AsyncRead + Unpin + Send + Sized>(
         &self,
         stream: &mut R,
     ) -> Result<String> {
-        // Messages should have a 4 byte header of magic digits.
-        // This is used for network debugging.
-        let mut magic = [0u8; 4];
-        trace!(target: "net::channel::read_command()", "Reading magic...");
-        stream.read_exact(&mut magic).await?;
-
-        trace!(target: "net::channel::read_command()", "Read magic {:?}", magic);
-        if magic != MAGIC_BYTES {
-            error!(target: "net::channel::read_command", "Error: Magic bytes mismatch");
-            return Err(Error::MalformedPacket)
-        }
-
-        // First extract the length from the stream
-        let cmd_len = VarInt::decode_async(stream).await?.0;
-
-        // Then extract precisely `cmd_len` items from the stream.
-        let mut take = stream.take(cmd_len);
-
-        // Deserialize into a vector of `cmd_len` size.
-        let mut bytes = vec![0; cmd_len.try_into().unwrap()];
-        take.read_exact(&mut bytes).await?;
-
-        let command = String::from_utf8(bytes)?;
-
+        trace!(target: "net::channel::read_command()", "Reading command...");
+        let command = Frame::read_command(stream).await?;
+        trace!(target: "net::channel::read_command()", "Read command: {}", command);
         Ok(command)
     }
 
@@ -316,12 +939,160 @@ impl Channel {
         sub
     }
 
+    /// Subscribe to the messages of type `M` that `filter` returns `true`
+    /// for, e.g. the replies to a particular request. Filtering happens
+    /// in the message subsystem, before messages are sent to subscribers.
+    pub async fn subscribe_msg_filtered<M: message::Message>(
+        &self,
+        filter: impl Fn(&M) -> bool + Send + Sync + 'static,
+    ) -> Result<MessageSubscription<M>> {
+        debug!(
+            target: "net::channel::subscribe_msg_filtered()", "command={} {:?}",
+            M::NAME, self
+        );
+
+        self.message_subsystem.subscribe_filtered(filter).await
+    }
+
+    /// Acknowledge a message received on this channel. Peers waiting on
+    /// a quorum through `P2p::broadcast_quorum()` count this channel as
+    /// having received it.
+    pub async fn ack<M: message::Message>(&self, message: &M) -> Result<()> {
+        let id = SerializedMessage::new(message).await.id();
+        self.send(&message::AckMessage { id }).await
+    }
+
+    /// Ask the peer for addresses and wait up to `timeout_` for its reply.
+    /// The request asks for as many addresses as we have outbound slots,
+    /// over our allowed transports. Returns `Error::ConnectTimeout` if no
+    /// reply arrives in time, or the stop error if the channel stops.
+    ///
+    /// The reply subscription is removed on return. If the future is
+    /// dropped early, the dispatcher discards it on the next message.
+    pub async fn request_addrs(&self, timeout_: Duration) -> Result<Vec<Url>> {
+        let (max, transports) = match self.try_p2p() {
+            Some(p2p) => {
+                let settings = p2p.settings();
+                let settings = settings.read().await;
+                (settings.outbound_connections as u32, settings.allowed_transports.clone())
+            }
+            None => {
+                let settings = Settings::default();
+                (settings.outbound_connections as u32, settings.allowed_transports)
+            }
+        };
+
+        // Subscribe before sending, so the reply can't be missed
+        let addrs_sub = self.subscribe_msg::<message::AddrsMessage>().await?;
+
+        let get_addrs = message::GetAddrsMessage { max, transports, since: None, signed: false };
+        let result = match self.send(&get_addrs).await {
+            Ok(()) => match timeout(timeout_, addrs_sub.receive()).await {
+                Ok(Ok(addrs_msg)) => {
+                    Ok(addrs_msg.addrs.iter().map(|(addr, _)| addr.clone()).collect())
+                }
+                Ok(Err(e)) => Err(e),
+                Err(_) => Err(Error::ConnectTimeout),
+            },
+            Err(e) => Err(e),
+        };
+
+        addrs_sub.unsubscribe().await;
+        result
+    }
+
+    /// Returns a stream of the raw `(command, payload)` pairs of messages
+    /// received on this channel, in arrival order. This lets a protocol
+    /// handle many message kinds in a single loop, and coexists with the
+    /// typed subscribers from `subscribe_msg()`. Only messages with a
+    /// registered dispatcher are yielded. The stream ends when the channel
+    /// stops.
+    pub fn message_stream(&self) -> impl Stream<Item = (String, Vec<u8>)> + Send + Unpin {
+        let (sender, receiver) = smol::channel::unbounded();
+
+        // handle_stop() sets the flag before clearing the senders, so
+        // checking it under the lock can't leak a sender.
+        let mut streams = self.message_streams.lock().unwrap();
+        if !self.is_stopped() {
+            streams.push(sender);
+        }
+
+        receiver
+    }
+
+    /// Register a hook called with the command and payload length of
+    /// every message sent on this channel, e.g. for metrics or tracing.
+    /// Hooks run in registration order and must not register new hooks.
+    pub fn on_send(&self, hook: impl Fn(&str, usize) + Send + Sync + 'static) {
+        self.send_hooks.lock().unwrap().push(Box::new(hook));
+    }
+
+    /// Register a hook called with the command and payload length of
+    /// every message received on this channel, before it's dispatched.
+    /// Hooks run in registration order and must not register new hooks.
+    pub fn on_recv(&self, hook: impl Fn(&str, usize) + Send + Sync + 'static) {
+        self.recv_hooks.lock().unwrap().push(Box::new(hook));
+    }
+
+    /// Enable or disable hex-dumping message payloads at trace level.
+    /// Defaults to the `log_payloads` setting. Payloads can contain
+    /// secrets, so register redactors with `add_payload_redactor()`.
+    pub fn set_log_payloads(&self, enabled: bool) {
+        self.log_payloads.store(enabled, SeqCst);
+    }
+
+    /// Register a redactor masking sensitive bytes of logged payloads.
+    /// Redactors run in registration order on a copy of the payload, so
+    /// the message itself is left untouched.
+    pub fn add_payload_redactor(&self, redactor: impl Fn(&str, &mut [u8]) + Send + Sync + 'static) {
+        self.payload_redactors.lock().unwrap().push(Box::new(redactor));
+    }
+
+    /// Returns true if payloads should be logged
+    fn logs_payloads(&self) -> bool {
+        self.log_payloads.load(SeqCst) && log_enabled!(target: "net::channel", Level::Trace)
+    }
+
+    /// Format a payload for logging, hex-encoded after the redactors ran
+    fn payload_log_line(&self, command: &str, payload: &[u8]) -> String {
+        let mut payload = payload.to_vec();
+        for redactor in self.payload_redactors.lock().unwrap().iter() {
+            redactor(command, &mut payload);
+        }
+
+        let hex: String = payload.iter().map(|b| format!("{:02x}", b)).collect();
+        format!("{} payload ({} bytes): {}", command, payload.len(), hex)
+    }
+
+    /// Returns true if any raw message stream is still being consumed
+    fn has_message_streams(&self) -> bool {
+        let mut streams = self.message_streams.lock().unwrap();
+        streams.retain(|sender| !sender.is_closed());
+        !streams.is_empty()
+    }
+
+    /// Read the length-prefixed payload of a message into memory.
+    /// Lengths over `max_len` (unless it's 0) are rejected unread, and
+    /// the buffer only grows with the data actually received, so a bogus
+    /// length can't make us allocate upfront.
+    async fn read_payload(
+        reader: &mut ReadHalf<Box<dyn PtStream>>,
+        max_len: u64,
+    ) -> Result<Vec<u8>> {
+        Frame::read_payload(reader, max_len).await
+    }
+
     /// Handle network errors. Panic if error passes silently, otherwise
     /// broadcast the error.
     async fn handle_stop(self: Arc<Self>, result: Result<()>) {
         debug!(target: "net::channel::handle_stop()", "[START] {:?}", self);
 
         self.stopped.store(true, SeqCst);
+        self.probe_task.stop_nowait();
+        // Fail pending stream rotations
+        while self.rotate_recv.try_recv().is_ok() {}
+        // Dropping the senders ends the raw message streams
+        self.message_streams.lock().unwrap().clear();
 
         match result {
             Ok(()) => panic!("Channel task should never complete without error status"),
@@ -335,33 +1106,122 @@ impl Channel {
         debug!(target: "net::channel::handle_stop()", "[END] {:?}", self);
     }
 
-    /// Run the receive loop. Start receiving messages or handle network failure.
-    async fn main_receive_loop(self: Arc<Self>) -> Result<()> {
-        debug!(target: "net::channel::main_receive_loop()", "[START] {:?}", self);
+    /// Periodically send a ping and require the matching pong within the
+    /// configured timeout. This catches half-open connections where the
+    /// OS still buffers our writes although the peer is gone. Returns
+    /// `Ok` right away if `channel_probe_interval` is 0.
+    async fn probe_loop(self: Arc<Self>) -> Result<()> {
+        let Some(p2p) = self.try_p2p() else { return Ok(()) };
+        let settings = p2p.settings();
+        let settings = settings.read().await;
+        let interval = settings.channel_probe_interval;
+        let deadline = Duration::from_secs(settings.channel_probe_timeout);
+        drop(settings);
+
+        if interval == 0 {
+            return Ok(())
+        }
 
-        // Acquire reader lock
-        let reader = &mut *self.reader.lock().await;
+        let pong_sub = self.subscribe_msg::<PongMessage>().await?;
+
+        loop {
+            sleep(interval).await;
+
+            // Pongs can't be read while paused, so don't mistake the
+            // pause for a half-open connection.
+            if self.is_paused() {
+                continue
+            }
+
+            let nonce: u16 = OsRng.gen();
+            self.probe_nonce.store(nonce as u32, SeqCst);
+            self.send(&PingMessage { nonce }).await?;
+
+            // Skip pongs answering pings sent by someone else
+            let wait_pong = async {
+                loop {
+                    if pong_sub.receive().await?.nonce == nonce {
+                        return Ok::<_, Error>(())
+                    }
+                }
+            };
+            Self::with_deadline(Some(deadline), wait_pong).await?;
+        }
+    }
+
+    /// Stop the channel if the probe timed out waiting for a pong.
+    /// Other errors mean the channel is already stopping.
+    async fn handle_probe_stop(self: Arc<Self>, result: Result<()>) {
+        if let Err(Error::ChannelTimeout) = result {
+            warn!(
+                target: "net::channel::handle_probe_stop()",
+                "[P2P] Probe got no pong from {}, stopping channel", self.address(),
+            );
+            self.receive_task.stop().await;
+        }
+    }
+
+    /// Returns true if `nonce` belongs to a ping sent by the half-open
+    /// connection probe, so other pong consumers can ignore its reply.
+    pub(in crate::net) fn is_probe_nonce(&self, nonce: u16) -> bool {
+        self.probe_nonce.load(SeqCst) == nonce as u32
+    }
+
+    /// Run the receive loop. Start receiving messages or handle network failure.
+    async fn main_receive_loop(self: Arc<Self>) -> Result<()> {
+        debug!(target: "net::channel::main_receive_loop()", "[START] {:?}", self);
+
+        // Acquire reader lock
+        let reader = &mut *self.reader.lock().await;
 
         // Run loop
         loop {
-            let command = match self.read_command(reader).await {
-                Ok(command) => command,
-                Err(err) => {
+            // Wait for the next message, or a stream rotation
+            let next = smol::future::or(
+                async {
+                    // Hold off reading while paused. The read deadline
+                    // only starts once we're reading again.
+                    self.resume_gate.wait().await;
+                    Either::Left(
+                        Self::with_deadline(self.read_deadline(), self.read_command(reader)).await,
+                    )
+                },
+                async {
+                    match self.rotate_recv.recv().await {
+                        Ok(rotation) => Either::Right(rotation),
+                        Err(_) => smol::future::pending().await,
+                    }
+                },
+            )
+            .await;
+
+            let command = match next {
+                Either::Right((new_reader, ack)) => {
+                    *reader = new_reader;
+                    let _ = ack.try_send(());
+                    continue
+                }
+                Either::Left(Ok(command)) => {
+                    self.touch();
+                    command
+                }
+                Either::Left(Err(err)) => {
                     if Self::is_eof_error(&err) {
                         info!(
                             target: "net::channel::main_receive_loop()",
                             "[P2P] Channel {} disconnected",
                             self.address(),
                         );
-                    } else if self.session.upgrade().unwrap().type_id() &
-                        (SESSION_ALL & !SESSION_REFINE) !=
-                        0
-                    {
-                        error!(
-                            target: "net::channel::main_receive_loop()",
-                            "[P2P] Read error on channel {}: {}",
-                            self.address(), err,
-                        );
+                    } else if self.session_type_id() & (SESSION_ALL & !SESSION_REFINE) != 0 {
+                        if let Some(suppressed) =
+                            self.error_log_throttle.check(self.address(), &err.to_string())
+                        {
+                            error!(
+                                target: "net::channel::main_receive_loop()",
+                                "[P2P] Read error on channel {}: {}{}",
+                                self.address(), err, Self::suppressed_suffix(suppressed),
+                            );
+                        }
                     }
 
                     debug!(
@@ -372,34 +1232,238 @@ impl Channel {
                 }
             };
 
-            dnetev!(self, RecvMessage, {
-                chan: self.info.clone(),
-                cmd: command.clone(),
-                time: NanoTimestamp::current_time(),
-            });
+            if !self.is_detached() {
+                dnetev!(self, RecvMessage, {
+                    chan: self.info.clone(),
+                    cmd: command.clone(),
+                    time: NanoTimestamp::current_time(),
+                });
+            }
+
+            // Send result to our publishers. If raw message streams are
+            // consumed or hooks need the payload length, the payload is
+            // buffered so all of them can read it.
+            let dedup = self.try_p2p().is_some_and(|p2p| p2p.seen_cache().is_enabled()) &&
+                self.message_subsystem.is_dedup(&command).await;
+            let payload_read = self.has_message_streams() ||
+                !self.recv_hooks.lock().unwrap().is_empty() ||
+                self.logs_payloads() ||
+                dedup;
+            // Size of the whole frame on the wire, for the traffic stats
+            let mut received =
+                MAGIC_BYTES.len() + VarInt(command.len() as u64).length() + command.len();
+            let notified = if payload_read {
+                let payload = match Self::read_payload(reader, self.max_payload_len).await {
+                    Ok(payload) => payload,
+                    Err(e) => {
+                        if matches!(e, Error::PayloadTooLarge) {
+                            self.judge_violation(ViolationKind::MalformedMessage).await;
+                        }
+                        debug!(
+                            target: "net::channel::main_receive_loop()",
+                            "Failed reading payload on {:?}: {}", self, e,
+                        );
+                        return Err(Error::ChannelStopped)
+                    }
+                };
+
+                // Drop gossip we already got over another channel
+                if dedup {
+                    let p2p = self.p2p();
+                    if !p2p.seen_cache().insert(&command, &payload) {
+                        debug!(
+                            target: "net::channel::main_receive_loop()",
+                            "Dropping duplicate '{}' message from {}", command, self.address(),
+                        );
+                        received += VarInt(payload.len() as u64).length() + payload.len();
+                        p2p.traffic_stats().record_recv(received);
+                        continue
+                    }
+                }
+
+                for hook in self.recv_hooks.lock().unwrap().iter() {
+                    hook(&command, payload.len());
+                }
 
-            // Send result to our publishers
-            match self.message_subsystem.notify(&command, reader).await {
-                Ok(()) => {}
-                // If we're getting messages without dispatchers, it's spam.
+                if self.logs_payloads() {
+                    trace!(
+                        target: "net::channel::main_receive_loop()", "Received {}",
+                        self.payload_log_line(&command, &payload),
+                    );
+                }
+
+                let mut frame = serialize(&VarInt(payload.len() as u64));
+                frame.extend_from_slice(&payload);
+                received += frame.len();
+
+                let notified = self
+                    .message_subsystem
+                    .notify(&command, &mut Cursor::new(frame), self.max_payload_len)
+                    .await;
+                if notified.is_ok() {
+                    for sender in self.message_streams.lock().unwrap().iter() {
+                        let _ = sender.try_send((command.clone(), payload.clone()));
+                    }
+                }
+                notified
+            } else {
+                let mut reader = CountingReader::new(reader);
+                let notified = self
+                    .message_subsystem
+                    .notify(&command, &mut reader, self.max_payload_len)
+                    .await;
+                received += reader.count;
+                notified
+            };
+
+            match notified {
+                Ok(()) => {
+                    if let Some(p2p) = self.try_p2p() {
+                        p2p.traffic_stats().record_recv(received);
+                    }
+                }
+                // If we're getting messages without dispatchers, it's spam,
+                // unless this session type is configured to tolerate it.
                 Err(Error::MissingDispatcher) => {
+                    let skip = match self.unknown_command_action().await {
+                        UnknownCommandAction::Ban => {
+                            self.judge_violation(ViolationKind::MissingDispatcher).await ==
+                                BanDecision::Warn
+                        }
+                        UnknownCommandAction::Warn => {
+                            warn!(
+                                target: "net::channel::main_receive_loop()",
+                                "[P2P] Peer {} sent unknown command '{}'", self.address(), command,
+                            );
+                            true
+                        }
+                        UnknownCommandAction::Ignore => true,
+                    };
+
+                    if skip {
+                        // Skip the payload if nobody consumed it yet
+                        if !payload_read &&
+                            Self::read_payload(reader, self.max_payload_len).await.is_err()
+                        {
+                            return Err(Error::ChannelStopped)
+                        }
+                        continue
+                    }
+
                     debug!(target: "net::channel::main_receive_loop()", "Stopping channel {:?}", self);
-                    if let BanPolicy::Strict = self.p2p().settings().read().await.ban_policy {
-                        self.ban(self.address()).await;
+                    return Err(Error::ChannelStopped)
+                }
+                // The rest of the payload was skipped, so the stream is
+                // still in sync if the peer gets away with a warning.
+                Err(Error::MalformedPacket) => {
+                    // The handshake waits on the version message, tell it
+                    // the peer's one is unusable instead of letting it time out
+                    if command == message::VersionMessage::NAME {
+                        self.message_subsystem
+                            .trigger_error_for(&command, Error::MalformedPacket)
+                            .await;
                     }
 
+                    if self.judge_violation(ViolationKind::MalformedMessage).await ==
+                        BanDecision::Warn
+                    {
+                        continue
+                    }
+
+                    debug!(target: "net::channel::main_receive_loop()", "Stopping channel {:?}", self);
+                    return Err(Error::ChannelStopped)
+                }
+                // The payload was rejected unread, so there's no way to
+                // get back in sync with the stream
+                Err(Error::PayloadTooLarge) => {
+                    self.judge_violation(ViolationKind::MalformedMessage).await;
+                    debug!(target: "net::channel::main_receive_loop()", "Stopping channel {:?}", self);
+                    return Err(Error::ChannelStopped)
+                }
+                // Reading the rest of the payload failed
+                Err(e) => {
+                    debug!(
+                        target: "net::channel::main_receive_loop()",
+                        "Failed reading payload on {:?}: {}", self, e,
+                    );
                     return Err(Error::ChannelStopped)
                 }
-                Err(_) => unreachable!("You added a new error in notify()"),
             }
         }
     }
 
+    /// Look up how messages without dispatchers are handled for the
+    /// session type of this channel.
+    async fn unknown_command_action(&self) -> UnknownCommandAction {
+        // Detached channels have no settings, treat it as a violation
+        let Some(p2p) = self.try_p2p() else { return UnknownCommandAction::Ban };
+        let settings = p2p.settings().read().await;
+
+        let type_id = self.session_type_id();
+        if type_id & SESSION_REFINE != 0 {
+            settings.refine_unknown_command_action
+        } else if type_id & SESSION_SEED != 0 {
+            settings.seed_unknown_command_action
+        } else {
+            settings.unknown_command_action
+        }
+    }
+
+    /// Report a violation committed by the peer. The P2P instance's
+    /// [`ViolationPolicy`] decides whether the peer is banned, only
+    /// disconnected, or merely warned about.
+    pub async fn report_violation(&self, kind: ViolationKind) -> BanDecision {
+        let decision = self.judge_violation(kind).await;
+        if decision == BanDecision::Disconnect {
+            self.stop().await;
+        }
+        decision
+    }
+
+    /// Consult the violation policy and apply a `Ban` decision. The
+    /// caller is responsible for stopping the channel on `Disconnect`.
+    async fn judge_violation(&self, kind: ViolationKind) -> BanDecision {
+        // Detached channels have nothing to ban the peer from
+        let Some(p2p) = self.try_p2p() else { return BanDecision::Disconnect };
+        let policy = match p2p.violation_policy() {
+            Some(policy) => policy,
+            None => {
+                let ban_policy = p2p.settings().read().await.ban_policy.clone();
+                Arc::new(DefaultViolationPolicy(ban_policy))
+            }
+        };
+
+        let decision = policy.on_violation(self.address(), kind);
+        match decision {
+            BanDecision::Warn => warn!(
+                target: "net::channel::judge_violation()",
+                "[P2P] Peer {} committed violation {:?}", self.address(), kind,
+            ),
+            BanDecision::Disconnect => {}
+            BanDecision::Ban => self.ban_for(self.address(), Some(kind)).await,
+        }
+
+        decision
+    }
+
     /// Ban a malicious peer and stop the channel.
     pub async fn ban(&self, peer: &Url) {
+        self.ban_for(peer, None).await
+    }
+
+    /// Ban a peer for the given violation and stop the channel. The ban
+    /// is reported to the subscribers of [`Hosts::subscribe_ban()`].
+    ///
+    /// [`Hosts::subscribe_ban()`]: super::hosts::Hosts::subscribe_ban
+    async fn ban_for(&self, peer: &Url, reason: Option<ViolationKind>) {
         debug!(target: "net::channel::ban()", "START {:?}", self);
         debug!(target: "net::channel::ban()", "Peer: {:?}", peer);
 
+        if self.is_detached() {
+            self.stop().await;
+            return
+        }
+
         // Just store the hostname if this is an inbound session.
         // This will block all ports from this peer by setting
         // `hosts.block_all_ports()` to true.
@@ -432,7 +1496,12 @@ impl Channel {
         };
 
         let last_seen = UNIX_EPOCH.elapsed().unwrap().as_secs();
-        self.p2p().hosts().move_host(&peer, last_seen, HostColor::Black).unwrap();
+        let hosts = self.p2p().hosts();
+        hosts.move_host(&peer, last_seen, HostColor::Black).unwrap();
+        // Local hosts are never blacklisted, so they don't count as bans
+        if hosts.container.contains(HostColor::Black as usize, &peer) {
+            hosts.record_ban(peer, reason).await;
+        }
         self.stop().await;
         debug!(target: "net::channel::ban()", "STOP {:?}", self);
     }
@@ -463,9 +1532,65 @@ impl Channel {
     /// Set the VersionMessage of the node this channel is connected
     /// to. Called on receiving a version message in `ProtocolVersion`.
     pub(crate) async fn set_version(&self, version: Arc<VersionMessage>) {
+        self.services.store(version.services, SeqCst);
         *self.version.lock().await = Some(version);
     }
 
+    /// Returns the service bitflags advertised by the node this channel
+    /// is connected to. Zero if the version exchange hasn't occurred yet.
+    pub fn services(&self) -> ServiceFlags {
+        self.services.load(SeqCst)
+    }
+
+    /// Returns `true` if the node this channel is connected to advertised
+    /// the given feature in its version message. Always `false` before
+    /// the version exchange.
+    pub async fn has_feature(&self, name: &str) -> bool {
+        let version = self.version.lock().await;
+        version.as_ref().is_some_and(|v| v.features.iter().any(|(f, _)| f == name))
+    }
+
+    /// Returns the time this channel last sent or received a message.
+    /// Until then, this is the time the channel was created.
+    pub fn last_activity(&self) -> NanoTimestamp {
+        NanoTimestamp(self.last_activity.load(SeqCst) as u128)
+    }
+
+    /// Mark the channel as active now
+    fn touch(&self) {
+        self.last_activity.fetch_max(Self::now_nanos(), SeqCst);
+    }
+
+    /// Current UNIX time in nanoseconds. Fits in a `u64` until 2554.
+    fn now_nanos() -> u64 {
+        NanoTimestamp::current_time().0 as u64
+    }
+
+    /// Check whether the node this channel is connected to advertises
+    /// all of the given service flags.
+    pub fn has_service(&self, flags: ServiceFlags) -> bool {
+        self.services() & flags == flags
+    }
+
+    /// Returns the commands this channel has message dispatchers for
+    pub async fn registered_commands(&self) -> Vec<String> {
+        self.message_subsystem.registered_commands().await
+    }
+
+    /// Snapshot the message types this channel has dispatchers for, so
+    /// they can be restored on another channel with
+    /// [`Channel::restore_subscriptions()`].
+    pub async fn subscription_snapshot(&self) -> SubscriptionSnapshot {
+        self.message_subsystem.snapshot().await
+    }
+
+    /// Add dispatchers for the message types of a snapshot taken from
+    /// another channel, e.g. when failing over to this one. Subscribers
+    /// of the old channel have to subscribe to this one again.
+    pub async fn restore_subscriptions(&self, snapshot: &SubscriptionSnapshot) {
+        self.message_subsystem.restore(snapshot).await
+    }
+
     /// Returns the inner [`MessageSubsystem`] reference
     pub fn message_subsystem(&self) -> &MessageSubsystem {
         &self.message_subsystem
@@ -475,15 +1600,48 @@ impl Channel {
         self.session.upgrade().unwrap()
     }
 
+    /// Returns the type of the session this channel belongs to, or 0
+    /// if the channel is detached.
     pub fn session_type_id(&self) -> SessionBitFlag {
-        let session = self.session();
-        session.type_id()
+        self.session.upgrade().map_or(0, |session| session.type_id())
     }
 
     pub(in crate::net) fn p2p(&self) -> P2pPtr {
         self.session().p2p()
     }
 
+    /// Reference the P2P instance, or `None` if the channel is detached
+    fn try_p2p(&self) -> Option<P2pPtr> {
+        self.session.upgrade().map(|session| session.p2p())
+    }
+
+    /// Whether the channel has no session, e.g. created with `new_detached()`
+    fn is_detached(&self) -> bool {
+        self.session.strong_count() == 0
+    }
+
+    /// Summary appended to a logged error when identical ones were
+    /// suppressed by the [`ErrorLogThrottle`].
+    fn suppressed_suffix(suppressed: usize) -> String {
+        if suppressed == 0 {
+            return String::new()
+        }
+
+        format!(" ({} identical errors suppressed)", suppressed)
+    }
+
+    /// Await `fut`, failing with `Error::ChannelTimeout` if the given
+    /// deadline elapses first.
+    async fn with_deadline<T>(
+        deadline: Option<Duration>,
+        fut: impl Future<Output = Result<T>>,
+    ) -> Result<T> {
+        match deadline {
+            Some(dur) => timeout(dur, fut).await.map_err(|_| Error::ChannelTimeout)?,
+            None => fut.await,
+        }
+    }
+
     fn is_eof_error(err: &Error) -> bool {
         match err {
             Error::Io(ioerr) => ioerr == &std::io::ErrorKind::UnexpectedEof,
@@ -492,8 +1650,1368 @@ impl Channel {
     }
 }
 
+/// Session type backing detached channels. It's never instantiated,
+/// the channel only holds an empty weak pointer to it.
+#[cfg(test)]
+struct DetachedSession;
+
+#[cfg(test)]
+#[async_trait]
+impl Session for DetachedSession {
+    fn p2p(&self) -> P2pPtr {
+        unreachable!("Detached channels have no P2P instance")
+    }
+
+    fn type_id(&self) -> SessionBitFlag {
+        0
+    }
+}
+
 impl fmt::Debug for Channel {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "<Channel addr='{}' id={}>", self.address(), self.info.id)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use darkfi_serial::{deserialize, deserialize_async, deserialize_partial, serialize_async};
+
+    use smol::{lock::RwLock as AsyncRwLock, stream::StreamExt};
+
+    use crate::{
+        error::HandshakeFailed,
+        impl_p2p_message,
+        net::Message,
+        system::{log_span::current_span, msleep},
+    };
+
+    use crate::net::{
+        message::{
+            PingMessage, PongMessage, MAX_COMMAND_LEN, SERVICE_ARCHIVAL, SERVICE_NONE,
+            SERVICE_RELAY, SERVICE_SEED,
+        },
+        protocol::ProtocolVersion,
+        stats,
+        transport::memory,
+        P2p, Settings,
+    };
+
+    /// Message with an arbitrarily sized payload
+    #[derive(Clone, Debug, SerialEncodable, SerialDecodable)]
+    struct BlobMessage {
+        data: Vec<u8>,
+    }
+    impl_p2p_message!(BlobMessage, "blob");
+
+    /// Gossip message opting into deduplication
+    #[derive(Clone, Debug, SerialEncodable, SerialDecodable)]
+    struct GossipMessage {
+        data: Vec<u8>,
+    }
+    impl_p2p_message!(GossipMessage, "gossip", dedup);
+
+    /// Build two channels connected to each other over an in-memory
+    /// duplex stream, attached to the inbound session of `p2p`.
+    async fn channel_pair(p2p: &P2pPtr) -> (ChannelPtr, ChannelPtr) {
+        channel_pair_in(p2p.session_inbound()).await
+    }
+
+    /// Same as `channel_pair()`, but attached to the given session
+    async fn channel_pair_in(session: Arc<dyn Session + Send + Sync>) -> (ChannelPtr, ChannelPtr) {
+        let session = Arc::downgrade(&session);
+        let url = Url::parse("tcp://127.0.0.1:26661").unwrap();
+
+        let (a, b) = memory::duplex(4096);
+        let chan_a = Channel::new(Box::new(a), None, url.clone(), session.clone()).await;
+        let chan_b = Channel::new(Box::new(b), None, url, session).await;
+
+        (chan_a, chan_b)
+    }
+
+    #[test]
+    fn deterministic_channel_ids() {
+        let executor = Arc::new(Executor::new());
+        let executor_ = executor.clone();
+        smol::block_on(executor.run(async move {
+            let p2p = P2p::new(Settings::default(), executor_.clone()).await.unwrap();
+            let (chan_a, chan_b) = channel_pair(&p2p).await;
+            assert_ne!(chan_a.info.id, chan_b.info.id);
+
+            let settings = Settings { deterministic_channel_ids: true, ..Default::default() };
+            let p2p = P2p::new(settings, executor_.clone()).await.unwrap();
+            let (chan_a, chan_b) = channel_pair(&p2p).await;
+            assert_eq!(chan_a.info.id, chan_b.info.id);
+
+            // Outbound channels to the same address get another id
+            let (chan_c, _) = channel_pair_in(p2p.session_outbound()).await;
+            assert_ne!(chan_c.info.id, chan_a.info.id);
+        }))
+    }
+
+    #[test]
+    fn channel_request_addrs() {
+        let executor = Arc::new(Executor::new());
+        let executor_ = executor.clone();
+        smol::block_on(executor.run(async move {
+            let (a, b) = memory::duplex(4096);
+            let chan_a = Channel::new_detached(Box::new(a)).await;
+            let chan_b = Channel::new_detached(Box::new(b)).await;
+            chan_a.clone().start(executor_.clone());
+            chan_b.clone().start(executor_.clone());
+
+            // The peer replies to the first request
+            let get_addrs_sub = chan_b.subscribe_msg::<message::GetAddrsMessage>().await.unwrap();
+            let peer = Url::parse("tcp://10.0.0.1:26661").unwrap();
+            let chan_b_ = chan_b.clone();
+            let peer_ = peer.clone();
+            executor_
+                .spawn(async move {
+                    get_addrs_sub.receive().await.unwrap();
+                    let addrs = message::AddrsMessage { addrs: vec![(peer_, 1000)] };
+                    chan_b_.send(&addrs).await.unwrap();
+                })
+                .detach();
+
+            let addrs = chan_a.request_addrs(Duration::from_secs(5)).await.unwrap();
+            assert_eq!(addrs, vec![peer]);
+
+            // Nobody answers the second one
+            let res = chan_a.request_addrs(Duration::from_millis(100)).await;
+            assert!(matches!(res, Err(Error::ConnectTimeout)));
+
+            // Both subscriptions were removed
+            let subs = chan_a.message_subsystem().subscriber_count::<message::AddrsMessage>().await;
+            assert_eq!(subs, 0);
+
+            chan_a.stop().await;
+            chan_b.stop().await;
+        }))
+    }
+
+    #[test]
+    fn detached_channel_roundtrip() {
+        let executor = Arc::new(Executor::new());
+        let executor_ = executor.clone();
+        smol::block_on(executor.run(async move {
+            let (a, b) = memory::duplex(4096);
+            let chan_a = Channel::new_detached(Box::new(a)).await;
+            let chan_b = Channel::new_detached(Box::new(b)).await;
+            assert_eq!(chan_a.session_type_id(), 0);
+
+            let ping_sub = chan_b.subscribe_msg::<PingMessage>().await.unwrap();
+            chan_a.clone().start(executor_.clone());
+            chan_b.clone().start(executor_.clone());
+
+            chan_a.send(&PingMessage { nonce: 42 }).await.unwrap();
+            assert_eq!(ping_sub.receive().await.unwrap().nonce, 42);
+
+            // Violations simply drop detached channels
+            let stop_sub = chan_b.subscribe_stop().await.unwrap();
+            chan_a.send(&BlobMessage { data: vec![] }).await.unwrap();
+            timeout(Duration::from_secs(5), stop_sub.receive()).await.unwrap();
+            assert!(chan_b.is_stopped());
+        }))
+    }
+
+    #[test]
+    fn channel_registered_commands() {
+        smol::block_on(async {
+            let (a, _b) = memory::duplex(4096);
+            let chan = Channel::new_detached(Box::new(a)).await;
+
+            let mut expected = vec![
+                message::VersionMessage::NAME,
+                message::VerackMessage::NAME,
+                PingMessage::NAME,
+                PongMessage::NAME,
+                message::GetAddrsMessage::NAME,
+                message::AddrsMessage::NAME,
+                message::SignedAddrsMessage::NAME,
+                message::AckMessage::NAME,
+            ];
+            expected.sort();
+            assert_eq!(chan.registered_commands().await, expected);
+
+            chan.message_subsystem().add_dispatch::<BlobMessage>().await;
+            assert!(chan.registered_commands().await.contains(&BlobMessage::NAME.to_string()));
+        });
+    }
+
+    #[test]
+    fn channel_subscription_snapshot() {
+        let executor = Arc::new(Executor::new());
+        let executor_ = executor.clone();
+        smol::block_on(executor.run(async move {
+            let (a, _) = memory::duplex(4096);
+            let old = Channel::new_detached(Box::new(a)).await;
+            old.message_subsystem().add_dispatch::<BlobMessage>().await;
+            let snapshot = old.subscription_snapshot().await;
+            assert!(snapshot.commands().contains(&BlobMessage::NAME));
+
+            // Fail over to a new channel, which doesn't know about blobs yet
+            let (a, b) = memory::duplex(4096);
+            let new = Channel::new_detached(Box::new(a)).await;
+            let peer = Channel::new_detached(Box::new(b)).await;
+            assert!(new.subscribe_msg::<BlobMessage>().await.is_err());
+
+            // Existing subscriptions survive the restore
+            let ping_sub = new.subscribe_msg::<PingMessage>().await.unwrap();
+            new.restore_subscriptions(&snapshot).await;
+            assert_eq!(new.registered_commands().await, old.registered_commands().await);
+
+            let blob_sub = new.subscribe_msg::<BlobMessage>().await.unwrap();
+            new.clone().start(executor_.clone());
+            peer.clone().start(executor_.clone());
+
+            peer.send(&PingMessage { nonce: 7 }).await.unwrap();
+            assert_eq!(ping_sub.receive().await.unwrap().nonce, 7);
+            peer.send(&BlobMessage { data: vec![1, 2, 3] }).await.unwrap();
+            assert_eq!(blob_sub.receive().await.unwrap().data, vec![1, 2, 3]);
+
+            // Restoring doesn't touch the old channel
+            assert_eq!(old.message_subsystem().subscriber_count::<BlobMessage>().await, 0);
+
+            new.stop().await;
+            peer.stop().await;
+        }))
+    }
+
+    #[test]
+    fn channel_message_hooks() {
+        let executor = Arc::new(Executor::new());
+        let executor_ = executor.clone();
+        smol::block_on(executor.run(async move {
+            let (a, b) = memory::duplex(4096);
+            let chan_a = Channel::new_detached(Box::new(a)).await;
+            let chan_b = Channel::new_detached(Box::new(b)).await;
+            let ping_sub = chan_a.subscribe_msg::<PingMessage>().await.unwrap();
+            let pong_sub = chan_b.subscribe_msg::<PongMessage>().await.unwrap();
+
+            let seen = Arc::new(SyncMutex::new(vec![]));
+            for (i, seen) in [seen.clone(), seen.clone()].into_iter().enumerate() {
+                chan_a.on_send({
+                    let seen = seen.clone();
+                    move |cmd, len| seen.lock().unwrap().push((i, "send", cmd.to_string(), len))
+                });
+                chan_a.on_recv(move |cmd, len| {
+                    seen.lock().unwrap().push((i, "recv", cmd.to_string(), len))
+                });
+            }
+
+            chan_a.clone().start(executor_.clone());
+            chan_b.clone().start(executor_.clone());
+
+            chan_a.send(&PongMessage { nonce: 1 }).await.unwrap();
+            pong_sub.receive().await.unwrap();
+            chan_b.send(&PingMessage { nonce: 2 }).await.unwrap();
+            ping_sub.receive().await.unwrap();
+
+            // Both hooks ran for both messages, in registration order
+            let len = serialize(&PongMessage { nonce: 1 }).len();
+            assert_eq!(
+                *seen.lock().unwrap(),
+                vec![
+                    (0, "send", "pong".to_string(), len),
+                    (1, "send", "pong".to_string(), len),
+                    (0, "recv", "ping".to_string(), len),
+                    (1, "recv", "ping".to_string(), len),
+                ]
+            );
+        }))
+    }
+
+    #[test]
+    fn channel_log_span() {
+        let executor = Arc::new(Executor::new());
+        let executor_ = executor.clone();
+        smol::block_on(executor.run(async move {
+            let (a, b) = memory::duplex(4096);
+            let chan_a = Channel::new_detached(Box::new(a)).await;
+            let chan_b = Channel::new_detached(Box::new(b)).await;
+            let ping_sub = chan_b.subscribe_msg::<PingMessage>().await.unwrap();
+
+            // Anything logged by the receive loop is within the span
+            let spans = Arc::new(SyncMutex::new(vec![]));
+            let spans_ = spans.clone();
+            chan_b.on_recv(move |_, _| spans_.lock().unwrap().push(current_span()));
+
+            chan_a.clone().start(executor_.clone());
+            chan_b.clone().start(executor_.clone());
+
+            chan_a.send(&PingMessage { nonce: 1 }).await.unwrap();
+            ping_sub.receive().await.unwrap();
+
+            let span = spans.lock().unwrap()[0].clone().unwrap();
+            assert!(span.contains(&format!("id={}", chan_b.info.id)));
+            assert!(span.contains("addr=memory://detached"));
+            assert_eq!(span, chan_b.log_span().to_string());
+
+            chan_a.stop().await;
+            chan_b.stop().await;
+        }))
+    }
+
+    #[test]
+    fn channel_payload_redaction() {
+        smol::block_on(async {
+            let (a, _b) = memory::duplex(4096);
+            let chan = Channel::new_detached(Box::new(a)).await;
+            let secret = [0xde, 0xad, 0xbe, 0xef];
+            let payload = [&[0x01, 0x02][..], &secret, &[0x03]].concat();
+
+            // Without redactors the payload is dumped as-is
+            let line = chan.payload_log_line("blob", &payload);
+            assert_eq!(line, "blob payload (7 bytes): 0102deadbeef03");
+
+            // Mask the secret wherever it appears in the payload
+            chan.add_payload_redactor(move |_, payload| {
+                for i in 0..payload.len().saturating_sub(secret.len() - 1) {
+                    if payload[i..i + secret.len()] == secret {
+                        payload[i..i + secret.len()].fill(0);
+                    }
+                }
+            });
+            let line = chan.payload_log_line("blob", &payload);
+            assert!(!line.contains("deadbeef"));
+            assert_eq!(line, "blob payload (7 bytes): 01020000000003");
+
+            // Logging is off by default and only redacts a copy
+            assert!(!chan.logs_payloads());
+            assert_eq!(&payload[2..6], &secret);
+        })
+    }
+
+    #[test]
+    fn p2p_traffic_stats() {
+        let executor = Arc::new(Executor::new());
+        let executor_ = executor.clone();
+        smol::block_on(executor.run(async move {
+            let p2p = P2p::new(Settings::default(), executor_.clone()).await.unwrap();
+            let (chan_a, chan_b) = channel_pair(&p2p).await;
+            let ping_sub = chan_b.subscribe_msg::<PingMessage>().await.unwrap();
+            let pong_sub = chan_a.subscribe_msg::<PongMessage>().await.unwrap();
+            // Buffer the payloads on one side, so both receive paths are counted
+            chan_a.on_recv(|_, _| {});
+            chan_a.clone().start(executor_.clone());
+            chan_b.clone().start(executor_.clone());
+
+            for nonce in 0..3 {
+                chan_a.send(&PingMessage { nonce }).await.unwrap();
+                ping_sub.receive().await.unwrap();
+                chan_b.send(&PongMessage { nonce }).await.unwrap();
+                pong_sub.receive().await.unwrap();
+            }
+
+            // Messages are counted once fully dispatched
+            let window = stats::TRAFFIC_STATS_WINDOW as f64;
+            while p2p.stats().recv_msgs_per_sec < 5.5 / window {
+                msleep(10).await;
+            }
+
+            // Ping and pong frames have the same size
+            let payload = serialize(&PingMessage { nonce: 0 });
+            let frame = MAGIC_BYTES.len() +
+                serialize(&"ping".to_string()).len() +
+                serialize(&payload).len();
+
+            // The traffic may be spread over two buckets
+            let close = |a: f64, b: f64| (a - b).abs() < 1e-9;
+            let stats = p2p.stats();
+            assert!(close(stats.send_msgs_per_sec, 6.0 / window));
+            assert!(close(stats.recv_msgs_per_sec, 6.0 / window));
+            assert!(close(stats.send_bytes_per_sec, (6 * frame) as f64 / window));
+            assert!(close(stats.recv_bytes_per_sec, (6 * frame) as f64 / window));
+
+            chan_a.stop().await;
+            chan_b.stop().await;
+        }))
+    }
+
+    #[test]
+    fn channel_ping_pong_over_duplex() {
+        let executor = Arc::new(Executor::new());
+        let executor_ = executor.clone();
+        smol::block_on(executor.run(async move {
+            let p2p = P2p::new(Settings::default(), executor_.clone()).await.unwrap();
+            let (chan_a, chan_b) = channel_pair(&p2p).await;
+            chan_a.clone().start(executor_.clone());
+            chan_b.clone().start(executor_.clone());
+
+            let ping_sub = chan_b.subscribe_msg::<PingMessage>().await.unwrap();
+            let pong_sub = chan_a.subscribe_msg::<PongMessage>().await.unwrap();
+
+            chan_a.send(&PingMessage { nonce: 1337 }).await.unwrap();
+            let ping = ping_sub.receive().await.unwrap();
+            assert_eq!(ping.nonce, 1337);
+
+            chan_b.send(&PongMessage { nonce: ping.nonce }).await.unwrap();
+            let pong = pong_sub.receive().await.unwrap();
+            assert_eq!(pong.nonce, 1337);
+
+            chan_a.stop().await;
+            chan_b.stop().await;
+            assert!(chan_a.is_stopped());
+            assert!(chan_b.is_stopped());
+        }))
+    }
+
+    #[test]
+    fn channel_services_handshake() {
+        let executor = Arc::new(Executor::new());
+        let executor_ = executor.clone();
+        smol::block_on(executor.run(async move {
+            let settings = Settings {
+                services: SERVICE_RELAY | SERVICE_ARCHIVAL,
+                channel_handshake_timeout: 5,
+                ..Default::default()
+            };
+            let p2p = P2p::new(settings, executor_.clone()).await.unwrap();
+            let (chan_a, chan_b) = channel_pair(&p2p).await;
+
+            let version_a = ProtocolVersion::new(chan_a.clone(), p2p.settings()).await;
+            let version_b = ProtocolVersion::new(chan_b.clone(), p2p.settings()).await;
+            chan_a.clone().start(executor_.clone());
+            chan_b.clone().start(executor_.clone());
+            assert!(!chan_a.has_feature(message::ADDRS_SINCE_FEATURE).await);
+
+            let (res_a, res_b) =
+                futures::join!(version_a.run(executor_.clone()), version_b.run(executor_.clone()));
+            res_a.unwrap();
+            res_b.unwrap();
+
+            for chan in [&chan_a, &chan_b] {
+                assert!(chan.has_feature(message::ADDRS_SINCE_FEATURE).await);
+                assert_eq!(chan.services(), SERVICE_RELAY | SERVICE_ARCHIVAL);
+                assert!(chan.has_service(SERVICE_RELAY));
+                assert!(chan.has_service(SERVICE_RELAY | SERVICE_ARCHIVAL));
+                assert!(!chan.has_service(SERVICE_SEED));
+            }
+
+            chan_a.stop().await;
+            chan_b.stop().await;
+        }))
+    }
+
+    #[test]
+    fn handshake_incompatible_version() {
+        let executor = Arc::new(Executor::new());
+        let executor_ = executor.clone();
+        smol::block_on(executor.run(async move {
+            let settings = Settings { channel_handshake_timeout: 5, ..Default::default() };
+            let p2p = P2p::new(settings.clone(), executor_.clone()).await.unwrap();
+            let (chan_a, chan_b) = channel_pair(&p2p).await;
+
+            let settings_b = Settings { app_version: semver::Version::new(99, 0, 0), ..settings };
+            let settings_b = Arc::new(AsyncRwLock::new(settings_b));
+
+            let version_a = ProtocolVersion::new(chan_a.clone(), p2p.settings()).await;
+            let version_b = ProtocolVersion::new(chan_b.clone(), settings_b).await;
+            chan_a.clone().start(executor_.clone());
+            chan_b.clone().start(executor_.clone());
+
+            let (res_a, _) =
+                futures::join!(version_a.run(executor_.clone()), version_b.run(executor_.clone()));
+            assert!(matches!(
+                res_a,
+                Err(Error::HandshakeFailed(HandshakeFailed::IncompatibleVersion(_, _)))
+            ));
+        }))
+    }
+
+    #[test]
+    fn handshake_timeout() {
+        let executor = Arc::new(Executor::new());
+        let executor_ = executor.clone();
+        smol::block_on(executor.run(async move {
+            let settings = Settings { channel_handshake_timeout: 1, ..Default::default() };
+            let p2p = P2p::new(settings, executor_.clone()).await.unwrap();
+            let (chan_a, chan_b) = channel_pair(&p2p).await;
+
+            // The peer never answers the version exchange
+            let version_a = ProtocolVersion::new(chan_a.clone(), p2p.settings()).await;
+            chan_a.clone().start(executor_.clone());
+            chan_b.clone().start(executor_.clone());
+
+            let res = version_a.run(executor_.clone()).await;
+            assert!(matches!(res, Err(Error::HandshakeFailed(HandshakeFailed::Timeout))));
+            chan_b.stop().await;
+        }))
+    }
+
+    #[test]
+    fn handshake_malformed_version() {
+        let executor = Arc::new(Executor::new());
+        let executor_ = executor.clone();
+        smol::block_on(executor.run(async move {
+            let settings = Settings { channel_handshake_timeout: 5, ..Default::default() };
+            let p2p = P2p::new(settings, executor_.clone()).await.unwrap();
+            let (chan_a, chan_b) = channel_pair(&p2p).await;
+
+            let version_a = ProtocolVersion::new(chan_a.clone(), p2p.settings()).await;
+            chan_a.clone().start(executor_.clone());
+            chan_b.clone().start(executor_.clone());
+
+            // A version frame whose payload can't be decoded
+            let version = SerializedMessage {
+                command: VersionMessage::NAME.to_string(),
+                payload: vec![0xff],
+            };
+            chan_b.send_serialized(&version).await.unwrap();
+
+            let res = version_a.run(executor_.clone()).await;
+            assert!(matches!(
+                res,
+                Err(Error::HandshakeFailed(HandshakeFailed::MalformedVersion(_)))
+            ));
+            chan_b.stop().await;
+        }))
+    }
+
+    #[test]
+    fn handshake_clock_skew() {
+        let executor = Arc::new(Executor::new());
+        let executor_ = executor.clone();
+        smol::block_on(executor.run(async move {
+            let settings = Settings {
+                channel_handshake_timeout: 5,
+                channel_max_clock_skew: 60,
+                ..Default::default()
+            };
+            let p2p = P2p::new(settings, executor_.clone()).await.unwrap();
+            let (chan_a, chan_b) = channel_pair(&p2p).await;
+
+            let version_a = ProtocolVersion::new(chan_a.clone(), p2p.settings()).await;
+            chan_a.clone().start(executor_.clone());
+            chan_b.clone().start(executor_.clone());
+
+            let version = VersionMessage {
+                node_id: String::new(),
+                version: semver::Version::new(0, 4, 1),
+                timestamp: UNIX_EPOCH.elapsed().unwrap().as_secs() + 86400,
+                connect_recv_addr: Url::parse("tcp://127.0.0.1:26661").unwrap(),
+                resolve_recv_addr: None,
+                ext_send_addr: vec![],
+                features: vec![],
+                services: SERVICE_NONE,
+            };
+            chan_b.send(&version).await.unwrap();
+
+            let res = version_a.run(executor_.clone()).await;
+            assert!(
+                matches!(res, Err(Error::HandshakeFailed(HandshakeFailed::ClockSkew(s))) if s > 60)
+            );
+            chan_b.stop().await;
+        }))
+    }
+
+    #[test]
+    fn error_log_throttle_dedup() {
+        let throttle = ErrorLogThrottle::new(Duration::from_secs(60));
+        let peer = Url::parse("tcp://127.0.0.1:26661").unwrap();
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+
+        // First occurrence gets logged, identical ones get suppressed.
+        assert_eq!(throttle.check_at(&peer, "Connection reset", at(0)), Some(0));
+        assert_eq!(throttle.check_at(&peer, "Connection reset", at(1)), None);
+        assert_eq!(throttle.check_at(&peer, "Connection reset", at(59)), None);
+
+        // Different errors are tracked separately.
+        assert_eq!(throttle.check_at(&peer, "Broken pipe", at(30)), Some(0));
+        assert_eq!(throttle.check_at(&peer, "Broken pipe", at(31)), None);
+
+        // Once the window elapses, the error is logged along with the
+        // count of suppressed occurrences.
+        assert_eq!(throttle.check_at(&peer, "Connection reset", at(60)), Some(2));
+        assert_eq!(throttle.check_at(&peer, "Connection reset", at(61)), None);
+
+        // Expired errors that didn't come up again were flushed and
+        // dropped, so varied errors don't pile up.
+        assert_eq!(throttle.check_at(&peer, "Connection reset", at(120)), Some(1));
+        assert_eq!(throttle.entries.lock().unwrap().len(), 1);
+        assert_eq!(Channel::suppressed_suffix(2), " (2 identical errors suppressed)");
+        assert_eq!(Channel::suppressed_suffix(0), "");
+    }
+
+    #[test]
+    fn channel_task_name() {
+        let executor = Arc::new(Executor::new());
+        let executor_ = executor.clone();
+        smol::block_on(executor.run(async move {
+            let p2p = P2p::new(Settings::default(), executor_.clone()).await.unwrap();
+            let (chan_a, _chan_b) = channel_pair(&p2p).await;
+            assert!(chan_a.task_name().contains(&chan_a.info.id.to_string()));
+            assert!(chan_a.task_name().contains("127.0.0.1:26661"));
+
+            let session: Arc<dyn Session + Send + Sync> = p2p.session_inbound();
+            let (stream, _) = memory::duplex(64);
+            let chan = Channel::new_with_task_name(
+                Box::new(stream),
+                None,
+                chan_a.connect_addr().clone(),
+                Arc::downgrade(&session),
+                Some("my-task".to_string()),
+            )
+            .await;
+            assert_eq!(chan.task_name(), "my-task");
+        }))
+    }
+
+    #[test]
+    fn channel_close_finishes_inflight_frame() {
+        let executor = Arc::new(Executor::new());
+        let executor_ = executor.clone();
+        smol::block_on(executor.run(async move {
+            let p2p = P2p::new(Settings::default(), executor_.clone()).await.unwrap();
+            let (chan_a, chan_b) = channel_pair(&p2p).await;
+            chan_a.clone().start(executor_.clone());
+
+            // The payload is much larger than the duplex buffer, so the send
+            // stays in flight until the other end starts reading.
+            let blob = BlobMessage { data: (0..65536).map(|i| i as u8).collect() };
+            let chan_a_ = chan_a.clone();
+            let blob_ = blob.clone();
+            let send_task = executor_.spawn(async move { chan_a_.send(&blob_).await });
+            msleep(50).await;
+
+            let chan_a_ = chan_a.clone();
+            let close_task = executor_.spawn(async move { chan_a_.close().await });
+            msleep(50).await;
+            assert!(!chan_a.is_stopped());
+
+            // Now let the other end drain the stream
+            chan_b.message_subsystem().add_dispatch::<BlobMessage>().await;
+            let blob_sub = chan_b.subscribe_msg::<BlobMessage>().await.unwrap();
+            chan_b.clone().start(executor_.clone());
+
+            let received = blob_sub.receive().await.unwrap();
+            assert_eq!(received.data, blob.data);
+
+            send_task.await.unwrap();
+            close_task.await;
+            assert!(chan_a.is_stopped());
+            assert!(chan_a.send(&PingMessage { nonce: 0 }).await.is_err());
+
+            chan_b.stop().await;
+        }))
+    }
+
+    #[test]
+    fn channel_message_dedup() {
+        let executor = Arc::new(Executor::new());
+        let executor_ = executor.clone();
+        smol::block_on(executor.run(async move {
+            let settings = Settings { message_dedup: true, ..Default::default() };
+            let p2p = P2p::new(settings, executor_.clone()).await.unwrap();
+
+            // The same gossip arrives over two channels
+            let (chan_a, chan_b) = channel_pair(&p2p).await;
+            let (chan_c, chan_d) = channel_pair(&p2p).await;
+            for chan in [&chan_b, &chan_d] {
+                chan.message_subsystem().add_dispatch::<GossipMessage>().await;
+                chan.message_subsystem().add_dispatch::<BlobMessage>().await;
+            }
+            let sub_b = chan_b.subscribe_msg::<GossipMessage>().await.unwrap();
+            let sub_d = chan_d.subscribe_msg::<GossipMessage>().await.unwrap();
+            for chan in [&chan_a, &chan_b, &chan_c, &chan_d] {
+                chan.clone().start(executor_.clone());
+            }
+
+            let gossip = GossipMessage { data: vec![1, 2, 3] };
+            chan_a.send(&gossip).await.unwrap();
+            assert_eq!(sub_b.receive().await.unwrap().data, gossip.data);
+            chan_c.send(&gossip).await.unwrap();
+
+            // Only a new payload makes it through on the second channel
+            let other = GossipMessage { data: vec![4, 5, 6] };
+            chan_c.send(&other).await.unwrap();
+            assert_eq!(sub_d.receive().await.unwrap().data, other.data);
+
+            // Messages that didn't opt in are never deduplicated
+            let blob_sub = chan_b.subscribe_msg::<BlobMessage>().await.unwrap();
+            let pong_sub = chan_b.subscribe_msg::<PongMessage>().await.unwrap();
+            for _ in 0..2 {
+                chan_a.send(&BlobMessage { data: vec![7] }).await.unwrap();
+                chan_a.send(&PongMessage { nonce: 7 }).await.unwrap();
+            }
+            for _ in 0..2 {
+                blob_sub.receive().await.unwrap();
+                pong_sub.receive().await.unwrap();
+            }
+        }))
+    }
+
+    #[test]
+    fn seen_cache_eviction() {
+        let cache = SeenCache::new(true, 2, Duration::from_millis(100));
+        assert!(cache.insert("gossip", &[1]));
+        assert!(cache.insert("gossip", &[2]));
+        assert!(!cache.insert("gossip", &[1]));
+        // The hash covers the command too
+        assert!(cache.insert("other", &[2]));
+
+        // The full cache evicted the oldest entry
+        assert!(cache.insert("gossip", &[1]));
+        assert!(!cache.insert("gossip", &[1]));
+        assert!(!cache.insert("other", &[2]));
+
+        // Expired entries are seen again
+        std::thread::sleep(Duration::from_millis(150));
+        assert!(cache.insert("other", &[2]));
+
+        cache.set_enabled(false);
+        assert!(cache.insert("other", &[2]));
+    }
+
+    #[test]
+    fn channel_pause_resume() {
+        let executor = Arc::new(Executor::new());
+        let executor_ = executor.clone();
+        smol::block_on(executor.run(async move {
+            let p2p = P2p::new(Settings::default(), executor_.clone()).await.unwrap();
+            let (chan_a, chan_b) = channel_pair(&p2p).await;
+            chan_b.message_subsystem().add_dispatch::<BlobMessage>().await;
+            let blob_sub = chan_b.subscribe_msg::<BlobMessage>().await.unwrap();
+            chan_a.clone().start(executor_.clone());
+            chan_b.clone().start(executor_.clone());
+
+            let blob = BlobMessage { data: vec![1, 2, 3] };
+            chan_a.send(&blob).await.unwrap();
+            assert_eq!(blob_sub.receive().await.unwrap().data, blob.data);
+
+            // Nothing is read while paused, so a payload larger than the
+            // duplex buffer backs up on the sender.
+            chan_b.pause();
+            assert!(chan_b.is_paused());
+            let large = BlobMessage { data: (0..65536).map(|i| i as u8).collect() };
+            let chan_a_ = chan_a.clone();
+            let large_ = large.clone();
+            let send_task = executor_.spawn(async move { chan_a_.send(&large_).await });
+            assert!(timeout(Duration::from_millis(100), blob_sub.receive()).await.is_err());
+            assert!(!send_task.is_finished());
+
+            // Resuming delivers the backlog
+            chan_b.resume();
+            assert!(!chan_b.is_paused());
+            assert_eq!(blob_sub.receive().await.unwrap().data, large.data);
+            send_task.await.unwrap();
+
+            chan_a.send(&blob).await.unwrap();
+            assert_eq!(blob_sub.receive().await.unwrap().data, blob.data);
+
+            // A paused channel still stops cleanly
+            chan_b.pause();
+            let stop_sub = chan_b.subscribe_stop().await.unwrap();
+            chan_b.stop().await;
+            timeout(Duration::from_secs(5), stop_sub.receive()).await.unwrap();
+            assert!(chan_b.is_stopped());
+        }))
+    }
+
+    #[test]
+    fn read_command_rejects_oversized_length() {
+        let executor = Arc::new(Executor::new());
+        let executor_ = executor.clone();
+        smol::block_on(executor.run(async move {
+            let p2p = P2p::new(Settings::default(), executor_.clone()).await.unwrap();
+            let (chan, _) = channel_pair(&p2p).await;
+
+            let frame = |len: u64, command: &[u8]| {
+                let mut bytes = MAGIC_BYTES.to_vec();
+                bytes.extend(darkfi_serial::serialize(&VarInt(len)));
+                bytes.extend(command);
+                bytes
+            };
+
+            let bytes = frame(4, b"ping");
+            assert_eq!(chan.read_command(&mut &bytes[..]).await.unwrap(), "ping");
+
+            let command = vec![b'a'; MAX_COMMAND_LEN as usize];
+            let bytes = frame(MAX_COMMAND_LEN, &command);
+            assert_eq!(chan.read_command(&mut &bytes[..]).await.unwrap().len(), command.len());
+
+            let command = vec![b'a'; MAX_COMMAND_LEN as usize + 1];
+            let bytes = frame(MAX_COMMAND_LEN + 1, &command);
+            let res = chan.read_command(&mut &bytes[..]).await;
+            assert!(matches!(res, Err(Error::MalformedPacket)));
+
+            // A huge length is rejected without trying to read it
+            let bytes = frame(u64::MAX, b"");
+            let res = chan.read_command(&mut &bytes[..]).await;
+            assert!(matches!(res, Err(Error::MalformedPacket)));
+        }))
+    }
+
+    #[test]
+    fn read_frames_delivered_byte_by_byte() {
+        smol::block_on(async {
+            let (detached, _) = memory::duplex(64);
+            let chan = Channel::new_detached(Box::new(detached)).await;
+            let ping_sub = chan.subscribe_msg::<PingMessage>().await.unwrap();
+
+            // A single byte buffer makes every read return at most one byte
+            let (a, mut b) = memory::duplex(1);
+            let writer = smol::spawn(async move {
+                for nonce in [1u16, 2] {
+                    let msg = SerializedMessage::new(&PingMessage { nonce }).await;
+                    let mut frame = MAGIC_BYTES.to_vec();
+                    frame.extend(serialize_async(&msg.command).await);
+                    frame.extend(serialize_async(&msg.payload).await);
+                    b.write_all(&frame).await.unwrap();
+                }
+                b
+            });
+
+            let stream: Box<dyn PtStream> = Box::new(a);
+            let (mut reader, _writer) = io::split(stream);
+
+            assert_eq!(chan.read_command(&mut reader).await.unwrap(), PingMessage::NAME);
+            let payload = Channel::read_payload(&mut reader, 0).await.unwrap();
+            assert_eq!(payload, 1u16.to_le_bytes());
+
+            let command = chan.read_command(&mut reader).await.unwrap();
+            chan.message_subsystem.notify(&command, &mut reader, 0).await.unwrap();
+            assert_eq!(ping_sub.receive().await.unwrap().nonce, 2);
+
+            // A real EOF mid-frame is still an error
+            let mut b = writer.await;
+            b.write_all(&MAGIC_BYTES[..2]).await.unwrap();
+            drop(b);
+            assert!(chan.read_command(&mut reader).await.is_err());
+        })
+    }
+
+    #[cfg(target_pointer_width = "32")]
+    #[test]
+    fn read_payload_rejects_length_over_usize() {
+        smol::block_on(async {
+            let (a, mut b) = memory::duplex(64);
+            b.write_all(&darkfi_serial::serialize(&VarInt(u32::MAX as u64 + 1))).await.unwrap();
+
+            let stream: Box<dyn PtStream> = Box::new(a);
+            let (mut reader, _writer) = io::split(stream);
+            let res = Channel::read_payload(&mut reader, 0).await;
+            assert!(matches!(res, Err(Error::MalformedPacket)));
+        })
+    }
+
+    #[test]
+    fn channel_last_activity() {
+        let executor = Arc::new(Executor::new());
+        let executor_ = executor.clone();
+        smol::block_on(executor.run(async move {
+            let p2p = P2p::new(Settings::default(), executor_.clone()).await.unwrap();
+            let (chan_a, chan_b) = channel_pair(&p2p).await;
+            chan_a.clone().start(executor_.clone());
+            chan_b.clone().start(executor_.clone());
+
+            let created_a = chan_a.last_activity().0;
+            let created_b = chan_b.last_activity().0;
+            assert!(created_a > 0 && created_b > 0);
+
+            let ping_sub = chan_b.subscribe_msg::<PingMessage>().await.unwrap();
+            msleep(10).await;
+            chan_a.send(&PingMessage { nonce: 7 }).await.unwrap();
+            assert!(chan_a.last_activity().0 > created_a);
+
+            assert_eq!(ping_sub.receive().await.unwrap().nonce, 7);
+            assert!(chan_b.last_activity().0 > created_b);
+
+            chan_a.stop().await;
+            chan_b.stop().await;
+        }))
+    }
+
+    proptest::proptest! {
+        #[test]
+        fn frame_encode_decode_roundtrip(
+            command in "[a-z]{1,64}",
+            payload in proptest::collection::vec(proptest::num::u8::ANY, 0..4096),
+        ) {
+            let frame = Frame::new(command, payload);
+            let bytes = serialize(&frame);
+            proptest::prop_assert_eq!(deserialize::<Frame>(&bytes).unwrap(), frame.clone());
+
+            // The async encoding used by channels is the same
+            let mut async_bytes = vec![];
+            smol::block_on(frame.encode_async(&mut async_bytes)).unwrap();
+            proptest::prop_assert_eq!(&async_bytes, &bytes);
+            let decoded = smol::block_on(Frame::decode_async(&mut &bytes[..])).unwrap();
+            proptest::prop_assert_eq!(decoded, frame);
+
+            // Truncated frames are an error
+            proptest::prop_assert!(deserialize::<Frame>(&bytes[..bytes.len() - 1]).is_err());
+        }
+
+        #[test]
+        fn frame_decode_garbage_never_panics(
+            garbage in proptest::collection::vec(proptest::num::u8::ANY, 0..512),
+        ) {
+            let _ = deserialize_partial::<Frame>(&garbage);
+
+            // Also get past the magic check to exercise the length decoding
+            let mut bytes = MAGIC_BYTES.to_vec();
+            bytes.extend(&garbage);
+            let _ = deserialize_partial::<Frame>(&bytes);
+        }
+    }
+
+    /// Tags every peer with the same location
+    struct StubResolver;
+
+    impl PeerResolver for StubResolver {
+        fn resolve(&self, _addr: &Url) -> PeerLocation {
+            PeerLocation { asn: Some(64496), country: Some("IS".to_string()) }
+        }
+    }
+
+    #[test]
+    fn channel_info_peer_location() {
+        let executor = Arc::new(Executor::new());
+        let executor_ = executor.clone();
+        smol::block_on(executor.run(async move {
+            let p2p = P2p::new(Settings::default(), executor_.clone()).await.unwrap();
+
+            // Without a resolver the location stays unknown
+            let (chan_a, _chan_b) = channel_pair(&p2p).await;
+            assert_eq!(chan_a.info.asn, None);
+            assert_eq!(chan_a.info.country, None);
+
+            p2p.set_peer_resolver(Some(Arc::new(StubResolver)));
+            let (chan_a, _chan_b) = channel_pair(&p2p).await;
+            assert_eq!(chan_a.info.asn, Some(64496));
+            assert_eq!(chan_a.info.country.as_deref(), Some("IS"));
+
+            // The location doesn't change the serialized info
+            let mut untagged = chan_a.info.clone();
+            untagged.asn = None;
+            untagged.country = None;
+            let bytes = serialize_async(&chan_a.info).await;
+            assert_eq!(bytes, serialize_async(&untagged).await);
+            let decoded: ChannelInfo = deserialize_async(&bytes).await.unwrap();
+            assert_eq!(decoded.asn, None);
+            assert_eq!(decoded.country, None);
+
+            p2p.set_peer_resolver(None);
+            let (chan_a, _chan_b) = channel_pair(&p2p).await;
+            assert_eq!(chan_a.info.asn, None);
+        }))
+    }
+
+    #[test]
+    fn channel_message_stream() {
+        let executor = Arc::new(Executor::new());
+        let executor_ = executor.clone();
+        smol::block_on(executor.run(async move {
+            let p2p = P2p::new(Settings::default(), executor_.clone()).await.unwrap();
+            let (chan_a, chan_b) = channel_pair(&p2p).await;
+            let mut stream = chan_b.message_stream();
+            let ping_sub = chan_b.subscribe_msg::<PingMessage>().await.unwrap();
+            chan_a.clone().start(executor_.clone());
+            chan_b.clone().start(executor_.clone());
+
+            chan_a.send(&PingMessage { nonce: 1 }).await.unwrap();
+            chan_a.send(&PongMessage { nonce: 2 }).await.unwrap();
+            chan_a.send(&PingMessage { nonce: 3 }).await.unwrap();
+
+            let mut received = vec![];
+            for _ in 0..3 {
+                let (command, payload) = stream.next().await.unwrap();
+                let nonce = match command.as_str() {
+                    "ping" => deserialize_async::<PingMessage>(&payload).await.unwrap().nonce,
+                    "pong" => deserialize_async::<PongMessage>(&payload).await.unwrap().nonce,
+                    _ => panic!("Unexpected command {}", command),
+                };
+                received.push((command, nonce));
+            }
+            assert_eq!(
+                received,
+                vec![("ping".to_string(), 1), ("pong".to_string(), 2), ("ping".to_string(), 3)]
+            );
+
+            // Typed subscribers still get their messages
+            assert_eq!(ping_sub.receive().await.unwrap().nonce, 1);
+            assert_eq!(ping_sub.receive().await.unwrap().nonce, 3);
+
+            // The stream ends once the channel stops
+            chan_b.stop().await;
+            assert!(stream.next().await.is_none());
+            chan_a.stop().await;
+        }))
+    }
+
+    #[test]
+    fn channel_try_send_skips_busy_writer() {
+        let executor = Arc::new(Executor::new());
+        let executor_ = executor.clone();
+        smol::block_on(executor.run(async move {
+            let p2p = P2p::new(Settings::default(), executor_.clone()).await.unwrap();
+            let (chan_a, chan_b) = channel_pair(&p2p).await;
+            chan_a.clone().start(executor_.clone());
+            chan_b.clone().start(executor_.clone());
+            let ping_sub = chan_b.subscribe_msg::<PingMessage>().await.unwrap();
+
+            // Another send holding the writer makes try_send bail out
+            let writer = chan_a.writer.lock().await;
+            assert!(!chan_a.try_send(&PingMessage { nonce: 1 }).await.unwrap());
+            drop(writer);
+
+            assert!(chan_a.try_send(&PingMessage { nonce: 2 }).await.unwrap());
+            let ping = ping_sub.receive().await.unwrap();
+            assert_eq!(ping.nonce, 2);
+
+            chan_a.stop().await;
+            chan_b.stop().await;
+            assert!(chan_a.try_send(&PingMessage { nonce: 3 }).await.is_err());
+        }))
+    }
+
+    #[test]
+    fn channel_rotate_stream() {
+        let executor = Arc::new(Executor::new());
+        let executor_ = executor.clone();
+        smol::block_on(executor.run(async move {
+            let p2p = P2p::new(Settings::default(), executor_.clone()).await.unwrap();
+            let (chan_a, chan_b) = channel_pair(&p2p).await;
+            chan_a.clone().start(executor_.clone());
+            chan_b.clone().start(executor_.clone());
+            let ping_sub = chan_b.subscribe_msg::<PingMessage>().await.unwrap();
+
+            chan_a.send(&PingMessage { nonce: 1 }).await.unwrap();
+            assert_eq!(ping_sub.receive().await.unwrap().nonce, 1);
+
+            // Migrate both ends onto a fresh duplex
+            let (a, b) = memory::duplex(4096);
+            chan_a.rotate_stream(Box::new(a)).await.unwrap();
+            chan_b.rotate_stream(Box::new(b)).await.unwrap();
+
+            // The existing subscription keeps receiving over the new stream
+            chan_a.send(&PingMessage { nonce: 2 }).await.unwrap();
+            assert_eq!(ping_sub.receive().await.unwrap().nonce, 2);
+
+            chan_a.stop().await;
+            chan_b.stop().await;
+            let (a, _b) = memory::duplex(64);
+            assert!(matches!(chan_a.rotate_stream(Box::new(a)).await, Err(Error::ChannelStopped)));
+        }))
+    }
+
+    #[test]
+    fn channel_send_limit_nonblocking() {
+        let executor = Arc::new(Executor::new());
+        let executor_ = executor.clone();
+        smol::block_on(executor.run(async move {
+            let p2p = P2p::new(Settings::default(), executor_.clone()).await.unwrap();
+            let (chan_a, chan_b) = channel_pair(&p2p).await;
+            chan_a.clone().start(executor_.clone());
+            chan_b.clone().start(executor_.clone());
+            let ping_sub = chan_b.subscribe_msg::<PingMessage>().await.unwrap();
+            chan_a.set_send_limit(Some(1), true);
+
+            // Park a send on the writer so it keeps holding the only slot
+            let writer = chan_a.writer.lock().await;
+            let chan_a_ = chan_a.clone();
+            let pending =
+                executor_.spawn(async move { chan_a_.send(&PingMessage { nonce: 1 }).await });
+            while chan_a
+                .send_limit
+                .lock()
+                .unwrap()
+                .as_ref()
+                .unwrap()
+                .permits
+                .try_acquire()
+                .is_some()
+            {
+                msleep(1).await;
+            }
+
+            let res = chan_a.send(&PingMessage { nonce: 2 }).await;
+            assert!(matches!(res, Err(Error::ChannelBusy)));
+
+            // The slot frees up once the pending send goes through
+            drop(writer);
+            pending.await.unwrap();
+            chan_a.send(&PingMessage { nonce: 3 }).await.unwrap();
+            assert_eq!(ping_sub.receive().await.unwrap().nonce, 1);
+            assert_eq!(ping_sub.receive().await.unwrap().nonce, 3);
+
+            chan_a.stop().await;
+            chan_b.stop().await;
+        }))
+    }
+
+    #[test]
+    fn channel_probe_stops_unresponsive_peer() {
+        let executor = Arc::new(Executor::new());
+        let executor_ = executor.clone();
+        smol::block_on(executor.run(async move {
+            let settings = Settings {
+                channel_probe_interval: 1,
+                channel_probe_timeout: 1,
+                ..Default::default()
+            };
+            let p2p = P2p::new(settings, executor_.clone()).await.unwrap();
+            let (chan_a, chan_b) = channel_pair(&p2p).await;
+            let ping_sub = chan_b.subscribe_msg::<PingMessage>().await.unwrap();
+            let stop_sub = chan_a.subscribe_stop().await.unwrap();
+            chan_a.clone().start(executor_.clone());
+            chan_b.clone().start(executor_.clone());
+
+            // The peer answers the first probe, so the channel stays open
+            let ping = ping_sub.receive().await.unwrap();
+            assert!(chan_a.is_probe_nonce(ping.nonce));
+            chan_b.send(&PongMessage { nonce: ping.nonce }).await.unwrap();
+            assert!(!chan_a.is_stopped());
+
+            // Then it goes silent and the next probe drops the channel
+            assert!(timeout(Duration::from_secs(10), stop_sub.receive()).await.is_ok());
+            assert!(chan_a.is_stopped());
+        }))
+    }
+
+    /// Policy warning on a peer's first violation and disconnecting it
+    /// on any further one
+    #[derive(Default)]
+    struct WarnOncePolicy {
+        violations: std::sync::atomic::AtomicUsize,
+    }
+
+    impl ViolationPolicy for WarnOncePolicy {
+        fn on_violation(&self, _peer: &Url, _kind: ViolationKind) -> BanDecision {
+            match self.violations.fetch_add(1, SeqCst) {
+                0 => BanDecision::Warn,
+                _ => BanDecision::Disconnect,
+            }
+        }
+    }
+
+    #[test]
+    fn channel_custom_violation_policy() {
+        let executor = Arc::new(Executor::new());
+        let executor_ = executor.clone();
+        smol::block_on(executor.run(async move {
+            let p2p = P2p::new(Settings::default(), executor_.clone()).await.unwrap();
+            let policy = Arc::new(WarnOncePolicy::default());
+            p2p.set_violation_policy(Some(policy.clone()));
+
+            let (chan_a, chan_b) = channel_pair(&p2p).await;
+            let ping_sub = chan_b.subscribe_msg::<PingMessage>().await.unwrap();
+            let stop_sub = chan_b.subscribe_stop().await.unwrap();
+            chan_a.clone().start(executor_.clone());
+            chan_b.clone().start(executor_.clone());
+
+            // Nobody dispatches blobs on the other end. The first one is
+            // only warned about and skipped, so later messages still arrive.
+            chan_a.send(&BlobMessage { data: vec![0; 128] }).await.unwrap();
+            chan_a.send(&PingMessage { nonce: 1 }).await.unwrap();
+            assert_eq!(ping_sub.receive().await.unwrap().nonce, 1);
+            assert!(!chan_b.is_stopped());
+
+            // The second violation disconnects the peer
+            chan_a.send(&BlobMessage { data: vec![] }).await.unwrap();
+            timeout(Duration::from_secs(5), stop_sub.receive()).await.unwrap();
+            assert!(chan_b.is_stopped());
+            assert_eq!(policy.violations.load(SeqCst), 2);
+        }))
+    }
+
+    #[test]
+    fn channel_rejects_oversized_payload() {
+        let executor = Arc::new(Executor::new());
+        let executor_ = executor.clone();
+        smol::block_on(executor.run(async move {
+            let settings = Settings { max_payload_len: 1024, ..Default::default() };
+            let p2p = P2p::new(settings, executor_.clone()).await.unwrap();
+            let policy = Arc::new(WarnOncePolicy::default());
+            p2p.set_violation_policy(Some(policy.clone()));
+
+            let session: Arc<dyn Session + Send + Sync> = p2p.session_inbound();
+            let url = Url::parse("tcp://127.0.0.1:26661").unwrap();
+            let (a, mut b) = memory::duplex(4096);
+            let chan = Channel::new(Box::new(a), None, url, Arc::downgrade(&session)).await;
+            let stop_sub = chan.subscribe_stop().await.unwrap();
+            chan.clone().start(executor_.clone());
+
+            // Only the header of a ping declaring a payload over the limit
+            let mut bytes = MAGIC_BYTES.to_vec();
+            bytes.extend(serialize_async(&PingMessage::NAME.to_string()).await);
+            bytes.extend(serialize_async(&VarInt(1025)).await);
+            b.write_all(&bytes).await.unwrap();
+
+            // The payload is never read, so even a warning stops the channel
+            timeout(Duration::from_secs(5), stop_sub.receive()).await.unwrap();
+            assert!(chan.is_stopped());
+            assert_eq!(policy.violations.load(SeqCst), 1);
+        }))
+    }
+
+    #[test]
+    fn channel_violation_bans_peer() {
+        let executor = Arc::new(Executor::new());
+        let executor_ = executor.clone();
+        smol::block_on(executor.run(async move {
+            let settings = Settings { ban_policy: BanPolicy::Strict, ..Default::default() };
+            let p2p = P2p::new(settings, executor_.clone()).await.unwrap();
+            let hosts = p2p.hosts();
+            let ban_sub = hosts.subscribe_ban().await;
+            assert_eq!(hosts.total_bans(), 0);
+
+            let session: Arc<dyn Session + Send + Sync> = p2p.session_outbound();
+            let peers = [
+                Url::parse("tcp+tls://nietzsche.king:333").unwrap(),
+                Url::parse("tcp+tls://agorism.xyz:26661").unwrap(),
+            ];
+
+            for peer in &peers {
+                let (a, mut b) = memory::duplex(4096);
+                let chan =
+                    Channel::new(Box::new(a), None, peer.clone(), Arc::downgrade(&session)).await;
+                let stop_sub = chan.subscribe_stop().await.unwrap();
+                chan.clone().start(executor_.clone());
+
+                // A ping too short to decode is malformed
+                let mut bytes = MAGIC_BYTES.to_vec();
+                bytes.extend(serialize_async(&PingMessage::NAME.to_string()).await);
+                bytes.extend(serialize_async(&vec![0u8]).await);
+                b.write_all(&bytes).await.unwrap();
+
+                timeout(Duration::from_secs(5), stop_sub.receive()).await.unwrap();
+                assert!(hosts.container.contains(HostColor::Black as usize, peer));
+            }
+
+            assert_eq!(hosts.total_bans(), 2);
+            for peer in peers {
+                let event = ban_sub.receive().await;
+                assert_eq!(event.peer, peer);
+                assert_eq!(event.reason, Some(ViolationKind::MalformedMessage));
+            }
+        }))
+    }
+
+    #[test]
+    fn channel_skips_trailing_payload_bytes() {
+        let executor = Arc::new(Executor::new());
+        let executor_ = executor.clone();
+        smol::block_on(executor.run(async move {
+            let p2p = P2p::new(Settings::default(), executor_.clone()).await.unwrap();
+            let policy = Arc::new(WarnOncePolicy::default());
+            p2p.set_violation_policy(Some(policy.clone()));
+
+            let session: Arc<dyn Session + Send + Sync> = p2p.session_inbound();
+            let url = Url::parse("tcp://127.0.0.1:26661").unwrap();
+            let (a, mut b) = memory::duplex(4096);
+            let chan = Channel::new(Box::new(a), None, url, Arc::downgrade(&session)).await;
+            let ping_sub = chan.subscribe_msg::<PingMessage>().await.unwrap();
+            chan.clone().start(executor_.clone());
+
+            // Frame a ping carrying the given raw payload
+            let ping = |payload: &[u8]| {
+                let mut frame = MAGIC_BYTES.to_vec();
+                frame.extend(serialize(&PingMessage::NAME.to_string()));
+                frame.extend(serialize(&payload.to_vec()));
+                frame
+            };
+
+            // A ping followed by fields we don't know about still arrives,
+            // and isn't counted against the peer
+            b.write_all(&ping(&[1, 0, 0xff, 0xff, 0xff])).await.unwrap();
+            assert_eq!(ping_sub.receive().await.unwrap().nonce, 1);
+            assert_eq!(policy.violations.load(SeqCst), 0);
+
+            // A ping too short to decode is a violation, but the stream
+            // stays in sync for the next one
+            b.write_all(&ping(&[2])).await.unwrap();
+            b.write_all(&ping(&[3, 0])).await.unwrap();
+            assert_eq!(ping_sub.receive().await.unwrap().nonce, 3);
+            assert_eq!(policy.violations.load(SeqCst), 1);
+            assert!(!chan.is_stopped());
+        }))
+    }
+
+    #[test]
+    fn channel_unknown_command_per_session() {
+        let executor = Arc::new(Executor::new());
+        let executor_ = executor.clone();
+        smol::block_on(executor.run(async move {
+            let p2p = P2p::new(Settings::default(), executor_.clone()).await.unwrap();
+
+            // Refine channels skip unknown commands by default
+            let (chan_a, chan_b) = channel_pair_in(p2p.session_refine()).await;
+            let ping_sub = chan_b.subscribe_msg::<PingMessage>().await.unwrap();
+            chan_a.clone().start(executor_.clone());
+            chan_b.clone().start(executor_.clone());
+
+            chan_a.send(&BlobMessage { data: vec![0; 128] }).await.unwrap();
+            chan_a.send(&PingMessage { nonce: 1 }).await.unwrap();
+            assert_eq!(ping_sub.receive().await.unwrap().nonce, 1);
+            assert!(!chan_b.is_stopped());
+
+            // Inbound channels ban the peer under the default strict policy
+            let (chan_a, chan_b) = channel_pair(&p2p).await;
+            let stop_sub = chan_b.subscribe_stop().await.unwrap();
+            chan_a.clone().start(executor_.clone());
+            chan_b.clone().start(executor_.clone());
+
+            chan_a.send(&BlobMessage { data: vec![0; 128] }).await.unwrap();
+            timeout(Duration::from_secs(5), stop_sub.receive()).await.unwrap();
+            assert!(chan_b.is_stopped());
+        }))
+    }
+
+    #[test]
+    fn channel_read_deadline_stops_silent_channel() {
+        let executor = Arc::new(Executor::new());
+        let executor_ = executor.clone();
+        smol::block_on(executor.run(async move {
+            let p2p = P2p::new(Settings::default(), executor_.clone()).await.unwrap();
+            let (chan_a, _chan_b) = channel_pair(&p2p).await;
+            assert_eq!(chan_a.read_deadline(), None);
+
+            // The other end never sends anything
+            chan_a.set_read_deadline(Some(Duration::from_millis(100)));
+            let stop_sub = chan_a.subscribe_stop().await.unwrap();
+            chan_a.clone().start(executor_.clone());
+
+            timeout(Duration::from_secs(5), stop_sub.receive()).await.unwrap();
+            assert!(chan_a.is_stopped());
+        }))
+    }
+
+    #[test]
+    fn channel_without_deadline_stays_open() {
+        let executor = Arc::new(Executor::new());
+        let executor_ = executor.clone();
+        smol::block_on(executor.run(async move {
+            let p2p = P2p::new(Settings::default(), executor_.clone()).await.unwrap();
+            let (chan_a, _chan_b) = channel_pair(&p2p).await;
+
+            chan_a.set_read_deadline(Some(Duration::from_millis(100)));
+            chan_a.set_read_deadline(None);
+            chan_a.clone().start(executor_.clone());
+
+            msleep(300).await;
+            assert!(!chan_a.is_stopped());
+            chan_a.stop().await;
+        }))
+    }
+
+    #[test]
+    fn channel_write_deadline_stops_stalled_send() {
+        let executor = Arc::new(Executor::new());
+        let executor_ = executor.clone();
+        smol::block_on(executor.run(async move {
+            let p2p = P2p::new(Settings::default(), executor_.clone()).await.unwrap();
+            let (chan_a, _chan_b) = channel_pair(&p2p).await;
+            chan_a.clone().start(executor_.clone());
+
+            // The other end never reads, so this can't fit in the buffer
+            chan_a.set_write_deadline(Some(Duration::from_millis(100)));
+            let blob = BlobMessage { data: vec![0; 65536] };
+            assert!(chan_a.send(&blob).await.is_err());
+            assert!(chan_a.is_stopped());
+        }))
+    }
+}
@@ -22,22 +22,36 @@ use std::{
 };
 
 use futures::{
-    future::{select, Either},
+    future::{select, BoxFuture, Either},
     pin_mut,
 };
-use log::warn;
+use log::{info, warn};
 use smol::lock::RwLock as AsyncRwLock;
 use url::Url;
 
 use super::{
     channel::{Channel, ChannelPtr},
-    hosts::HostColor,
     session::SessionWeakPtr,
     settings::Settings,
-    transport::Dialer,
+    transport::{Dialer, TorBridgeConfig},
 };
 use crate::{system::CondVar, Error, Result};
 
+/// Decides whether a peer that couldn't be reached over Tor may be
+/// retried over clearnet TCP. Falling back reveals our IP address to the
+/// peer and anyone watching the network, so it never happens unless a
+/// policy is installed with [`P2p::set_tor_fallback_policy()`].
+///
+/// [`P2p::set_tor_fallback_policy()`]: super::P2p::set_tor_fallback_policy
+pub trait TorFallbackPolicy: Send + Sync {
+    /// Return `true` to retry `peer` at its clearnet address after
+    /// dialing it over Tor failed with `err`
+    fn allow_clearnet(&self, peer: &Url, err: &Error) -> bool;
+}
+
+/// Atomic pointer to a Tor fallback policy
+pub type TorFallbackPolicyPtr = Arc<dyn TorFallbackPolicy>;
+
 /// Create outbound socket connections
 pub struct Connector {
     /// P2P settings
@@ -56,17 +70,41 @@ impl Connector {
 
     /// Establish an outbound connection
     pub async fn connect(&self, url: &Url) -> Result<(Url, ChannelPtr)> {
-        let hosts = self.session.upgrade().unwrap().p2p().hosts();
-        if hosts.container.contains(HostColor::Black as usize, url) || hosts.block_all_ports(url) {
+        let settings = self.settings.read().await;
+        let datastore = settings.p2p_datastore.clone();
+        let outbound_connect_timeout = settings.outbound_connect_timeout;
+        drop(settings);
+
+        let dial = |endpoint: Url, tor_bridges| {
+            let datastore = datastore.clone();
+            Box::pin(async move {
+                self.dial(url, &endpoint, datastore, tor_bridges, outbound_connect_timeout).await
+            }) as BoxFuture<'_, Result<(Url, ChannelPtr)>>
+        };
+
+        self.connect_with(url, dial).await
+    }
+
+    /// Same as `connect()`, but dialing the chosen endpoint with `dial`
+    async fn connect_with<'a>(
+        &self,
+        url: &Url,
+        dial: impl Fn(Url, TorBridgeConfig) -> BoxFuture<'a, Result<(Url, ChannelPtr)>>,
+    ) -> Result<(Url, ChannelPtr)> {
+        let p2p = self.session.upgrade().unwrap().p2p();
+        let hosts = p2p.hosts();
+        if hosts.is_banned(url) {
             warn!(target: "net::connector::connect", "Peer {} is blacklisted", url);
-            return Err(Error::ConnectFailed)
+            return Err(Error::PeerBanned)
         }
 
         let settings = self.settings.read().await;
         let transports = settings.allowed_transports.clone();
         let transport_mixing = settings.transport_mixing;
-        let datastore = settings.p2p_datastore.clone();
-        let outbound_connect_timeout = settings.outbound_connect_timeout;
+        let tor_bridges = TorBridgeConfig {
+            bridges: settings.tor_bridges.clone(),
+            pt_path: settings.tor_pt_path.clone(),
+        };
         drop(settings);
 
         let mut endpoint = url.clone();
@@ -84,7 +122,68 @@ impl Connector {
             }
         }
 
-        let dialer = Dialer::new(endpoint.clone(), datastore).await?;
+        self.dial_with_fallback(url, endpoint, tor_bridges, dial).await
+    }
+
+    /// Dial `endpoint` using `dial`, and retry at the peer's clearnet
+    /// address if dialing it over Tor failed and the Tor fallback policy
+    /// allows it.
+    async fn dial_with_fallback<'a>(
+        &self,
+        url: &Url,
+        endpoint: Url,
+        tor_bridges: TorBridgeConfig,
+        dial: impl Fn(Url, TorBridgeConfig) -> BoxFuture<'a, Result<(Url, ChannelPtr)>>,
+    ) -> Result<(Url, ChannelPtr)> {
+        let err = match dial(endpoint.clone(), tor_bridges).await {
+            Err(Error::ConnectorStopped) => return Err(Error::ConnectorStopped),
+            Err(e) => e,
+            Ok(connection) => return Ok(connection),
+        };
+
+        // Tor failed, see if we're allowed to reach the peer over clearnet instead
+        let Some(clearnet) = Self::clearnet_endpoint(&endpoint) else { return Err(err) };
+        let p2p = self.session.upgrade().unwrap().p2p();
+        if !p2p.tor_fallback_policy().is_some_and(|policy| policy.allow_clearnet(url, &err)) {
+            return Err(err)
+        }
+
+        info!(
+            target: "net::connector::connect",
+            "Dialing {} over Tor failed ({}), falling back to {}", url, err, clearnet,
+        );
+        dial(clearnet, TorBridgeConfig::default()).await
+    }
+
+    /// Dial `endpoint` and set up a channel for `url` on success
+    async fn dial(
+        &self,
+        url: &Url,
+        endpoint: &Url,
+        datastore: Option<String>,
+        tor_bridges: TorBridgeConfig,
+        outbound_connect_timeout: u64,
+    ) -> Result<(Url, ChannelPtr)> {
+        let p2p = self.session.upgrade().unwrap().p2p();
+        let hosts = p2p.hosts();
+
+        let dialer = Dialer::new_with_tor_bridges(endpoint.clone(), datastore, tor_bridges)
+            .await?
+            .with_dns_cache(p2p.dns_cache());
+
+        #[cfg(feature = "p2p-noise")]
+        let dialer = match p2p.noise_keypair() {
+            Some(keypair) => dialer.with_noise_keypair(keypair),
+            None => dialer,
+        };
+        #[cfg(feature = "p2p-noise")]
+        let dialer = dialer.with_noise_remote_keys(p2p.noise_remote_keys());
+
+        #[cfg(feature = "p2p-tor")]
+        let dialer = match p2p.tor_client() {
+            Some(client) => dialer.with_tor_client(client),
+            None => dialer,
+        };
         let timeout = Duration::from_secs(outbound_connect_timeout);
 
         let stop_fut = async {
@@ -110,13 +209,7 @@ impl Connector {
             Either::Left((Err(e), _)) => {
                 // If we get ENETUNREACH, we don't have IPv6 connectivity so note it down.
                 if e.raw_os_error() == Some(libc::ENETUNREACH) {
-                    self.session
-                        .upgrade()
-                        .unwrap()
-                        .p2p()
-                        .hosts()
-                        .ipv6_available
-                        .store(false, Ordering::SeqCst);
+                    hosts.ipv6_available.store(false, Ordering::SeqCst);
                 }
                 Err(e.into())
             }
@@ -125,7 +218,285 @@ impl Connector {
         }
     }
 
+    /// Returns the clearnet address a Tor `endpoint` can be dialed at, or
+    /// `None` if it isn't a Tor endpoint or only exists as an onion service.
+    pub fn clearnet_endpoint(endpoint: &Url) -> Option<Url> {
+        let scheme = match endpoint.scheme() {
+            "tor" => "tcp",
+            "tor+tls" => "tcp+tls",
+            _ => return None,
+        };
+
+        let host = endpoint.host_str()?;
+        if host.ends_with(".onion") {
+            return None
+        }
+
+        let mut clearnet = endpoint.clone();
+        clearnet.set_scheme(scheme).ok()?;
+        Some(clearnet)
+    }
+
+    /// Establish an outbound connection to a peer reachable over several
+    /// addresses, trying them in the configured transport preference
+    /// order until one succeeds. Returns the last error if all fail.
+    pub async fn connect_any(&self, urls: &[Url]) -> Result<(Url, ChannelPtr)> {
+        let preference = self.settings.read().await.transport_preference.clone();
+
+        let mut last_err = Error::ConnectFailed;
+        for url in Self::order_by_preference(urls, &preference) {
+            match self.connect(&url).await {
+                Ok(connection) => return Ok(connection),
+                Err(Error::ConnectorStopped) => return Err(Error::ConnectorStopped),
+                Err(e) => {
+                    warn!(target: "net::connector::connect_any", "Failed connecting to {}: {}", url, e);
+                    last_err = e;
+                }
+            }
+        }
+
+        Err(last_err)
+    }
+
+    /// Sort `urls` by the position of their scheme in `preference`.
+    /// The sort is stable, so addresses with equally preferred (or
+    /// unlisted) transports keep their given order.
+    pub fn order_by_preference(urls: &[Url], preference: &[String]) -> Vec<Url> {
+        let mut urls = urls.to_vec();
+        urls.sort_by_key(|url| {
+            preference.iter().position(|t| t == url.scheme()).unwrap_or(preference.len())
+        });
+        urls
+    }
+
     pub(crate) fn stop(&self) {
         self.stop_signal.notify()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::net::{hosts::HostColor, session::Session, transport::memory, P2p};
+    use smol::{net::TcpListener, Executor, Timer};
+    use std::sync::Mutex as SyncMutex;
+
+    #[test]
+    fn connect_to_banned_peer() {
+        let executor = Arc::new(Executor::new());
+        let executor_ = executor.clone();
+        smol::block_on(executor.run(async move {
+            let p2p = P2p::new(Settings::default(), executor_.clone()).await.unwrap();
+
+            // A reachable peer, so any dial attempt would show up as a connection
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let port = listener.local_addr().unwrap().port();
+            let peer = Url::parse(&format!("tcp://127.0.0.1:{}", port)).unwrap();
+            p2p.hosts().container.store(HostColor::Black as usize, peer.clone(), 0);
+            assert!(p2p.hosts().is_banned(&peer));
+
+            let session: Arc<dyn Session + Send + Sync> = p2p.session_manual();
+            let connector = Connector::new(p2p.settings(), Arc::downgrade(&session));
+            assert!(matches!(connector.connect(&peer).await, Err(Error::PeerBanned)));
+
+            let accept = async { listener.accept().await.is_ok() };
+            let timeout = async {
+                Timer::after(Duration::from_millis(200)).await;
+                false
+            };
+            assert!(!smol::future::or(accept, timeout).await);
+
+            // Blacklisting the bare hostname bans every port
+            let other = Url::parse("tcp://10.0.0.1:26661").unwrap();
+            assert!(!p2p.hosts().is_banned(&other));
+            p2p.hosts().container.store(
+                HostColor::Black as usize,
+                Url::parse("tcp://10.0.0.1").unwrap(),
+                0,
+            );
+            assert!(p2p.hosts().is_banned(&other));
+        }));
+    }
+
+    #[test]
+    fn connect_any_falls_back_after_failed_dial() {
+        let executor = Arc::new(Executor::new());
+        let executor_ = executor.clone();
+        smol::block_on(executor.run(async move {
+            let settings = Settings {
+                transport_preference: vec!["tcp+tls".to_string(), "tcp".to_string()],
+                outbound_connect_timeout: 1,
+                ..Default::default()
+            };
+            let p2p = P2p::new(settings, executor_.clone()).await.unwrap();
+
+            // The preferred address has nothing listening on it
+            let closed = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let closed_port = closed.local_addr().unwrap().port();
+            drop(closed);
+            let preferred = Url::parse(&format!("tcp+tls://127.0.0.1:{}", closed_port)).unwrap();
+
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let port = listener.local_addr().unwrap().port();
+            let fallback = Url::parse(&format!("tcp://127.0.0.1:{}", port)).unwrap();
+
+            let session: Arc<dyn Session + Send + Sync> = p2p.session_manual();
+            let connector = Connector::new(p2p.settings(), Arc::downgrade(&session));
+
+            let urls = [fallback.clone(), preferred.clone()];
+            let (accepted, connected) =
+                futures::join!(listener.accept(), connector.connect_any(&urls));
+            assert!(accepted.is_ok());
+            let (endpoint, channel) = connected.unwrap();
+            assert_eq!(endpoint, fallback);
+            assert_eq!(channel.address(), &fallback);
+            channel.stop().await;
+
+            // With every address down the last dial error is returned
+            drop(listener);
+            assert!(connector.connect_any(&[preferred]).await.is_err());
+        }));
+    }
+
+    #[test]
+    fn tor_clearnet_endpoint() {
+        let clearnet = |url: &str| {
+            Connector::clearnet_endpoint(&Url::parse(url).unwrap()).map(|u| u.to_string())
+        };
+
+        assert_eq!(clearnet("tor://a.example:26661").unwrap(), "tcp://a.example:26661");
+        assert_eq!(clearnet("tor+tls://a.example:26661").unwrap(), "tcp+tls://a.example:26661");
+        // Onion services have no clearnet address, and other transports
+        // aren't Tor endpoints in the first place.
+        assert!(clearnet("tor://abcdef.onion:26661").is_none());
+        assert!(clearnet("tcp://a.example:26661").is_none());
+        assert!(clearnet("nym://a.example:26661").is_none());
+    }
+
+    #[test]
+    fn tor_failure_falls_back_to_tcp() {
+        /// Lets every peer fall back to clearnet
+        struct AllowClearnet;
+
+        impl TorFallbackPolicy for AllowClearnet {
+            fn allow_clearnet(&self, _peer: &Url, _err: &Error) -> bool {
+                true
+            }
+        }
+
+        let executor = Arc::new(Executor::new());
+        let executor_ = executor.clone();
+        smol::block_on(executor.run(async move {
+            let p2p = P2p::new(Settings::default(), executor_.clone()).await.unwrap();
+            let session: Arc<dyn Session + Send + Sync> = p2p.session_manual();
+            let session = Arc::downgrade(&session);
+            let connector = Connector::new(p2p.settings(), session.clone());
+
+            let peer = Url::parse("tcp://a.example:26661").unwrap();
+            let tor_endpoint = Url::parse("tor://a.example:26661").unwrap();
+
+            // Every Tor dial fails, while clearnet ones get a channel
+            let dialed = SyncMutex::new(vec![]);
+            let dial = |endpoint: Url, _: TorBridgeConfig| {
+                dialed.lock().unwrap().push(endpoint.clone());
+                let peer = peer.clone();
+                let session = session.clone();
+                Box::pin(async move {
+                    if endpoint.scheme() == "tor" {
+                        return Err(Error::ConnectFailed)
+                    }
+                    let (stream, _) = memory::duplex(4096);
+                    let channel =
+                        Channel::new(Box::new(stream), Some(endpoint.clone()), peer, session).await;
+                    Ok((endpoint, channel))
+                }) as BoxFuture<'static, Result<(Url, ChannelPtr)>>
+            };
+
+            // Without a policy the peer is never dialed over TCP
+            let bridges = TorBridgeConfig::default();
+            let res =
+                connector.dial_with_fallback(&peer, tor_endpoint.clone(), bridges.clone(), &dial);
+            assert!(matches!(res.await, Err(Error::ConnectFailed)));
+            assert_eq!(*dialed.lock().unwrap(), vec![tor_endpoint.clone()]);
+
+            // With it, the failed Tor dial is retried over TCP
+            dialed.lock().unwrap().clear();
+            p2p.set_tor_fallback_policy(Some(Arc::new(AllowClearnet)));
+            let res = connector.dial_with_fallback(&peer, tor_endpoint.clone(), bridges, &dial);
+            let (endpoint, channel) = res.await.unwrap();
+            assert_eq!(endpoint, peer);
+            assert_eq!(*dialed.lock().unwrap(), vec![tor_endpoint, peer.clone()]);
+            channel.stop().await;
+        }));
+    }
+
+    #[test]
+    fn tor_dial_only_connects_without_listener() {
+        let executor = Arc::new(Executor::new());
+        let executor_ = executor.clone();
+        smol::block_on(executor.run(async move {
+            let peer = Url::parse("tcp://a.example:26661").unwrap();
+            let settings = Settings {
+                allowed_transports: vec!["tor".to_string()],
+                transport_mixing: true,
+                tor_dial_only: true,
+                outbound_connections: 0,
+                peers: vec![peer.clone()],
+                ..Default::default()
+            };
+            settings.validate().unwrap();
+            let p2p = P2p::new(settings, executor_.clone()).await.unwrap();
+
+            // Nothing to listen on, so no onion service is launched
+            p2p.session_inbound().start().await.unwrap();
+
+            let session: Arc<dyn Session + Send + Sync> = p2p.session_manual();
+            let session = Arc::downgrade(&session);
+            let connector = Connector::new(p2p.settings(), session.clone());
+
+            // A mocked Tor dial, standing in for `TorDialer::do_dial()`
+            let dialed = SyncMutex::new(vec![]);
+            let dial = |endpoint: Url, _: TorBridgeConfig| {
+                dialed.lock().unwrap().push(endpoint.clone());
+                let peer = peer.clone();
+                let session = session.clone();
+                Box::pin(async move {
+                    let (stream, _) = memory::duplex(4096);
+                    let channel =
+                        Channel::new(Box::new(stream), Some(endpoint.clone()), peer, session).await;
+                    Ok((endpoint, channel))
+                }) as BoxFuture<'static, Result<(Url, ChannelPtr)>>
+            };
+
+            // The peer is dialed over Tor, needing nothing but the dialer
+            let (endpoint, channel) = connector.connect_with(&peer, &dial).await.unwrap();
+            let tor_endpoint = Url::parse("tor://a.example:26661").unwrap();
+            assert_eq!(endpoint, tor_endpoint);
+            assert_eq!(*dialed.lock().unwrap(), vec![tor_endpoint]);
+            assert_eq!(channel.address(), &peer);
+            channel.stop().await;
+        }));
+    }
+
+    #[test]
+    fn transport_preference_order() {
+        let urls: Vec<Url> =
+            ["tcp+tls://a.example:26661", "tor://b.onion:26661", "tcp://c.example:26661"]
+                .iter()
+                .map(|u| Url::parse(u).unwrap())
+                .collect();
+
+        // Without a preference the given order is kept
+        assert_eq!(Connector::order_by_preference(&urls, &[]), urls);
+
+        let preference = vec!["tor".to_string(), "tcp+tls".to_string()];
+        let ordered = Connector::order_by_preference(&urls, &preference);
+        let schemes: Vec<&str> = ordered.iter().map(|u| u.scheme()).collect();
+        assert_eq!(schemes, ["tor", "tcp+tls", "tcp"]);
+
+        let preference = vec!["tcp".to_string()];
+        let ordered = Connector::order_by_preference(&urls, &preference);
+        let schemes: Vec<&str> = ordered.iter().map(|u| u.scheme()).collect();
+        assert_eq!(schemes, ["tcp", "tcp+tls", "tor"]);
+    }
+}
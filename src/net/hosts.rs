@@ -21,7 +21,7 @@ use std::{
     fmt, fs,
     fs::File,
     sync::{
-        atomic::{AtomicBool, Ordering},
+        atomic::{AtomicBool, AtomicU64, Ordering},
         Arc, Mutex, RwLock,
     },
     time::{Instant, UNIX_EPOCH},
@@ -33,6 +33,7 @@ use smol::lock::RwLock as AsyncRwLock;
 use url::Url;
 
 use super::{
+    channel::ViolationKind,
     session::{SESSION_REFINE, SESSION_SEED},
     settings::Settings,
     ChannelPtr,
@@ -320,6 +321,28 @@ pub enum HostColor {
     Dark = 4,
 }
 
+impl HostColor {
+    /// Returns true if `Hosts::move_host()` may move a host from this
+    /// list to `destination`. Hosts never leave the blacklist, so a
+    /// banned peer can't be reinstated by accident, and only
+    /// the refinery promotes greylist hosts to the whitelist.
+    pub fn can_move_to(&self, destination: &HostColor) -> bool {
+        use HostColor::*;
+
+        match (self, destination) {
+            // Anything can be banned, including hosts that already are
+            (_, Black) => true,
+            (Black, _) => false,
+            // The Dark list is only filled by `Hosts::insert()`
+            (_, Dark) => false,
+            (Grey | White | Gold, Grey | Gold) => true,
+            (Grey | White, White) => true,
+            (Gold, White) => false,
+            (Dark, _) => false,
+        }
+    }
+}
+
 impl TryFrom<usize> for HostColor {
     type Error = Error;
 
@@ -335,6 +358,24 @@ impl TryFrom<usize> for HostColor {
     }
 }
 
+/// A known host along with its hostlist metadata, as returned by
+/// [`Hosts::list()`].
+#[derive(Clone, Debug)]
+pub struct HostEntry {
+    /// Address of the host
+    pub addr: Url,
+    /// Last time the host was seen
+    pub last_seen: u64,
+    /// Hostlist the host is on
+    pub color: HostColor,
+}
+
+/// Check whether a host with the given `last_seen` passes an optional
+/// `since` filter
+fn seen_since(last_seen: u64, since: Option<u64>) -> bool {
+    since.map_or(true, |since| last_seen > since)
+}
+
 /// A Container for managing Grey, White, Gold and Black hostlists. Exposes
 /// a common interface for writing to and querying hostlists.
 // TODO: Benchmark hostlist operations when the hostlist is at max size.
@@ -559,7 +600,13 @@ impl HostContainer {
     }
 
     /// Get up to n random peers. Schemes are not taken into account.
-    pub(in crate::net) fn fetch_n_random(&self, color: HostColor, n: u32) -> Vec<(Url, u64)> {
+    /// If `since` is provided, only peers last seen after it are returned.
+    pub(in crate::net) fn fetch_n_random(
+        &self,
+        color: HostColor,
+        n: u32,
+        since: Option<u64>,
+    ) -> Vec<(Url, u64)> {
         trace!(target: "net::hosts::fetch_n_random()", "[START] {:?}", color);
         let n = n as usize;
         if n == 0 {
@@ -570,7 +617,9 @@ impl HostContainer {
         let list = self.hostlists[color as usize].read().unwrap();
 
         for (addr, last_seen) in list.iter() {
-            hosts.push((addr.clone(), *last_seen));
+            if seen_since(*last_seen, since) {
+                hosts.push((addr.clone(), *last_seen));
+            }
         }
 
         if hosts.is_empty() {
@@ -584,11 +633,13 @@ impl HostContainer {
     }
 
     /// Get up to n random peers that match the given transport schemes.
+    /// If `since` is provided, only peers last seen after it are returned.
     pub(in crate::net) fn fetch_n_random_with_schemes(
         &self,
         color: HostColor,
         schemes: &[String],
         n: u32,
+        since: Option<u64>,
     ) -> Vec<(Url, u64)> {
         trace!(target: "net::hosts::fetch_n_random_with_schemes()", "[START] {:?}", color);
         let index = color as usize;
@@ -598,7 +649,8 @@ impl HostContainer {
         }
 
         // Retrieve all peers corresponding to that transport schemes
-        let hosts = self.fetch_with_schemes(index, schemes, None);
+        let mut hosts = self.fetch_with_schemes(index, schemes, None);
+        hosts.retain(|(_, last_seen)| seen_since(*last_seen, since));
         if hosts.is_empty() {
             debug!(target: "net::hosts::fetch_n_random_with_schemes()",
                   "No such schemes found!");
@@ -611,12 +663,14 @@ impl HostContainer {
     }
 
     /// Get up to n random peers that don't match the given transport schemes
-    /// from a hostlist.
+    /// from a hostlist. If `since` is provided, only peers last seen after
+    /// it are returned.
     pub(in crate::net) fn fetch_n_random_excluding_schemes(
         &self,
         color: HostColor,
         schemes: &[String],
         n: u32,
+        since: Option<u64>,
     ) -> Vec<(Url, u64)> {
         trace!(target: "net::hosts::fetch_excluding_schemes()", "[START] {:?}", color);
         let index = color as usize;
@@ -625,7 +679,8 @@ impl HostContainer {
             return vec![]
         }
         // Retrieve all peers not corresponding to that transport schemes
-        let hosts = self.fetch_excluding_schemes(index, schemes, None);
+        let mut hosts = self.fetch_excluding_schemes(index, schemes, None);
+        hosts.retain(|(_, last_seen)| seen_since(*last_seen, since));
 
         if hosts.is_empty() {
             debug!(target: "net::hosts::fetch_n_random_excluding_schemes()",
@@ -653,11 +708,25 @@ impl HostContainer {
         self.hostlists[color as usize].read().unwrap().is_empty()
     }
 
+    /// Return the number of hosts on a hostlist.
+    pub fn len(&self, color: HostColor) -> usize {
+        self.hostlists[color as usize].read().unwrap().len()
+    }
+
     /// Check if host is in a hostlist
     pub fn contains(&self, color: usize, addr: &Url) -> bool {
         self.hostlists[color].read().unwrap().iter().any(|(u, _t)| u == addr)
     }
 
+    /// Find the hostlist a host is on, if any. The blacklist is checked
+    /// first, so banned hosts are reported as such even if they were
+    /// also stored elsewhere.
+    pub fn color_of(&self, addr: &Url) -> Option<HostColor> {
+        [HostColor::Black, HostColor::Gold, HostColor::White, HostColor::Grey, HostColor::Dark]
+            .into_iter()
+            .find(|color| self.contains(color.clone() as usize, addr))
+    }
+
     /// Get the index for a given addr on a hostlist.
     pub fn get_index_at_addr(&self, color: usize, addr: Url) -> Option<usize> {
         self.hostlists[color].read().unwrap().iter().position(|a| a.0 == addr)
@@ -842,6 +911,16 @@ impl HostContainer {
     }
 }
 
+/// Notification sent every time a peer gets banned
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BanEvent {
+    /// Address stored on the blacklist
+    pub peer: Url,
+    /// Violation that caused the ban, `None` when banned directly
+    /// with [`Channel::ban()`](super::channel::Channel::ban)
+    pub reason: Option<ViolationKind>,
+}
+
 /// Main parent class for the management and manipulation of
 /// hostlists.
 ///
@@ -866,6 +945,12 @@ pub struct Hosts {
     /// Publisher listening for network disconnects
     pub(in crate::net) disconnect_publisher: PublisherPtr<Error>,
 
+    /// Publisher for notifications of banned peers
+    ban_publisher: PublisherPtr<BanEvent>,
+
+    /// Amount of bans since the hosts were created
+    ban_count: AtomicU64,
+
     /// Keeps track of the last time a connection was made.
     pub(in crate::net) last_connection: Mutex<Instant>,
 
@@ -885,6 +970,8 @@ impl Hosts {
             store_publisher: Publisher::new(),
             channel_publisher: Publisher::new(),
             disconnect_publisher: Publisher::new(),
+            ban_publisher: Publisher::new(),
+            ban_count: AtomicU64::new(0),
             last_connection: Mutex::new(Instant::now()),
             ipv6_available: AtomicBool::new(true),
             settings,
@@ -1130,6 +1217,23 @@ impl Hosts {
         self.disconnect_publisher.clone().subscribe().await
     }
 
+    /// Get notified when a peer gets banned
+    pub async fn subscribe_ban(&self) -> Subscription<BanEvent> {
+        self.ban_publisher.clone().subscribe().await
+    }
+
+    /// Total amount of peers banned since the hosts were created.
+    /// Peers banned again after reconnecting are counted every time.
+    pub fn total_bans(&self) -> u64 {
+        self.ban_count.load(Ordering::SeqCst)
+    }
+
+    /// Count a ban of `peer` and notify the ban subscribers
+    pub(in crate::net) async fn record_ban(&self, peer: Url, reason: Option<ViolationKind>) {
+        self.ban_count.fetch_add(1, Ordering::SeqCst);
+        self.ban_publisher.notify(BanEvent { peer, reason }).await;
+    }
+
     // Verify whether a URL is local.
     // NOTE: This function is stateless and not specific to
     // `Hosts`. For this reason, it might make more sense
@@ -1215,6 +1319,16 @@ impl Hosts {
             .any(|(u, _t)| u.host().unwrap() == host && u.port().is_none())
     }
 
+    /// Check whether a peer is banned, either by its exact address or
+    /// by a blacklisted hostname covering all of its ports.
+    pub fn is_banned(&self, url: &Url) -> bool {
+        if self.container.contains(HostColor::Black as usize, url) {
+            return true
+        }
+
+        url.host().is_some() && self.block_all_ports(url)
+    }
+
     /// Filter given addresses based on certain rulesets and validity. Strictly called only on
     /// the first time learning of new peers.
     async fn filter_addresses(&self, addrs: &[(Url, u64)]) -> Vec<(Url, u64)> {
@@ -1319,7 +1433,7 @@ impl Hosts {
                 "nym" | "nym+tls" => continue, // <-- Temp skip
 
                 #[cfg(feature = "p2p-tcp")]
-                "tcp" | "tcp+tls" => {
+                "tcp" | "tcp+tls" | "tcp+noise" => {
                     trace!(
                         target: "net::hosts::filter_addresses",
                         "[TCP] Valid: {}", host_str,
@@ -1378,25 +1492,59 @@ impl Hosts {
         }
     }
 
+    /// Return up to `limit` known hosts, starting at `offset`, so callers
+    /// can page through large hostlists. If `color` is `None`, hosts from
+    /// every hostlist are returned. Entries are ordered by hostlist, then
+    /// by most recently seen, then by address, so pages are stable as long
+    /// as the hostlists don't change in between calls.
+    pub fn list(&self, color: Option<HostColor>, offset: usize, limit: usize) -> Vec<HostEntry> {
+        let colors = match color {
+            Some(color) => vec![color],
+            None => vec![
+                HostColor::Grey,
+                HostColor::White,
+                HostColor::Gold,
+                HostColor::Black,
+                HostColor::Dark,
+            ],
+        };
+
+        let mut entries = vec![];
+        for color in colors {
+            let mut list = self.container.fetch_all(color.clone());
+            list.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.as_str().cmp(b.0.as_str())));
+
+            entries.extend(list.into_iter().map(|(addr, last_seen)| HostEntry {
+                addr,
+                last_seen,
+                color: color.clone(),
+            }));
+        }
+
+        entries.into_iter().skip(offset).take(limit).collect()
+    }
+
     /// Downgrade host to Greylist, remove from Gold or White list.
     pub fn greylist_host(&self, addr: &Url, last_seen: u64) -> Result<()> {
         debug!(target: "net::hosts:greylist_host()", "Downgrading addr={}", addr);
-        self.move_host(addr, last_seen, HostColor::Grey)?;
+        let res = self.move_host(addr, last_seen, HostColor::Grey);
 
-        // Free up this addr for future operations.
+        // Free up this addr for future operations, even if it couldn't
+        // be moved.
         self.unregister(addr);
 
-        Ok(())
+        res
     }
 
     pub fn whitelist_host(&self, addr: &Url, last_seen: u64) -> Result<()> {
         debug!(target: "net::hosts:whitelist_host()", "Upgrading addr={}", addr);
-        self.move_host(addr, last_seen, HostColor::White)?;
+        let res = self.move_host(addr, last_seen, HostColor::White);
 
-        // Free up this addr for future operations.
+        // Free up this addr for future operations, even if it couldn't
+        // be moved.
         self.unregister(addr);
 
-        Ok(())
+        res
     }
 
     /// A single atomic function for moving hosts between hostlists. Called on the following occasions:
@@ -1407,6 +1555,9 @@ impl Hosts {
     /// * When we connect to a peer, move to gold, remove from white or grey.
     /// * When we add a peer to the black list: move to black, remove from all other lists.
     ///
+    /// Moves which [`HostColor::can_move_to()`] disallows, such as taking a host off the
+    /// blacklist, fail with `Error::InvalidHostTransition` and leave the host untouched.
+    ///
     /// Note that this method puts a given Url into the "Move" state but does not reset the
     /// state afterwards. This is because the next state will differ depending on its usage.
     /// The state transition from `Move` to `Connected` or `Suspend` are both valid operations.
@@ -1421,6 +1572,14 @@ impl Hosts {
         debug!(target: "net::hosts::move_host()", "Trying to move addr={} destination={:?}",
                addr, destination);
 
+        if let Some(source) = self.container.color_of(addr) {
+            if !source.can_move_to(&destination) {
+                debug!(target: "net::hosts::move_host()", "Refusing to move addr={} from {:?} to {:?}",
+                       addr, source, destination);
+                return Err(Error::InvalidHostTransition)
+            }
+        }
+
         // This should never panic. Failure indicates a misuse of the HostState API.
         self.try_register(addr.clone(), HostState::Move).unwrap();
 
@@ -1483,6 +1642,7 @@ impl Hosts {
 mod tests {
     use super::*;
     use crate::system::sleep;
+    use std::collections::HashSet;
 
     #[test]
     fn test_is_local_host() {
@@ -1608,6 +1768,57 @@ mod tests {
         assert!(hosts.container.contains(HostColor::Gold as usize, &gold_hosts[2]));
     }
 
+    #[test]
+    fn test_list_pagination() {
+        let settings = Settings { ..Default::default() };
+        let hosts = Hosts::new(Arc::new(AsyncRwLock::new(settings)));
+
+        // Use a few identical last_seen values to exercise the ordering tiebreak
+        for i in 0..23 {
+            let addr = Url::parse(&format!("tcp://grey{}.dark.fi:123", i)).unwrap();
+            hosts.container.store(HostColor::Grey as usize, addr, 1000 + i / 3);
+        }
+        for i in 0..7 {
+            let addr = Url::parse(&format!("tcp://gold{}.dark.fi:123", i)).unwrap();
+            hosts.container.store(HostColor::Gold as usize, addr, 2000 + i);
+        }
+
+        let full = hosts.list(None, 0, usize::MAX);
+        assert_eq!(full.len(), 30);
+
+        for page_size in [1, 4, 7, 30, 100] {
+            let mut paged = vec![];
+            let mut offset = 0;
+            loop {
+                let page = hosts.list(None, offset, page_size);
+                assert!(page.len() <= page_size);
+                if page.is_empty() {
+                    break
+                }
+                offset += page.len();
+                paged.extend(page);
+            }
+
+            // Paging concatenates back to the full list, without gaps or duplicates
+            assert_eq!(paged.len(), full.len());
+            for (a, b) in paged.iter().zip(full.iter()) {
+                assert_eq!(a.addr, b.addr);
+                assert_eq!(a.last_seen, b.last_seen);
+                assert_eq!(a.color.clone() as usize, b.color.clone() as usize);
+            }
+            let unique: HashSet<_> = paged.iter().map(|e| e.addr.clone()).collect();
+            assert_eq!(unique.len(), full.len());
+        }
+
+        // Filtering by color only returns hosts from that hostlist
+        let gold = hosts.list(Some(HostColor::Gold), 0, usize::MAX);
+        assert_eq!(gold.len(), 7);
+        assert!(gold.iter().all(|e| matches!(e.color, HostColor::Gold)));
+        assert_eq!(gold[0].last_seen, 2006);
+        assert_eq!(hosts.list(Some(HostColor::Gold), 5, 10).len(), 2);
+        assert!(hosts.list(Some(HostColor::White), 0, 10).is_empty());
+    }
+
     #[test]
     fn test_refresh() {
         smol::block_on(async {
@@ -1683,4 +1894,69 @@ mod tests {
             println!("last entry: {} {}", entry.0, entry.1);
         });
     }
+
+    #[test]
+    fn test_fetch_since() {
+        let settings = Settings { ..Default::default() };
+        let hosts = Hosts::new(Arc::new(AsyncRwLock::new(settings)));
+        let schemes = vec!["tcp".to_string()];
+
+        for (i, scheme) in ["tcp", "tcp", "tor", "tor"].iter().enumerate() {
+            let url = Url::parse(&format!("{}://whitelist{}:123", scheme, i)).unwrap();
+            hosts.container.store(HostColor::White as usize, url, 100 * i as u64);
+        }
+
+        // Without `since`, the full list is returned
+        assert_eq!(hosts.container.fetch_n_random(HostColor::White, 10, None).len(), 4);
+
+        // Only entries last seen strictly after `since` are returned
+        let newer = hosts.container.fetch_n_random(HostColor::White, 10, Some(100));
+        let mut newer: Vec<u64> = newer.into_iter().map(|(_, last_seen)| last_seen).collect();
+        newer.sort();
+        assert_eq!(newer, vec![200, 300]);
+
+        let with =
+            hosts.container.fetch_n_random_with_schemes(HostColor::White, &schemes, 10, Some(0));
+        assert_eq!(with, vec![(Url::parse("tcp://whitelist1:123").unwrap(), 100)]);
+
+        let without = hosts.container.fetch_n_random_excluding_schemes(
+            HostColor::White,
+            &schemes,
+            10,
+            Some(250),
+        );
+        assert_eq!(without, vec![(Url::parse("tor://whitelist3:123").unwrap(), 300)]);
+    }
+
+    #[test]
+    fn test_move_host_transitions() {
+        let settings = Settings { ..Default::default() };
+        let hosts = Hosts::new(Arc::new(AsyncRwLock::new(settings)));
+        let addr = Url::parse("tcp://peer.example:123").unwrap();
+
+        // Grey -> White is what the refinery does
+        hosts.container.store(HostColor::Grey as usize, addr.clone(), 100);
+        hosts.whitelist_host(&addr, 200).unwrap();
+        assert!(matches!(hosts.container.color_of(&addr), Some(HostColor::White)));
+        assert!(!hosts.container.contains(HostColor::Grey as usize, &addr));
+
+        hosts.move_host(&addr, 300, HostColor::Black).unwrap();
+        hosts.unregister(&addr);
+
+        // Banned hosts can't be reinstated
+        for color in [HostColor::White, HostColor::Grey, HostColor::Gold] {
+            let err = hosts.move_host(&addr, 400, color).unwrap_err();
+            assert!(matches!(err, Error::InvalidHostTransition));
+        }
+        assert!(hosts.container.contains(HostColor::Black as usize, &addr));
+        assert!(!hosts.container.contains(HostColor::White as usize, &addr));
+        assert!(matches!(hosts.container.color_of(&addr), Some(HostColor::Black)));
+
+        // A refinery failing to upgrade it still releases the host
+        assert!(hosts.refinable(addr.clone()));
+        assert!(hosts.whitelist_host(&addr, 500).is_err());
+
+        // The rejected moves left the host free for other operations
+        hosts.try_register(addr.clone(), HostState::Move).unwrap();
+    }
 }
@@ -16,16 +16,209 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
+use std::{
+    io::{self, Read, Write},
+    time::UNIX_EPOCH,
+};
+
+use darkfi_sdk::crypto::{
+    schnorr::{SchnorrPublic, SchnorrSecret, Signature},
+    Keypair, PublicKey,
+};
 use darkfi_serial::{
-    async_trait, serialize_async, AsyncDecodable, AsyncEncodable, SerialDecodable, SerialEncodable,
+    async_trait, serialize, serialize_async, AsyncDecodable, AsyncEncodable, AsyncRead, AsyncWrite,
+    Decodable, Encodable, SerialDecodable, SerialEncodable, VarInt,
 };
+use log::error;
+use smol::io::{AsyncReadExt, AsyncWriteExt};
 use url::Url;
 
 pub(in crate::net) const MAGIC_BYTES: [u8; 4] = [0xd9, 0xef, 0xb6, 0x7d];
 
+/// Maximum length of a message command name. Commands are short
+/// identifiers, so anything longer is treated as a malformed packet.
+pub(in crate::net) const MAX_COMMAND_LEN: u64 = 64;
+
+/// Convert a length decoded from a VarInt into a `usize`. On 32-bit
+/// targets a peer can encode lengths that don't fit, which would
+/// otherwise be truncated, so these are rejected as malformed packets.
+pub(in crate::net) fn frame_len(len: u64) -> crate::Result<usize> {
+    usize::try_from(len).map_err(|_| crate::Error::MalformedPacket)
+}
+
+/// Check a payload length decoded from a VarInt against `max_len`.
+/// A `max_len` of 0 disables the check.
+pub(in crate::net) fn payload_len(len: u64, max_len: u64) -> crate::Result<usize> {
+    if max_len > 0 && len > max_len {
+        error!(
+            target: "net::message::payload_len",
+            "Error: Payload length {} exceeds limit of {}", len, max_len,
+        );
+        return Err(crate::Error::PayloadTooLarge)
+    }
+    frame_len(len)
+}
+
+/// A single message as laid out on the wire: [`MAGIC_BYTES`], followed
+/// by the command and the payload, each prefixed with its VarInt length.
+///
+/// Channels read and write through these functions, but they don't
+/// depend on a live stream or session, so framing can be tested and
+/// fuzzed on plain byte buffers.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Frame {
+    pub command: String,
+    pub payload: Vec<u8>,
+}
+
+impl Frame {
+    pub fn new(command: String, payload: Vec<u8>) -> Self {
+        Self { command, payload }
+    }
+
+    /// Write the frame to `stream`, returning the amount of bytes written.
+    pub async fn encode_async<W: AsyncWrite + Unpin + Send + Sized>(
+        &self,
+        stream: &mut W,
+    ) -> io::Result<usize> {
+        Self::write(stream, &self.command, &self.payload).await
+    }
+
+    /// Read a full frame from `stream`.
+    pub async fn decode_async<R: AsyncRead + Unpin + Send + Sized>(
+        stream: &mut R,
+    ) -> crate::Result<Self> {
+        let command = Self::read_command(stream).await?;
+        let payload = Self::read_payload(stream, 0).await?;
+        Ok(Self { command, payload })
+    }
+
+    /// Write a frame made of `command` and `payload` to `stream`, returning
+    /// the amount of bytes written. The stream is not flushed.
+    pub(in crate::net) async fn write<W: AsyncWrite + Unpin + Send + Sized>(
+        stream: &mut W,
+        command: &str,
+        payload: &[u8],
+    ) -> io::Result<usize> {
+        let mut written = MAGIC_BYTES.encode_async(stream).await?;
+        written += command.to_string().encode_async(stream).await?;
+
+        // First extract the length of the payload as a VarInt and write it to the stream.
+        written += VarInt(payload.len() as u64).encode_async(stream).await?;
+        // Then write the encoded payload itself to the stream.
+        stream.write_all(payload).await?;
+        written += payload.len();
+
+        Ok(written)
+    }
+
+    /// Read the magic bytes and the command of a frame from `stream`.
+    /// Oversized command lengths are rejected before reading the command
+    /// itself.
+    pub(in crate::net) async fn read_command<R: AsyncRead + Unpin + Send + Sized>(
+        stream: &mut R,
+    ) -> crate::Result<String> {
+        // Messages should have a 4 byte header of magic digits.
+        // This is used for network debugging.
+        let mut magic = [0u8; 4];
+        stream.read_exact(&mut magic).await?;
+        if magic != MAGIC_BYTES {
+            error!(target: "net::message::read_command", "Error: Magic bytes mismatch");
+            return Err(crate::Error::MalformedPacket)
+        }
+
+        // First extract the length from the stream
+        let cmd_len = VarInt::decode_async(stream).await?.0;
+
+        // Command names are short, so reject oversized lengths before
+        // reading anything else.
+        if cmd_len > MAX_COMMAND_LEN {
+            error!(
+                target: "net::message::read_command",
+                "Error: Command length {} exceeds limit of {}", cmd_len, MAX_COMMAND_LEN,
+            );
+            return Err(crate::Error::MalformedPacket)
+        }
+
+        // Then extract precisely `cmd_len` items from the stream.
+        let mut bytes = vec![0; frame_len(cmd_len)?];
+        stream.take(cmd_len).read_exact(&mut bytes).await?;
+
+        Ok(String::from_utf8(bytes)?)
+    }
+
+    /// Read the length-prefixed payload of a frame from `stream`.
+    /// Lengths over `max_len` (unless it's 0) are rejected before reading
+    /// the payload, and the buffer only grows with the data actually
+    /// received, so a bogus length can't make us allocate upfront.
+    pub(in crate::net) async fn read_payload<R: AsyncRead + Unpin + Send + Sized>(
+        stream: &mut R,
+        max_len: u64,
+    ) -> crate::Result<Vec<u8>> {
+        let len = VarInt::decode_async(stream).await?.0;
+        let expected = payload_len(len, max_len)?;
+
+        let mut payload = vec![];
+        stream.take(len).read_to_end(&mut payload).await?;
+        if payload.len() != expected {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into())
+        }
+
+        Ok(payload)
+    }
+}
+
+impl Encodable for Frame {
+    fn encode<W: Write>(&self, s: &mut W) -> io::Result<usize> {
+        let mut len = MAGIC_BYTES.encode(s)?;
+        len += self.command.encode(s)?;
+        len += VarInt(self.payload.len() as u64).encode(s)?;
+        s.write_all(&self.payload)?;
+        Ok(len + self.payload.len())
+    }
+}
+
+impl Decodable for Frame {
+    /// Decode a frame, applying the same limits as [`Frame::decode_async()`].
+    /// Any trailing data is left unread.
+    fn decode<D: Read>(d: &mut D) -> io::Result<Self> {
+        let magic: [u8; 4] = Decodable::decode(d)?;
+        if magic != MAGIC_BYTES {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Magic bytes mismatch"))
+        }
+
+        let cmd_len = VarInt::decode(d)?.0;
+        if cmd_len > MAX_COMMAND_LEN {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Command too long"))
+        }
+        let command = String::from_utf8(read_exact_len(d, cmd_len)?)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "Invalid command"))?;
+
+        let len = VarInt::decode(d)?.0;
+        let payload = read_exact_len(d, len)?;
+
+        Ok(Self { command, payload })
+    }
+}
+
+/// Read exactly `len` bytes, growing the buffer only with the data
+/// actually received
+fn read_exact_len<D: Read>(d: &mut D, len: u64) -> io::Result<Vec<u8>> {
+    let mut bytes = vec![];
+    d.take(len).read_to_end(&mut bytes)?;
+    if bytes.len() as u64 != len {
+        return Err(io::ErrorKind::UnexpectedEof.into())
+    }
+    Ok(bytes)
+}
+
 /// Generic message template.
 pub trait Message: 'static + Send + Sync + AsyncDecodable + AsyncEncodable {
     const NAME: &'static str;
+    /// Drop copies of this message arriving again over any channel while
+    /// message deduplication is enabled. Meant for gossip relayed through
+    /// the network, not for messages peers legitimately repeat.
+    const DEDUP: bool = false;
 }
 
 /// Generic serialized message template.
@@ -38,6 +231,16 @@ impl SerializedMessage {
     pub async fn new<M: Message>(message: &M) -> Self {
         Self { command: M::NAME.to_string(), payload: serialize_async(message).await }
     }
+
+    /// Identifier of this message, used to match it with `AckMessage`s.
+    /// Both ends derive it from the command and payload, so it doesn't
+    /// need to be sent along with the message.
+    pub fn id(&self) -> [u8; 32] {
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(self.command.as_bytes());
+        hasher.update(&self.payload);
+        *hasher.finalize().as_bytes()
+    }
 }
 
 #[macro_export]
@@ -47,6 +250,12 @@ macro_rules! impl_p2p_message {
             const NAME: &'static str = $nm;
         }
     };
+    ($st:ty, $nm:expr, dedup) => {
+        impl Message for $st {
+            const NAME: &'static str = $nm;
+            const DEDUP: bool = true;
+        }
+    };
 }
 
 /// Outbound keepalive message.
@@ -64,7 +273,7 @@ pub struct PongMessage {
 impl_p2p_message!(PongMessage, "pong");
 
 /// Requests address of outbound connecction.
-#[derive(Debug, Clone, SerialEncodable, SerialDecodable)]
+#[derive(Debug, Clone)]
 pub struct GetAddrsMessage {
     /// Maximum number of addresses with preferred
     /// transports to receive. Response vector will
@@ -73,9 +282,65 @@ pub struct GetAddrsMessage {
     pub max: u32,
     /// Preferred addresses transports
     pub transports: Vec<String>,
+    /// If set, only request addresses last seen after this UNIX
+    /// timestamp. Appended at the end of the message when set, and
+    /// absent in messages from older peers, which request the full list.
+    /// Older peers reject messages carrying it, so it must only be set
+    /// for peers advertising [`ADDRS_SINCE_FEATURE`].
+    pub since: Option<u64>,
+    /// Request the response as a [`SignedAddrsMessage`]. Appended after
+    /// `since` when set, and absent in messages from older peers. It must
+    /// only be set for peers advertising [`SIGNED_ADDRS_FEATURE`].
+    pub signed: bool,
 }
 impl_p2p_message!(GetAddrsMessage, "getaddr");
 
+/// Entry of [`VersionMessage::features`] advertising that the sender
+/// understands [`GetAddrsMessage::since`].
+pub const ADDRS_SINCE_FEATURE: &str = "addrs_since";
+
+#[async_trait]
+impl AsyncEncodable for GetAddrsMessage {
+    async fn encode_async<S: AsyncWrite + Unpin + Send>(&self, s: &mut S) -> io::Result<usize> {
+        let mut len = 0;
+        len += self.max.encode_async(s).await?;
+        len += self.transports.encode_async(s).await?;
+
+        // Trailing fields are only encoded up to the last one that's set,
+        // so requests not using them keep the layout older peers expect.
+        if self.since.is_some() || self.signed {
+            len += self.since.encode_async(s).await?;
+        }
+        if self.signed {
+            len += self.signed.encode_async(s).await?;
+        }
+        Ok(len)
+    }
+}
+
+#[async_trait]
+impl AsyncDecodable for GetAddrsMessage {
+    async fn decode_async<D: AsyncRead + Unpin + Send>(d: &mut D) -> io::Result<Self> {
+        let max = AsyncDecodable::decode_async(d).await?;
+        let transports = AsyncDecodable::decode_async(d).await?;
+
+        // Older peers end the message here, requesting the full list
+        let since = match Option::<u64>::decode_async(d).await {
+            Ok(v) => v,
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => None,
+            Err(e) => return Err(e),
+        };
+
+        let signed = match bool::decode_async(d).await {
+            Ok(v) => v,
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => false,
+            Err(e) => return Err(e),
+        };
+
+        Ok(Self { max, transports, since, signed })
+    }
+}
+
 /// Sends address information to inbound connection.
 #[derive(Debug, Clone, SerialEncodable, SerialDecodable)]
 pub struct AddrsMessage {
@@ -84,8 +349,85 @@ pub struct AddrsMessage {
 
 impl_p2p_message!(AddrsMessage, "addr");
 
-/// Requests version information of outbound connection.
+/// Entry of [`VersionMessage::features`] advertising that the sender
+/// understands [`GetAddrsMessage::signed`] and [`SignedAddrsMessage`].
+pub const SIGNED_ADDRS_FEATURE: &str = "signed_addrs";
+
+/// Domain separator for the payload signed in a [`SignedAddrsMessage`]
+const SIGNED_ADDRS_DOMAIN: &[u8] = b"darkfi:net:signedaddr";
+
+/// How far in seconds the timestamp of a [`SignedAddrsMessage`] may be
+/// from our clock before the list is considered stale
+pub const SIGNED_ADDRS_MAX_AGE: u64 = 600;
+
+/// Address information signed by a seed node, letting peers check the
+/// list wasn't tampered with before trusting its entries. The signature
+/// covers when the list was signed, so old lists can't be replayed.
 #[derive(Debug, Clone, SerialEncodable, SerialDecodable)]
+pub struct SignedAddrsMessage {
+    pub addrs: Vec<(Url, u64)>,
+    /// UNIX timestamp of when the list was signed
+    pub timestamp: u64,
+    /// Public key of the seed that signed the list
+    pub signer: PublicKey,
+    /// Schnorr signature over the address list and timestamp
+    pub signature: Signature,
+}
+
+impl_p2p_message!(SignedAddrsMessage, "signedaddr");
+
+impl SignedAddrsMessage {
+    /// Sign the given address list with the seed's keypair
+    pub fn sign(addrs: Vec<(Url, u64)>, keypair: &Keypair) -> Self {
+        Self::sign_at(addrs, keypair, UNIX_EPOCH.elapsed().unwrap().as_secs())
+    }
+
+    fn sign_at(addrs: Vec<(Url, u64)>, keypair: &Keypair, timestamp: u64) -> Self {
+        let signature = keypair.secret.sign(&Self::signing_payload(&addrs, timestamp));
+        Self { addrs, timestamp, signer: keypair.public, signature }
+    }
+
+    /// Returns `true` if the list was signed by one of the `trusted`
+    /// keys, the signature is valid, and it was signed within
+    /// [`SIGNED_ADDRS_MAX_AGE`] of now.
+    pub fn verify(&self, trusted: &[PublicKey]) -> bool {
+        let now = UNIX_EPOCH.elapsed().unwrap().as_secs();
+        if now.abs_diff(self.timestamp) > SIGNED_ADDRS_MAX_AGE {
+            return false
+        }
+
+        let payload = Self::signing_payload(&self.addrs, self.timestamp);
+        trusted.contains(&self.signer) && self.signer.verify(&payload, &self.signature)
+    }
+
+    fn signing_payload(addrs: &[(Url, u64)], timestamp: u64) -> Vec<u8> {
+        let mut payload = SIGNED_ADDRS_DOMAIN.to_vec();
+        payload.extend_from_slice(&serialize(&addrs.to_vec()));
+        payload.extend_from_slice(&serialize(&timestamp));
+        payload
+    }
+}
+
+/// Bitwise flags for the services a node advertises in its [`VersionMessage`].
+/// They are sent as the version of the [`SERVICES_FEATURE`] feature, so
+/// they're limited to 32 bits.
+pub type ServiceFlags = u32;
+/// Node does not advertise any services
+pub const SERVICE_NONE: ServiceFlags = 0;
+/// Node relays messages to other peers
+pub const SERVICE_RELAY: ServiceFlags = 0b0001;
+/// Node keeps and serves historical data
+pub const SERVICE_ARCHIVAL: ServiceFlags = 0b0010;
+/// Node acts as a seed for peer discovery
+pub const SERVICE_SEED: ServiceFlags = 0b0100;
+
+/// Entry of [`VersionMessage::features`] carrying the sender's
+/// [`ServiceFlags`] as its version. Older peers ignore features they
+/// don't know, so this keeps the version message readable for them.
+pub const SERVICES_FEATURE: &str = "services";
+
+/// Requests version information of outbound connection.
+#[derive(Debug, Clone)]
 pub struct VersionMessage {
     /// Only used for debugging. Compromises privacy when set.
     pub node_id: String,
@@ -105,9 +447,64 @@ pub struct VersionMessage {
     /// List of features consisting of a tuple of (services, version)
     /// to be enabled for this connection
     pub features: Vec<(String, u32)>,
+    /// Bitflags of the services this node provides. Sent as the
+    /// [`SERVICES_FEATURE`] entry of `features`, and defaulted to
+    /// [`SERVICE_NONE`] for older peers that don't send it.
+    pub services: ServiceFlags,
 }
 impl_p2p_message!(VersionMessage, "version");
 
+#[async_trait]
+impl AsyncEncodable for VersionMessage {
+    async fn encode_async<S: AsyncWrite + Unpin + Send>(&self, s: &mut S) -> io::Result<usize> {
+        let mut len = 0;
+        len += self.node_id.encode_async(s).await?;
+        len += self.version.encode_async(s).await?;
+        len += self.timestamp.encode_async(s).await?;
+        len += self.connect_recv_addr.encode_async(s).await?;
+        len += self.resolve_recv_addr.encode_async(s).await?;
+        len += self.ext_send_addr.encode_async(s).await?;
+
+        let mut features = self.features.clone();
+        if self.services != SERVICE_NONE {
+            features.push((SERVICES_FEATURE.to_string(), self.services));
+        }
+        len += features.encode_async(s).await?;
+        Ok(len)
+    }
+}
+
+#[async_trait]
+impl AsyncDecodable for VersionMessage {
+    async fn decode_async<D: AsyncRead + Unpin + Send>(d: &mut D) -> io::Result<Self> {
+        let node_id = AsyncDecodable::decode_async(d).await?;
+        let version = AsyncDecodable::decode_async(d).await?;
+        let timestamp = AsyncDecodable::decode_async(d).await?;
+        let connect_recv_addr = AsyncDecodable::decode_async(d).await?;
+        let resolve_recv_addr = AsyncDecodable::decode_async(d).await?;
+        let ext_send_addr = AsyncDecodable::decode_async(d).await?;
+        let mut features: Vec<(String, u32)> = AsyncDecodable::decode_async(d).await?;
+
+        // Older peers don't send the services feature, so treat it as
+        // not advertising any services.
+        let mut services = SERVICE_NONE;
+        if let Some(i) = features.iter().position(|(name, _)| name == SERVICES_FEATURE) {
+            services = features.remove(i).1;
+        }
+
+        Ok(Self {
+            node_id,
+            version,
+            timestamp,
+            connect_recv_addr,
+            resolve_recv_addr,
+            ext_send_addr,
+            features,
+            services,
+        })
+    }
+}
+
 /// Sends version information to inbound connection.
 /// Response to `VersionMessage`.
 #[derive(Debug, Clone, SerialEncodable, SerialDecodable)]
@@ -116,3 +513,208 @@ pub struct VerackMessage {
     pub app_version: semver::Version,
 }
 impl_p2p_message!(VerackMessage, "verack");
+
+/// Acknowledges that a message was received and handled.
+/// Sent by protocols that confirm delivery, see `Channel::ack()`.
+#[derive(Debug, Copy, Clone, SerialEncodable, SerialDecodable)]
+pub struct AckMessage {
+    /// Identifier of the acknowledged message, see `SerializedMessage::id()`
+    pub id: [u8; 32],
+}
+impl_p2p_message!(AckMessage, "ack");
+
+#[cfg(test)]
+mod tests {
+    use darkfi_serial::deserialize_async;
+    use rand::rngs::OsRng;
+
+    use super::*;
+
+    /// Version message as sent and decoded by peers predating services
+    #[derive(Debug, Clone, SerialEncodable, SerialDecodable)]
+    struct LegacyVersionMessage {
+        node_id: String,
+        version: semver::Version,
+        timestamp: u64,
+        connect_recv_addr: Url,
+        resolve_recv_addr: Option<Url>,
+        ext_send_addr: Vec<Url>,
+        features: Vec<(String, u32)>,
+    }
+
+    fn version_message(services: ServiceFlags) -> VersionMessage {
+        VersionMessage {
+            node_id: String::new(),
+            version: semver::Version::new(0, 4, 1),
+            timestamp: 1000,
+            connect_recv_addr: Url::parse("tcp://127.0.0.1:26661").unwrap(),
+            resolve_recv_addr: None,
+            ext_send_addr: vec![],
+            features: vec![("protocol".to_string(), 1)],
+            services,
+        }
+    }
+
+    #[test]
+    fn version_message_services_compat() {
+        smol::block_on(async {
+            let version = version_message(SERVICE_SEED | SERVICE_RELAY);
+            let bytes = serialize_async(&version).await;
+
+            // Services survive the wire, without leaking into the features
+            let decoded: VersionMessage = deserialize_async(&bytes).await.unwrap();
+            assert_eq!(decoded.services, SERVICE_SEED | SERVICE_RELAY);
+            assert_eq!(decoded.features, version.features);
+
+            // Older peers decode the whole message, and see services as
+            // just another feature
+            let legacy: LegacyVersionMessage = deserialize_async(&bytes).await.unwrap();
+            assert_eq!(legacy.timestamp, 1000);
+            assert_eq!(legacy.features[1], (SERVICES_FEATURE.to_string(), version.services));
+
+            // Without services, nothing is added for them to decode
+            let version = version_message(SERVICE_NONE);
+            let bytes = serialize_async(&version).await;
+            let legacy: LegacyVersionMessage = deserialize_async(&bytes).await.unwrap();
+            assert_eq!(legacy.features, version.features);
+
+            // Messages from older peers don't advertise any services
+            let bytes = serialize_async(&legacy).await;
+            let decoded: VersionMessage = deserialize_async(&bytes).await.unwrap();
+            assert_eq!(decoded.services, SERVICE_NONE);
+            assert_eq!(decoded.features, version.features);
+        })
+    }
+
+    /// Address request as sent and decoded by peers predating `since`
+    #[derive(Debug, Clone, SerialEncodable, SerialDecodable)]
+    struct LegacyGetAddrsMessage {
+        max: u32,
+        transports: Vec<String>,
+    }
+
+    #[test]
+    fn get_addrs_message_since_compat() {
+        smol::block_on(async {
+            let transports = vec!["tcp".to_string()];
+
+            // Without `since`, the request is exactly what older peers
+            // decode, with nothing left over
+            let get_addrs = GetAddrsMessage {
+                max: 8,
+                transports: transports.clone(),
+                since: None,
+                signed: false,
+            };
+            let bytes = serialize_async(&get_addrs).await;
+            let legacy: LegacyGetAddrsMessage = deserialize_async(&bytes).await.unwrap();
+            assert_eq!(legacy.max, 8);
+            assert_eq!(legacy.transports, transports);
+
+            // With it, the filter survives the wire
+            let get_addrs = GetAddrsMessage { since: Some(1000), ..get_addrs };
+            let bytes = serialize_async(&get_addrs).await;
+            let decoded: GetAddrsMessage = deserialize_async(&bytes).await.unwrap();
+            assert_eq!(decoded.since, Some(1000));
+            assert!(!decoded.signed);
+
+            // Requests from older peers ask for the full list
+            let bytes = serialize_async(&legacy).await;
+            let decoded: GetAddrsMessage = deserialize_async(&bytes).await.unwrap();
+            assert_eq!(decoded.max, 8);
+            assert_eq!(decoded.since, None);
+            assert!(!decoded.signed);
+        })
+    }
+
+    #[test]
+    fn get_addrs_message_signed_compat() {
+        smol::block_on(async {
+            let get_addrs =
+                GetAddrsMessage { max: 8, transports: vec![], since: None, signed: true };
+
+            // Asking for a signed list also encodes the unset `since`
+            let bytes = serialize_async(&get_addrs).await;
+            let decoded: GetAddrsMessage = deserialize_async(&bytes).await.unwrap();
+            assert_eq!(decoded.since, None);
+            assert!(decoded.signed);
+
+            // Without it, older peers decode the request as usual
+            let get_addrs = GetAddrsMessage { signed: false, ..get_addrs };
+            let bytes = serialize_async(&get_addrs).await;
+            let legacy: LegacyGetAddrsMessage = deserialize_async(&bytes).await.unwrap();
+            assert_eq!(legacy.max, 8);
+        })
+    }
+
+    #[test]
+    fn signed_addrs_message_verification() {
+        smol::block_on(async {
+            let seed = Keypair::random(&mut OsRng);
+            let other = Keypair::random(&mut OsRng);
+            let addrs = vec![
+                (Url::parse("tcp+tls://127.0.0.1:26661").unwrap(), 1000),
+                (Url::parse("tcp+tls://127.0.0.1:26662").unwrap(), 2000),
+            ];
+
+            // A valid list survives the wire and verifies against the seed key
+            let signed = SignedAddrsMessage::sign(addrs.clone(), &seed);
+            let bytes = serialize_async(&signed).await;
+            let decoded: SignedAddrsMessage = deserialize_async(&bytes).await.unwrap();
+            assert_eq!(decoded.addrs, addrs);
+            assert!(decoded.verify(&[seed.public]));
+
+            // Lists signed by keys we don't trust are rejected
+            assert!(!decoded.verify(&[other.public]));
+            assert!(!decoded.verify(&[]));
+
+            // Tampering with any entry invalidates the signature
+            let mut tampered = decoded.clone();
+            tampered.addrs[1].0 = Url::parse("tcp+tls://6.6.6.6:26662").unwrap();
+            assert!(!tampered.verify(&[seed.public]));
+
+            let mut tampered = decoded.clone();
+            tampered.addrs.push((Url::parse("tcp+tls://6.6.6.6:26663").unwrap(), 3000));
+            assert!(!tampered.verify(&[seed.public]));
+
+            // Swapping in another signer doesn't help either
+            let mut tampered = decoded.clone();
+            tampered.signer = other.public;
+            assert!(!tampered.verify(&[seed.public, other.public]));
+
+            // Nor does refreshing the timestamp of an old list
+            let now = UNIX_EPOCH.elapsed().unwrap().as_secs();
+            let stale = SignedAddrsMessage::sign_at(addrs.clone(), &seed, now - 3600);
+            assert!(!stale.verify(&[seed.public]));
+            let mut replayed = stale;
+            replayed.timestamp = now;
+            assert!(!replayed.verify(&[seed.public]));
+
+            // Lists signed too far in the future are rejected too
+            let future = SignedAddrsMessage::sign_at(addrs, &seed, now + 3600);
+            assert!(!future.verify(&[seed.public]));
+        })
+    }
+
+    #[test]
+    fn read_payload_bounded() {
+        smol::block_on(async {
+            // The longest allowed payload is read, and is missing here
+            let bytes = serialize_async(&VarInt(1024)).await;
+            let res = Frame::read_payload(&mut &bytes[..], 1024).await;
+            assert!(res.is_err());
+            assert!(!matches!(res, Err(crate::Error::PayloadTooLarge)));
+
+            // Anything longer is rejected upfront
+            let mut bytes = serialize_async(&VarInt(1025)).await;
+            bytes.extend(vec![0; 16]);
+            let res = Frame::read_payload(&mut &bytes[..], 1024).await;
+            assert!(matches!(res, Err(crate::Error::PayloadTooLarge)));
+
+            // Unless the limit is disabled
+            let mut bytes = serialize_async(&VarInt(16)).await;
+            bytes.extend(vec![0; 16]);
+            assert_eq!(Frame::read_payload(&mut &bytes[..], 0).await.unwrap(), vec![0; 16]);
+        })
+    }
+}
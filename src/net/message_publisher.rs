@@ -16,29 +16,61 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
-use std::{any::Any, collections::HashMap, sync::Arc, time::Duration};
+use std::{any::Any, collections::HashMap, fmt, sync::Arc, time::Duration};
 
 use async_trait::async_trait;
 use futures::stream::{FuturesUnordered, StreamExt};
 use log::{debug, error, warn};
 use rand::{rngs::OsRng, Rng};
-use smol::{io::AsyncReadExt, lock::Mutex};
+use smol::{
+    io::{self, AsyncRead, AsyncReadExt, Take},
+    lock::Mutex,
+};
 
-use super::message::Message;
-use crate::{net::transport::PtStream, system::timeout::timeout, Error, Result};
+use super::message::{payload_len, Message};
+use crate::{system::timeout::timeout, Error, Result};
 use darkfi_serial::{AsyncDecodable, VarInt};
 
 /// 64-bit identifier for message subscription.
 pub type MessageSubscriptionId = u64;
 type MessageResult<M> = Result<Arc<M>>;
 
+/// Predicate deciding which messages a filtered subscription receives
+pub type MessageFilter<M> = Arc<dyn Fn(&M) -> bool + Send + Sync>;
+
+/// Sending end of a subscription, along with its optional filter
+struct Subscriber<M: Message> {
+    sender: smol::channel::Sender<MessageResult<M>>,
+    filter: Option<MessageFilter<M>>,
+}
+
+impl<M: Message> Subscriber<M> {
+    /// Check whether `message` should be sent to this subscriber.
+    /// Errors are always delivered.
+    fn wants(&self, message: &MessageResult<M>) -> bool {
+        match (&self.filter, message) {
+            (Some(filter), Ok(message)) => filter(message),
+            _ => true,
+        }
+    }
+}
+
+impl<M: Message> fmt::Debug for Subscriber<M> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Subscriber")
+            .field("sender", &self.sender)
+            .field("filtered", &self.filter.is_some())
+            .finish()
+    }
+}
+
 /// A dispatcher that is unique to every [`Message`].
 /// Maintains a list of subscriptions to a unique Message
 /// type and handles sending messages across these
 /// subscriptions.
 #[derive(Debug)]
 struct MessageDispatcher<M: Message> {
-    subs: Mutex<HashMap<MessageSubscriptionId, smol::channel::Sender<MessageResult<M>>>>,
+    subs: Mutex<HashMap<MessageSubscriptionId, Subscriber<M>>>,
 }
 
 impl<M: Message> MessageDispatcher<M> {
@@ -54,7 +86,12 @@ impl<M: Message> MessageDispatcher<M> {
 
     /// Subscribe to a channel.
     /// Assigns a new ID and adds it to the list of subscriptions.
-    pub async fn subscribe(self: Arc<Self>) -> MessageSubscription<M> {
+    /// With a `filter`, only messages it returns `true` for are sent
+    /// to the subscription.
+    pub async fn subscribe(
+        self: Arc<Self>,
+        filter: Option<MessageFilter<M>>,
+    ) -> MessageSubscription<M> {
         let (sender, recv_queue) = smol::channel::unbounded();
         // Guard against overwriting
         let mut id = Self::random_id();
@@ -65,7 +102,7 @@ impl<M: Message> MessageDispatcher<M> {
                 continue
             }
 
-            subs.insert(id, sender);
+            subs.insert(id, Subscriber { sender, filter });
             break
         }
 
@@ -93,10 +130,11 @@ impl<M: Message> MessageDispatcher<M> {
         let mut futures = FuturesUnordered::new();
         let mut garbage_ids = vec![];
 
-        // Prep the futures for concurrent execution
-        for (sub_id, sub) in &*subs {
+        // Prep the futures for concurrent execution. Filters are applied
+        // here so subscribers only get woken up for messages they want.
+        for (sub_id, sub) in subs.iter().filter(|(_, sub)| sub.wants(&message)) {
             let sub_id = *sub_id;
-            let sub = sub.clone();
+            let sub = sub.sender.clone();
             let message = message.clone();
             futures.push(async move {
                 match sub.send(message).await {
@@ -175,14 +213,23 @@ impl<M: Message> MessageSubscription<M> {
     }
 }
 
+/// Stream of a single message payload, limited to its declared length
+type PayloadReader<'a> = Take<&'a mut (dyn AsyncRead + Unpin + Send)>;
+
 /// Generic interface for the message dispatcher.
 #[async_trait]
 trait MessageDispatcherInterface: Send + Sync {
-    async fn trigger(&self, stream: &mut smol::io::ReadHalf<Box<dyn PtStream + 'static>>);
+    async fn trigger(&self, stream: &mut PayloadReader<'_>) -> Result<()>;
 
     async fn trigger_error(&self, err: Error);
 
     fn as_any(self: Arc<Self>) -> Arc<dyn Any + Send + Sync>;
+
+    /// Create a dispatcher for the same message type, without subscribers
+    fn fresh(&self) -> Arc<dyn MessageDispatcherInterface>;
+
+    /// Whether the message type opted into deduplication
+    fn dedup(&self) -> bool;
 }
 
 /// Local implementation of the Message Dispatcher Interface
@@ -190,39 +237,24 @@ trait MessageDispatcherInterface: Send + Sync {
 impl<M: Message> MessageDispatcherInterface for MessageDispatcher<M> {
     /// Internal function to deserialize data into a message type
     /// and dispatch it across subscriber channels. Reads directly
-    /// from an inbound stream.
-    ///
-    /// We extract the message length from the stream and use `take()`
-    /// to allocate an appropiately sized buffer as a basic DDOS protection.
-    async fn trigger(&self, stream: &mut smol::io::ReadHalf<Box<dyn PtStream + 'static>>) {
-        match VarInt::decode_async(stream).await {
-            Ok(int) => {
-                // TODO: check the message length does not exceed some bound.
-                let len = int.0;
-                let mut take = stream.take(len);
-
-                // Deserialize stream into type, send down the pipes.
-                match M::decode_async(&mut take).await {
-                    Ok(payload) => {
-                        let message = Ok(Arc::new(payload));
-                        self._trigger_all(message).await
-                    }
-
-                    Err(err) => {
-                        error!(
-                            target: "net::message_publisher::trigger()",
-                            "Unable to decode data. Dropping...: {}",
-                            err,
-                        );
-                    }
-                }
+    /// from an inbound stream, which ends with the message payload.
+    /// Returns an error if the payload doesn't decode into a message.
+    async fn trigger(&self, stream: &mut PayloadReader<'_>) -> Result<()> {
+        // Deserialize stream into type, send down the pipes.
+        match M::decode_async(stream).await {
+            Ok(payload) => {
+                let message = Ok(Arc::new(payload));
+                self._trigger_all(message).await;
+                Ok(())
             }
+
             Err(err) => {
                 error!(
                     target: "net::message_publisher::trigger()",
-                    "Unable to decode VarInt. Dropping...: {}",
+                    "Unable to decode data. Dropping...: {}",
                     err,
                 );
+                Err(Error::MalformedPacket)
             }
         }
     }
@@ -236,6 +268,35 @@ impl<M: Message> MessageDispatcherInterface for MessageDispatcher<M> {
     fn as_any(self: Arc<Self>) -> Arc<dyn Any + Send + Sync> {
         self
     }
+
+    fn fresh(&self) -> Arc<dyn MessageDispatcherInterface> {
+        Arc::new(Self::new())
+    }
+
+    fn dedup(&self) -> bool {
+        M::DEDUP
+    }
+}
+
+/// The set of message types a [`MessageSubsystem`] has dispatchers for,
+/// which can be restored on another subsystem, e.g. when failing over
+/// to a new channel. Subscriptions themselves aren't carried over.
+#[derive(Clone)]
+pub struct SubscriptionSnapshot {
+    dispatchers: Vec<(&'static str, Arc<dyn MessageDispatcherInterface>)>,
+}
+
+impl SubscriptionSnapshot {
+    /// Returns the commands in the snapshot, sorted by name
+    pub fn commands(&self) -> Vec<&'static str> {
+        self.dispatchers.iter().map(|(command, _)| *command).collect()
+    }
+}
+
+impl fmt::Debug for SubscriptionSnapshot {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SubscriptionSnapshot").field("commands", &self.commands()).finish()
+    }
 }
 
 /// Generic publish/subscribe class that maintains a list of dispatchers.
@@ -257,10 +318,80 @@ impl MessageSubsystem {
         self.dispatchers.lock().await.insert(M::NAME, Arc::new(MessageDispatcher::<M>::new()));
     }
 
+    /// Returns the commands of all registered dispatchers, sorted by name.
+    /// Messages with any other command are treated as spam.
+    pub async fn registered_commands(&self) -> Vec<String> {
+        let mut commands: Vec<String> =
+            self.dispatchers.lock().await.keys().map(|command| command.to_string()).collect();
+        commands.sort();
+        commands
+    }
+
+    /// Take a snapshot of the message types this subsystem dispatches
+    pub async fn snapshot(&self) -> SubscriptionSnapshot {
+        let mut dispatchers: Vec<_> = self
+            .dispatchers
+            .lock()
+            .await
+            .iter()
+            .map(|(command, dispatcher)| (*command, dispatcher.fresh()))
+            .collect();
+        dispatchers.sort_by_key(|(command, _)| *command);
+        SubscriptionSnapshot { dispatchers }
+    }
+
+    /// Add dispatchers for the message types in `snapshot`. Dispatchers
+    /// that are already registered are kept along with their subscribers.
+    pub async fn restore(&self, snapshot: &SubscriptionSnapshot) {
+        let mut dispatchers = self.dispatchers.lock().await;
+        for (command, dispatcher) in &snapshot.dispatchers {
+            dispatchers.entry(*command).or_insert_with(|| dispatcher.fresh());
+        }
+    }
+
+    /// Returns `true` if `command` is dispatched as a [`Message`] that
+    /// opted into deduplication with [`Message::DEDUP`]
+    pub async fn is_dedup(&self, command: &str) -> bool {
+        self.dispatchers.lock().await.get(command).is_some_and(|dispatcher| dispatcher.dedup())
+    }
+
+    /// Returns the amount of active subscriptions to a [`Message`], or
+    /// zero if it has no dispatcher.
+    pub async fn subscriber_count<M: Message>(&self) -> usize {
+        let Some(dispatcher) = self.dispatchers.lock().await.get(M::NAME).cloned() else {
+            return 0
+        };
+
+        let dispatcher: Arc<MessageDispatcher<M>> = dispatcher
+            .as_any()
+            .downcast::<MessageDispatcher<M>>()
+            .expect("Multiple messages registered with different names");
+
+        let count = dispatcher.subs.lock().await.len();
+        count
+    }
+
     /// Subscribes to a [`Message`]. Using the Message name, the method
     /// returns the associated `MessageDispatcher` from the list of
     /// dispatchers and calls `subscribe()`.
     pub async fn subscribe<M: Message>(&self) -> Result<MessageSubscription<M>> {
+        self.subscribe_with_filter(None).await
+    }
+
+    /// Subscribes to the [`Message`]s `filter` returns `true` for. The
+    /// filter runs once per message before it's sent to subscribers, so
+    /// errors are delivered regardless.
+    pub async fn subscribe_filtered<M: Message>(
+        &self,
+        filter: impl Fn(&M) -> bool + Send + Sync + 'static,
+    ) -> Result<MessageSubscription<M>> {
+        self.subscribe_with_filter(Some(Arc::new(filter))).await
+    }
+
+    async fn subscribe_with_filter<M: Message>(
+        &self,
+        filter: Option<MessageFilter<M>>,
+    ) -> Result<MessageSubscription<M>> {
         let dispatcher = self.dispatchers.lock().await.get(M::NAME).cloned();
 
         let sub = match dispatcher {
@@ -270,7 +401,7 @@ impl MessageSubsystem {
                     .downcast::<MessageDispatcher<M>>()
                     .expect("Multiple messages registered with different names");
 
-                dispatcher.subscribe().await
+                dispatcher.subscribe(filter).await
             }
 
             None => {
@@ -283,11 +414,19 @@ impl MessageSubsystem {
     }
 
     /// Transmits a payload to a dispatcher.
-    /// Returns an error if the payload fails to transmit.
+    ///
+    /// We extract the payload length from the stream and use `take()` so
+    /// the dispatcher can only read its own payload, which also bounds the
+    /// buffers it allocates as a basic DDOS protection. Bytes trailing the
+    /// message are skipped. Returns an error if the dispatcher is missing,
+    /// or if the payload doesn't decode into the message. The stream is
+    /// left at the start of the next frame either way, except for payloads
+    /// over `max_payload_len` (unless it's 0), which are rejected unread.
     pub async fn notify(
         &self,
         command: &str,
-        reader: &mut smol::io::ReadHalf<Box<dyn PtStream + 'static>>,
+        mut reader: &mut (dyn AsyncRead + Unpin + Send),
+        max_payload_len: u64,
     ) -> Result<()> {
         let Some(dispatcher) = self.dispatchers.lock().await.get(command).cloned() else {
             warn!(
@@ -298,8 +437,35 @@ impl MessageSubsystem {
             return Err(Error::MissingDispatcher)
         };
 
-        dispatcher.trigger(reader).await;
-        Ok(())
+        let len = match VarInt::decode_async(&mut reader).await {
+            Ok(int) => int.0,
+            Err(err) => {
+                error!(
+                    target: "net::message_publisher::notify",
+                    "Unable to decode VarInt: {}", err,
+                );
+                return Err(Error::MalformedPacket)
+            }
+        };
+        payload_len(len, max_payload_len)?;
+
+        let mut payload = reader.take(len);
+        let res = dispatcher.trigger(&mut payload).await;
+
+        // Skip whatever the dispatcher left unread, keeping the stream
+        // in sync for the next frame. Newer peers may append fields we
+        // don't know about, so trailing bytes aren't an error by themselves.
+        let leftover = payload.limit();
+        if leftover != 0 {
+            debug!(
+                target: "net::message_publisher::notify",
+                "message_publisher::notify: Command '{}' left {} payload bytes unread",
+                command, leftover,
+            );
+            io::copy(&mut payload, &mut io::sink()).await?;
+        }
+
+        res
     }
 
     /// Concurrently transmits an error message across dispatchers.
@@ -318,4 +484,101 @@ impl MessageSubsystem {
 
         while let Some(_r) = futures.next().await {}
     }
+
+    /// Transmits an error message to the subscribers of a single command.
+    pub async fn trigger_error_for(&self, command: &str, err: Error) {
+        let dispatcher = self.dispatchers.lock().await.get(command).cloned();
+        if let Some(dispatcher) = dispatcher {
+            dispatcher.trigger_error(err).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use smol::io::Cursor;
+
+    use super::*;
+    use crate::net::message::PingMessage;
+    use darkfi_serial::serialize;
+
+    /// Frame `payload` with its VarInt length, as found on the wire
+    fn frame(payload: &[u8]) -> Vec<u8> {
+        let mut frame = serialize(&VarInt(payload.len() as u64));
+        frame.extend_from_slice(payload);
+        frame
+    }
+
+    #[test]
+    fn notify_bounds_payload() {
+        smol::block_on(async {
+            let subsystem = MessageSubsystem::new();
+            subsystem.add_dispatch::<PingMessage>().await;
+            let sub = subsystem.subscribe::<PingMessage>().await.unwrap();
+
+            // A ping needs 2 bytes, the first frame only declares 1. The
+            // dispatcher can't read into the next frame, which still goes
+            // through.
+            let mut stream = frame(&[0x01]);
+            stream.extend(frame(&2u16.to_le_bytes()));
+            // Extra payload bytes are skipped, the message still goes through
+            stream.extend(frame(&[0x03, 0x00, 0xff, 0xff]));
+            stream.extend(frame(&4u16.to_le_bytes()));
+            let mut reader = Cursor::new(stream);
+
+            assert!(matches!(
+                subsystem.notify("ping", &mut reader, 0).await,
+                Err(Error::MalformedPacket)
+            ));
+            subsystem.notify("ping", &mut reader, 0).await.unwrap();
+            assert_eq!(sub.receive().await.unwrap().nonce, 2);
+
+            subsystem.notify("ping", &mut reader, 0).await.unwrap();
+            assert_eq!(sub.receive().await.unwrap().nonce, 3);
+            subsystem.notify("ping", &mut reader, 0).await.unwrap();
+            assert_eq!(sub.receive().await.unwrap().nonce, 4);
+
+            // The short frame wasn't dispatched
+            assert!(sub.recv_queue.is_empty());
+        });
+    }
+
+    #[test]
+    fn filtered_subscriptions() {
+        smol::block_on(async {
+            let subsystem = MessageSubsystem::new();
+            subsystem.add_dispatch::<PingMessage>().await;
+            let even = subsystem
+                .subscribe_filtered(|ping: &PingMessage| ping.nonce % 2 == 0)
+                .await
+                .unwrap();
+            let odd = subsystem
+                .subscribe_filtered(|ping: &PingMessage| ping.nonce % 2 == 1)
+                .await
+                .unwrap();
+            let all = subsystem.subscribe::<PingMessage>().await.unwrap();
+
+            let mut stream = vec![];
+            for nonce in 1u16..=4 {
+                stream.extend(frame(&nonce.to_le_bytes()));
+            }
+            let mut reader = Cursor::new(stream);
+            for _ in 0..4 {
+                subsystem.notify("ping", &mut reader, 0).await.unwrap();
+            }
+
+            assert_eq!(even.receive().await.unwrap().nonce, 2);
+            assert_eq!(even.receive().await.unwrap().nonce, 4);
+            assert!(even.recv_queue.is_empty());
+            assert_eq!(odd.receive().await.unwrap().nonce, 1);
+            assert_eq!(odd.receive().await.unwrap().nonce, 3);
+            assert!(odd.recv_queue.is_empty());
+            assert_eq!(all.recv_queue.len(), 4);
+
+            // Errors reach every subscriber
+            subsystem.trigger_error(Error::ChannelStopped).await;
+            assert!(even.receive().await.is_err());
+            assert!(odd.receive().await.is_err());
+        });
+    }
 }
@@ -49,7 +49,7 @@ pub use message::Message;
 /// trait called Message Dispatcher Interface, which allows us to process any
 /// kind of payload as a message.
 pub mod message_publisher;
-pub use message_publisher::MessageSubscription;
+pub use message_publisher::{MessageSubscription, SubscriptionSnapshot};
 
 /// Network transports, holds implementations of pluggable transports.
 /// Exposes agnostic dialers and agnostic listeners.
@@ -119,10 +119,19 @@ pub mod acceptor;
 /// Used to establish an outbound connection.
 pub mod connector;
 
+/// Outbound connection to a single peer which is redialed on disconnect,
+/// keeping its message subscriptions alive across reconnects.
+pub mod persistent_channel;
+pub use persistent_channel::{PersistentChannel, PersistentChannelPtr, PersistentSubscription};
+
 /// Network configuration settings. This holds the configured P2P instance
 /// behaviour and is controlled by clients of this API.
 pub mod settings;
-pub use settings::{BanPolicy, Settings};
+pub use settings::{BanPolicy, Settings, SettingsBuilder, UnknownCommandAction};
+
+/// Aggregate traffic statistics across all channels of a P2P instance.
+pub mod stats;
+pub use stats::{NetStats, P2pHealth};
 
 /// Optional events based debug-notify subsystem. Off by default. Enabled in P2P instance,
 /// and then call `p2p.dnet_sub()` to start receiving events.
@@ -16,9 +16,12 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
-use std::sync::{
-    atomic::{AtomicBool, Ordering},
-    Arc,
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex as SyncMutex,
+    },
+    time::{Duration, Instant},
 };
 
 use futures::{stream::FuturesUnordered, TryFutureExt};
@@ -31,24 +34,41 @@ use smol::{
 };
 use url::Url;
 
+use darkfi_sdk::crypto::Keypair;
+
 use super::{
-    channel::ChannelPtr,
+    acceptor::{AcceptAll, AcceptFilterPtr},
+    channel::{ChannelPtr, PeerResolverPtr, SeenCache, ViolationPolicyPtr},
+    connector::TorFallbackPolicyPtr,
     dnet::DnetEvent,
-    hosts::{Hosts, HostsPtr},
-    message::{Message, SerializedMessage},
+    hosts::{HostColor, Hosts, HostsPtr},
+    message::{AckMessage, Message, SerializedMessage},
     protocol::{protocol_registry::ProtocolRegistry, register_default_protocols},
     session::{
+        outbound_session::{LastSeenSelector, SlotSelectorPtr},
         InboundSession, InboundSessionPtr, ManualSession, ManualSessionPtr, OutboundSession,
         OutboundSessionPtr, RefineSession, RefineSessionPtr, SeedSyncSession, SeedSyncSessionPtr,
+        SESSION_INBOUND, SESSION_MANUAL, SESSION_OUTBOUND, SESSION_REFINE,
     },
     settings::Settings,
+    stats::{NetStats, P2pHealth, TrafficStats},
+    transport::{
+        dns::{DnsCache, DnsCachePtr, DnsResolverPtr},
+        transport_status_with_tor, TransportHealth, TransportName,
+    },
 };
+
+#[cfg(feature = "p2p-tor")]
+use super::transport::{tor, SharedTorClient, TorBridgeConfig};
 use crate::{
-    system::{ExecutorPtr, Publisher, PublisherPtr, Subscription},
+    system::{msleep, timeout::timeout, ExecutorPtr, Publisher, PublisherPtr, Subscription},
     util::path::expand_path,
     Result,
 };
 
+/// How long [`P2p::shutdown()`] waits for channels to close gracefully
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(10);
+
 /// Atomic pointer to the p2p interface
 pub type P2pPtr = Arc<P2p>;
 
@@ -76,6 +96,40 @@ pub struct P2p {
     pub dnet_enabled: AtomicBool,
     /// The publisher for which we can give dnet info over
     dnet_publisher: PublisherPtr<DnetEvent>,
+    /// Optional application-provided resolver used to tag new channels
+    /// with their peer's network location
+    peer_resolver: SyncMutex<Option<PeerResolverPtr>>,
+    /// Optional application-provided policy deciding how to handle
+    /// misbehaving peers
+    violation_policy: SyncMutex<Option<ViolationPolicyPtr>>,
+    /// Filter deciding which inbound connections are accepted
+    accept_filter: SyncMutex<AcceptFilterPtr>,
+    /// Optional policy allowing peers to be retried over clearnet when
+    /// dialing them over Tor fails
+    tor_fallback_policy: SyncMutex<Option<TorFallbackPolicyPtr>>,
+    /// Traffic rates aggregated across all channels
+    traffic_stats: TrafficStats,
+    /// Hashes of recently received messages, for deduplicating gossip
+    seen_cache: SeenCache,
+    /// Strategy ordering the candidate addresses of outbound slots
+    slot_selector: SyncMutex<SlotSelectorPtr>,
+    /// Keypair used to sign address lists served to peers
+    addrs_signing_key: SyncMutex<Option<Keypair>>,
+    /// When the P2P subsystem was started, if it's running
+    start_time: SyncMutex<Option<Instant>>,
+    /// Keypair the Noise static key of `tcp+noise` connections is
+    /// derived from
+    #[cfg(feature = "p2p-noise")]
+    noise_keypair: SyncMutex<Option<Keypair>>,
+    /// Noise static public keys `tcp+noise` peers must authenticate with
+    #[cfg(feature = "p2p-noise")]
+    noise_remote_keys: SyncMutex<Vec<[u8; 32]>>,
+    /// `TorClient` shared by the embedding application, used instead of
+    /// bootstrapping one for the Tor transport
+    #[cfg(feature = "p2p-tor")]
+    tor_client: SyncMutex<Option<SharedTorClient>>,
+    /// Cache for hostnames resolved when dialing TCP peers
+    dns_cache: DnsCachePtr,
 }
 
 impl P2p {
@@ -98,6 +152,17 @@ impl P2p {
         // Register a CryptoProvider for rustls
         let _ = CryptoProvider::install_default(ring::default_provider());
 
+        let dns_cache = Arc::new(DnsCache::new(
+            settings.dns_cache_size,
+            Duration::from_secs(settings.dns_cache_ttl),
+        ));
+
+        let seen_cache = SeenCache::new(
+            settings.message_dedup,
+            settings.message_dedup_size,
+            Duration::from_secs(settings.message_dedup_ttl),
+        );
+
         // Wrap the Settings into an Arc<RwLock>
         let settings = Arc::new(AsyncRwLock::new(settings));
 
@@ -113,6 +178,22 @@ impl P2p {
             session_seedsync: SeedSyncSession::new(p2p.clone()),
             dnet_enabled: AtomicBool::new(false),
             dnet_publisher: Publisher::new(),
+            peer_resolver: SyncMutex::new(None),
+            violation_policy: SyncMutex::new(None),
+            accept_filter: SyncMutex::new(Arc::new(AcceptAll)),
+            tor_fallback_policy: SyncMutex::new(None),
+            traffic_stats: TrafficStats::new(),
+            seen_cache,
+            slot_selector: SyncMutex::new(Arc::new(LastSeenSelector)),
+            addrs_signing_key: SyncMutex::new(None),
+            start_time: SyncMutex::new(None),
+            #[cfg(feature = "p2p-noise")]
+            noise_keypair: SyncMutex::new(None),
+            #[cfg(feature = "p2p-noise")]
+            noise_remote_keys: SyncMutex::new(vec![]),
+            #[cfg(feature = "p2p-tor")]
+            tor_client: SyncMutex::new(None),
+            dns_cache,
         });
 
         register_default_protocols(self_.clone()).await;
@@ -144,6 +225,7 @@ impl P2p {
         // Start the refine session
         self.session_refine().start().await;
 
+        *self.start_time.lock().unwrap() = Some(Instant::now());
         info!(target: "net::p2p::start", "[P2P] P2P subsystem started successfully");
         Ok(())
     }
@@ -166,6 +248,68 @@ impl P2p {
         self.session_seedsync().stop().await;
         self.session_outbound().stop().await;
         self.session_refine().stop().await;
+        *self.start_time.lock().unwrap() = None;
+    }
+
+    /// Shut down the P2P subsystem in order: stop accepting and opening
+    /// connections, gracefully close all channels and wait for the hosts
+    /// to be updated, and only then persist the hostlist. Returns once
+    /// the hostlist has been saved.
+    pub async fn shutdown(&self) {
+        self.shutdown_with_timeout(SHUTDOWN_TIMEOUT).await
+    }
+
+    /// Same as [`P2p::shutdown()`], but channels still open after
+    /// `timeout_` are stopped forcefully and removed from the hosts.
+    pub async fn shutdown_with_timeout(&self, timeout_: Duration) {
+        info!(target: "net::p2p::shutdown()", "[P2P] Shutting down P2P subsystem");
+
+        // Stop accepting connections first, so no channels appear while
+        // we're closing the existing ones.
+        self.session_inbound().stop().await;
+        self.session_manual().stop().await;
+        self.session_seedsync().stop().await;
+        self.session_outbound().stop().await;
+        self.session_refine().refinery.clone().stop().await;
+
+        // Refine channels are handled by the refinery, which is already stopped
+        let open_channels = || -> Vec<ChannelPtr> {
+            let channels = self.hosts.channels();
+            channels.into_iter().filter(|c| c.session_type_id() & SESSION_REFINE == 0).collect()
+        };
+
+        let close = async {
+            let channels = self.hosts.channels();
+            debug!(
+                target: "net::p2p::shutdown()",
+                "Closing {} channels", channels.len(),
+            );
+            let mut futures: FuturesUnordered<_> = channels.iter().map(|c| c.close()).collect();
+            while futures.next().await.is_some() {}
+
+            // Stopped channels are removed from the registry by their
+            // sessions, which also downgrades outbound peers.
+            while !open_channels().is_empty() {
+                msleep(10).await;
+            }
+        };
+
+        if timeout(timeout_, close).await.is_err() {
+            let channels = open_channels();
+            warn!(
+                target: "net::p2p::shutdown()",
+                "[P2P] {} channels didn't close in time, stopping them", channels.len(),
+            );
+            for channel in channels {
+                channel.stop().await;
+                self.hosts.unregister(channel.address());
+            }
+        }
+
+        // Saves the hostlist
+        self.session_refine().stop().await;
+        *self.start_time.lock().unwrap() = None;
+        info!(target: "net::p2p::shutdown()", "[P2P] P2P subsystem shut down");
     }
 
     /// Broadcasts a message concurrently across all active peers.
@@ -188,16 +332,92 @@ impl P2p {
 
     /// Broadcast a message concurrently to all given peers.
     pub async fn broadcast_to<M: Message>(&self, message: &M, channel_list: &[ChannelPtr]) {
+        let message = SerializedMessage::new(message).await;
+        self.broadcast_serialized(&message, channel_list).await
+    }
+
+    /// Broadcast a message concurrently across all active peers, and wait
+    /// until `quorum` of them acknowledge it with an `AckMessage`, or until
+    /// `timeout_` elapses. Returns the addresses of the peers that acked,
+    /// which is less than `quorum` if the timeout was hit.
+    pub async fn broadcast_quorum<M: Message>(
+        &self,
+        message: &M,
+        quorum: usize,
+        timeout_: Duration,
+    ) -> Vec<Url> {
+        let channels = self.hosts().peers();
+        self.broadcast_quorum_to(message, &channels, quorum, timeout_).await
+    }
+
+    /// Same as `broadcast_quorum()`, but broadcasting to the given peers.
+    pub async fn broadcast_quorum_to<M: Message>(
+        &self,
+        message: &M,
+        channel_list: &[ChannelPtr],
+        quorum: usize,
+        timeout_: Duration,
+    ) -> Vec<Url> {
+        let message = SerializedMessage::new(message).await;
+        let id = message.id();
+
+        // Subscribe before sending, so no ack can be missed
+        let mut subs = Vec::with_capacity(channel_list.len());
+        for channel in channel_list {
+            match channel.subscribe_msg_filtered::<AckMessage>(move |ack| ack.id == id).await {
+                Ok(sub) => subs.push((channel, sub)),
+                Err(e) => warn!(
+                    target: "net::p2p::broadcast_quorum()",
+                    "[P2P] Unable to subscribe to acks from {}: {}",
+                    channel.address(), e
+                ),
+            }
+        }
+
+        self.broadcast_serialized(&message, channel_list).await;
+
+        let mut acks: FuturesUnordered<_> = subs
+            .iter()
+            .map(|(channel, sub)| async move { sub.receive().await.map(|_| channel.address()) })
+            .collect();
+
+        let mut acked = vec![];
+        let wait_quorum = async {
+            while acked.len() < quorum {
+                match acks.next().await {
+                    Some(Ok(addr)) => acked.push(addr.clone()),
+                    // The channel stopped before acking
+                    Some(Err(_)) => continue,
+                    None => break,
+                }
+            }
+        };
+        if timeout(timeout_, wait_quorum).await.is_err() {
+            debug!(
+                target: "net::p2p::broadcast_quorum()",
+                "[P2P] Timed out with {}/{} acks", acked.len(), quorum,
+            );
+        }
+        drop(acks);
+
+        for (_, sub) in subs {
+            sub.unsubscribe().await;
+        }
+
+        acked
+    }
+
+    /// Send an already serialized message concurrently to all given peers.
+    async fn broadcast_serialized(&self, message: &SerializedMessage, channel_list: &[ChannelPtr]) {
         if channel_list.is_empty() {
             warn!(target: "net::p2p::broadcast()", "[P2P] No connected channels found for broadcast");
             return
         }
 
-        let message = SerializedMessage::new(message).await;
         let futures = FuturesUnordered::new();
 
         for channel in channel_list {
-            futures.push(channel.send_serialized(&message).map_err(|e| {
+            futures.push(channel.send_serialized(message).map_err(|e| {
                 error!(
                     target: "net::p2p::broadcast()",
                     "[P2P] Broadcasting message to {} failed: {}",
@@ -218,6 +438,27 @@ impl P2p {
         !self.hosts().peers().is_empty()
     }
 
+    /// Wait until at least `n` peers are connected, e.g. before
+    /// bootstrapping. Seed and refinery connections don't count, like in
+    /// `is_connected()`. Fails with `Error::TimeoutError` if `dur` elapses
+    /// first.
+    pub async fn wait_for_peers(&self, n: usize, dur: Duration) -> Result<()> {
+        // Subscribe before counting, so no new channel can be missed
+        let channel_sub = self.hosts().subscribe_channel().await;
+
+        let wait = async {
+            while self.hosts().peers().len() < n {
+                let _ = channel_sub.receive().await;
+            }
+        };
+
+        let result = timeout(dur, wait).await;
+        channel_sub.unsubscribe().await;
+        result?;
+
+        Ok(())
+    }
+
     /// Return an atomic pointer to the set network settings
     pub fn settings(&self) -> Arc<AsyncRwLock<Settings>> {
         Arc::clone(&self.settings)
@@ -285,8 +526,480 @@ impl P2p {
         self.dnet_publisher.notify(event).await;
     }
 
+    /// Set the resolver used to tag new channels with their peer's ASN
+    /// and country. Passing `None` disables tagging. Channels that are
+    /// already open keep their existing tags.
+    pub fn set_peer_resolver(&self, resolver: Option<PeerResolverPtr>) {
+        *self.peer_resolver.lock().unwrap() = resolver;
+    }
+
+    /// Reference the configured peer resolver, if any
+    pub(in crate::net) fn peer_resolver(&self) -> Option<PeerResolverPtr> {
+        self.peer_resolver.lock().unwrap().clone()
+    }
+
+    /// Set the strategy ordering the candidate addresses of outbound
+    /// slots, e.g. [`DiversitySelector`](super::session::DiversitySelector)
+    /// to spread connections across networks. Passing `None` restores the
+    /// default, which prefers the most recently seen addresses.
+    pub fn set_slot_selector(&self, selector: Option<SlotSelectorPtr>) {
+        *self.slot_selector.lock().unwrap() =
+            selector.unwrap_or_else(|| Arc::new(LastSeenSelector));
+    }
+
+    /// Reference the configured outbound slot selection strategy
+    pub(in crate::net) fn slot_selector(&self) -> SlotSelectorPtr {
+        self.slot_selector.lock().unwrap().clone()
+    }
+
+    /// Set the policy deciding how to handle misbehaving peers. Passing
+    /// `None` restores the default policy, which follows the configured
+    /// [`BanPolicy`](super::BanPolicy).
+    pub fn set_violation_policy(&self, policy: Option<ViolationPolicyPtr>) {
+        *self.violation_policy.lock().unwrap() = policy;
+    }
+
+    /// Reference the configured violation policy, if any
+    pub(in crate::net) fn violation_policy(&self) -> Option<ViolationPolicyPtr> {
+        self.violation_policy.lock().unwrap().clone()
+    }
+
+    /// Returns the send and receive rates of all channels combined
+    pub fn stats(&self) -> NetStats {
+        self.traffic_stats.stats()
+    }
+
+    /// Returns a snapshot of the channels, hosts and traffic of this
+    /// P2P instance, e.g. to serve a health check.
+    pub fn health(&self) -> P2pHealth {
+        let mut health = P2pHealth::default();
+
+        for channel in self.hosts.channels() {
+            let type_id = channel.session_type_id();
+            if type_id & SESSION_INBOUND != 0 {
+                health.inbound_channels += 1;
+            } else if type_id & SESSION_OUTBOUND != 0 {
+                health.outbound_channels += 1;
+            } else if type_id & SESSION_MANUAL != 0 {
+                health.manual_channels += 1;
+            }
+        }
+
+        let container = &self.hosts.container;
+        health.known_hosts = container.len(HostColor::Grey) +
+            container.len(HostColor::White) +
+            container.len(HostColor::Gold);
+        health.banned_hosts = container.len(HostColor::Black);
+
+        (health.bytes_sent, health.bytes_received) = self.traffic_stats.totals();
+        health.traffic = self.traffic_stats.stats();
+        health.uptime = self.start_time.lock().unwrap().map_or(Duration::ZERO, |t| t.elapsed());
+
+        health
+    }
+
+    /// Reference the traffic stats collector fed by the channels
+    pub(in crate::net) fn traffic_stats(&self) -> &TrafficStats {
+        &self.traffic_stats
+    }
+
+    /// Reference the cache deduplicating received messages
+    pub(in crate::net) fn seen_cache(&self) -> &SeenCache {
+        &self.seen_cache
+    }
+
+    /// Enable or disable dropping messages already received over another
+    /// channel. The cache size is fixed by the `message_dedup_size`
+    /// setting, and a size of 0 keeps deduplication disabled.
+    pub fn set_message_dedup(&self, enabled: bool) {
+        self.seen_cache.set_enabled(enabled);
+    }
+
+    /// Set the keypair seeds sign address lists with, for peers asking
+    /// for a [`SignedAddrsMessage`](super::message::SignedAddrsMessage).
+    /// Without one, peers receive unsigned address lists.
+    pub fn set_addrs_signing_key(&self, keypair: Option<Keypair>) {
+        *self.addrs_signing_key.lock().unwrap() = keypair;
+    }
+
+    /// Reference the configured address list signing keypair, if any
+    pub(in crate::net) fn addrs_signing_key(&self) -> Option<Keypair> {
+        *self.addrs_signing_key.lock().unwrap()
+    }
+
+    /// Set the filter deciding which inbound connections are accepted.
+    /// Rejected connections are dropped before a channel is created.
+    /// Connections that are already open aren't affected.
+    pub fn set_accept_filter(&self, filter: AcceptFilterPtr) {
+        *self.accept_filter.lock().unwrap() = filter;
+    }
+
+    /// Reference the configured accept filter
+    pub(in crate::net) fn accept_filter(&self) -> AcceptFilterPtr {
+        self.accept_filter.lock().unwrap().clone()
+    }
+
+    /// Set the policy deciding whether peers that can't be reached over
+    /// Tor are retried over clearnet TCP. This deanonymizes us to those
+    /// peers, so it's disabled unless a policy is set. Passing `None`
+    /// disables it again.
+    pub fn set_tor_fallback_policy(&self, policy: Option<TorFallbackPolicyPtr>) {
+        *self.tor_fallback_policy.lock().unwrap() = policy;
+    }
+
+    /// Reference the configured Tor fallback policy, if any
+    pub(in crate::net) fn tor_fallback_policy(&self) -> Option<TorFallbackPolicyPtr> {
+        self.tor_fallback_policy.lock().unwrap().clone()
+    }
+
+    /// Set the node keypair `tcp+noise` connections authenticate with.
+    /// Without one, every connection uses a random Noise static key.
+    /// Must be set before `start()` for inbound connections to use it.
+    #[cfg(feature = "p2p-noise")]
+    pub fn set_noise_keypair(&self, keypair: Option<Keypair>) {
+        *self.noise_keypair.lock().unwrap() = keypair;
+    }
+
+    /// Reference the configured Noise keypair, if any
+    #[cfg(feature = "p2p-noise")]
+    pub(in crate::net) fn noise_keypair(&self) -> Option<Keypair> {
+        *self.noise_keypair.lock().unwrap()
+    }
+
+    /// Pin the Noise static public keys `tcp+noise` peers may
+    /// authenticate with, rejecting any other peer in both directions.
+    /// An empty list accepts any peer. Must be set before `start()` for
+    /// inbound connections to use it.
+    #[cfg(feature = "p2p-noise")]
+    pub fn set_noise_remote_keys(&self, remote_keys: Vec<[u8; 32]>) {
+        *self.noise_remote_keys.lock().unwrap() = remote_keys;
+    }
+
+    /// Reference the pinned Noise remote keys
+    #[cfg(feature = "p2p-noise")]
+    pub(in crate::net) fn noise_remote_keys(&self) -> Vec<[u8; 32]> {
+        self.noise_remote_keys.lock().unwrap().clone()
+    }
+
+    /// Dial and listen on Tor-based transports through the given
+    /// `TorClient`, so the application and the P2P network share a single
+    /// Tor instance. Without one, a client is bootstrapped on first use.
+    /// Must be set before `start()` for listeners to use it.
+    #[cfg(feature = "p2p-tor")]
+    pub fn set_tor_client(&self, client: Option<SharedTorClient>) {
+        *self.tor_client.lock().unwrap() = client;
+    }
+
+    /// Reference the shared `TorClient`, if any
+    #[cfg(feature = "p2p-tor")]
+    pub(in crate::net) fn tor_client(&self) -> Option<SharedTorClient> {
+        self.tor_client.lock().unwrap().clone()
+    }
+
+    /// List every known transport with its health, like
+    /// [`transport_status()`], but reporting the bootstrap state of the
+    /// shared `TorClient` if one was set with `set_tor_client()`, or else
+    /// of the Tor client for the configured datastore and bridges.
+    ///
+    /// [`transport_status()`]: super::transport::transport_status
+    pub async fn transport_status(&self) -> Vec<(TransportName, TransportHealth)> {
+        #[cfg(feature = "p2p-tor")]
+        let tor = match self.tor_client() {
+            Some(client) => tor::client_health(&client),
+            None => {
+                let settings = self.settings.read().await;
+                let datastore = settings.p2p_datastore.clone();
+                let tor_bridges = TorBridgeConfig {
+                    bridges: settings.tor_bridges.clone(),
+                    pt_path: settings.tor_pt_path.clone(),
+                };
+                drop(settings);
+
+                tor::config_health(&datastore, &tor_bridges).await
+            }
+        };
+        #[cfg(not(feature = "p2p-tor"))]
+        let tor = TransportHealth::NotCompiled;
+
+        transport_status_with_tor(tor)
+    }
+
+    /// Set the resolver used to look up hostnames when dialing TCP peers,
+    /// e.g. a DNS-over-HTTPS client. This also clears the DNS cache.
+    pub fn set_dns_resolver(&self, resolver: DnsResolverPtr) {
+        self.dns_cache.set_resolver(resolver);
+    }
+
+    /// Reference the DNS cache used when dialing peers
+    pub(in crate::net) fn dns_cache(&self) -> DnsCachePtr {
+        self.dns_cache.clone()
+    }
+
     /// Grab the channel pointer of provided channel ID, if it exists.
     pub fn get_channel(&self, id: u32) -> Option<ChannelPtr> {
         self.hosts.get_channel(id)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::UNIX_EPOCH;
+
+    use smol::Executor;
+
+    use super::*;
+    use crate::{
+        net::{channel::Channel, message::PingMessage, session::Session, transport::memory},
+        Error,
+    };
+
+    #[test]
+    fn p2p_wait_for_peers() {
+        let executor = Arc::new(Executor::new());
+        let executor_ = executor.clone();
+        smol::block_on(executor.run(async move {
+            let p2p = P2p::new(Settings::default(), executor_.clone()).await.unwrap();
+
+            // Nothing connects in time
+            let err = p2p.wait_for_peers(1, Duration::from_millis(100)).await.unwrap_err();
+            assert!(matches!(err, Error::TimeoutError(_)));
+
+            let p2p_ = p2p.clone();
+            let barrier = executor_
+                .spawn(async move { p2p_.wait_for_peers(2, Duration::from_secs(10)).await });
+
+            let session: Arc<dyn Session + Send + Sync> = p2p.session_inbound();
+            for i in 0..2 {
+                assert!(!barrier.is_finished());
+
+                let url = Url::parse(&format!("tcp://127.0.0.1:{}", 26661 + i)).unwrap();
+                let (stream, _) = memory::duplex(4096);
+                let channel =
+                    Channel::new(Box::new(stream), None, url, Arc::downgrade(&session)).await;
+                p2p.hosts().register_channel(channel).await;
+                msleep(50).await;
+            }
+
+            // The barrier releases once the second peer is connected
+            barrier.await.unwrap();
+
+            // Already satisfied barriers return right away
+            p2p.wait_for_peers(2, Duration::from_millis(100)).await.unwrap();
+        }))
+    }
+
+    #[test]
+    fn p2p_health_counts() {
+        let executor = Arc::new(Executor::new());
+        let executor_ = executor.clone();
+        smol::block_on(executor.run(async move {
+            let p2p = P2p::new(Settings::default(), executor_.clone()).await.unwrap();
+
+            // One inbound and one outbound channel, registered as if
+            // their handshakes had finished
+            let inbound: Arc<dyn Session + Send + Sync> = p2p.session_inbound();
+            let outbound: Arc<dyn Session + Send + Sync> = p2p.session_outbound();
+            for (i, session) in [inbound, outbound].iter().enumerate() {
+                let url = Url::parse(&format!("tcp://127.0.0.1:{}", 26661 + i)).unwrap();
+                let (stream, _) = memory::duplex(4096);
+                let channel =
+                    Channel::new(Box::new(stream), None, url, Arc::downgrade(session)).await;
+                p2p.hosts().register_channel(channel).await;
+            }
+
+            let grey = Url::parse("tcp://grey.example:26661").unwrap();
+            let black = Url::parse("tcp://black.example:26661").unwrap();
+            for (addr, color) in [(grey, HostColor::Grey), (black, HostColor::Black)] {
+                p2p.hosts().move_host(&addr, 0, color).unwrap();
+                p2p.hosts().unregister(&addr);
+            }
+            p2p.traffic_stats().record_send(100);
+            p2p.traffic_stats().record_recv(40);
+
+            let health = p2p.health();
+            assert_eq!(health.inbound_channels, 1);
+            assert_eq!(health.outbound_channels, 1);
+            assert_eq!(health.manual_channels, 0);
+            assert_eq!(health.known_hosts, 1);
+            assert_eq!(health.banned_hosts, 1);
+            assert_eq!((health.bytes_sent, health.bytes_received), (100, 40));
+            assert_eq!(health.uptime, Duration::ZERO);
+        }));
+    }
+
+    #[test]
+    fn p2p_shutdown_stops_lingering_channels() {
+        let executor = Arc::new(Executor::new());
+        let executor_ = executor.clone();
+        smol::block_on(executor.run(async move {
+            let p2p = P2p::new(Settings::default(), executor_.clone()).await.unwrap();
+            let session: Arc<dyn Session + Send + Sync> = p2p.session_inbound();
+
+            // No session tracks this channel, so nothing removes it from
+            // the hosts once it's closed
+            let url = Url::parse("tcp://127.0.0.1:26661").unwrap();
+            let (a, b) = memory::duplex(4096);
+            let channel = Channel::new(Box::new(a), None, url, Arc::downgrade(&session)).await;
+            let peer = Channel::new_detached(Box::new(b)).await;
+            channel.clone().start(executor_.clone());
+            peer.clone().start(executor_.clone());
+            p2p.hosts().register_channel(channel.clone()).await;
+
+            let start = Instant::now();
+            p2p.shutdown_with_timeout(Duration::from_millis(200)).await;
+            assert!(start.elapsed() < Duration::from_secs(5));
+            assert!(channel.is_stopped());
+            assert!(p2p.hosts().channels().is_empty());
+        }));
+    }
+
+    #[test]
+    fn p2p_broadcast_quorum() {
+        let executor = Arc::new(Executor::new());
+        let executor_ = executor.clone();
+        smol::block_on(executor.run(async move {
+            // Every peer acks with the same payload, which must still be
+            // counted once per peer when message deduplication is on
+            for message_dedup in [false, true] {
+                let settings = Settings { message_dedup, ..Default::default() };
+                let p2p = P2p::new(settings, executor_.clone()).await.unwrap();
+                let session: Arc<dyn Session + Send + Sync> = p2p.session_inbound();
+
+                // Three mock peers, of which only the first two ack
+                let mut urls = vec![];
+                for i in 0..3 {
+                    let url = Url::parse(&format!("tcp://127.0.0.1:{}", 26661 + i)).unwrap();
+                    let (a, b) = memory::duplex(4096);
+                    let channel =
+                        Channel::new(Box::new(a), None, url.clone(), Arc::downgrade(&session))
+                            .await;
+                    let peer = Channel::new_detached(Box::new(b)).await;
+                    channel.clone().start(executor_.clone());
+                    peer.clone().start(executor_.clone());
+                    p2p.hosts().register_channel(channel).await;
+
+                    if i < 2 {
+                        let ping_sub = peer.subscribe_msg::<PingMessage>().await.unwrap();
+                        executor_
+                            .spawn(async move {
+                                let ping = ping_sub.receive().await.unwrap();
+                                peer.ack(&*ping).await.unwrap();
+                            })
+                            .detach();
+                    }
+                    urls.push(url);
+                }
+
+                // The call resolves as soon as the quorum acks
+                let start = Instant::now();
+                let ping = PingMessage { nonce: 42 };
+                let mut acked = p2p.broadcast_quorum(&ping, 2, Duration::from_secs(10)).await;
+                assert!(start.elapsed() < Duration::from_secs(5));
+                acked.sort();
+                assert_eq!(acked, urls[..2]);
+
+                // Without a quorum, the acks received until the timeout are returned
+                let acked = p2p.broadcast_quorum(&ping, 3, Duration::from_millis(200)).await;
+                assert!(acked.is_empty());
+            }
+        }));
+    }
+
+    #[test]
+    fn p2p_shutdown_persists_hosts() {
+        let executor = Arc::new(Executor::new());
+        let executor_ = executor.clone();
+        smol::block_on(executor.run(async move {
+            let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+            let addr = Url::parse(&format!("tcp://{}", listener.local_addr().unwrap())).unwrap();
+            drop(listener);
+
+            let hostlist =
+                std::env::temp_dir().join(format!("darkfi_hosts_{}.tsv", addr.port().unwrap()));
+            let _ = std::fs::remove_file(&hostlist);
+
+            let settings = Settings {
+                localnet: true,
+                outbound_connections: 0,
+                allowed_transports: vec!["tcp".to_string()],
+                ..Default::default()
+            };
+
+            let server = P2p::new(
+                Settings { inbound_addrs: vec![addr.clone()], ..settings.clone() },
+                executor_.clone(),
+            )
+            .await
+            .unwrap();
+            server.clone().start().await.unwrap();
+
+            // The client connects to the server from its whitelist
+            let client = P2p::new(
+                Settings {
+                    outbound_connections: 1,
+                    hostlist: Some(hostlist.to_str().unwrap().to_string()),
+                    ..settings
+                },
+                executor_.clone(),
+            )
+            .await
+            .unwrap();
+            let last_seen = UNIX_EPOCH.elapsed().unwrap().as_secs();
+            client.hosts().insert(HostColor::White, &[(addr.clone(), last_seen)]).await;
+            client.clone().start().await.unwrap();
+
+            while client.hosts().peers().is_empty() {
+                msleep(100).await;
+            }
+            let channels = client.hosts().channels();
+
+            client.shutdown().await;
+
+            // Every channel was stopped before the hostlist got saved, so
+            // the server shows up downgraded from gold to grey.
+            assert!(channels.iter().all(|c| c.is_stopped()));
+            let saved = std::fs::read_to_string(&hostlist).unwrap();
+            assert!(saved.lines().any(|line| line.starts_with(&format!("grey\t{}", addr))));
+
+            let _ = std::fs::remove_file(&hostlist);
+            server.stop().await;
+        }));
+    }
+
+    #[test]
+    fn p2p_transport_status() {
+        let executor = Arc::new(Executor::new());
+        let executor_ = executor.clone();
+        smol::block_on(executor.run(async move {
+            let p2p = P2p::new(Settings::default(), executor_.clone()).await.unwrap();
+
+            let status = p2p.transport_status().await;
+            let health = |status: &[(TransportName, TransportHealth)], name: &str| {
+                status.iter().find(|(n, _)| *n == name).map(|(_, health)| *health).unwrap()
+            };
+            assert_eq!(health(&status, "tcp"), TransportHealth::Available);
+            assert_eq!(health(&status, "tcp+tls"), TransportHealth::Available);
+
+            #[cfg(not(feature = "p2p-tor"))]
+            assert_eq!(health(&status, "tor"), TransportHealth::NotCompiled);
+
+            // A shared client that never bootstraps is reported as such
+            #[cfg(feature = "p2p-tor")]
+            {
+                use arti_client::{config::TorClientConfigBuilder, TorClient};
+
+                let dir = std::env::temp_dir().join("darkfi_tor_transport_status");
+                let config =
+                    TorClientConfigBuilder::from_directories(dir.join("state"), dir.join("cache"))
+                        .build()
+                        .unwrap();
+                let client = TorClient::builder().config(config).create_unbootstrapped().unwrap();
+                p2p.set_tor_client(Some(client));
+
+                let status = p2p.transport_status().await;
+                assert_eq!(health(&status, "tor"), TransportHealth::NotBootstrapped);
+                assert_eq!(health(&status, "tor+tls"), TransportHealth::NotBootstrapped);
+            }
+        }));
+    }
+}
@@ -0,0 +1,349 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2024 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Supervised outbound connection to a single peer.
+//!
+//! A [`PersistentChannel`] keeps redialing its peer whenever the
+//! underlying [`Channel`](super::channel::Channel) stops, and re-attaches
+//! every subscription made through it to the new channel. Subscribers
+//! hold a [`PersistentSubscription`] which stays valid across reconnects,
+//! so long-lived protocol clients don't have to rebuild their state.
+//!
+//! Messages received while the peer is disconnected, or before the
+//! handshake of a new channel finishes, are not delivered.
+
+use std::sync::Arc;
+
+use log::{debug, error, info, warn};
+use smol::{
+    channel::{Receiver, Sender},
+    future::Boxed,
+    lock::Mutex as AsyncMutex,
+};
+use url::Url;
+
+use super::{
+    channel::ChannelPtr,
+    connector::Connector,
+    hosts::HostState,
+    message::Message,
+    p2p::P2pPtr,
+    session::{ManualSessionPtr, Session, SessionWeakPtr},
+};
+use crate::{
+    system::{sleep, StoppableTask, StoppableTaskPtr},
+    Error, Result,
+};
+
+pub type PersistentChannelPtr = Arc<PersistentChannel>;
+
+/// Forwards messages of one subscription from a freshly connected channel
+type Forwarder = Box<dyn Fn(ChannelPtr) -> Boxed<()> + Send + Sync>;
+
+/// Outbound connection to a fixed peer that is re-established on
+/// disconnect, preserving the subscriptions made through it.
+pub struct PersistentChannel {
+    /// Address of the peer
+    addr: Url,
+    /// Session the channels are registered with
+    session: ManualSessionPtr,
+    /// Connector used to (re)dial the peer
+    connector: Connector,
+    /// Currently connected channel, if any
+    channel: AsyncMutex<Option<ChannelPtr>>,
+    /// Subscriptions re-attached to every new channel
+    forwarders: AsyncMutex<Vec<Forwarder>>,
+    /// Supervisor task redialing the peer
+    process: StoppableTaskPtr,
+}
+
+impl PersistentChannel {
+    /// Create a new persistent channel to `addr`. The channels are
+    /// registered with the manual session of `p2p`. Call `start()` to
+    /// begin connecting.
+    pub fn new(p2p: &P2pPtr, addr: Url) -> PersistentChannelPtr {
+        let session = p2p.session_manual();
+        let session_: Arc<dyn Session + Send + Sync> = session.clone();
+        let session_weak: SessionWeakPtr = Arc::downgrade(&session_);
+
+        Arc::new(Self {
+            addr,
+            session,
+            connector: Connector::new(p2p.settings(), session_weak),
+            channel: AsyncMutex::new(None),
+            forwarders: AsyncMutex::new(vec![]),
+            process: StoppableTask::new(),
+        })
+    }
+
+    /// Start the supervisor, connecting to the peer and reconnecting
+    /// whenever the channel stops.
+    pub fn start(self: Arc<Self>) {
+        let ex = self.session.p2p().executor();
+
+        self.process.clone().start(
+            self.run(),
+            |res| async {
+                match res {
+                    Ok(()) | Err(Error::NetworkServiceStopped) => {}
+                    Err(e) => error!(target: "net::persistent_channel", "{}", e),
+                }
+            },
+            Error::NetworkServiceStopped,
+            ex,
+        );
+    }
+
+    /// Stop the supervisor and the current channel, if any.
+    /// Subscriptions stay attached but won't receive further messages.
+    pub async fn stop(&self) {
+        self.connector.stop();
+        self.process.stop().await;
+
+        if let Some(channel) = self.channel.lock().await.take() {
+            channel.stop().await;
+        }
+    }
+
+    /// Returns the currently connected channel, if any
+    pub async fn channel(&self) -> Option<ChannelPtr> {
+        self.channel.lock().await.clone()
+    }
+
+    /// Send a message over the current channel. Returns an error if
+    /// the peer is currently disconnected.
+    pub async fn send<M: Message>(&self, message: &M) -> Result<()> {
+        let Some(channel) = self.channel().await else { return Err(Error::ChannelStopped) };
+        channel.send(message).await
+    }
+
+    /// Subscribe to a message type. The returned handle keeps receiving
+    /// messages from every channel established to the peer. As with
+    /// `Channel::subscribe_msg()`, a protocol must have registered a
+    /// dispatcher for the message.
+    pub async fn subscribe<M: Message>(&self) -> PersistentSubscription<M> {
+        let (sender, recv_queue) = smol::channel::unbounded();
+
+        let forwarder = move |channel: ChannelPtr| {
+            Box::pin(Self::forward::<M>(channel, sender.clone())) as Boxed<()>
+        };
+
+        // Hold the channel lock so a reconnect can't attach the
+        // forwarders while we're adding this one.
+        let channel = self.channel.lock().await;
+        if let Some(channel) = channel.as_ref() {
+            let ex = self.session.p2p().executor();
+            ex.spawn(forwarder(channel.clone())).detach();
+        }
+        self.forwarders.lock().await.push(Box::new(forwarder));
+
+        PersistentSubscription { recv_queue }
+    }
+
+    /// Supervisor loop connecting to the peer, modeled after the
+    /// manual session slots.
+    async fn run(self: Arc<Self>) -> Result<()> {
+        let ex = self.session.p2p().executor();
+
+        loop {
+            let hosts = self.session.p2p().hosts();
+            let outbound_connect_timeout =
+                self.session.p2p().settings().read().await.outbound_connect_timeout;
+
+            if let Err(e) = hosts.try_register(self.addr.clone(), HostState::Connect) {
+                debug!(
+                    target: "net::persistent_channel",
+                    "Cannot connect to {}, err={}", self.addr, e,
+                );
+
+                sleep(outbound_connect_timeout).await;
+                continue
+            }
+
+            match self.connector.connect(&self.addr).await {
+                Ok((url, channel)) => {
+                    let stop_sub = channel.subscribe_stop().await?;
+
+                    match self.session.register_channel(channel.clone(), ex.clone()).await {
+                        Ok(()) => {
+                            info!(
+                                target: "net::persistent_channel",
+                                "[P2P] Persistent channel connected [{}]", url,
+                            );
+
+                            self.attach(channel).await;
+                            stop_sub.receive().await;
+                            *self.channel.lock().await = None;
+
+                            info!(
+                                target: "net::persistent_channel",
+                                "[P2P] Persistent channel disconnected [{}]", url,
+                            );
+                        }
+                        Err(e) => self.handle_failure(e, &url),
+                    }
+                }
+                Err(Error::ConnectorStopped) => {
+                    self.session.p2p().hosts().unregister(&self.addr);
+                    return Err(Error::NetworkServiceStopped)
+                }
+                Err(e) => self.handle_failure(e, &self.addr),
+            }
+
+            sleep(outbound_connect_timeout).await;
+        }
+    }
+
+    /// Make `channel` the current channel and re-attach all subscriptions
+    async fn attach(&self, channel: ChannelPtr) {
+        let ex = self.session.p2p().executor();
+
+        let mut current = self.channel.lock().await;
+        for forwarder in self.forwarders.lock().await.iter() {
+            ex.spawn(forwarder(channel.clone())).detach();
+        }
+        *current = Some(channel);
+    }
+
+    /// Relay messages of type `M` from `channel` until either the channel
+    /// stops or the subscription handle is dropped.
+    async fn forward<M: Message>(channel: ChannelPtr, sender: Sender<Arc<M>>) {
+        let sub = match channel.subscribe_msg::<M>().await {
+            Ok(sub) => sub,
+            Err(e) => {
+                warn!(
+                    target: "net::persistent_channel",
+                    "[P2P] Unable to subscribe to {} on {}: {}", M::NAME, channel.address(), e,
+                );
+                return
+            }
+        };
+
+        while let Ok(msg) = sub.receive().await {
+            if sender.send(msg).await.is_err() {
+                break
+            }
+        }
+    }
+
+    fn handle_failure(&self, error: Error, addr: &Url) {
+        warn!(
+            target: "net::persistent_channel",
+            "[P2P] Unable to connect to persistent peer [{}]: {}", self.addr, error,
+        );
+
+        // Free up this addr for future operations.
+        self.session.p2p().hosts().unregister(addr);
+    }
+}
+
+/// Subscription to a message type of a [`PersistentChannel`], valid
+/// across reconnects.
+pub struct PersistentSubscription<M: Message> {
+    recv_queue: Receiver<Arc<M>>,
+}
+
+impl<M: Message> PersistentSubscription<M> {
+    /// Receive the next message from whichever channel is connected.
+    /// Returns an error once the persistent channel has been dropped.
+    pub async fn receive(&self) -> Result<Arc<M>> {
+        self.recv_queue.recv().await.map_err(|_| Error::ChannelStopped)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use smol::Executor;
+
+    use super::*;
+    use crate::{
+        net::{message::PingMessage, P2p, Settings},
+        system::timeout::timeout,
+    };
+
+    /// Broadcast a ping with `nonce` from `server` until `sub` receives it
+    async fn deliver(server: &P2pPtr, sub: &PersistentSubscription<PingMessage>, nonce: u16) {
+        loop {
+            server.broadcast(&PingMessage { nonce }).await;
+
+            // Skip heartbeat pings from the protocols
+            while let Ok(msg) = timeout(Duration::from_millis(200), sub.receive()).await {
+                if msg.unwrap().nonce == nonce {
+                    return
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn persistent_channel_reconnect() {
+        let executor = Arc::new(Executor::new());
+        let executor_ = executor.clone();
+        smol::block_on(executor.run(async move {
+            let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+            let addr = Url::parse(&format!("tcp://{}", listener.local_addr().unwrap())).unwrap();
+            drop(listener);
+
+            let settings = Settings {
+                localnet: true,
+                outbound_connections: 0,
+                outbound_connect_timeout: 1,
+                // Our pings would be answered with pongs the heartbeat
+                // doesn't expect, so keep it out of the way.
+                channel_heartbeat_interval: 3600,
+                allowed_transports: vec!["tcp".to_string()],
+                ..Default::default()
+            };
+
+            let server = P2p::new(
+                Settings { inbound_addrs: vec![addr.clone()], ..settings.clone() },
+                executor_.clone(),
+            )
+            .await
+            .unwrap();
+            server.clone().start().await.unwrap();
+
+            let client = P2p::new(settings, executor_.clone()).await.unwrap();
+            let persistent = PersistentChannel::new(&client, addr);
+            let sub = persistent.subscribe::<PingMessage>().await;
+            persistent.clone().start();
+
+            deliver(&server, &sub, 1).await;
+
+            // Simulate a disconnect, the supervisor redials the server
+            let channel = persistent.channel().await.unwrap();
+            channel.stop().await;
+            loop {
+                if let Some(c) = persistent.channel().await {
+                    if c.info.id != channel.info.id {
+                        break
+                    }
+                }
+                sleep(1).await;
+            }
+
+            // Messages from the new channel arrive through the same handle
+            deliver(&server, &sub, 2).await;
+
+            persistent.stop().await;
+            server.stop().await;
+        }));
+    }
+}
@@ -19,6 +19,7 @@
 use std::{sync::Arc, time::UNIX_EPOCH};
 
 use async_trait::async_trait;
+use darkfi_sdk::crypto::Keypair;
 use log::debug;
 use smol::{lock::RwLock as AsyncRwLock, Executor};
 
@@ -26,7 +27,7 @@ use super::{
     super::{
         channel::ChannelPtr,
         hosts::{HostColor, HostsPtr},
-        message::{AddrsMessage, GetAddrsMessage},
+        message::{AddrsMessage, GetAddrsMessage, SignedAddrsMessage},
         message_publisher::MessageSubscription,
         p2p::P2pPtr,
         session::SESSION_OUTBOUND,
@@ -65,6 +66,8 @@ pub struct ProtocolAddress {
     get_addrs_sub: MessageSubscription<GetAddrsMessage>,
     hosts: HostsPtr,
     settings: Arc<AsyncRwLock<Settings>>,
+    /// Keypair signing address lists for peers requesting them
+    signing_key: Option<Keypair>,
     jobsman: ProtocolJobsManagerPtr,
 }
 
@@ -74,7 +77,8 @@ const PROTO_NAME: &str = "ProtocolAddress";
 /// combinations.  Should be updated if and when new transports are
 /// added. Creates a upper bound on the number of transports a given peer
 /// can request.
-const TRANSPORT_COMBOS: [&str; 7] = ["tor", "tls", "tcp", "nym", "tor+tls", "nym+tls", "tcp+tls"];
+const TRANSPORT_COMBOS: [&str; 8] =
+    ["tor", "tls", "tcp", "nym", "tor+tls", "nym+tls", "tcp+tls", "tcp+noise"];
 
 impl ProtocolAddress {
     /// Creates a new address protocol. Makes an address, an external address
@@ -96,6 +100,7 @@ impl ProtocolAddress {
             hosts: p2p.hosts(),
             jobsman: ProtocolJobsManager::new(PROTO_NAME, channel),
             settings: p2p.settings(),
+            signing_key: p2p.addrs_signing_key(),
         })
     }
 
@@ -154,6 +159,7 @@ impl ProtocolAddress {
                 HostColor::Gold,
                 &get_addrs_msg.transports,
                 get_addrs_msg.max,
+                get_addrs_msg.since,
             );
 
             // Then we grab address with the requested transports from the whitelist
@@ -163,6 +169,7 @@ impl ProtocolAddress {
                 HostColor::White,
                 &get_addrs_msg.transports,
                 get_addrs_msg.max,
+                get_addrs_msg.since,
             ));
 
             // Next we grab addresses without the requested transports
@@ -176,6 +183,7 @@ impl ProtocolAddress {
                 HostColor::Gold,
                 &get_addrs_msg.transports,
                 remain,
+                get_addrs_msg.since,
             ));
 
             // Then we grab address without the requested transports from the white list
@@ -186,6 +194,7 @@ impl ProtocolAddress {
                 HostColor::White,
                 &get_addrs_msg.transports,
                 remain,
+                get_addrs_msg.since,
             ));
 
             // If there's still space available, take from the Dark list.
@@ -198,15 +207,27 @@ impl ProtocolAddress {
             debug!(target: "net::protocol_address::handle_receive_get_addrs()",
             "Fetching dark entries");
             let remain = 2 * get_addrs_msg.max - addrs.len() as u32;
-            addrs.append(&mut self.hosts.container.fetch_n_random(HostColor::Dark, remain));
+            addrs.append(&mut self.hosts.container.fetch_n_random(
+                HostColor::Dark,
+                remain,
+                get_addrs_msg.since,
+            ));
 
             debug!(
                 target: "net::protocol_address::handle_receive_get_addrs()",
                 "Sending {} addresses to {}", addrs.len(), self.channel.address(),
             );
 
-            let addrs_msg = AddrsMessage { addrs };
-            self.channel.send(&addrs_msg).await?;
+            match self.signing_key {
+                Some(ref keypair) if get_addrs_msg.signed => {
+                    let addrs_msg = SignedAddrsMessage::sign(addrs, keypair);
+                    self.channel.send(&addrs_msg).await?;
+                }
+                _ => {
+                    let addrs_msg = AddrsMessage { addrs };
+                    self.channel.send(&addrs_msg).await?;
+                }
+            }
         }
     }
 
@@ -287,8 +308,12 @@ impl ProtocolBase for ProtocolAddress {
         self.jobsman.spawn(self.clone().handle_receive_get_addrs(), ex).await;
 
         // Send get_address message.
-        let get_addrs =
-            GetAddrsMessage { max: outbound_connections as u32, transports: allowed_transports };
+        let get_addrs = GetAddrsMessage {
+            max: outbound_connections as u32,
+            transports: allowed_transports,
+            since: None,
+            signed: false,
+        };
         self.channel.send(&get_addrs).await?;
 
         debug!(
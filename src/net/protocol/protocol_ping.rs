@@ -99,29 +99,35 @@ impl ProtocolPing {
             // Start the timer for the ping timer
             let timer = Instant::now();
 
-            // Wait for pong, check nonce matches.
-            let pong_msg = match timeout(
-                Duration::from_secs(outbound_connect_timeout),
-                self.pong_sub.receive(),
-            )
-            .await
-            {
-                Ok(msg) => {
-                    // msg will be an error when the channel is stopped
-                    // so just yield out of this function.
-                    msg?
-                }
-                Err(_e) => {
-                    // Pong timeout. We didn't receive any message back
-                    // so close the connection.
-                    warn!(
-                        target: "net::protocol_ping::run_ping_pong()",
-                        "[P2P] Ping-Pong protocol timed out for {}", self.channel.address(),
-                    );
-                    self.channel.stop().await;
-                    return Err(Error::ChannelStopped)
+            // Wait for pong, check nonce matches. Replies to the channel's
+            // half-open connection probe are not meant for us.
+            let wait_pong = async {
+                loop {
+                    let msg = self.pong_sub.receive().await?;
+                    if msg.nonce != nonce && self.channel.is_probe_nonce(msg.nonce) {
+                        continue
+                    }
+                    return Ok::<_, Error>(msg)
                 }
             };
+            let pong_msg =
+                match timeout(Duration::from_secs(outbound_connect_timeout), wait_pong).await {
+                    Ok(msg) => {
+                        // msg will be an error when the channel is stopped
+                        // so just yield out of this function.
+                        msg?
+                    }
+                    Err(_e) => {
+                        // Pong timeout. We didn't receive any message back
+                        // so close the connection.
+                        warn!(
+                            target: "net::protocol_ping::run_ping_pong()",
+                            "[P2P] Ping-Pong protocol timed out for {}", self.channel.address(),
+                        );
+                        self.channel.stop().await;
+                        return Err(Error::ChannelStopped)
+                    }
+                };
 
             if pong_msg.nonce != nonce {
                 error!(
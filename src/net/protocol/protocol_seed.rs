@@ -16,17 +16,20 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
-use std::{sync::Arc, time::UNIX_EPOCH};
+use std::{str::FromStr, sync::Arc, time::UNIX_EPOCH};
 
 use async_trait::async_trait;
-use log::debug;
-use smol::{lock::RwLock as AsyncRwLock, Executor};
+use darkfi_sdk::crypto::PublicKey;
+use futures::future::Either;
+use log::{debug, warn};
+use smol::{future, lock::RwLock as AsyncRwLock, Executor};
+use url::Url;
 
 use super::{
     super::{
         channel::ChannelPtr,
         hosts::{HostColor, HostsPtr},
-        message::{AddrsMessage, GetAddrsMessage},
+        message::{AddrsMessage, GetAddrsMessage, SignedAddrsMessage, SIGNED_ADDRS_FEATURE},
         message_publisher::MessageSubscription,
         p2p::P2pPtr,
         settings::Settings,
@@ -41,6 +44,7 @@ pub struct ProtocolSeed {
     hosts: HostsPtr,
     settings: Arc<AsyncRwLock<Settings>>,
     addr_sub: MessageSubscription<AddrsMessage>,
+    signed_addr_sub: MessageSubscription<SignedAddrsMessage>,
 }
 
 const PROTO_NAME: &str = "ProtocolSeed";
@@ -52,7 +56,74 @@ impl ProtocolSeed {
         let addr_sub =
             channel.subscribe_msg::<AddrsMessage>().await.expect("Missing addr dispatcher!");
 
-        Arc::new(Self { channel, hosts: p2p.hosts(), settings: p2p.settings(), addr_sub })
+        // Create a subscription to signed address message
+        let signed_addr_sub = channel
+            .subscribe_msg::<SignedAddrsMessage>()
+            .await
+            .expect("Missing signedaddr dispatcher!");
+
+        Arc::new(Self {
+            channel,
+            hosts: p2p.hosts(),
+            settings: p2p.settings(),
+            addr_sub,
+            signed_addr_sub,
+        })
+    }
+
+    /// Parse the configured trusted seed keys, skipping invalid ones
+    fn trusted_keys(keys: &[String]) -> Vec<PublicKey> {
+        keys.iter()
+            .filter_map(|key| match PublicKey::from_str(key) {
+                Ok(key) => Some(key),
+                Err(e) => {
+                    warn!(
+                        target: "net::protocol_seed::trusted_keys",
+                        "Ignoring invalid trusted seed key {}: {}", key, e,
+                    );
+                    None
+                }
+            })
+            .collect()
+    }
+
+    /// Wait for the seed's address list, signed or not. Returns `None`
+    /// if the list can't be trusted and must be discarded.
+    async fn receive_addrs(
+        &self,
+        trusted_keys: &[PublicKey],
+        strict: bool,
+    ) -> Result<Option<Vec<(Url, u64)>>> {
+        let unsigned = async { self.addr_sub.receive().await.map(Either::Left) };
+        let signed = async { self.signed_addr_sub.receive().await.map(Either::Right) };
+        let msg = future::or(unsigned, signed).await?;
+
+        match msg {
+            Either::Left(addrs_msg) => {
+                if strict {
+                    warn!(
+                        target: "net::protocol_seed::receive_addrs",
+                        "Discarding unsigned addrs from {}", self.channel.address(),
+                    );
+                    return Ok(None)
+                }
+
+                Ok(Some(addrs_msg.addrs.clone()))
+            }
+
+            Either::Right(signed_msg) => {
+                if !signed_msg.verify(trusted_keys) {
+                    warn!(
+                        target: "net::protocol_seed::receive_addrs",
+                        "Discarding addrs with untrusted signature from {}",
+                        self.channel.address(),
+                    );
+                    return Ok(None)
+                }
+
+                Ok(Some(signed_msg.addrs.clone()))
+            }
+        }
     }
 
     /// Send our own external addresses over a channel. Set the
@@ -112,26 +183,35 @@ impl ProtocolBase for ProtocolSeed {
         let settings = self.settings.read().await;
         let outbound_connections = settings.outbound_connections;
         let allowed_transports = settings.allowed_transports.clone();
+        let trusted_keys = Self::trusted_keys(&settings.trusted_seed_keys);
+        let strict = settings.strict_signed_addrs;
         drop(settings);
 
-        // Send get address message
-        let get_addr =
-            GetAddrsMessage { max: outbound_connections as u32, transports: allowed_transports };
+        // Send get address message, asking for a signed list if we
+        // have seed keys to verify it against and the seed can sign it.
+        let signed =
+            !trusted_keys.is_empty() && self.channel.has_feature(SIGNED_ADDRS_FEATURE).await;
+        let get_addr = GetAddrsMessage {
+            max: outbound_connections as u32,
+            transports: allowed_transports,
+            since: None,
+            signed,
+        };
         self.channel.send(&get_addr).await?;
 
         // Receive addresses
-        let addrs_msg = self.addr_sub.receive().await?;
+        let Some(addrs) = self.receive_addrs(&trusted_keys, strict).await? else { return Ok(()) };
         debug!(
             target: "net::protocol_seed::start()",
-            "Received {} addrs from {}", addrs_msg.addrs.len(), self.channel.address(),
+            "Received {} addrs from {}", addrs.len(), self.channel.address(),
         );
 
-        if !addrs_msg.addrs.is_empty() {
+        if !addrs.is_empty() {
             debug!(
                 target: "net::protocol_seed::start()",
                 "Appending to greylist...",
             );
-            self.hosts.insert(HostColor::Grey, &addrs_msg.addrs).await;
+            self.hosts.insert(HostColor::Grey, &addrs).await;
         }
 
         debug!(target: "net::protocol_seed::start()", "END => address={}", self.channel.address());
@@ -30,11 +30,11 @@ use smol::{lock::RwLock as AsyncRwLock, Executor, Timer};
 
 use super::super::{
     channel::ChannelPtr,
-    message::{VerackMessage, VersionMessage},
+    message::{VerackMessage, VersionMessage, ADDRS_SINCE_FEATURE, SIGNED_ADDRS_FEATURE},
     message_publisher::MessageSubscription,
     settings::Settings,
 };
-use crate::{Error, Result};
+use crate::{error::HandshakeFailed, Error, Result};
 
 /// Implements the protocol version handshake sent out by nodes at
 /// the beginning of a connection.
@@ -103,7 +103,7 @@ impl ProtocolVersion {
                 );
 
                 self.channel.stop().await;
-                Err(Error::ChannelTimeout)
+                Err(HandshakeFailed::Timeout.into())
             }
         }
     }
@@ -119,21 +119,21 @@ impl ProtocolVersion {
         let recv = executor.spawn(self.clone().recv_version());
 
         let rets = join_all(vec![send, recv]).await;
-        if let Err(e) = &rets[0] {
+        let mut result = Ok(());
+        for (func, ret) in ["send_version()", "recv_version()"].into_iter().zip(rets) {
+            let Err(e) = ret else { continue };
             error!(
                 target: "net::protocol_version::exchange_versions()",
-                "send_version() failed: {}", e,
+                "{} failed: {}", func, e,
             );
-            return Err(e.clone())
-        }
 
-        if let Err(e) = &rets[1] {
-            error!(
-                target: "net::protocol_version::exchange_versions()",
-                "recv_version() failed: {}", e,
-            );
-            return Err(e.clone())
+            // A handshake failure on one side stops the channel, which
+            // fails the other side too. Report the root cause.
+            if result.is_ok() || matches!(e, Error::HandshakeFailed(_)) {
+                result = Err(e);
+            }
         }
+        result?;
 
         debug!(
             target: "net::protocol_version::exchange_versions()",
@@ -154,6 +154,7 @@ impl ProtocolVersion {
         let node_id = settings.node_id.clone();
         let app_version = settings.app_version.clone();
         let external_addrs = settings.external_addrs.clone();
+        let services = settings.services;
         drop(settings);
 
         let version = VersionMessage {
@@ -166,7 +167,11 @@ impl ProtocolVersion {
             /* NOTE: `features` is a list of enabled features in the
             format Vec<(service, version)>. In the future, Protocols will
             add their own data to this field when they are attached.*/
-            features: vec![],
+            features: vec![
+                (ADDRS_SINCE_FEATURE.to_string(), 1),
+                (SIGNED_ADDRS_FEATURE.to_string(), 1),
+            ],
+            services,
         };
         self.channel.send(&version).await?;
 
@@ -191,7 +196,11 @@ impl ProtocolVersion {
             );
 
             self.channel.stop().await;
-            return Err(Error::ChannelStopped)
+            return Err(HandshakeFailed::IncompatibleVersion(
+                app_version.to_string(),
+                verack_msg.app_version.to_string(),
+            )
+            .into())
         }
 
         // Versions are compatible
@@ -211,7 +220,42 @@ impl ProtocolVersion {
         );
 
         // Receive version message
-        let version = self.version_sub.receive().await?;
+        let version = match self.version_sub.receive().await {
+            Ok(version) => version,
+            // The channel forwards this when the peer's version message
+            // couldn't be decoded
+            Err(Error::MalformedPacket) => {
+                error!(
+                    target: "net::protocol_version::recv_version()",
+                    "[P2P] Malformed version message from {}. Disconnecting...",
+                    self.channel.address(),
+                );
+
+                self.channel.stop().await;
+                return Err(HandshakeFailed::MalformedVersion(
+                    "undecodable version message".to_string(),
+                )
+                .into())
+            }
+            Err(e) => return Err(e),
+        };
+
+        // Time-based protocol logic breaks down with peers whose clocks
+        // are too far off from ours.
+        let max_skew = self.settings.read().await.channel_max_clock_skew;
+        if max_skew > 0 {
+            let skew = version.timestamp as i64 - UNIX_EPOCH.elapsed()?.as_secs() as i64;
+            if skew.unsigned_abs() > max_skew {
+                error!(
+                    target: "net::protocol_version::recv_version()",
+                    "[P2P] Peer {} clock is skewed by {}s. Disconnecting...",
+                    self.channel.address(), skew,
+                );
+
+                self.channel.stop().await;
+                return Err(HandshakeFailed::ClockSkew(skew).into())
+            }
+        }
         self.channel.set_version(version).await;
 
         // Send verack
@@ -76,39 +76,32 @@ impl InboundSession {
 
         let ex = self.p2p().executor();
 
-        // Activate mutex lock on accept tasks.
-        let mut accept_tasks = self.accept_tasks.lock().await;
-
-        for (index, accept_addr) in inbound_addrs.iter().enumerate() {
-            // First initialize an Acceptor and its Subscriber.
-            let parent = Arc::downgrade(&self);
-            let acceptor = Acceptor::new(parent);
-
-            // Now start the Subscriber. The Subscriber will return a Channel once it has been
-            // prepared by the Acceptor.
-            let channel_sub = acceptor.clone().subscribe().await;
-
-            // Then start listening for a Channel returned by the Subscriber. Call setup_channel()
-            // to register the Channel when it has been received.
-            let task = StoppableTask::new();
-            task.clone().start(
-                self.clone().channel_sub_loop(channel_sub, index, ex.clone()),
-                // Ignore stop handler
-                |_| async {},
-                Error::NetworkServiceStopped,
-                ex.clone(),
-            );
-
-            accept_tasks.push(task);
-
-            // Finally, run the Acceptor to start accepting inbound connections. Only when
-            // the Subscriber has been set up can we safely do this.
-            self.clone()
-                .start_accept_session(index, accept_addr.clone(), acceptor, ex.clone())
-                .await?;
-        }
+        // First initialize an Acceptor and its Subscriber. A single Acceptor
+        // listens on all of the inbound addresses, so their connections go
+        // through one accept loop and share the inbound connection limit.
+        let parent = Arc::downgrade(&self);
+        let acceptor = Acceptor::new(parent);
+
+        // Now start the Subscriber. The Subscriber will return a Channel once it has been
+        // prepared by the Acceptor.
+        let channel_sub = acceptor.clone().subscribe().await;
+
+        // Then start listening for a Channel returned by the Subscriber. Call setup_channel()
+        // to register the Channel when it has been received.
+        let task = StoppableTask::new();
+        task.clone().start(
+            self.clone().channel_sub_loop(channel_sub, ex.clone()),
+            // Ignore stop handler
+            |_| async {},
+            Error::NetworkServiceStopped,
+            ex.clone(),
+        );
 
-        Ok(())
+        self.accept_tasks.lock().await.push(task);
+
+        // Finally, run the Acceptor to start accepting inbound connections. Only when
+        // the Subscriber has been set up can we safely do this.
+        self.clone().start_accept_session(inbound_addrs, acceptor, ex).await
     }
 
     /// Stops the inbound session.
@@ -132,16 +125,18 @@ impl InboundSession {
     /// Start accepting connections for inbound session.
     async fn start_accept_session(
         self: Arc<Self>,
-        index: usize,
-        accept_addr: Url,
+        accept_addrs: Vec<Url>,
         acceptor: AcceptorPtr,
         ex: Arc<Executor<'_>>,
     ) -> Result<()> {
-        info!(target: "net::inbound_session", "[P2P] Starting Inbound session #{} on {}", index, accept_addr);
-        // Start listener
-        let result = acceptor.clone().start(accept_addr, ex).await;
+        for accept_addr in &accept_addrs {
+            info!(target: "net::inbound_session", "[P2P] Starting Inbound session on {}", accept_addr);
+        }
+
+        // Start listeners
+        let result = acceptor.clone().start_multi(accept_addrs, ex).await;
         if let Err(e) = &result {
-            error!(target: "net::inbound_session", "[P2P] Error starting listener #{}: {}", index, e);
+            error!(target: "net::inbound_session", "[P2P] Error starting listeners: {}", e);
             acceptor.stop().await;
         } else {
             self.acceptors.lock().await.push(acceptor);
@@ -154,7 +149,6 @@ impl InboundSession {
     async fn channel_sub_loop(
         self: Arc<Self>,
         channel_sub: Subscription<Result<ChannelPtr>>,
-        index: usize,
         ex: Arc<Executor<'_>>,
     ) -> Result<()> {
         loop {
@@ -162,21 +156,16 @@ impl InboundSession {
 
             // Spawn a detached task to process the channel.
             // This will just perform the channel setup then exit.
-            ex.spawn(self.clone().setup_channel(index, channel, ex.clone())).detach();
+            ex.spawn(self.clone().setup_channel(channel, ex.clone())).detach();
         }
     }
 
     /// Registers the channel. First performs a network handshake and starts the channel.
     /// Then starts sending keep-alive and address messages across the channel.
-    async fn setup_channel(
-        self: Arc<Self>,
-        index: usize,
-        channel: ChannelPtr,
-        ex: Arc<Executor<'_>>,
-    ) {
+    async fn setup_channel(self: Arc<Self>, channel: ChannelPtr, ex: Arc<Executor<'_>>) {
         info!(
              target: "net::inbound_session::setup_channel",
-             "[P2P] Connected Inbound #{} [{}]", index, channel.address(),
+             "[P2P] Connected Inbound [{}]", channel.address(),
         );
 
         dnetev!(self, InboundConnected, {
@@ -74,10 +74,20 @@ impl ManualSession {
 
         let self_ = Arc::downgrade(&self);
 
+        // Configured addresses sharing a host are the same peer reachable
+        // over several transports, so they get one slot between them.
+        let mut peers: Vec<Vec<Url>> = vec![];
+        for addr in &self.p2p().settings().read().await.peers {
+            match peers.iter_mut().find(|addrs| addrs[0].host_str() == addr.host_str()) {
+                Some(addrs) => addrs.push(addr.clone()),
+                None => peers.push(vec![addr.clone()]),
+            }
+        }
+
         // Initialize a slot for each configured peer.
         // Connections will be started by not yet activated.
-        for peer in &self.p2p().settings().read().await.peers {
-            let slot = Slot::new(self_.clone(), peer.clone(), self.p2p().settings());
+        for addrs in peers {
+            let slot = Slot::new(self_.clone(), addrs, self.p2p().settings());
             futures.push(slot.clone().start());
             slots.push(slot);
         }
@@ -110,7 +120,9 @@ impl Session for ManualSession {
 }
 
 struct Slot {
-    addr: Url,
+    /// Addresses the peer can be reached at, tried in the configured
+    /// transport preference order
+    addrs: Vec<Url>,
     process: StoppableTaskPtr,
     session: Weak<ManualSession>,
     connector: Connector,
@@ -119,11 +131,11 @@ struct Slot {
 impl Slot {
     fn new(
         session: Weak<ManualSession>,
-        addr: Url,
+        addrs: Vec<Url>,
         settings: Arc<AsyncRwLock<Settings>>,
     ) -> Arc<Self> {
         Arc::new(Self {
-            addr,
+            addrs,
             process: StoppableTask::new(),
             session: session.clone(),
             connector: Connector::new(settings, session),
@@ -149,6 +161,8 @@ impl Slot {
     /// Attempts a connection on the associated Connector object.
     async fn run(self: Arc<Self>) -> Result<()> {
         let ex = self.p2p().executor();
+        let hosts = self.p2p().hosts();
+        let peer = &self.addrs[0];
 
         let mut attempts = 0;
         loop {
@@ -157,7 +171,7 @@ impl Slot {
             info!(
                 target: "net::manual_session",
                 "[P2P] Connecting to manual outbound [{}] (attempt #{})",
-                self.addr, attempts
+                peer, attempts
             );
 
             let settings = self.p2p().settings().read_arc().await;
@@ -167,30 +181,40 @@ impl Slot {
 
             // Do not establish a connection to a host that is also configured as a seed.
             // This indicates a user misconfiguration.
-            if seeds.contains(&self.addr) {
+            if let Some(addr) = self.addrs.iter().find(|addr| seeds.contains(addr)) {
                 error!(
                     target: "net::manual_session",
-                    "[P2P] Suspending manual connection to seed [{}]", self.addr.clone(),
+                    "[P2P] Suspending manual connection to seed [{}]", addr,
                 );
                 return Ok(())
             }
 
-            if let Err(e) = self.p2p().hosts().try_register(self.addr.clone(), HostState::Connect) {
-                debug!(target: "net::manual_session",
-                    "Cannot connect to manual={}, err={}", &self.addr, e);
+            let mut addrs = vec![];
+            for addr in &self.addrs {
+                match hosts.try_register(addr.clone(), HostState::Connect) {
+                    Ok(_) => addrs.push(addr.clone()),
+                    Err(e) => debug!(target: "net::manual_session",
+                        "Cannot connect to manual={}, err={}", addr, e),
+                }
+            }
 
+            if addrs.is_empty() {
                 sleep(outbound_connect_timeout).await;
-
                 continue
             }
 
-            match self.connector.connect(&self.addr).await {
+            match self.connector.connect_any(&addrs).await {
                 Ok((url, channel)) => {
                     info!(
                         target: "net::manual_session",
                         "[P2P] Manual outbound connected [{}]", url,
                     );
 
+                    // Free up the addresses we didn't end up connecting to
+                    for addr in addrs.iter().filter(|addr| *addr != channel.address()) {
+                        hosts.unregister(addr);
+                    }
+
                     let stop_sub = channel.subscribe_stop().await?;
 
                     // Channel is now connected but not yet setup
@@ -207,34 +231,36 @@ impl Slot {
                             );
                         }
                         Err(e) => {
-                            self.handle_failure(e, &url);
+                            self.handle_failure(e, &[channel.address().clone()]);
                         }
                     }
                 }
                 Err(e) => {
-                    self.handle_failure(e, &self.addr);
+                    self.handle_failure(e, &addrs);
                 }
             }
 
             info!(
                 target: "net::manual_session",
                 "[P2P] Waiting {} seconds until next manual outbound connection attempt [{}]",
-                outbound_connect_timeout, self.addr,
+                outbound_connect_timeout, peer,
             );
 
             sleep(outbound_connect_timeout).await;
         }
     }
 
-    fn handle_failure(&self, error: Error, addr: &Url) {
+    fn handle_failure(&self, error: Error, addrs: &[Url]) {
         warn!(
             target: "net::manual_session",
             "[P2P] Unable to connect to manual outbound [{}]: {}",
-            self.addr, error,
+            self.addrs[0], error,
         );
 
-        // Free up this addr for future operations.
-        self.p2p().hosts().unregister(addr);
+        // Free up these addrs for future operations.
+        for addr in addrs {
+            self.p2p().hosts().unregister(addr);
+        }
     }
 
     fn session(&self) -> ManualSessionPtr {
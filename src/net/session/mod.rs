@@ -26,14 +26,20 @@ use log::{debug, trace};
 use smol::Executor;
 
 use super::{channel::ChannelPtr, hosts::HostColor, p2p::P2pPtr, protocol::ProtocolVersion};
-use crate::{system::Subscription, Error, Result};
+use crate::{
+    system::{Instrument, Subscription},
+    Error, Result,
+};
 
 pub mod inbound_session;
 pub use inbound_session::{InboundSession, InboundSessionPtr};
 pub mod manual_session;
 pub use manual_session::{ManualSession, ManualSessionPtr};
 pub mod outbound_session;
-pub use outbound_session::{OutboundSession, OutboundSessionPtr};
+pub use outbound_session::{
+    DiversitySelector, LastSeenSelector, OutboundSession, OutboundSessionPtr, SlotSelector,
+    SlotSelectorPtr,
+};
 pub mod seedsync_session;
 pub use seedsync_session::{SeedSyncSession, SeedSyncSessionPtr};
 pub mod refine_session;
@@ -78,8 +84,14 @@ pub async fn remove_sub_on_stop(
             "Downgrading {}", addr,
         );
 
+        // Banned peers stay on the blacklist
         let last_seen = hosts.fetch_last_seen(addr).unwrap();
-        hosts.move_host(addr, last_seen, HostColor::Grey).unwrap();
+        if let Err(e) = hosts.move_host(addr, last_seen, HostColor::Grey) {
+            debug!(
+                target: "net::session::remove_sub_on_stop()",
+                "Not downgrading {}: {}", addr, e,
+            );
+        }
     }
 
     // For all sessions that are not refine sessions, mark this addr as
@@ -131,8 +143,9 @@ pub trait Session: Sync {
             "Performing handshake protocols {}", channel.clone().address(),
         );
 
-        let handshake_task =
-            self.perform_handshake_protocols(protocol_version, channel.clone(), executor.clone());
+        let handshake_task = self
+            .perform_handshake_protocols(protocol_version, channel.clone(), executor.clone())
+            .instrument(channel.log_span());
 
         // Switch on the channel
         channel.clone().start(executor.clone());
@@ -188,11 +201,16 @@ pub trait Session: Sync {
                         "Upgrading {}", channel.address(),
                     );
 
+                    // This fails if the peer got banned during the handshake
                     let last_seen = UNIX_EPOCH.elapsed().unwrap().as_secs();
-                    self.p2p()
-                        .hosts()
-                        .move_host(channel.address(), last_seen, HostColor::Gold)
-                        .unwrap();
+                    if let Err(e) =
+                        self.p2p().hosts().move_host(channel.address(), last_seen, HostColor::Gold)
+                    {
+                        debug!(
+                            target: "net::session::perform_handshake_protocols()",
+                            "Not upgrading {}: {}", channel.address(), e,
+                        );
+                    }
                 }
 
                 // Attempt to add channel to registry
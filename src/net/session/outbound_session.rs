@@ -27,6 +27,7 @@
 //! same time.
 
 use std::{
+    collections::HashMap,
     sync::{
         atomic::{AtomicU32, Ordering},
         Arc, Weak,
@@ -42,7 +43,7 @@ use url::Url;
 
 use super::{
     super::{
-        channel::ChannelPtr,
+        channel::{ChannelPtr, PeerResolver},
         connector::Connector,
         dnet::{self, dnetev, DnetEvent},
         hosts::{HostColor, HostState},
@@ -58,6 +59,111 @@ use crate::{
 
 pub type OutboundSessionPtr = Arc<OutboundSession>;
 
+/// Strategy ordering the candidate addresses of an outbound slot. Slots
+/// try the candidates in the returned order until one can be used.
+pub trait SlotSelector: Send + Sync {
+    /// Order `candidates`, given as (address, last seen) sorted by last
+    /// seen, knowing the addresses of the peers we're connected to. The
+    /// P2P peer resolver is passed along if one is set.
+    fn select(
+        &self,
+        candidates: Vec<(Url, u64)>,
+        connected: &[Url],
+        resolver: Option<&dyn PeerResolver>,
+    ) -> Vec<(Url, u64)>;
+}
+
+/// Atomic pointer to an outbound slot selection strategy
+pub type SlotSelectorPtr = Arc<dyn SlotSelector>;
+
+/// Default strategy, trying the most recently seen candidates first
+pub struct LastSeenSelector;
+
+impl SlotSelector for LastSeenSelector {
+    fn select(
+        &self,
+        candidates: Vec<(Url, u64)>,
+        _connected: &[Url],
+        _resolver: Option<&dyn PeerResolver>,
+    ) -> Vec<(Url, u64)> {
+        candidates
+    }
+}
+
+/// Strategy spreading outbound connections across networks, making it
+/// harder for an attacker controlling a few networks to eclipse us.
+///
+/// Candidates are grouped by ASN if the peer resolver knows it, and by
+/// subnet (/16 for IPv4, /32 for IPv6) or hostname otherwise. Groups take
+/// turns weighted by how many peers we're already connected to in them,
+/// so the least represented networks come first. Within a group the most
+/// recently seen candidates come first.
+pub struct DiversitySelector;
+
+impl DiversitySelector {
+    /// Network group an address belongs to
+    fn group(addr: &Url, resolver: Option<&dyn PeerResolver>) -> String {
+        if let Some(asn) = resolver.and_then(|r| r.resolve(addr).asn) {
+            return format!("as{}", asn)
+        }
+
+        match addr.host() {
+            Some(url::Host::Ipv4(ip)) => {
+                let [a, b, _, _] = ip.octets();
+                format!("{}.{}.0.0/16", a, b)
+            }
+            Some(url::Host::Ipv6(ip)) => {
+                let [a, b, ..] = ip.segments();
+                format!("{:x}:{:x}::/32", a, b)
+            }
+            Some(url::Host::Domain(domain)) => domain.to_string(),
+            None => String::new(),
+        }
+    }
+}
+
+impl SlotSelector for DiversitySelector {
+    fn select(
+        &self,
+        candidates: Vec<(Url, u64)>,
+        connected: &[Url],
+        resolver: Option<&dyn PeerResolver>,
+    ) -> Vec<(Url, u64)> {
+        let mut load: HashMap<String, usize> = HashMap::new();
+        for addr in connected {
+            *load.entry(Self::group(addr, resolver)).or_default() += 1;
+        }
+
+        // Bucket the candidates by group, in order of first appearance
+        let mut groups: Vec<(String, Vec<(Url, u64)>)> = vec![];
+        for candidate in candidates {
+            let group = Self::group(&candidate.0, resolver);
+            match groups.iter_mut().find(|(g, _)| *g == group) {
+                Some((_, members)) => members.push(candidate),
+                None => groups.push((group, vec![candidate])),
+            }
+        }
+        for (_, members) in groups.iter_mut() {
+            members.reverse();
+        }
+
+        // Repeatedly pick from the least loaded group, counting each pick
+        let mut ordered = vec![];
+        loop {
+            let next = groups
+                .iter_mut()
+                .filter(|(_, members)| !members.is_empty())
+                .min_by_key(|(group, _)| load.get(group).copied().unwrap_or(0));
+            let Some((group, members)) = next else { break };
+
+            ordered.push(members.pop().unwrap());
+            *load.entry(group.clone()).or_default() += 1;
+        }
+
+        ordered
+    }
+}
+
 /// Defines outbound connections session.
 pub struct OutboundSession {
     /// Weak pointer to parent p2p object
@@ -238,6 +344,12 @@ impl Slot {
             container.fetch(HostColor::Grey, &transports, transport_mixing)
         };
 
+        // Let the configured strategy decide which candidates to try first
+        let p2p = self.p2p();
+        let connected: Vec<Url> = hosts.peers().iter().map(|c| c.address().clone()).collect();
+        let resolver = p2p.peer_resolver();
+        let addrs = p2p.slot_selector().select(addrs, &connected, resolver.as_deref());
+
         hosts.check_addrs(addrs).await
     }
 
@@ -367,10 +479,7 @@ impl Slot {
                 );
 
                 // At this point we failed to connect. We'll downgrade this peer now.
-                self.p2p().hosts().move_host(&addr, last_seen, HostColor::Grey)?;
-
-                // Mark its state as Suspend, which sends this node to the Refinery for processing.
-                self.p2p().hosts().try_register(addr.clone(), HostState::Suspend).unwrap();
+                self.suspend_host(&addr, last_seen);
 
                 continue
             }
@@ -384,6 +493,24 @@ impl Slot {
         }
     }
 
+    /// Downgrade a peer we failed to connect to, and mark its state as
+    /// Suspend, which sends it to the Refinery for processing. Banned
+    /// peers can't be downgraded, so they're just freed up instead.
+    fn suspend_host(&self, addr: &Url, last_seen: u64) {
+        let hosts = self.p2p().hosts();
+
+        if let Err(e) = hosts.move_host(addr, last_seen, HostColor::Grey) {
+            debug!(
+                target: "net::outbound_session::suspend_host()",
+                "Not downgrading {}: {}", addr, e,
+            );
+            hosts.unregister(addr);
+            return
+        }
+
+        hosts.try_register(addr.clone(), HostState::Suspend).unwrap();
+    }
+
     /// Start making an outbound connection, using provided [`Connector`].
     /// Tries to find a valid address to connect to, otherwise does peer
     /// discovery. The peer discovery loops until some peer we can connect
@@ -410,10 +537,7 @@ impl Slot {
                 }
 
                 // At this point we failed to connect. We'll downgrade this peer now.
-                self.p2p().hosts().move_host(&addr, last_seen, HostColor::Grey)?;
-
-                // Mark its state as Suspend, which sends it to the Refinery for processing.
-                self.p2p().hosts().try_register(addr.clone(), HostState::Suspend).unwrap();
+                self.suspend_host(&addr, last_seen);
 
                 // Notify that channel processing failed
                 self.p2p().hosts().channel_publisher.notify(Err(Error::ConnectFailed)).await;
@@ -571,6 +695,8 @@ impl PeerDiscoveryBase for PeerDiscovery {
                 let get_addrs = GetAddrsMessage {
                     max: outbound_connections as u32,
                     transports: allowed_transports,
+                    since: None,
+                    signed: false,
                 };
 
                 self.p2p().broadcast(&get_addrs).await;
@@ -655,3 +781,60 @@ impl PeerDiscoveryBase for PeerDiscovery {
         self.session().p2p()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::net::channel::PeerLocation;
+
+    fn candidates(addrs: &[&str]) -> Vec<(Url, u64)> {
+        addrs.iter().enumerate().map(|(i, a)| (Url::parse(a).unwrap(), 100 - i as u64)).collect()
+    }
+
+    fn hosts(selected: &[(Url, u64)]) -> Vec<&str> {
+        selected.iter().map(|(u, _)| u.host_str().unwrap()).collect()
+    }
+
+    #[test]
+    fn diversity_selector_spreads_subnets() {
+        // Most recently seen candidates all sit in one subnet
+        let candidates = candidates(&[
+            "tcp://10.1.0.1:26661",
+            "tcp://10.1.0.2:26661",
+            "tcp://10.1.0.3:26661",
+            "tcp://10.2.0.1:26661",
+            "tcp://10.2.0.2:26661",
+        ]);
+
+        let selected = LastSeenSelector.select(candidates.clone(), &[], None);
+        assert_eq!(selected, candidates);
+
+        // Without connections the subnets take turns
+        let selected = DiversitySelector.select(candidates.clone(), &[], None);
+        assert_eq!(hosts(&selected), ["10.1.0.1", "10.2.0.1", "10.1.0.2", "10.2.0.2", "10.1.0.3"]);
+
+        // Subnets we're already connected to come last
+        let connected = vec![Url::parse("tcp://10.1.9.9:26661").unwrap()];
+        let selected = DiversitySelector.select(candidates, &connected, None);
+        assert_eq!(hosts(&selected)[..2], ["10.2.0.1", "10.1.0.1"]);
+    }
+
+    struct AsnResolver;
+
+    impl PeerResolver for AsnResolver {
+        fn resolve(&self, addr: &Url) -> PeerLocation {
+            // Both subnets belong to the same AS, except for one host
+            let asn = if addr.host_str() == Some("10.2.0.2") { 64497 } else { 64496 };
+            PeerLocation { asn: Some(asn), country: None }
+        }
+    }
+
+    #[test]
+    fn diversity_selector_uses_asn() {
+        let candidates =
+            candidates(&["tcp://10.1.0.1:26661", "tcp://10.2.0.1:26661", "tcp://10.2.0.2:26661"]);
+
+        let selected = DiversitySelector.select(candidates, &[], Some(&AsnResolver));
+        assert_eq!(hosts(&selected), ["10.1.0.1", "10.2.0.2", "10.2.0.1"]);
+    }
+}
@@ -294,7 +294,12 @@ impl GreylistRefinery {
                     );
                     let last_seen = UNIX_EPOCH.elapsed().unwrap().as_secs();
 
-                    hosts.whitelist_host(url, last_seen).unwrap();
+                    // The peer may have been banned or connected to meanwhile
+                    if let Err(e) = hosts.whitelist_host(url, last_seen) {
+                        debug!(target: "net::refinery", "Unable to whitelist addr={}, err={}",
+                               url.clone(), e);
+                        continue
+                    }
 
                     debug!(target: "net::refinery", "GreylistRefinery complete!");
 
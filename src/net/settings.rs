@@ -19,6 +19,12 @@
 use structopt::StructOpt;
 use url::Url;
 
+use super::{
+    message::{ServiceFlags, SERVICE_NONE},
+    transport::parse_endpoint,
+};
+use crate::{Error, Result};
+
 type BlacklistEntry = (String, Vec<String>, Vec<u16>);
 
 /// Ban policies definitions.
@@ -39,6 +45,23 @@ pub enum BanPolicy {
     Relaxed,
 }
 
+/// Action taken when a peer sends a message without a corresponding
+/// MessageDispatcher.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UnknownCommandAction {
+    /// Treat it as a violation, judged by the violation policy. The
+    /// default policy bans the peer under a `Strict` ban policy.
+    #[default]
+    Ban,
+
+    /// Log a warning and skip the message
+    Warn,
+
+    /// Silently skip the message
+    Ignore,
+}
+
 /// P2P network settings. The scope of this is a P2P network instance
 /// configured by the library user.
 #[derive(Debug, Clone)]
@@ -50,7 +73,8 @@ pub struct Settings {
     /// P2P external addresses the instance advertises so other peers can
     /// reach us and connect to us, as long as inbound addrs are configured
     pub external_addrs: Vec<Url>,
-    /// Peer nodes to manually connect to
+    /// Peer nodes to manually connect to. Addresses sharing a host are
+    /// treated as one peer reachable over several transports.
     pub peers: Vec<Url>,
     /// Seed nodes to connect to for peer discovery and/or adversising our
     /// own external addresses
@@ -61,20 +85,56 @@ pub struct Settings {
     pub allowed_transports: Vec<String>,
     /// Allow transport mixing (e.g. Tor would be allowed to connect to `tcp://`)
     pub transport_mixing: bool,
+    /// Order in which transports are tried when a peer is reachable over
+    /// several of them, most preferred first (e.g. `["tor", "tcp+tls"]`
+    /// for anonymity). Unlisted transports are tried last.
+    pub transport_preference: Vec<String>,
     /// Outbound connection slots number, this many connections will be
     /// attempted. (This does not include manual connections)
     pub outbound_connections: usize,
     /// Inbound connection slots number, this many active listening connections
     /// will be allowed. (This does not include manual connections)
     pub inbound_connections: usize,
+    /// Maximum amount of inbound connections from a single remote
+    /// address, 0 for unlimited
+    pub inbound_connections_per_address: usize,
     /// Outbound connection timeout (in seconds)
     pub outbound_connect_timeout: u64,
     /// Exchange versions (handshake) timeout (in seconds)
     pub channel_handshake_timeout: u64,
     /// Ping-pong exchange execution interval (in seconds)
     pub channel_heartbeat_interval: u64,
+    /// Half-open connection probe interval (in seconds), 0 disables it
+    pub channel_probe_interval: u64,
+    /// Time to wait for the probe's pong before stopping the channel (in seconds)
+    pub channel_probe_timeout: u64,
+    /// Maximum difference between our clock and the timestamp in a
+    /// peer's version message (in seconds), 0 disables the check.
+    /// Disabled by default.
+    pub channel_max_clock_skew: u64,
     /// Allow localnet hosts
     pub localnet: bool,
+    /// Derive channel ids from the connection tuple instead of picking
+    /// them at random, so a reconnection keeps the same id
+    pub deterministic_channel_ids: bool,
+    /// Hex-dump message payloads at trace level. Payloads can contain
+    /// sensitive data, so this should only be enabled for debugging.
+    pub log_payloads: bool,
+    /// Maximum length of a message payload (in bytes). Peers declaring a
+    /// longer one are disconnected before it's read. 0 disables the limit.
+    pub max_payload_len: u64,
+    /// Drop messages opting into deduplication that were already
+    /// received over another channel
+    pub message_dedup: bool,
+    /// Maximum amount of message hashes kept for deduplication
+    pub message_dedup_size: usize,
+    /// How long received messages are remembered (in seconds)
+    pub message_dedup_ttl: u64,
+    /// Base58-encoded public keys of seeds trusted to sign address lists.
+    /// If set, seeds are asked for signed address lists.
+    pub trusted_seed_keys: Vec<String>,
+    /// Only accept seed address lists signed by a trusted seed key
+    pub strict_signed_addrs: bool,
     /// Cooling off time for peer discovery when unsuccessful
     pub outbound_peer_discovery_cooloff_time: u64,
     /// Time between peer discovery attempts
@@ -104,6 +164,33 @@ pub struct Settings {
     /// Do not ban nodes that send messages without dispatchers if set
     /// to `Relaxed`. For most uses, should be set to `Strict`.
     pub ban_policy: BanPolicy,
+    /// How inbound, outbound and manual channels handle messages
+    /// without dispatchers
+    pub unknown_command_action: UnknownCommandAction,
+    /// How seed channels handle messages without dispatchers
+    pub seed_unknown_command_action: UnknownCommandAction,
+    /// How refine channels handle messages without dispatchers. These
+    /// only run the version handshake, so other messages are expected.
+    pub refine_unknown_command_action: UnknownCommandAction,
+    /// Service bitflags advertised to peers in the version exchange
+    pub services: ServiceFlags,
+    /// Tor bridge lines to use on networks where Tor is blocked
+    pub tor_bridges: Vec<String>,
+    /// Path to the pluggable transport binary used by the Tor bridges
+    pub tor_pt_path: Option<String>,
+    /// Maximum amount of inbound Tor rendezvous handshakes negotiated
+    /// concurrently
+    pub tor_handshake_limit: usize,
+    /// Nicknames of the onion services launched by Tor listeners.
+    /// If empty, a single default service is launched.
+    pub tor_onion_services: Vec<String>,
+    /// Only use Tor for dialing, never launching an onion service.
+    /// Inbound Tor addresses are rejected when this is set.
+    pub tor_dial_only: bool,
+    /// Maximum amount of hostnames kept in the DNS cache, 0 disables it
+    pub dns_cache_size: usize,
+    /// How long resolved hostnames stay in the DNS cache (in seconds)
+    pub dns_cache_ttl: u64,
 }
 
 impl Default for Settings {
@@ -120,12 +207,25 @@ impl Default for Settings {
             app_version,
             allowed_transports: vec!["tcp+tls".to_string()],
             transport_mixing: true,
+            transport_preference: vec![],
             outbound_connections: 8,
             inbound_connections: 8,
+            inbound_connections_per_address: 0,
             outbound_connect_timeout: 15,
             channel_handshake_timeout: 10,
             channel_heartbeat_interval: 30,
+            channel_probe_interval: 0,
+            channel_probe_timeout: 10,
+            channel_max_clock_skew: 0,
             localnet: false,
+            deterministic_channel_ids: false,
+            log_payloads: false,
+            max_payload_len: 0,
+            message_dedup: false,
+            message_dedup_size: 4096,
+            message_dedup_ttl: 120,
+            trusted_seed_keys: vec![],
+            strict_signed_addrs: false,
             outbound_peer_discovery_cooloff_time: 30,
             outbound_peer_discovery_attempt_time: 5,
             p2p_datastore: None,
@@ -137,6 +237,17 @@ impl Default for Settings {
             time_with_no_connections: 30,
             blacklist: vec![],
             ban_policy: BanPolicy::Strict,
+            unknown_command_action: UnknownCommandAction::Ban,
+            seed_unknown_command_action: UnknownCommandAction::Ignore,
+            refine_unknown_command_action: UnknownCommandAction::Ignore,
+            services: SERVICE_NONE,
+            tor_bridges: vec![],
+            tor_pt_path: None,
+            tor_handshake_limit: 8,
+            tor_onion_services: vec![],
+            tor_dial_only: false,
+            dns_cache_size: 256,
+            dns_cache_ttl: 300,
         }
     }
 }
@@ -149,8 +260,8 @@ impl Default for Settings {
 #[structopt()]
 pub struct SettingsOpt {
     /// P2P accept address node listens to for inbound connections
-    #[serde(default)]
-    #[structopt(long = "accept")]
+    #[serde(default, deserialize_with = "deserialize_endpoints")]
+    #[structopt(long = "accept", parse(try_from_str = parse_endpoint))]
     pub inbound: Vec<Url>,
 
     /// Outbound connection slots number
@@ -161,22 +272,28 @@ pub struct SettingsOpt {
     #[structopt(long = "inbound-slots")]
     pub inbound_connections: Option<usize>,
 
+    /// Maximum amount of inbound connections from a single remote
+    /// address, 0 for unlimited
+    #[serde(default)]
+    #[structopt(long)]
+    pub inbound_connections_per_address: Option<usize>,
+
     /// P2P external addresses node advertises so other peers can
     /// reach us and connect to us, as long as inbound addresses
     /// are also configured
-    #[serde(default)]
-    #[structopt(long)]
+    #[serde(default, deserialize_with = "deserialize_endpoints")]
+    #[structopt(long, parse(try_from_str = parse_endpoint))]
     pub external_addrs: Vec<Url>,
 
-    /// Peer nodes to manually connect to
-    #[serde(default)]
-    #[structopt(long)]
+    /// Peer nodes to manually connect to, as URLs or multiaddrs
+    #[serde(default, deserialize_with = "deserialize_endpoints")]
+    #[structopt(long, parse(try_from_str = parse_endpoint))]
     pub peers: Vec<Url>,
 
     /// Seed nodes to connect to for peers retrieval and/or
-    /// advertising our own external addresses
-    #[serde(default)]
-    #[structopt(long)]
+    /// advertising our own external addresses, as URLs or multiaddrs
+    #[serde(default, deserialize_with = "deserialize_endpoints")]
+    #[structopt(long, parse(try_from_str = parse_endpoint))]
     pub seeds: Vec<Url>,
 
     /// Connection establishment timeout in seconds
@@ -191,6 +308,18 @@ pub struct SettingsOpt {
     #[structopt(skip)]
     pub channel_heartbeat_interval: Option<u64>,
 
+    /// Half-open connection probe interval in seconds, 0 disables it
+    #[structopt(skip)]
+    pub channel_probe_interval: Option<u64>,
+
+    /// Probe pong timeout in seconds
+    #[structopt(skip)]
+    pub channel_probe_timeout: Option<u64>,
+
+    /// Maximum peer clock skew in seconds, 0 disables the check
+    #[structopt(skip)]
+    pub channel_max_clock_skew: Option<u64>,
+
     /// Only used for debugging. Compromises privacy when set.
     #[serde(default)]
     #[structopt(skip)]
@@ -205,6 +334,12 @@ pub struct SettingsOpt {
     #[structopt(long)]
     pub transport_mixing: Option<bool>,
 
+    /// Order in which transports are tried when a peer is reachable
+    /// over several of them, most preferred first
+    #[serde(default)]
+    #[structopt(long)]
+    pub transport_preference: Vec<String>,
+
     /// If this is true, strictly follow the gold_connect_count and
     /// white_connect_percent settings. Otherwise, connect to greylist
     /// entries if we have no white or gold connections.
@@ -212,6 +347,48 @@ pub struct SettingsOpt {
     #[structopt(long)]
     pub localnet: bool,
 
+    /// Derive channel ids from the connection tuple instead of picking
+    /// them at random, so a reconnection keeps the same id
+    #[serde(default)]
+    #[structopt(long)]
+    pub deterministic_channel_ids: bool,
+
+    /// Hex-dump message payloads at trace level (for debugging)
+    #[serde(default)]
+    #[structopt(long)]
+    pub log_payloads: bool,
+
+    /// Maximum length of a message payload in bytes (0 for no limit)
+    #[serde(default)]
+    #[structopt(long)]
+    pub max_payload_len: Option<u64>,
+
+    /// Drop messages opting into deduplication that were already
+    /// received over another channel
+    #[serde(default)]
+    #[structopt(long)]
+    pub message_dedup: bool,
+
+    /// Maximum amount of message hashes kept for deduplication
+    #[serde(default)]
+    #[structopt(long)]
+    pub message_dedup_size: Option<usize>,
+
+    /// How long received messages are remembered (in seconds)
+    #[serde(default)]
+    #[structopt(long)]
+    pub message_dedup_ttl: Option<u64>,
+
+    /// Base58-encoded public keys of seeds trusted to sign address lists
+    #[serde(default)]
+    #[structopt(long)]
+    pub trusted_seed_keys: Vec<String>,
+
+    /// Only accept seed address lists signed by a trusted seed key
+    #[serde(default)]
+    #[structopt(long)]
+    pub strict_signed_addrs: bool,
+
     /// Cooling off time for peer discovery when unsuccessful
     #[structopt(skip)]
     pub outbound_peer_discovery_cooloff_time: Option<u64>,
@@ -265,6 +442,72 @@ pub struct SettingsOpt {
     #[serde(default)]
     #[structopt(skip)]
     pub ban_policy: BanPolicy,
+
+    /// How inbound, outbound and manual channels handle messages
+    /// without dispatchers
+    #[structopt(skip)]
+    pub unknown_command_action: Option<UnknownCommandAction>,
+
+    /// How seed channels handle messages without dispatchers
+    #[structopt(skip)]
+    pub seed_unknown_command_action: Option<UnknownCommandAction>,
+
+    /// How refine channels handle messages without dispatchers
+    #[structopt(skip)]
+    pub refine_unknown_command_action: Option<UnknownCommandAction>,
+
+    /// Service bitflags advertised to peers in the version exchange
+    #[structopt(skip)]
+    pub services: Option<ServiceFlags>,
+
+    /// Tor bridge lines to use on networks where Tor is blocked
+    #[serde(default)]
+    #[structopt(long)]
+    pub tor_bridges: Vec<String>,
+
+    /// Path to the pluggable transport binary used by the Tor bridges
+    #[serde(default)]
+    #[structopt(long)]
+    pub tor_pt_path: Option<String>,
+
+    /// Maximum amount of inbound Tor rendezvous handshakes negotiated
+    /// concurrently
+    #[serde(default)]
+    #[structopt(long)]
+    pub tor_handshake_limit: Option<usize>,
+
+    /// Nicknames of the onion services launched by Tor listeners
+    #[serde(default)]
+    #[structopt(long)]
+    pub tor_onion_services: Vec<String>,
+
+    /// Only use Tor for dialing, never launching an onion service
+    #[serde(default)]
+    #[structopt(long)]
+    pub tor_dial_only: bool,
+
+    /// Maximum amount of hostnames kept in the DNS cache, 0 disables it
+    #[serde(default)]
+    #[structopt(long)]
+    pub dns_cache_size: Option<usize>,
+
+    /// How long resolved hostnames stay in the DNS cache (in seconds)
+    #[serde(default)]
+    #[structopt(long)]
+    pub dns_cache_ttl: Option<u64>,
+}
+
+/// Parse endpoints from a config file with [`parse_endpoint()`], so they
+/// are accepted in the same forms as on the command line
+fn deserialize_endpoints<'de, D>(deserializer: D) -> std::result::Result<Vec<Url>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let endpoints: Vec<String> = serde::Deserialize::deserialize(deserializer)?;
+    endpoints
+        .iter()
+        .map(|endpoint| parse_endpoint(endpoint).map_err(serde::de::Error::custom))
+        .collect()
 }
 
 impl From<SettingsOpt> for Settings {
@@ -280,8 +523,12 @@ impl From<SettingsOpt> for Settings {
             app_version: def.app_version,
             allowed_transports: opt.allowed_transports.unwrap_or(def.allowed_transports),
             transport_mixing: opt.transport_mixing.unwrap_or(def.transport_mixing),
+            transport_preference: opt.transport_preference,
             outbound_connections: opt.outbound_connections.unwrap_or(def.outbound_connections),
             inbound_connections: opt.inbound_connections.unwrap_or(def.inbound_connections),
+            inbound_connections_per_address: opt
+                .inbound_connections_per_address
+                .unwrap_or(def.inbound_connections_per_address),
             outbound_connect_timeout: opt
                 .outbound_connect_timeout
                 .unwrap_or(def.outbound_connect_timeout),
@@ -291,7 +538,22 @@ impl From<SettingsOpt> for Settings {
             channel_heartbeat_interval: opt
                 .channel_heartbeat_interval
                 .unwrap_or(def.channel_heartbeat_interval),
+            channel_probe_interval: opt
+                .channel_probe_interval
+                .unwrap_or(def.channel_probe_interval),
+            channel_probe_timeout: opt.channel_probe_timeout.unwrap_or(def.channel_probe_timeout),
+            channel_max_clock_skew: opt
+                .channel_max_clock_skew
+                .unwrap_or(def.channel_max_clock_skew),
             localnet: opt.localnet,
+            deterministic_channel_ids: opt.deterministic_channel_ids,
+            log_payloads: opt.log_payloads,
+            max_payload_len: opt.max_payload_len.unwrap_or(def.max_payload_len),
+            message_dedup: opt.message_dedup,
+            message_dedup_size: opt.message_dedup_size.unwrap_or(def.message_dedup_size),
+            message_dedup_ttl: opt.message_dedup_ttl.unwrap_or(def.message_dedup_ttl),
+            trusted_seed_keys: opt.trusted_seed_keys,
+            strict_signed_addrs: opt.strict_signed_addrs,
             outbound_peer_discovery_cooloff_time: opt
                 .outbound_peer_discovery_cooloff_time
                 .unwrap_or(def.outbound_peer_discovery_cooloff_time),
@@ -311,6 +573,414 @@ impl From<SettingsOpt> for Settings {
                 .unwrap_or(def.time_with_no_connections),
             blacklist: opt.blacklist,
             ban_policy: opt.ban_policy,
+            unknown_command_action: opt
+                .unknown_command_action
+                .unwrap_or(def.unknown_command_action),
+            seed_unknown_command_action: opt
+                .seed_unknown_command_action
+                .unwrap_or(def.seed_unknown_command_action),
+            refine_unknown_command_action: opt
+                .refine_unknown_command_action
+                .unwrap_or(def.refine_unknown_command_action),
+            services: opt.services.unwrap_or(def.services),
+            tor_bridges: opt.tor_bridges,
+            tor_pt_path: opt.tor_pt_path,
+            tor_handshake_limit: opt.tor_handshake_limit.unwrap_or(def.tor_handshake_limit),
+            tor_onion_services: opt.tor_onion_services,
+            tor_dial_only: opt.tor_dial_only,
+            dns_cache_size: opt.dns_cache_size.unwrap_or(def.dns_cache_size),
+            dns_cache_ttl: opt.dns_cache_ttl.unwrap_or(def.dns_cache_ttl),
         }
     }
 }
+
+/// Builds [`Settings`], checking the result for invalid combinations
+/// so misconfiguration is caught before the P2P instance is started.
+/// Fields without a setter keep their default values.
+#[derive(Clone, Debug, Default)]
+pub struct SettingsBuilder {
+    settings: Settings,
+}
+
+impl From<Settings> for SettingsBuilder {
+    fn from(settings: Settings) -> Self {
+        Self { settings }
+    }
+}
+
+impl SettingsBuilder {
+    /// Start from the default settings
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn node_id(mut self, node_id: &str) -> Self {
+        self.settings.node_id = node_id.to_string();
+        self
+    }
+
+    pub fn inbound_addrs(mut self, addrs: Vec<Url>) -> Self {
+        self.settings.inbound_addrs = addrs;
+        self
+    }
+
+    pub fn external_addrs(mut self, addrs: Vec<Url>) -> Self {
+        self.settings.external_addrs = addrs;
+        self
+    }
+
+    pub fn peers(mut self, peers: Vec<Url>) -> Self {
+        self.settings.peers = peers;
+        self
+    }
+
+    pub fn seeds(mut self, seeds: Vec<Url>) -> Self {
+        self.settings.seeds = seeds;
+        self
+    }
+
+    pub fn app_version(mut self, version: semver::Version) -> Self {
+        self.settings.app_version = version;
+        self
+    }
+
+    pub fn allowed_transports(mut self, transports: &[&str]) -> Self {
+        self.settings.allowed_transports = transports.iter().map(|t| t.to_string()).collect();
+        self
+    }
+
+    pub fn transport_mixing(mut self, mixing: bool) -> Self {
+        self.settings.transport_mixing = mixing;
+        self
+    }
+
+    pub fn transport_preference(mut self, transports: &[&str]) -> Self {
+        self.settings.transport_preference = transports.iter().map(|t| t.to_string()).collect();
+        self
+    }
+
+    pub fn outbound_connections(mut self, slots: usize) -> Self {
+        self.settings.outbound_connections = slots;
+        self
+    }
+
+    pub fn inbound_connections(mut self, slots: usize) -> Self {
+        self.settings.inbound_connections = slots;
+        self
+    }
+
+    pub fn inbound_connections_per_address(mut self, limit: usize) -> Self {
+        self.settings.inbound_connections_per_address = limit;
+        self
+    }
+
+    /// Outbound connection timeout (in seconds)
+    pub fn outbound_connect_timeout(mut self, secs: u64) -> Self {
+        self.settings.outbound_connect_timeout = secs;
+        self
+    }
+
+    /// Exchange versions (handshake) timeout (in seconds)
+    pub fn channel_handshake_timeout(mut self, secs: u64) -> Self {
+        self.settings.channel_handshake_timeout = secs;
+        self
+    }
+
+    /// Ping-pong exchange execution interval (in seconds)
+    pub fn channel_heartbeat_interval(mut self, secs: u64) -> Self {
+        self.settings.channel_heartbeat_interval = secs;
+        self
+    }
+
+    pub fn localnet(mut self, localnet: bool) -> Self {
+        self.settings.localnet = localnet;
+        self
+    }
+
+    pub fn hostlist(mut self, path: &str) -> Self {
+        self.settings.hostlist = Some(path.to_string());
+        self
+    }
+
+    pub fn white_connect_percent(mut self, percent: usize) -> Self {
+        self.settings.white_connect_percent = percent;
+        self
+    }
+
+    pub fn gold_connect_count(mut self, count: usize) -> Self {
+        self.settings.gold_connect_count = count;
+        self
+    }
+
+    pub fn ban_policy(mut self, policy: BanPolicy) -> Self {
+        self.settings.ban_policy = policy;
+        self
+    }
+
+    pub fn unknown_command_action(mut self, action: UnknownCommandAction) -> Self {
+        self.settings.unknown_command_action = action;
+        self
+    }
+
+    pub fn seed_unknown_command_action(mut self, action: UnknownCommandAction) -> Self {
+        self.settings.seed_unknown_command_action = action;
+        self
+    }
+
+    pub fn refine_unknown_command_action(mut self, action: UnknownCommandAction) -> Self {
+        self.settings.refine_unknown_command_action = action;
+        self
+    }
+
+    pub fn services(mut self, services: ServiceFlags) -> Self {
+        self.settings.services = services;
+        self
+    }
+
+    /// Validate and return the assembled settings
+    pub fn build(self) -> Result<Settings> {
+        self.settings.validate()?;
+        Ok(self.settings)
+    }
+}
+
+/// Whether `url` uses one of the Tor transports
+pub(in crate::net) fn is_tor_scheme(url: &Url) -> bool {
+    url.scheme() == "tor" || url.scheme() == "tor+tls"
+}
+
+impl Settings {
+    /// Check the settings for values the P2P stack can't work with,
+    /// returning `Error::InvalidSettings` describing the first one found.
+    pub fn validate(&self) -> Result<()> {
+        let invalid = |msg: String| Err(Error::InvalidSettings(msg));
+
+        for (name, value) in [
+            ("outbound_connect_timeout", self.outbound_connect_timeout),
+            ("channel_handshake_timeout", self.channel_handshake_timeout),
+            ("channel_heartbeat_interval", self.channel_heartbeat_interval),
+        ] {
+            if value == 0 {
+                return invalid(format!("{} must be non-zero", name))
+            }
+        }
+
+        if self.channel_probe_interval > 0 && self.channel_probe_timeout == 0 {
+            return invalid("channel_probe_timeout must be non-zero when probing".to_string())
+        }
+
+        if self.allowed_transports.is_empty() {
+            return invalid("allowed_transports is empty".to_string())
+        }
+
+        for (name, urls) in [
+            ("inbound_addrs", &self.inbound_addrs),
+            ("external_addrs", &self.external_addrs),
+            ("peers", &self.peers),
+            ("seeds", &self.seeds),
+        ] {
+            for url in urls {
+                // Unix sockets are addressed by path, everything else by host:port
+                if url.scheme() != "unix" && (url.host_str().is_none() || url.port().is_none()) {
+                    return invalid(format!("{} entry {} is missing a host or port", name, url))
+                }
+            }
+        }
+
+        if self.tor_dial_only {
+            if let Some(url) = self.inbound_addrs.iter().find(|url| is_tor_scheme(url)) {
+                return invalid(format!(
+                    "inbound_addrs entry {} requires a Tor listener, but tor_dial_only is set",
+                    url
+                ))
+            }
+        }
+
+        if !self.external_addrs.is_empty() && self.inbound_addrs.is_empty() {
+            return invalid("external_addrs requires inbound_addrs".to_string())
+        }
+
+        if self.outbound_connections > 0 && self.seeds.is_empty() && self.hostlist.is_none() {
+            return invalid("outbound_connections requires seeds or a hostlist".to_string())
+        }
+
+        if self.white_connect_percent > 100 {
+            return invalid(format!(
+                "white_connect_percent is {}, must be at most 100",
+                self.white_connect_percent
+            ))
+        }
+
+        if self.gold_connect_count > self.outbound_connections {
+            return invalid(format!(
+                "gold_connect_count ({}) exceeds outbound_connections ({})",
+                self.gold_connect_count, self.outbound_connections
+            ))
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn seed() -> Url {
+        Url::parse("tcp+tls://seed.example:26661").unwrap()
+    }
+
+    fn assert_invalid(builder: SettingsBuilder, expected: &str) {
+        match builder.build() {
+            Err(Error::InvalidSettings(msg)) => assert!(msg.contains(expected), "{}", msg),
+            res => panic!("expected invalid settings, got {:?}", res),
+        }
+    }
+
+    #[test]
+    fn settings_builder_validation() {
+        let base = SettingsBuilder::new().seeds(vec![seed()]);
+
+        let settings = base
+            .clone()
+            .node_id("test")
+            .inbound_addrs(vec![Url::parse("tcp://0.0.0.0:26661").unwrap()])
+            .external_addrs(vec![Url::parse("tcp://node.example:26661").unwrap()])
+            .outbound_connections(4)
+            .gold_connect_count(1)
+            .build()
+            .unwrap();
+        assert_eq!(settings.node_id, "test");
+        assert_eq!(settings.outbound_connections, 4);
+
+        assert_invalid(base.clone().outbound_connect_timeout(0), "outbound_connect_timeout");
+        assert_invalid(base.clone().channel_handshake_timeout(0), "channel_handshake_timeout");
+        assert_invalid(base.clone().channel_heartbeat_interval(0), "channel_heartbeat_interval");
+        assert_invalid(
+            SettingsBuilder::from(Settings {
+                seeds: vec![seed()],
+                channel_probe_interval: 10,
+                channel_probe_timeout: 0,
+                ..Default::default()
+            }),
+            "channel_probe_timeout",
+        );
+        assert_invalid(base.clone().allowed_transports(&[]), "allowed_transports");
+        assert_invalid(
+            base.clone().peers(vec![Url::parse("tcp://peer.example").unwrap()]),
+            "peers entry",
+        );
+        assert_invalid(
+            base.clone().external_addrs(vec![Url::parse("tcp://node.example:26661").unwrap()]),
+            "requires inbound_addrs",
+        );
+        assert_invalid(SettingsBuilder::new(), "requires seeds");
+        assert_invalid(base.clone().white_connect_percent(101), "white_connect_percent");
+        assert_invalid(base.outbound_connections(1).gold_connect_count(2), "gold_connect_count");
+
+        // Nodes without outbound slots don't need seeds
+        assert!(SettingsBuilder::new()
+            .outbound_connections(0)
+            .gold_connect_count(0)
+            .build()
+            .is_ok());
+    }
+
+    #[test]
+    fn settings_tor_dial_only() {
+        let tor = Url::parse("tor://abcdefghij.onion:26661").unwrap();
+        let settings = Settings {
+            seeds: vec![seed()],
+            peers: vec![tor.clone()],
+            allowed_transports: vec!["tor".to_string()],
+            tor_dial_only: true,
+            ..Default::default()
+        };
+        assert!(settings.validate().is_ok());
+
+        // Listening on Tor would launch an onion service
+        for inbound in ["tor://127.0.0.1:26661", "tor+tls://127.0.0.1:26661"] {
+            let settings =
+                Settings { inbound_addrs: vec![Url::parse(inbound).unwrap()], ..settings.clone() };
+            assert_invalid(SettingsBuilder::from(settings), "tor_dial_only");
+        }
+    }
+
+    #[test]
+    fn settings_opt_scoped_endpoints() {
+        use structopt_toml::StructOptToml;
+
+        let zone = |url: &Url| {
+            url.query_pairs().find(|(k, _)| k == "zone").map(|(_, v)| v.to_string()).unwrap()
+        };
+
+        // Command line
+        let opt = SettingsOpt::from_iter([
+            "p2p",
+            "--accept",
+            "tcp://[fe80::1%eth0]:26661",
+            "--peers",
+            "tcp+tls://[fe80::2%252]:26661",
+        ]);
+        let settings = Settings::from(opt);
+        assert_eq!(zone(&settings.inbound_addrs[0]), "eth0");
+        assert_eq!(zone(&settings.peers[0]), "2");
+
+        // Config file
+        let config = r#"
+            seeds = ["tcp+tls://[fe80::3%eth1]:26661"]
+            external_addrs = ["tcp://[2001:db8::1]:26661"]
+        "#;
+        let opt = SettingsOpt::from_iter_with_toml(config, ["p2p"]).unwrap();
+        let settings = Settings::from(opt);
+        assert_eq!(zone(&settings.seeds[0]), "eth1");
+        assert_eq!(settings.external_addrs[0], Url::parse("tcp://[2001:db8::1]:26661").unwrap());
+
+        let config = r#"peers = ["tcp://[fe80::1%]:26661"]"#;
+        assert!(SettingsOpt::from_iter_with_toml(config, ["p2p"]).is_err());
+    }
+
+    #[test]
+    fn settings_opt_multiaddr_endpoints() {
+        use structopt_toml::StructOptToml;
+
+        let onion = "vww6ybal4bd7szmgncyruucpgfkqahzddi37ktceo3ah7ngmcopnpyyd";
+        let tor_seed = format!("tor://{}.onion:25551", onion);
+
+        // Command line
+        let opt = SettingsOpt::from_iter([
+            "p2p",
+            "--peers",
+            "/ip4/10.0.0.1/tcp/26661",
+            "--seeds",
+            &format!("/onion3/{}:25551", onion),
+        ]);
+        let settings = Settings::from(opt);
+        assert_eq!(settings.peers, vec![Url::parse("tcp://10.0.0.1:26661").unwrap()]);
+        assert_eq!(settings.seeds, vec![Url::parse(&tor_seed).unwrap()]);
+
+        // Config file, mixed with URLs
+        let config = format!(
+            r#"
+            peers = ["/ip6/::1/tcp/26661/tls", "tcp://10.0.0.2:26661"]
+            seeds = ["/onion3/{}:25551"]
+            "#,
+            onion
+        );
+        let opt = SettingsOpt::from_iter_with_toml(&config, ["p2p"]).unwrap();
+        let settings = Settings::from(opt);
+        assert_eq!(
+            settings.peers,
+            vec![
+                Url::parse("tcp+tls://[::1]:26661").unwrap(),
+                Url::parse("tcp://10.0.0.2:26661").unwrap(),
+            ]
+        );
+        assert_eq!(settings.seeds, vec![Url::parse(&tor_seed).unwrap()]);
+
+        let config = r#"seeds = ["/ip4/10.0.0.1/udp/26661"]"#;
+        assert!(SettingsOpt::from_iter_with_toml(config, ["p2p"]).is_err());
+        let args = ["p2p", "--peers", "/dns4/example.com/tcp/26661"];
+        assert!(SettingsOpt::from_iter_safe(args).is_err());
+    }
+}
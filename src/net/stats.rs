@@ -0,0 +1,226 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2024 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::{
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    task::{Context, Poll},
+    time::{Duration, UNIX_EPOCH},
+};
+
+use smol::io::{self, AsyncRead};
+
+/// Amount of seconds the traffic rates are averaged over
+pub const TRAFFIC_STATS_WINDOW: u64 = 60;
+
+/// Aggregate traffic rates of all channels, averaged over the last
+/// [`TRAFFIC_STATS_WINDOW`] seconds. Byte counts include the message
+/// framing, so they reflect what goes over the wire.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct NetStats {
+    /// Bytes sent per second
+    pub send_bytes_per_sec: f64,
+    /// Bytes received per second
+    pub recv_bytes_per_sec: f64,
+    /// Messages sent per second
+    pub send_msgs_per_sec: f64,
+    /// Messages received per second
+    pub recv_msgs_per_sec: f64,
+}
+
+/// Snapshot of the overall state of a P2P instance, as returned by
+/// [`P2p::health()`](super::P2p::health)
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct P2pHealth {
+    /// Amount of channels accepted by the inbound session
+    pub inbound_channels: usize,
+    /// Amount of channels opened by the outbound session
+    pub outbound_channels: usize,
+    /// Amount of channels opened by the manual session
+    pub manual_channels: usize,
+    /// Amount of hosts on the grey, white and gold lists
+    pub known_hosts: usize,
+    /// Amount of hosts on the blacklist
+    pub banned_hosts: usize,
+    /// Total bytes sent since the instance was created
+    pub bytes_sent: u64,
+    /// Total bytes received since the instance was created
+    pub bytes_received: u64,
+    /// Current traffic rates
+    pub traffic: NetStats,
+    /// Time since the instance was started, zero if it isn't running
+    pub uptime: Duration,
+}
+
+/// Traffic recorded within a single second
+#[derive(Clone, Copy, Default)]
+struct Bucket {
+    second: u64,
+    send_bytes: u64,
+    recv_bytes: u64,
+    send_msgs: u64,
+    recv_msgs: u64,
+}
+
+/// Collects the traffic of all channels into per-second buckets, kept
+/// in a ring covering the stats window.
+pub struct TrafficStats {
+    buckets: Mutex<Vec<Bucket>>,
+    /// Bytes sent over the lifetime of the collector
+    total_send_bytes: AtomicU64,
+    /// Bytes received over the lifetime of the collector
+    total_recv_bytes: AtomicU64,
+}
+
+impl Default for TrafficStats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TrafficStats {
+    pub fn new() -> Self {
+        Self {
+            buckets: Mutex::new(vec![Bucket::default(); TRAFFIC_STATS_WINDOW as usize]),
+            total_send_bytes: AtomicU64::new(0),
+            total_recv_bytes: AtomicU64::new(0),
+        }
+    }
+
+    /// Record a message of `bytes` sent by a channel
+    pub fn record_send(&self, bytes: usize) {
+        self.total_send_bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+        self.record(Self::now(), |bucket| {
+            bucket.send_bytes += bytes as u64;
+            bucket.send_msgs += 1;
+        });
+    }
+
+    /// Record a message of `bytes` received by a channel
+    pub fn record_recv(&self, bytes: usize) {
+        self.total_recv_bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+        self.record(Self::now(), |bucket| {
+            bucket.recv_bytes += bytes as u64;
+            bucket.recv_msgs += 1;
+        });
+    }
+
+    /// Returns the traffic rates over the stats window
+    pub fn stats(&self) -> NetStats {
+        self.stats_at(Self::now())
+    }
+
+    /// Returns the total bytes sent and received
+    pub fn totals(&self) -> (u64, u64) {
+        (
+            self.total_send_bytes.load(Ordering::Relaxed),
+            self.total_recv_bytes.load(Ordering::Relaxed),
+        )
+    }
+
+    fn now() -> u64 {
+        UNIX_EPOCH.elapsed().unwrap().as_secs()
+    }
+
+    fn record(&self, second: u64, update: impl FnOnce(&mut Bucket)) {
+        let mut buckets = self.buckets.lock().unwrap();
+        let bucket = &mut buckets[(second % TRAFFIC_STATS_WINDOW) as usize];
+
+        // The slot still holds a second which fell out of the window
+        if bucket.second != second {
+            *bucket = Bucket { second, ..Default::default() };
+        }
+
+        update(bucket);
+    }
+
+    fn stats_at(&self, now: u64) -> NetStats {
+        let buckets = self.buckets.lock().unwrap();
+        let window = TRAFFIC_STATS_WINDOW as f64;
+        let mut stats = NetStats::default();
+
+        for bucket in buckets.iter().filter(|b| b.second + TRAFFIC_STATS_WINDOW > now) {
+            stats.send_bytes_per_sec += bucket.send_bytes as f64 / window;
+            stats.recv_bytes_per_sec += bucket.recv_bytes as f64 / window;
+            stats.send_msgs_per_sec += bucket.send_msgs as f64 / window;
+            stats.recv_msgs_per_sec += bucket.recv_msgs as f64 / window;
+        }
+
+        stats
+    }
+}
+
+/// Reader counting the bytes read through it, used to measure messages
+/// which are decoded straight off the stream.
+pub(in crate::net) struct CountingReader<'a, R> {
+    inner: &'a mut R,
+    pub count: usize,
+}
+
+impl<'a, R> CountingReader<'a, R> {
+    pub fn new(inner: &'a mut R) -> Self {
+        Self { inner, count: 0 }
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for CountingReader<'_, R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let poll = Pin::new(&mut *self.inner).poll_read(cx, buf);
+        if let Poll::Ready(Ok(n)) = poll {
+            self.count += n;
+        }
+        poll
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn traffic_stats_window() {
+        let stats = TrafficStats::new();
+        stats.record(100, |b| {
+            b.send_bytes += 600;
+            b.send_msgs += 6;
+        });
+        stats.record(130, |b| {
+            b.recv_bytes += 1200;
+            b.recv_msgs += 3;
+        });
+
+        let rates = stats.stats_at(130);
+        assert_eq!(rates.send_bytes_per_sec, 10.0);
+        assert_eq!(rates.send_msgs_per_sec, 0.1);
+        assert_eq!(rates.recv_bytes_per_sec, 20.0);
+        assert_eq!(rates.recv_msgs_per_sec, 0.05);
+
+        // Second 100 falls out of the window, and its slot gets reused
+        assert_eq!(stats.stats_at(160).send_bytes_per_sec, 0.0);
+        stats.record(160, |b| b.send_bytes += 60);
+        assert_eq!(stats.stats_at(160).send_bytes_per_sec, 1.0);
+        assert_eq!(stats.stats_at(160).recv_bytes_per_sec, 20.0);
+    }
+}
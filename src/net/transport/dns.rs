@@ -0,0 +1,235 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2024 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::{
+    collections::HashMap,
+    io,
+    net::{SocketAddr, ToSocketAddrs},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use log::debug;
+use url::{Host, Url};
+
+use super::endpoint_host_port;
+
+/// Resolves hostnames for the TCP transport. The default uses the system
+/// resolver. Applications can provide their own (e.g. DNS-over-HTTPS) to
+/// avoid leaking lookups to the local network.
+#[async_trait]
+pub trait DnsResolver: Send + Sync {
+    /// Look up the socket addresses of `host` at the given port
+    async fn resolve(&self, host: &str, port: u16) -> io::Result<Vec<SocketAddr>>;
+}
+
+/// Atomic pointer to a DNS resolver
+pub type DnsResolverPtr = Arc<dyn DnsResolver>;
+
+/// Resolver using the operating system's configured DNS
+pub struct SystemResolver;
+
+#[async_trait]
+impl DnsResolver for SystemResolver {
+    async fn resolve(&self, host: &str, port: u16) -> io::Result<Vec<SocketAddr>> {
+        smol::net::resolve((host, port)).await
+    }
+}
+
+/// Atomic pointer to a DNS cache
+pub type DnsCachePtr = Arc<DnsCache>;
+
+/// Caches DNS lookups for a fixed TTL, so nodes reconnecting frequently
+/// don't repeatedly resolve the same hostnames.
+pub struct DnsCache {
+    /// Resolver used on cache misses
+    resolver: Mutex<DnsResolverPtr>,
+    /// Maximum amount of cached hostnames, 0 disables caching
+    capacity: usize,
+    /// How long a lookup stays valid
+    ttl: Duration,
+    /// Maps (host, port) to the lookup time and the resolved addresses
+    entries: Mutex<HashMap<(String, u16), (Instant, Vec<SocketAddr>)>>,
+}
+
+impl DnsCache {
+    /// Create a new cache holding up to `capacity` hostnames for `ttl`,
+    /// backed by the system resolver.
+    pub fn new(capacity: usize, ttl: Duration) -> Self {
+        Self::new_with_resolver(capacity, ttl, Arc::new(SystemResolver))
+    }
+
+    /// Same as `new()`, but looks up hostnames with the given resolver.
+    pub fn new_with_resolver(capacity: usize, ttl: Duration, resolver: DnsResolverPtr) -> Self {
+        Self {
+            resolver: Mutex::new(resolver),
+            capacity,
+            ttl,
+            entries: Mutex::new(HashMap::with_capacity(capacity)),
+        }
+    }
+
+    /// Replace the resolver used on cache misses. Cached entries are
+    /// dropped since they came from the previous resolver.
+    pub fn set_resolver(&self, resolver: DnsResolverPtr) {
+        *self.resolver.lock().unwrap() = resolver;
+        self.entries.lock().unwrap().clear();
+    }
+
+    /// Resolve the host and port of the given endpoint. IP addresses are
+    /// returned as-is without consulting the resolver.
+    pub async fn resolve_url(&self, endpoint: &Url) -> io::Result<Vec<SocketAddr>> {
+        let (host, port) = endpoint_host_port(endpoint)?;
+        match endpoint.host() {
+            Some(Host::Domain(_)) => self.resolve(&host, port).await,
+            _ => Ok((host.as_str(), port).to_socket_addrs()?.collect()),
+        }
+    }
+
+    /// Resolve `host` at the given port, using the cached addresses if
+    /// they haven't expired yet.
+    pub async fn resolve(&self, host: &str, port: u16) -> io::Result<Vec<SocketAddr>> {
+        let key = (host.to_string(), port);
+
+        if let Some((resolved, addrs)) = self.entries.lock().unwrap().get(&key) {
+            if resolved.elapsed() < self.ttl {
+                debug!(target: "net::dns::resolve", "Cache hit for {}:{}", host, port);
+                return Ok(addrs.clone())
+            }
+        }
+
+        let resolver = self.resolver.lock().unwrap().clone();
+        let addrs = resolver.resolve(host, port).await?;
+        if addrs.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("No addresses found for {}", host),
+            ))
+        }
+
+        if self.capacity > 0 {
+            self.insert(key, addrs.clone());
+        }
+
+        Ok(addrs)
+    }
+
+    /// Insert a lookup, evicting expired entries and then the oldest one
+    /// if the cache is full.
+    fn insert(&self, key: (String, u16), addrs: Vec<SocketAddr>) {
+        let mut entries = self.entries.lock().unwrap();
+
+        if entries.len() >= self.capacity && !entries.contains_key(&key) {
+            entries.retain(|_, (resolved, _)| resolved.elapsed() < self.ttl);
+        }
+
+        if entries.len() >= self.capacity && !entries.contains_key(&key) {
+            let oldest =
+                entries.iter().min_by_key(|(_, (resolved, _))| *resolved).map(|(k, _)| k.clone());
+            if let Some(oldest) = oldest {
+                entries.remove(&oldest);
+            }
+        }
+
+        entries.insert(key, (Instant::now(), addrs));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Resolves every host to a fixed address and counts lookups
+    struct StubResolver {
+        addr: SocketAddr,
+        lookups: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl DnsResolver for StubResolver {
+        async fn resolve(&self, _host: &str, port: u16) -> io::Result<Vec<SocketAddr>> {
+            self.lookups.fetch_add(1, Ordering::SeqCst);
+            Ok(vec![SocketAddr::new(self.addr.ip(), port)])
+        }
+    }
+
+    #[test]
+    fn dns_cache_ttl_and_capacity() {
+        smol::block_on(async {
+            let addr = "127.0.0.1:0".parse().unwrap();
+            let stub = Arc::new(StubResolver { addr, lookups: AtomicUsize::new(0) });
+            let cache = DnsCache::new_with_resolver(1, Duration::from_millis(100), stub.clone());
+
+            cache.resolve("a.example", 1).await.unwrap();
+            cache.resolve("a.example", 1).await.unwrap();
+            assert_eq!(stub.lookups.load(Ordering::SeqCst), 1);
+
+            // A full cache evicts the oldest entry
+            cache.resolve("b.example", 1).await.unwrap();
+            cache.resolve("a.example", 1).await.unwrap();
+            assert_eq!(stub.lookups.load(Ordering::SeqCst), 3);
+
+            // Expired entries are looked up again
+            smol::Timer::after(Duration::from_millis(150)).await;
+            cache.resolve("a.example", 1).await.unwrap();
+            assert_eq!(stub.lookups.load(Ordering::SeqCst), 4);
+
+            // IP addresses never reach the resolver
+            let url = Url::parse("tcp://127.0.0.1:26661").unwrap();
+            assert_eq!(
+                cache.resolve_url(&url).await.unwrap(),
+                vec!["127.0.0.1:26661".parse().unwrap()]
+            );
+            assert_eq!(stub.lookups.load(Ordering::SeqCst), 4);
+        });
+    }
+
+    #[cfg(feature = "p2p-tcp")]
+    #[test]
+    fn dial_uses_dns_cache() {
+        smol::block_on(async {
+            let listener = smol::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let port = listener.local_addr().unwrap().port();
+            let accept = smol::spawn(async move {
+                for _ in 0..2 {
+                    listener.accept().await.unwrap();
+                }
+            });
+
+            let addr = "127.0.0.1:0".parse().unwrap();
+            let stub = Arc::new(StubResolver { addr, lookups: AtomicUsize::new(0) });
+            let cache =
+                Arc::new(DnsCache::new_with_resolver(8, Duration::from_secs(60), stub.clone()));
+
+            let endpoint = Url::parse(&format!("tcp://node.example:{}", port)).unwrap();
+            for _ in 0..2 {
+                let dialer = super::super::Dialer::new(endpoint.clone(), None)
+                    .await
+                    .unwrap()
+                    .with_dns_cache(cache.clone());
+                dialer.dial(Some(Duration::from_secs(5))).await.unwrap();
+            }
+
+            // The second dial was served from the cache
+            assert_eq!(stub.lookups.load(Ordering::SeqCst), 1);
+            accept.await;
+        });
+    }
+}
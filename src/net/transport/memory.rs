@@ -0,0 +1,212 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2024 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::{
+    collections::VecDeque,
+    io,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll, Waker},
+};
+
+use smol::io::{AsyncRead, AsyncWrite};
+
+use super::PtStream;
+
+/// One direction of an in-memory stream. Bytes written by one end are
+/// buffered here until the other end reads them.
+struct Pipe {
+    /// Buffered bytes not yet read by the receiving end
+    buf: VecDeque<u8>,
+    /// Maximum amount of buffered bytes before writes wait
+    capacity: usize,
+    /// Marks that either end has been closed or dropped
+    closed: bool,
+    /// Reader waiting for data
+    read_waker: Option<Waker>,
+    /// Writer waiting for buffer space
+    write_waker: Option<Waker>,
+}
+
+impl Pipe {
+    fn new(capacity: usize) -> Arc<Mutex<Self>> {
+        Arc::new(Mutex::new(Self {
+            buf: VecDeque::with_capacity(capacity),
+            capacity,
+            closed: false,
+            read_waker: None,
+            write_waker: None,
+        }))
+    }
+
+    fn close(&mut self) {
+        self.closed = true;
+        if let Some(waker) = self.read_waker.take() {
+            waker.wake();
+        }
+        if let Some(waker) = self.write_waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+/// In-memory byte stream, one end of a pair created with [`duplex()`].
+///
+/// Used to exercise channel-level logic (framing, handshakes, bans,
+/// timeouts) deterministically without opening any sockets.
+pub struct MemoryStream {
+    /// Pipe we read from, written by the other end
+    read: Arc<Mutex<Pipe>>,
+    /// Pipe we write to, read by the other end
+    write: Arc<Mutex<Pipe>>,
+}
+
+/// Create a pair of connected in-memory streams. Anything written to
+/// one end can be read from the other one. `capacity` bounds how many
+/// bytes can be buffered in each direction before writes start waiting.
+/// Dropping or closing one end results in EOF on the other.
+pub fn duplex(capacity: usize) -> (MemoryStream, MemoryStream) {
+    assert!(capacity > 0);
+    let a_to_b = Pipe::new(capacity);
+    let b_to_a = Pipe::new(capacity);
+
+    let a = MemoryStream { read: b_to_a.clone(), write: a_to_b.clone() };
+    let b = MemoryStream { read: a_to_b, write: b_to_a };
+
+    (a, b)
+}
+
+impl AsyncRead for MemoryStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let mut pipe = self.read.lock().unwrap();
+
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0))
+        }
+
+        if pipe.buf.is_empty() {
+            if pipe.closed {
+                return Poll::Ready(Ok(0))
+            }
+
+            pipe.read_waker = Some(cx.waker().clone());
+            return Poll::Pending
+        }
+
+        let n = buf.len().min(pipe.buf.len());
+        for (dst, src) in buf.iter_mut().zip(pipe.buf.drain(..n)) {
+            *dst = src;
+        }
+
+        if let Some(waker) = pipe.write_waker.take() {
+            waker.wake();
+        }
+
+        Poll::Ready(Ok(n))
+    }
+}
+
+impl AsyncWrite for MemoryStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let mut pipe = self.write.lock().unwrap();
+
+        if pipe.closed {
+            return Poll::Ready(Err(io::ErrorKind::BrokenPipe.into()))
+        }
+
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0))
+        }
+
+        let available = pipe.capacity - pipe.buf.len();
+        if available == 0 {
+            pipe.write_waker = Some(cx.waker().clone());
+            return Poll::Pending
+        }
+
+        let n = buf.len().min(available);
+        pipe.buf.extend(&buf[..n]);
+
+        if let Some(waker) = pipe.read_waker.take() {
+            waker.wake();
+        }
+
+        Poll::Ready(Ok(n))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.write.lock().unwrap().close();
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl Drop for MemoryStream {
+    fn drop(&mut self) {
+        self.read.lock().unwrap().close();
+        self.write.lock().unwrap().close();
+    }
+}
+
+impl PtStream for MemoryStream {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use smol::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[test]
+    fn duplex_roundtrip() {
+        smol::block_on(async {
+            // Use a tiny capacity so writes have to wait on the reader.
+            let (mut a, mut b) = duplex(3);
+
+            let writer = smol::spawn(async move {
+                a.write_all(b"darkfi").await.unwrap();
+                a
+            });
+
+            let mut buf = [0u8; 6];
+            b.read_exact(&mut buf).await.unwrap();
+            assert_eq!(&buf, b"darkfi");
+
+            b.write_all(b"ok").await.unwrap();
+            let mut a = writer.await;
+            let mut buf = [0u8; 2];
+            a.read_exact(&mut buf).await.unwrap();
+            assert_eq!(&buf, b"ok");
+
+            // Dropping one end yields EOF on the other.
+            drop(a);
+            let mut buf = [0u8; 1];
+            assert_eq!(b.read(&mut buf).await.unwrap(), 0);
+            assert!(b.write_all(b"x").await.is_err());
+        });
+    }
+}
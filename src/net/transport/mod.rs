@@ -18,24 +18,47 @@
 
 use std::{
     io::{self, ErrorKind},
+    sync::Arc,
     time::Duration,
 };
 
 use async_trait::async_trait;
 use log::error;
-use smol::io::{AsyncRead, AsyncWrite};
+use smol::{
+    channel::Receiver,
+    io::{AsyncRead, AsyncWrite},
+    Executor, Task,
+};
 use url::Url;
 
+#[cfg(feature = "p2p-noise")]
+use darkfi_sdk::crypto::Keypair;
+
+use dns::DnsCachePtr;
+
 /// TLS upgrade mechanism
 pub(crate) mod tls;
 
+/// DNS resolution and caching
+pub mod dns;
+
 #[cfg(feature = "p2p-tcp")]
 /// TCP transport
 pub(crate) mod tcp;
 
+#[cfg(feature = "p2p-noise")]
+/// Noise upgrade mechanism
+pub(crate) mod noise;
+
 #[cfg(feature = "p2p-tor")]
 /// Tor transport
 pub(crate) mod tor;
+#[cfg(feature = "p2p-tor")]
+pub use tor::{set_tor_log_level, TOR_BOOTSTRAP_LOG_TARGET, TOR_ONION_LOG_TARGET};
+
+/// `TorClient` an embedding application can share with the Tor transport
+#[cfg(feature = "p2p-tor")]
+pub type SharedTorClient = arti_client::TorClient<tor_rtcompat::PreferredRuntime>;
 
 #[cfg(feature = "p2p-nym")]
 /// Nym transport
@@ -45,6 +68,290 @@ pub(crate) mod nym;
 /// Unix socket transport
 pub(crate) mod unix;
 
+#[cfg(test)]
+/// In-memory transport used for deterministic tests
+pub(crate) mod memory;
+
+/// Pluggable transport bridge configuration for the Tor transport,
+/// used on networks where connecting to Tor directly is blocked.
+#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct TorBridgeConfig {
+    /// Bridge lines, e.g. `obfs4 192.0.2.1:443 <FINGERPRINT> cert=... iat-mode=0`.
+    /// If this is not empty, Tor will only connect through these bridges.
+    pub bridges: Vec<String>,
+    /// Path to the pluggable transport binary (e.g. `lyrebird`) handling
+    /// the transports used in the bridge lines.
+    pub pt_path: Option<String>,
+}
+
+/// Name of a transport, i.e. the URL scheme of its endpoints
+pub type TransportName = &'static str;
+
+/// Whether a transport can currently be used, see [`transport_status()`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TransportHealth {
+    /// The transport is usable
+    Available,
+    /// Support for the transport wasn't compiled in
+    NotCompiled,
+    /// The transport needs a client (e.g. Tor) that hasn't started
+    /// bootstrapping. It will bootstrap on first use.
+    NotBootstrapped,
+    /// The transport's client is bootstrapping and not usable yet
+    Bootstrapping,
+    /// Bootstrapping the transport's client failed. It's retried on
+    /// next use.
+    Failed,
+}
+
+/// List every known transport with its health, e.g. for diagnostics.
+/// Tor health reflects the bootstrap state of the Tor transport's client
+/// for the default configuration, without a datastore or bridges. Use
+/// [`P2p::transport_status()`] for the one a P2P instance is configured
+/// with instead.
+///
+/// [`P2p::transport_status()`]: super::P2p::transport_status
+pub async fn transport_status() -> Vec<(TransportName, TransportHealth)> {
+    #[cfg(feature = "p2p-tor")]
+    let tor = tor::config_health(&None, &TorBridgeConfig::default()).await;
+    #[cfg(not(feature = "p2p-tor"))]
+    let tor = TransportHealth::NotCompiled;
+
+    transport_status_with_tor(tor)
+}
+
+/// Build the transport list, using `tor` as the health of the Tor
+/// transports. Everything else only depends on compile-time features.
+pub(crate) fn transport_status_with_tor(
+    tor: TransportHealth,
+) -> Vec<(TransportName, TransportHealth)> {
+    let compiled = |enabled: bool| {
+        if enabled {
+            TransportHealth::Available
+        } else {
+            TransportHealth::NotCompiled
+        }
+    };
+
+    let tcp = compiled(cfg!(feature = "p2p-tcp"));
+    let nym = compiled(cfg!(feature = "p2p-nym"));
+
+    vec![
+        ("tcp", tcp),
+        ("tcp+tls", tcp),
+        ("tcp+noise", compiled(cfg!(feature = "p2p-noise"))),
+        ("tor", tor),
+        ("tor+tls", tor),
+        ("nym", nym),
+        ("nym+tls", nym),
+        ("unix", compiled(cfg!(feature = "p2p-unix"))),
+    ]
+}
+
+/// Query key carrying the zone (scope) of a link-local IPv6 endpoint,
+/// since [`Url`] can't represent zone identifiers in the host.
+pub const ZONE_QUERY_KEY: &str = "zone";
+
+/// Parse an endpoint URL, additionally accepting scoped IPv6 hosts like
+/// `tcp://[fe80::1%eth0]:26661`. The zone is moved into the `zone` query
+/// pair, and applied to the resolved addresses when dialing or listening.
+/// Multiaddrs like `/ip4/1.2.3.4/tcp/5678` are converted with
+/// [`parse_multiaddr()`].
+pub fn parse_endpoint(endpoint: &str) -> io::Result<Url> {
+    if endpoint.starts_with('/') {
+        return parse_multiaddr(endpoint)
+    }
+
+    let parse = |endpoint: &str| {
+        Url::parse(endpoint).map_err(|e| {
+            io::Error::new(ErrorKind::InvalidInput, format!("Invalid endpoint: {}", e))
+        })
+    };
+
+    let (Some(start), Some(end)) = (endpoint.find('['), endpoint.find(']')) else {
+        return parse(endpoint)
+    };
+
+    let Some(pct) = endpoint[start..end].find('%').map(|i| start + i) else {
+        return parse(endpoint)
+    };
+
+    // Allow the URL-encoded form `%25` as well
+    let zone = endpoint[pct + 1..end].strip_prefix("25").unwrap_or(&endpoint[pct + 1..end]);
+    if zone.is_empty() {
+        return Err(io::Error::new(ErrorKind::InvalidInput, "Endpoint has an empty IPv6 zone"))
+    }
+
+    let mut url = parse(&format!("{}{}", &endpoint[..pct], &endpoint[end..]))?;
+    url.query_pairs_mut().append_pair(ZONE_QUERY_KEY, zone);
+    Ok(url)
+}
+
+/// Extract the host and port transports connect to from an endpoint URL.
+/// IPv6 hosts are returned without brackets, and the port falls back to
+/// the scheme's well-known default (e.g. 443 for `https`) if missing.
+/// Our own transports (`tcp`, `tor`, `nym`, ...) have no such default
+/// since every network picks its own port, so they must specify one.
+pub fn endpoint_host_port(endpoint: &Url) -> io::Result<(String, u16)> {
+    let host = match endpoint.host() {
+        Some(url::Host::Domain(domain)) => domain.to_string(),
+        Some(url::Host::Ipv4(ip)) => ip.to_string(),
+        Some(url::Host::Ipv6(ip)) => ip.to_string(),
+        None => return Err(io::Error::new(ErrorKind::InvalidInput, "Endpoint has no host")),
+    };
+
+    let Some(port) = endpoint.port_or_known_default() else {
+        let msg =
+            format!("{} endpoint has no port and its scheme has no default", endpoint.scheme());
+        return Err(io::Error::new(ErrorKind::InvalidInput, msg))
+    };
+
+    Ok((host, port))
+}
+
+/// Parse a libp2p-style multiaddr like `/ip4/1.2.3.4/tcp/5678` into the
+/// endpoint URL transports dial. Supported are `ip4`/`ip6` with `tcp`,
+/// and `onion3`, optionally followed by `tls`, e.g. `/onion3/<addr>:<port>`
+/// becomes `tor://<addr>.onion:<port>`. Any other component is rejected.
+pub fn parse_multiaddr(multiaddr: &str) -> io::Result<Url> {
+    let invalid = |msg: String| io::Error::new(ErrorKind::InvalidInput, msg);
+
+    let Some(multiaddr) = multiaddr.strip_prefix('/') else {
+        return Err(invalid(format!("Multiaddr {} doesn't start with /", multiaddr)))
+    };
+    let mut components = multiaddr.split('/');
+    let mut value = |protocol: &str| match components.next() {
+        Some(value) if !value.is_empty() => Ok(value),
+        _ => Err(invalid(format!("Multiaddr is missing the /{} value", protocol))),
+    };
+
+    let (scheme, host, port) = match value("protocol")? {
+        protocol @ ("ip4" | "ip6") => {
+            let host = match protocol {
+                "ip4" => value(protocol)?.parse::<std::net::Ipv4Addr>().map(|ip| ip.to_string()),
+                _ => value(protocol)?.parse::<std::net::Ipv6Addr>().map(|ip| format!("[{}]", ip)),
+            }
+            .map_err(|_| invalid(format!("Invalid /{} address", protocol)))?;
+
+            let transport = value("transport")?;
+            if transport != "tcp" {
+                return Err(invalid(format!("Unsupported multiaddr transport /{}", transport)))
+            }
+            let port =
+                value("tcp")?.parse::<u16>().map_err(|_| invalid("Invalid /tcp port".into()))?;
+
+            ("tcp", host, port)
+        }
+
+        "onion3" => {
+            let Some((addr, port)) = value("onion3")?.split_once(':') else {
+                return Err(invalid("Multiaddr /onion3 value is missing the port".into()))
+            };
+            let addr = addr.to_lowercase();
+            if addr.len() != 56 || !addr.bytes().all(|b| matches!(b, b'a'..=b'z' | b'2'..=b'7')) {
+                return Err(invalid("Invalid /onion3 address".into()))
+            }
+            let port = match port.parse::<u16>() {
+                Ok(port) if port != 0 => port,
+                _ => return Err(invalid("Invalid /onion3 port".into())),
+            };
+
+            ("tor", format!("{}.onion", addr), port)
+        }
+
+        other => return Err(invalid(format!("Unsupported multiaddr protocol /{}", other))),
+    };
+
+    let tls = match components.next() {
+        None => false,
+        Some("tls") => true,
+        Some(other) => return Err(invalid(format!("Unsupported multiaddr protocol /{}", other))),
+    };
+    if let Some(other) = components.next() {
+        return Err(invalid(format!("Unsupported multiaddr protocol /{}", other)))
+    }
+
+    let scheme = if tls { format!("{}+tls", scheme) } else { scheme.to_string() };
+    Url::parse(&format!("{}://{}:{}", scheme, host, port))
+        .map_err(|e| invalid(format!("Invalid multiaddr: {}", e)))
+}
+
+/// Inverse of [`parse_multiaddr()`], formatting an endpoint URL as a
+/// multiaddr. Only the endpoints a multiaddr can be parsed into are
+/// supported, e.g. `tcp://` with a domain host isn't.
+pub fn endpoint_multiaddr(endpoint: &Url) -> io::Result<String> {
+    let unsupported =
+        || io::Error::new(ErrorKind::InvalidInput, format!("No multiaddr for {}", endpoint));
+
+    let (transport, tls) = match endpoint.scheme().split_once('+') {
+        Some((transport, "tls")) => (transport, "/tls"),
+        Some(_) => return Err(unsupported()),
+        None => (endpoint.scheme(), ""),
+    };
+    let Some(port) = endpoint.port() else { return Err(unsupported()) };
+
+    let multiaddr = match (transport, endpoint.host()) {
+        ("tcp", Some(url::Host::Ipv4(ip))) => format!("/ip4/{}/tcp/{}", ip, port),
+        // IPv4 hosts of non-special schemes like ours are kept as domains
+        ("tcp", Some(url::Host::Domain(domain))) => match domain.parse::<std::net::Ipv4Addr>() {
+            Ok(ip) => format!("/ip4/{}/tcp/{}", ip, port),
+            Err(_) => return Err(unsupported()),
+        },
+        ("tcp", Some(url::Host::Ipv6(ip))) => format!("/ip6/{}/tcp/{}", ip, port),
+        ("tor", Some(url::Host::Domain(domain))) => {
+            // Only v3 onion addresses have a multiaddr form
+            let onion3 = domain.strip_suffix(".onion").filter(|addr| addr.len() == 56);
+            let Some(addr) = onion3 else { return Err(unsupported()) };
+            format!("/onion3/{}:{}", addr, port)
+        }
+        _ => return Err(unsupported()),
+    };
+
+    Ok(format!("{}{}", multiaddr, tls))
+}
+
+/// Look up the IPv6 scope id requested by the endpoint's `zone` query
+/// pair. The zone can either be a numeric id or an interface name.
+#[cfg(feature = "p2p-tcp")]
+fn endpoint_scope_id(endpoint: &Url) -> io::Result<Option<u32>> {
+    let Some((_, zone)) = endpoint.query_pairs().find(|(k, _)| k == ZONE_QUERY_KEY) else {
+        return Ok(None)
+    };
+
+    if let Ok(id) = zone.parse::<u32>() {
+        return Ok(Some(id))
+    }
+
+    let Ok(name) = std::ffi::CString::new(zone.as_bytes()) else {
+        return Err(io::Error::new(ErrorKind::InvalidInput, "Invalid IPv6 zone"))
+    };
+
+    // SAFETY: `name` is a valid NUL-terminated string for the whole call
+    let id = unsafe { libc::if_nametoindex(name.as_ptr()) };
+    if id == 0 {
+        return Err(io::Error::new(ErrorKind::NotFound, format!("Unknown interface {}", zone)))
+    }
+
+    Ok(Some(id))
+}
+
+/// Apply the endpoint's IPv6 zone, if any, to the resolved addresses
+#[cfg(feature = "p2p-tcp")]
+fn scoped_socket_addrs(
+    endpoint: &Url,
+    mut addrs: Vec<std::net::SocketAddr>,
+) -> io::Result<Vec<std::net::SocketAddr>> {
+    let Some(scope_id) = endpoint_scope_id(endpoint)? else { return Ok(addrs) };
+
+    for addr in addrs.iter_mut() {
+        if let std::net::SocketAddr::V6(addr) = addr {
+            addr.set_scope_id(scope_id);
+        }
+    }
+
+    Ok(addrs)
+}
+
 /// Dialer variants
 #[derive(Debug, Clone)]
 pub enum DialerVariant {
@@ -56,6 +363,10 @@ pub enum DialerVariant {
     /// TCP with TLS
     TcpTls(tcp::TcpDialer),
 
+    #[cfg(feature = "p2p-noise")]
+    /// TCP with Noise
+    TcpNoise(tcp::TcpDialer, noise::NoiseUpgrade),
+
     #[cfg(feature = "p2p-tor")]
     /// Tor
     Tor(tor::TorDialer),
@@ -88,6 +399,10 @@ pub enum ListenerVariant {
     /// TCP with TLS
     TcpTls(tcp::TcpListener),
 
+    #[cfg(feature = "p2p-noise")]
+    /// TCP with Noise
+    TcpNoise(tcp::TcpListener, noise::NoiseUpgrade),
+
     #[cfg(feature = "p2p-tor")]
     /// Tor
     Tor(tor::TorListener),
@@ -103,6 +418,8 @@ pub struct Dialer {
     endpoint: Url,
     /// The dialer variant (transport protocol)
     variant: DialerVariant,
+    /// Optional cache used to resolve TCP endpoint hostnames
+    dns_cache: Option<DnsCachePtr>,
 }
 
 macro_rules! enforce_hostport {
@@ -128,6 +445,22 @@ macro_rules! enforce_abspath {
 impl Dialer {
     /// Instantiate a new [`Dialer`] with the given [`Url`] and datastore path.
     pub async fn new(endpoint: Url, datastore: Option<String>) -> io::Result<Self> {
+        Self::new_with_tor_bridges(endpoint, datastore, TorBridgeConfig::default()).await
+    }
+
+    /// Same as `new()`, but the endpoint is first parsed with
+    /// [`parse_endpoint()`], so it can also be given as a multiaddr.
+    pub async fn parse(endpoint: &str, datastore: Option<String>) -> io::Result<Self> {
+        Self::new(parse_endpoint(endpoint)?, datastore).await
+    }
+
+    /// Same as `new()`, but Tor-based dialers will connect through the
+    /// given pluggable transport bridges.
+    pub async fn new_with_tor_bridges(
+        endpoint: Url,
+        datastore: Option<String>,
+        tor_bridges: TorBridgeConfig,
+    ) -> io::Result<Self> {
         match endpoint.scheme().to_lowercase().as_str() {
             #[cfg(feature = "p2p-tcp")]
             "tcp" => {
@@ -135,7 +468,7 @@ impl Dialer {
                 enforce_hostport!(endpoint);
                 let variant = tcp::TcpDialer::new(None).await?;
                 let variant = DialerVariant::Tcp(variant);
-                Ok(Self { endpoint, variant })
+                Ok(Self { endpoint, variant, dns_cache: None })
             }
 
             #[cfg(feature = "p2p-tcp")]
@@ -144,25 +477,34 @@ impl Dialer {
                 enforce_hostport!(endpoint);
                 let variant = tcp::TcpDialer::new(None).await?;
                 let variant = DialerVariant::TcpTls(variant);
-                Ok(Self { endpoint, variant })
+                Ok(Self { endpoint, variant, dns_cache: None })
+            }
+
+            #[cfg(feature = "p2p-noise")]
+            "tcp+noise" => {
+                // Build a TCP dialer wrapped with Noise
+                enforce_hostport!(endpoint);
+                let variant = tcp::TcpDialer::new(None).await?;
+                let variant = DialerVariant::TcpNoise(variant, noise::NoiseUpgrade::new(None));
+                Ok(Self { endpoint, variant, dns_cache: None })
             }
 
             #[cfg(feature = "p2p-tor")]
             "tor" => {
                 // Build a Tor dialer
                 enforce_hostport!(endpoint);
-                let variant = tor::TorDialer::new(datastore).await?;
+                let variant = tor::TorDialer::new(datastore, tor_bridges).await?;
                 let variant = DialerVariant::Tor(variant);
-                Ok(Self { endpoint, variant })
+                Ok(Self { endpoint, variant, dns_cache: None })
             }
 
             #[cfg(feature = "p2p-tor")]
             "tor+tls" => {
                 // Build a Tor dialer wrapped with TLS
                 enforce_hostport!(endpoint);
-                let variant = tor::TorDialer::new(datastore).await?;
+                let variant = tor::TorDialer::new(datastore, tor_bridges).await?;
                 let variant = DialerVariant::TorTls(variant);
-                Ok(Self { endpoint, variant })
+                Ok(Self { endpoint, variant, dns_cache: None })
             }
 
             #[cfg(feature = "p2p-nym")]
@@ -171,7 +513,7 @@ impl Dialer {
                 enforce_hostport!(endpoint);
                 let variant = nym::NymDialer::new().await?;
                 let variant = DialerVariant::Nym(variant);
-                Ok(Self { endpoint, variant })
+                Ok(Self { endpoint, variant, dns_cache: None })
             }
 
             #[cfg(feature = "p2p-nym")]
@@ -180,7 +522,7 @@ impl Dialer {
                 enforce_hostport!(endpoint);
                 let variant = nym::NymDialer::new().await?;
                 let variant = DialerVariant::NymTls(variant);
-                Ok(Self { endpoint, variant })
+                Ok(Self { endpoint, variant, dns_cache: None })
             }
 
             #[cfg(feature = "p2p-unix")]
@@ -189,7 +531,7 @@ impl Dialer {
                 // Build a Unix socket dialer
                 let variant = unix::UnixDialer::new().await?;
                 let variant = DialerVariant::Unix(variant);
-                Ok(Self { endpoint, variant })
+                Ok(Self { endpoint, variant, dns_cache: None })
             }
 
             x => {
@@ -199,6 +541,58 @@ impl Dialer {
         }
     }
 
+    /// Resolve TCP endpoint hostnames through the given cache instead
+    /// of the system resolver.
+    pub fn with_dns_cache(mut self, dns_cache: DnsCachePtr) -> Self {
+        self.dns_cache = Some(dns_cache);
+        self
+    }
+
+    /// Derive the Noise static key of `tcp+noise` dialers from the given
+    /// keypair. Otherwise, a random key is used for every dialer.
+    #[cfg(feature = "p2p-noise")]
+    pub fn with_noise_keypair(mut self, keypair: Keypair) -> Self {
+        if let DialerVariant::TcpNoise(_, upgrade) = &mut self.variant {
+            let remote_keys = upgrade.remote_keys().to_vec();
+            *upgrade = noise::NoiseUpgrade::new(Some(&keypair)).with_remote_keys(remote_keys);
+        }
+        self
+    }
+
+    /// Only accept `tcp+noise` peers authenticating with one of the given
+    /// Noise static public keys. An empty list accepts any peer.
+    #[cfg(feature = "p2p-noise")]
+    pub fn with_noise_remote_keys(mut self, remote_keys: Vec<[u8; 32]>) -> Self {
+        if let DialerVariant::TcpNoise(_, upgrade) = &mut self.variant {
+            *upgrade = upgrade.clone().with_remote_keys(remote_keys);
+        }
+        self
+    }
+
+    /// Dial Tor-based endpoints through the given `TorClient` instead of
+    /// bootstrapping one.
+    #[cfg(feature = "p2p-tor")]
+    pub fn with_tor_client(mut self, client: SharedTorClient) -> Self {
+        if let DialerVariant::Tor(dialer) | DialerVariant::TorTls(dialer) = &mut self.variant {
+            dialer.set_client(client);
+        }
+        self
+    }
+
+    /// Resolve the endpoint to socket addresses
+    #[cfg(feature = "p2p-tcp")]
+    async fn resolve_endpoint(&self) -> io::Result<Vec<std::net::SocketAddr>> {
+        let addrs = match &self.dns_cache {
+            Some(cache) => cache.resolve_url(&self.endpoint).await?,
+            None => {
+                let (host, port) = endpoint_host_port(&self.endpoint)?;
+                std::net::ToSocketAddrs::to_socket_addrs(&(host.as_str(), port))?.collect()
+            }
+        };
+
+        scoped_socket_addrs(&self.endpoint, addrs)
+    }
+
     /// Dial an instantiated [`Dialer`]. This creates a connection and returns a stream.
     /// The Tor-based Dialer variants can panic: this is intended. There exists validation
     /// for hosts and ports in other parts of the codebase. A panic occurring here
@@ -209,33 +603,39 @@ impl Dialer {
             #[cfg(feature = "p2p-tcp")]
             DialerVariant::Tcp(dialer) => {
                 // NOTE: sockaddr here is an array, can contain both ipv4 and ipv6
-                let sockaddr = self.endpoint.socket_addrs(|| None)?;
+                let sockaddr = self.resolve_endpoint().await?;
                 let stream = dialer.do_dial(sockaddr[0], timeout).await?;
                 Ok(Box::new(stream))
             }
 
             #[cfg(feature = "p2p-tcp")]
             DialerVariant::TcpTls(dialer) => {
-                let sockaddr = self.endpoint.socket_addrs(|| None)?;
+                let sockaddr = self.resolve_endpoint().await?;
                 let stream = dialer.do_dial(sockaddr[0], timeout).await?;
                 let tlsupgrade = tls::TlsUpgrade::new().await;
                 let stream = tlsupgrade.upgrade_dialer_tls(stream).await?;
                 Ok(Box::new(stream))
             }
 
+            #[cfg(feature = "p2p-noise")]
+            DialerVariant::TcpNoise(dialer, upgrade) => {
+                let sockaddr = self.resolve_endpoint().await?;
+                let stream = dialer.do_dial(sockaddr[0], timeout).await?;
+                let stream = upgrade.upgrade_dialer_noise(stream).await?;
+                Ok(Box::new(stream))
+            }
+
             #[cfg(feature = "p2p-tor")]
             DialerVariant::Tor(dialer) => {
-                let host = self.endpoint.host_str().unwrap();
-                let port = self.endpoint.port().unwrap();
-                let stream = dialer.do_dial(host, port, timeout).await?;
+                let (host, port) = endpoint_host_port(&self.endpoint)?;
+                let stream = dialer.do_dial(&host, port, timeout).await?;
                 Ok(Box::new(stream))
             }
 
             #[cfg(feature = "p2p-tor")]
             DialerVariant::TorTls(dialer) => {
-                let host = self.endpoint.host_str().unwrap();
-                let port = self.endpoint.port().unwrap();
-                let stream = dialer.do_dial(host, port, timeout).await?;
+                let (host, port) = endpoint_host_port(&self.endpoint)?;
+                let stream = dialer.do_dial(&host, port, timeout).await?;
                 let tlsupgrade = tls::TlsUpgrade::new().await;
                 let stream = tlsupgrade.upgrade_dialer_tls(stream).await?;
                 Ok(Box::new(stream))
@@ -289,6 +689,16 @@ impl Listener {
     /// Instantiate a new [`Listener`] with the given [`Url`] and datastore path.
     /// Must contain a scheme, host string, and a port.
     pub async fn new(endpoint: Url, datastore: Option<String>) -> io::Result<Self> {
+        Self::new_with_tor_bridges(endpoint, datastore, TorBridgeConfig::default()).await
+    }
+
+    /// Same as `new()`, but a Tor-based listener will connect through the
+    /// given pluggable transport bridges.
+    pub async fn new_with_tor_bridges(
+        endpoint: Url,
+        datastore: Option<String>,
+        tor_bridges: TorBridgeConfig,
+    ) -> io::Result<Self> {
         match endpoint.scheme().to_lowercase().as_str() {
             #[cfg(feature = "p2p-tcp")]
             "tcp" => {
@@ -308,11 +718,20 @@ impl Listener {
                 Ok(Self { endpoint, variant })
             }
 
+            #[cfg(feature = "p2p-noise")]
+            "tcp+noise" => {
+                // Build a TCP listener wrapped with Noise
+                enforce_hostport!(endpoint);
+                let variant = tcp::TcpListener::new(1024).await?;
+                let variant = ListenerVariant::TcpNoise(variant, noise::NoiseUpgrade::new(None));
+                Ok(Self { endpoint, variant })
+            }
+
             #[cfg(feature = "p2p-tor")]
             "tor" => {
                 // Build a Tor Hidden Service listener
                 enforce_hostport!(endpoint);
-                let variant = tor::TorListener::new(datastore).await?;
+                let variant = tor::TorListener::new(datastore, tor_bridges).await?;
                 let variant = ListenerVariant::Tor(variant);
                 Ok(Self { endpoint, variant })
             }
@@ -332,6 +751,57 @@ impl Listener {
         }
     }
 
+    /// Derive the Noise static key of a `tcp+noise` listener from the
+    /// given keypair. Otherwise, a random key is used.
+    #[cfg(feature = "p2p-noise")]
+    pub fn with_noise_keypair(mut self, keypair: Keypair) -> Self {
+        if let ListenerVariant::TcpNoise(_, upgrade) = &mut self.variant {
+            let remote_keys = upgrade.remote_keys().to_vec();
+            *upgrade = noise::NoiseUpgrade::new(Some(&keypair)).with_remote_keys(remote_keys);
+        }
+        self
+    }
+
+    /// Only accept `tcp+noise` peers authenticating with one of the given
+    /// Noise static public keys. An empty list accepts any peer.
+    #[cfg(feature = "p2p-noise")]
+    pub fn with_noise_remote_keys(mut self, remote_keys: Vec<[u8; 32]>) -> Self {
+        if let ListenerVariant::TcpNoise(_, upgrade) = &mut self.variant {
+            *upgrade = upgrade.clone().with_remote_keys(remote_keys);
+        }
+        self
+    }
+
+    /// Set the maximum amount of inbound rendezvous handshakes a
+    /// Tor-based listener negotiates concurrently.
+    #[cfg(feature = "p2p-tor")]
+    pub fn with_tor_handshake_limit(mut self, limit: usize) -> Self {
+        if let ListenerVariant::Tor(listener) = &mut self.variant {
+            listener.set_handshake_limit(limit);
+        }
+        self
+    }
+
+    /// Launch the onion services of a Tor-based listener on the given
+    /// `TorClient` instead of bootstrapping one.
+    #[cfg(feature = "p2p-tor")]
+    pub fn with_tor_client(mut self, client: SharedTorClient) -> Self {
+        if let ListenerVariant::Tor(listener) = &mut self.variant {
+            listener.set_client(client);
+        }
+        self
+    }
+
+    /// Launch an onion service for each of the given nicknames on a
+    /// Tor-based listener. Empty keeps the default single service.
+    #[cfg(feature = "p2p-tor")]
+    pub fn with_tor_onion_services(mut self, nicknames: Vec<String>) -> Self {
+        if let ListenerVariant::Tor(listener) = &mut self.variant {
+            listener.set_onion_services(nicknames);
+        }
+        self
+    }
+
     /// Listen on an instantiated [`Listener`].
     /// This will open a socket and return the listener.
     pub async fn listen(&self) -> io::Result<Box<dyn PtListener>> {
@@ -339,6 +809,7 @@ impl Listener {
             #[cfg(feature = "p2p-tcp")]
             ListenerVariant::Tcp(listener) => {
                 let sockaddr = self.endpoint.socket_addrs(|| None)?;
+                let sockaddr = scoped_socket_addrs(&self.endpoint, sockaddr)?;
                 let l = listener.do_listen(sockaddr[0]).await?;
                 Ok(Box::new(l))
             }
@@ -346,12 +817,22 @@ impl Listener {
             #[cfg(feature = "p2p-tcp")]
             ListenerVariant::TcpTls(listener) => {
                 let sockaddr = self.endpoint.socket_addrs(|| None)?;
+                let sockaddr = scoped_socket_addrs(&self.endpoint, sockaddr)?;
                 let l = listener.do_listen(sockaddr[0]).await?;
                 let tlsupgrade = tls::TlsUpgrade::new().await;
                 let l = tlsupgrade.upgrade_listener_tcp_tls(l).await?;
                 Ok(Box::new(l))
             }
 
+            #[cfg(feature = "p2p-noise")]
+            ListenerVariant::TcpNoise(listener, upgrade) => {
+                let sockaddr = self.endpoint.socket_addrs(|| None)?;
+                let sockaddr = scoped_socket_addrs(&self.endpoint, sockaddr)?;
+                let l = listener.do_listen(sockaddr[0]).await?;
+                let l = upgrade.clone().upgrade_listener_tcp_noise(l).await?;
+                Ok(Box::new(l))
+            }
+
             #[cfg(feature = "p2p-tor")]
             ListenerVariant::Tor(listener) => {
                 let port = self.endpoint.port().unwrap();
@@ -392,6 +873,9 @@ impl PtStream for smol::net::TcpStream {}
 #[cfg(feature = "p2p-tcp")]
 impl PtStream for futures_rustls::TlsStream<smol::net::TcpStream> {}
 
+#[cfg(feature = "p2p-noise")]
+impl PtStream for noise::NoiseStream<smol::net::TcpStream> {}
+
 #[cfg(feature = "p2p-tor")]
 impl PtStream for arti_client::DataStream {}
 
@@ -406,3 +890,228 @@ impl PtStream for smol::net::unix::UnixStream {}
 pub trait PtListener: Send + Unpin {
     async fn next(&self) -> io::Result<(Box<dyn PtStream>, Url)>;
 }
+
+/// Listener merging the connections accepted by several listeners, e.g.
+/// a TCP and a Tor one, so multi-transport nodes need one accept loop.
+pub struct MultiListener {
+    /// Connections (or errors) yielded by any of the listeners
+    incoming: Receiver<io::Result<(Box<dyn PtStream>, Url)>>,
+    /// Tasks forwarding from each listener. Dropping them stops listening.
+    _tasks: Vec<Task<()>>,
+}
+
+impl MultiListener {
+    /// Start forwarding the connections of all `listeners`
+    pub fn new(listeners: Vec<Box<dyn PtListener>>, ex: Arc<Executor<'_>>) -> Self {
+        let (sender, incoming) = smol::channel::bounded(listeners.len().max(1));
+
+        let _tasks = listeners
+            .into_iter()
+            .map(|listener| {
+                let sender = sender.clone();
+                ex.spawn(async move {
+                    loop {
+                        let result = listener.next().await;
+                        if sender.send(result).await.is_err() {
+                            break
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        Self { incoming, _tasks }
+    }
+}
+
+#[async_trait]
+impl PtListener for MultiListener {
+    async fn next(&self) -> io::Result<(Box<dyn PtStream>, Url)> {
+        match self.incoming.recv().await {
+            Ok(result) => result,
+            // Only happens when no listeners were given
+            Err(_) => Err(io::Error::new(ErrorKind::NotConnected, "No listeners to accept from")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_scoped_ipv6_endpoint() {
+        let url = parse_endpoint("tcp://[fe80::1%eth0]:26661").unwrap();
+        assert_eq!(url.host_str(), Some("[fe80::1]"));
+        assert_eq!(url.port(), Some(26661));
+        assert_eq!(url.query(), Some("zone=eth0"));
+
+        let url = parse_endpoint("tcp+tls://[fe80::1%252]:26661").unwrap();
+        assert_eq!(url.query(), Some("zone=2"));
+
+        // As formatted for the peer address of accepted connections
+        let peer = std::net::SocketAddrV6::new("fe80::1".parse().unwrap(), 26661, 0, 2);
+        let url = parse_endpoint(&format!("tcp://{}", peer)).unwrap();
+        assert_eq!(url.query(), Some("zone=2"));
+
+        // Unscoped endpoints are parsed unchanged
+        let url = parse_endpoint("tcp://[::1]:26661").unwrap();
+        assert_eq!(url, Url::parse("tcp://[::1]:26661").unwrap());
+        assert!(parse_endpoint("tcp://[fe80::1%]:26661").is_err());
+    }
+
+    #[test]
+    fn parse_multiaddr_endpoints() {
+        let url = parse_endpoint("/ip4/1.2.3.4/tcp/5678").unwrap();
+        assert_eq!(url, Url::parse("tcp://1.2.3.4:5678").unwrap());
+
+        let url = parse_endpoint("/ip6/::1/tcp/26661/tls").unwrap();
+        assert_eq!(url, Url::parse("tcp+tls://[::1]:26661").unwrap());
+
+        let onion = "vww6ybal4bd7szmgncyruucpgfkqahzddi37ktceo3ah7ngmcopnpyyd";
+        let url = parse_endpoint(&format!("/onion3/{}:25551", onion)).unwrap();
+        assert_eq!(url, Url::parse(&format!("tor://{}.onion:25551", onion)).unwrap());
+
+        // Malformed multiaddrs are rejected like malformed URLs
+        let err = parse_endpoint("/ip4/1.2.3.4/udp/5678").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+        let err = parse_endpoint("not an endpoint").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn endpoint_host_ports() {
+        let host_port = |endpoint: &str| endpoint_host_port(&Url::parse(endpoint).unwrap());
+
+        assert_eq!(host_port("tcp://127.0.0.1:26661").unwrap(), ("127.0.0.1".to_string(), 26661));
+        assert_eq!(host_port("tcp+tls://example.com:26661").unwrap().0, "example.com");
+        assert_eq!(host_port("tor://abcdefghij.onion:25551").unwrap().1, 25551);
+
+        // IPv6 hosts lose their brackets, and scoped endpoints their zone
+        assert_eq!(host_port("tcp://[::1]:26661").unwrap(), ("::1".to_string(), 26661));
+        let scoped = parse_endpoint("tcp://[fe80::1%eth0]:26661").unwrap();
+        assert_eq!(endpoint_host_port(&scoped).unwrap(), ("fe80::1".to_string(), 26661));
+
+        // Missing ports fall back to the scheme default, if it has one
+        assert_eq!(host_port("https://example.com").unwrap().1, 443);
+        assert_eq!(host_port("ws://example.com").unwrap().1, 80);
+
+        // Our transports have no default port, so leaving it out is an error
+        for scheme in ["tcp", "tcp+tls", "tor", "tor+tls", "nym", "nym+tls"] {
+            let err = host_port(&format!("{}://example.com", scheme)).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::InvalidInput);
+            assert!(err.to_string().starts_with(&format!("{} endpoint", scheme)));
+        }
+
+        // Endpoints without a host are rejected
+        assert!(host_port("unix:///tmp/darkfi.sock").is_err());
+    }
+
+    #[test]
+    fn parse_multiaddrs() {
+        let onion = "vww6ybal4bd7szmgncyruucpgfkqahzddi37ktceo3ah7ngmcopnpyyd";
+        let roundtrip = |multiaddr: &str, endpoint: &str| {
+            let url = parse_multiaddr(multiaddr).unwrap();
+            assert_eq!(url, Url::parse(endpoint).unwrap());
+            assert_eq!(endpoint_multiaddr(&url).unwrap(), multiaddr);
+        };
+
+        roundtrip("/ip4/1.2.3.4/tcp/5678", "tcp://1.2.3.4:5678");
+        roundtrip("/ip4/127.0.0.1/tcp/26661/tls", "tcp+tls://127.0.0.1:26661");
+        roundtrip("/ip6/::1/tcp/26661", "tcp://[::1]:26661");
+        roundtrip("/ip6/2001:db8::1/tcp/443/tls", "tcp+tls://[2001:db8::1]:443");
+        roundtrip(&format!("/onion3/{}:1234", onion), &format!("tor://{}.onion:1234", onion));
+        roundtrip(
+            &format!("/onion3/{}:25551/tls", onion),
+            &format!("tor+tls://{}.onion:25551", onion),
+        );
+
+        // Onion addresses are case insensitive
+        let url = parse_multiaddr(&format!("/onion3/{}:1234", onion.to_uppercase())).unwrap();
+        assert_eq!(url.host_str(), Some(format!("{}.onion", onion).as_str()));
+
+        // Unsupported and malformed components are rejected
+        for multiaddr in [
+            "",
+            "ip4/1.2.3.4/tcp/5678",
+            "/ip4/1.2.3.4",
+            "/ip4/1.2.3.4/tcp",
+            "/ip4/1.2.3.4/udp/5678/quic",
+            "/ip4/1.2.3.4/tcp/5678/ws",
+            "/ip4/1.2.3.4/tcp/5678/p2p/QmYyQSo1c1Ym7orWxLYvCrM2EmxFTANf8wXmmE7DWjhx5N",
+            "/ip4/1.2.3.4/tcp/5678/tls/ws",
+            "/ip4/::1/tcp/5678",
+            "/ip6/1.2.3.4/tcp/5678",
+            "/ip4/1.2.3.4/tcp/65536",
+            "/dns4/example.com/tcp/5678",
+            "/onion/aaimaq4ygg2iegci:80",
+            "/onion3/tooshort:1234",
+            &format!("/onion3/{}", onion),
+            &format!("/onion3/{}:0", onion),
+        ] {
+            let err = parse_multiaddr(multiaddr).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::InvalidInput, "{}", multiaddr);
+        }
+
+        // Only endpoints with a multiaddr form can be converted back
+        for endpoint in [
+            "tcp://example.com:26661",
+            "tor://example.com:80",
+            "tor://aaimaq4ygg2iegci.onion:80",
+            "unix:///tmp/a.sock",
+        ] {
+            assert!(endpoint_multiaddr(&Url::parse(endpoint).unwrap()).is_err());
+        }
+    }
+
+    #[cfg(feature = "p2p-tcp")]
+    #[test]
+    fn dial_ipv6_endpoints() {
+        smol::block_on(async {
+            // Skip on hosts without IPv6 support
+            let Ok(listener) = smol::net::TcpListener::bind("[::1]:0").await else { return };
+            let port = listener.local_addr().unwrap().port();
+            let accept = smol::spawn(async move {
+                for _ in 0..2 {
+                    listener.accept().await.unwrap();
+                }
+            });
+
+            let endpoint = parse_endpoint(&format!("tcp://[::1]:{}", port)).unwrap();
+            let dialer = Dialer::new(endpoint, None).await.unwrap();
+            dialer.dial(Some(Duration::from_secs(5))).await.unwrap();
+
+            // The zone is resolved into the socket address scope id
+            let endpoint = parse_endpoint(&format!("tcp://[::1%1]:{}", port)).unwrap();
+            let dialer = Dialer::new(endpoint, None).await.unwrap();
+            let addrs = dialer.resolve_endpoint().await.unwrap();
+            match addrs[0] {
+                std::net::SocketAddr::V6(addr) => assert_eq!(addr.scope_id(), 1),
+                _ => panic!("Expected an IPv6 address"),
+            }
+            dialer.dial(Some(Duration::from_secs(5))).await.unwrap();
+
+            accept.await;
+        });
+    }
+
+    #[cfg(feature = "p2p-tcp")]
+    #[test]
+    fn dial_multiaddr_endpoint() {
+        smol::block_on(async {
+            let listener = smol::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let port = listener.local_addr().unwrap().port();
+            let accept = smol::spawn(async move { listener.accept().await.unwrap() });
+
+            let multiaddr = format!("/ip4/127.0.0.1/tcp/{}", port);
+            let dialer = Dialer::parse(&multiaddr, None).await.unwrap();
+            let endpoint = Url::parse(&format!("tcp://127.0.0.1:{}", port)).unwrap();
+            assert_eq!(dialer.endpoint(), &endpoint);
+            dialer.dial(Some(Duration::from_secs(5))).await.unwrap();
+            accept.await;
+
+            let err = Dialer::parse("/ip4/127.0.0.1/udp/26661", None).await.unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::InvalidInput);
+        });
+    }
+}
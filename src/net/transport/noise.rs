@@ -0,0 +1,464 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2024 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::{
+    io,
+    pin::Pin,
+    task::{ready, Context, Poll},
+};
+
+use darkfi_sdk::crypto::Keypair;
+use rand::rngs::OsRng;
+use smol::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use snow::{
+    params::DHChoice,
+    resolvers::{CryptoResolver, DefaultResolver},
+};
+
+/// Noise protocol used for the upgrade. XX transmits both static keys
+/// during the handshake, so no prior knowledge of the peer is needed to
+/// connect. Peers are only authenticated against pinned remote keys.
+const NOISE_PARAMS: &str = "Noise_XX_25519_ChaChaPoly_BLAKE2s";
+
+/// Maximum size of a Noise message, including the AEAD tag
+const NOISE_MAX_MSG_LEN: usize = 65535;
+
+/// Size of the ChaChaPoly authentication tag
+const NOISE_TAG_LEN: usize = 16;
+
+/// Maximum amount of plaintext carried in one Noise message
+const NOISE_MAX_PLAINTEXT_LEN: usize = NOISE_MAX_MSG_LEN - NOISE_TAG_LEN;
+
+/// Context string used to derive the Noise static key from a [`Keypair`]
+const NOISE_KEY_CONTEXT: &str = "DarkFi P2P Noise static key";
+
+fn noise_error(e: snow::Error) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e)
+}
+
+/// Noise upgrade mechanism for plaintext streams. Both ends prove they
+/// hold the static key they present, and when remote keys are pinned,
+/// peers presenting any other key are rejected.
+#[derive(Clone)]
+pub struct NoiseUpgrade {
+    /// X25519 static private key
+    static_key: [u8; 32],
+    /// Static public keys peers may authenticate with, any key is
+    /// accepted if empty
+    remote_keys: Vec<[u8; 32]>,
+}
+
+impl std::fmt::Debug for NoiseUpgrade {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("NoiseUpgrade").finish_non_exhaustive()
+    }
+}
+
+impl NoiseUpgrade {
+    /// Derive the Noise static key from the node's keypair. Without one,
+    /// a random keypair is used, which still encrypts the stream but
+    /// doesn't give the node a stable identity.
+    pub fn new(keypair: Option<&Keypair>) -> Self {
+        let keypair = keypair.copied().unwrap_or_else(|| Keypair::random(&mut OsRng));
+        let static_key = blake3::derive_key(NOISE_KEY_CONTEXT, &keypair.secret.to_bytes());
+        Self { static_key, remote_keys: vec![] }
+    }
+
+    /// Only accept peers authenticating with one of the given static
+    /// public keys. An empty list accepts any peer.
+    pub fn with_remote_keys(mut self, remote_keys: Vec<[u8; 32]>) -> Self {
+        self.remote_keys = remote_keys;
+        self
+    }
+
+    /// Returns the pinned remote static keys
+    pub fn remote_keys(&self) -> &[[u8; 32]] {
+        &self.remote_keys
+    }
+
+    /// Returns the static public key this upgrade authenticates with,
+    /// which is what peers pin to trust this node.
+    pub fn public_key(&self) -> [u8; 32] {
+        let mut dh = DefaultResolver.resolve_dh(&DHChoice::Curve25519).unwrap();
+        dh.set(&self.static_key);
+        dh.pubkey().try_into().unwrap()
+    }
+
+    /// Check the static key the peer authenticated with against the
+    /// pinned remote keys.
+    fn verify_remote(&self, hs: &snow::HandshakeState) -> io::Result<()> {
+        if self.remote_keys.is_empty() {
+            return Ok(())
+        }
+
+        match hs.get_remote_static() {
+            Some(key) if self.remote_keys.iter().any(|k| k == key) => Ok(()),
+            _ => Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "Noise peer static key is not trusted",
+            )),
+        }
+    }
+
+    fn builder(&self) -> snow::Builder<'_> {
+        snow::Builder::new(NOISE_PARAMS.parse().unwrap()).local_private_key(&self.static_key)
+    }
+
+    /// Perform the handshake as the initiator, over a freshly dialed stream
+    pub async fn upgrade_dialer_noise<S>(&self, mut stream: S) -> io::Result<NoiseStream<S>>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        let mut hs = self.builder().build_initiator().map_err(noise_error)?;
+        let mut buf = vec![0u8; NOISE_MAX_MSG_LEN];
+
+        // -> e
+        let n = hs.write_message(&[], &mut buf).map_err(noise_error)?;
+        write_frame(&mut stream, &buf[..n]).await?;
+
+        // <- e, ee, s, es
+        let msg = read_frame(&mut stream).await?;
+        hs.read_message(&msg, &mut buf).map_err(noise_error)?;
+
+        // The responder's key is known at this point, so an untrusted
+        // peer is dropped before we send our own static key.
+        self.verify_remote(&hs)?;
+
+        // -> s, se
+        let n = hs.write_message(&[], &mut buf).map_err(noise_error)?;
+        write_frame(&mut stream, &buf[..n]).await?;
+
+        NoiseStream::new(stream, hs)
+    }
+
+    /// Perform the handshake as the responder, over an accepted stream
+    pub async fn accept<S>(&self, mut stream: S) -> io::Result<NoiseStream<S>>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        let mut hs = self.builder().build_responder().map_err(noise_error)?;
+        let mut buf = vec![0u8; NOISE_MAX_MSG_LEN];
+
+        // -> e
+        let msg = read_frame(&mut stream).await?;
+        hs.read_message(&msg, &mut buf).map_err(noise_error)?;
+
+        // <- e, ee, s, es
+        let n = hs.write_message(&[], &mut buf).map_err(noise_error)?;
+        write_frame(&mut stream, &buf[..n]).await?;
+
+        // -> s, se
+        let msg = read_frame(&mut stream).await?;
+        hs.read_message(&msg, &mut buf).map_err(noise_error)?;
+        self.verify_remote(&hs)?;
+
+        NoiseStream::new(stream, hs)
+    }
+
+    /// Pair a TCP listener with this upgrade, so accepted streams
+    /// perform the handshake before being handed out.
+    pub async fn upgrade_listener_tcp_noise(
+        self,
+        listener: smol::net::TcpListener,
+    ) -> io::Result<(NoiseUpgrade, smol::net::TcpListener)> {
+        Ok((self, listener))
+    }
+}
+
+/// Write a handshake message, prefixed with its big-endian u16 length
+async fn write_frame<S: AsyncWrite + Unpin>(stream: &mut S, msg: &[u8]) -> io::Result<()> {
+    stream.write_all(&(msg.len() as u16).to_be_bytes()).await?;
+    stream.write_all(msg).await?;
+    stream.flush().await
+}
+
+/// Read a length-prefixed handshake message
+async fn read_frame<S: AsyncRead + Unpin>(stream: &mut S) -> io::Result<Vec<u8>> {
+    let mut len = [0u8; 2];
+    stream.read_exact(&mut len).await?;
+    let mut msg = vec![0u8; u16::from_be_bytes(len) as usize];
+    stream.read_exact(&mut msg).await?;
+    Ok(msg)
+}
+
+/// Stream encrypted with an established Noise session. Writes are split
+/// into length-prefixed Noise messages, and reads are decrypted back
+/// into a contiguous byte stream.
+pub struct NoiseStream<S> {
+    inner: S,
+    transport: snow::TransportState,
+    /// Decrypted bytes not yet returned to the reader
+    read_plain: Vec<u8>,
+    read_pos: usize,
+    /// Raw bytes of the incoming frame, including the length prefix
+    read_frame: Vec<u8>,
+    read_filled: usize,
+    /// Encrypted outgoing frame not yet fully written
+    write_frame: Vec<u8>,
+    write_pos: usize,
+}
+
+impl<S> NoiseStream<S> {
+    fn new(inner: S, hs: snow::HandshakeState) -> io::Result<Self> {
+        let transport = hs.into_transport_mode().map_err(noise_error)?;
+        Ok(Self {
+            inner,
+            transport,
+            read_plain: vec![],
+            read_pos: 0,
+            read_frame: vec![],
+            read_filled: 0,
+            write_frame: vec![],
+            write_pos: 0,
+        })
+    }
+
+    /// Returns the static public key the peer authenticated with
+    pub fn remote_static(&self) -> Option<&[u8]> {
+        self.transport.get_remote_static()
+    }
+}
+
+impl<S: AsyncWrite + Unpin> NoiseStream<S> {
+    /// Write out the pending encrypted frame, if any
+    fn poll_write_frame(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        while self.write_pos < self.write_frame.len() {
+            let n = ready!(
+                Pin::new(&mut self.inner).poll_write(cx, &self.write_frame[self.write_pos..])
+            )?;
+            if n == 0 {
+                return Poll::Ready(Err(io::ErrorKind::WriteZero.into()))
+            }
+            self.write_pos += n;
+        }
+
+        self.write_frame.clear();
+        self.write_pos = 0;
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for NoiseStream<S> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = &mut *self;
+
+        loop {
+            if this.read_pos < this.read_plain.len() {
+                let n = buf.len().min(this.read_plain.len() - this.read_pos);
+                buf[..n].copy_from_slice(&this.read_plain[this.read_pos..this.read_pos + n]);
+                this.read_pos += n;
+                return Poll::Ready(Ok(n))
+            }
+
+            // Read the length prefix first, then the rest of the frame
+            let want = if this.read_filled < 2 {
+                2
+            } else {
+                2 + u16::from_be_bytes([this.read_frame[0], this.read_frame[1]]) as usize
+            };
+
+            if this.read_filled >= 2 && this.read_filled == want {
+                this.read_plain.resize(NOISE_MAX_MSG_LEN, 0);
+                let n = this
+                    .transport
+                    .read_message(&this.read_frame[2..want], &mut this.read_plain)
+                    .map_err(noise_error)?;
+                this.read_plain.truncate(n);
+                this.read_pos = 0;
+                this.read_filled = 0;
+                continue
+            }
+
+            if this.read_frame.len() < want {
+                this.read_frame.resize(want, 0);
+            }
+
+            let n = ready!(Pin::new(&mut this.inner)
+                .poll_read(cx, &mut this.read_frame[this.read_filled..want]))?;
+            if n == 0 {
+                // Only a clean EOF between frames is a graceful close
+                if this.read_filled == 0 {
+                    return Poll::Ready(Ok(0))
+                }
+                return Poll::Ready(Err(io::ErrorKind::UnexpectedEof.into()))
+            }
+            this.read_filled += n;
+        }
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for NoiseStream<S> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = &mut *self;
+        ready!(this.poll_write_frame(cx))?;
+
+        let len = buf.len().min(NOISE_MAX_PLAINTEXT_LEN);
+        let mut frame = vec![0u8; 2 + len + NOISE_TAG_LEN];
+        let n = this.transport.write_message(&buf[..len], &mut frame[2..]).map_err(noise_error)?;
+        frame[..2].copy_from_slice(&(n as u16).to_be_bytes());
+        frame.truncate(2 + n);
+        this.write_frame = frame;
+
+        // The plaintext is consumed once encrypted, the frame is pushed
+        // out here or by the next write or flush.
+        if let Poll::Ready(Err(e)) = this.poll_write_frame(cx) {
+            return Poll::Ready(Err(e))
+        }
+
+        Poll::Ready(Ok(len))
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        ready!(self.poll_write_frame(cx))?;
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        ready!(self.poll_write_frame(cx))?;
+        Pin::new(&mut self.inner).poll_close(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use url::Url;
+
+    use crate::net::transport::{Dialer, Listener, PtListener};
+
+    #[test]
+    fn noise_loopback_handshake() {
+        smol::block_on(async {
+            let port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+            let endpoint = Url::parse(&format!("tcp+noise://{}", port)).unwrap();
+
+            let server_keypair = Keypair::random(&mut OsRng);
+            let client_keypair = Keypair::random(&mut OsRng);
+
+            let listener = Listener::new(endpoint.clone(), None)
+                .await
+                .unwrap()
+                .with_noise_keypair(server_keypair);
+            let listener = listener.listen().await.unwrap();
+
+            let server = smol::spawn(async move {
+                let (mut stream, url) = listener.next().await.unwrap();
+                assert_eq!(url.scheme(), "tcp+noise");
+
+                // Large enough to be split into several Noise messages
+                let mut msg = vec![0u8; 100_000];
+                stream.read_exact(&mut msg).await.unwrap();
+                stream.write_all(&msg).await.unwrap();
+                stream.flush().await.unwrap();
+            });
+
+            let dialer =
+                Dialer::new(endpoint, None).await.unwrap().with_noise_keypair(client_keypair);
+            let mut stream = dialer.dial(None).await.unwrap();
+
+            let msg: Vec<u8> = (0..100_000).map(|i| i as u8).collect();
+            stream.write_all(&msg).await.unwrap();
+            stream.flush().await.unwrap();
+
+            let mut echo = vec![0u8; msg.len()];
+            stream.read_exact(&mut echo).await.unwrap();
+            assert_eq!(echo, msg);
+            server.await;
+        });
+    }
+
+    /// Run a handshake between `client` and `server` over memory
+    /// and return the static keys each side saw from its peer.
+    async fn handshake(client: &NoiseUpgrade, server: &NoiseUpgrade) -> (Vec<u8>, Vec<u8>) {
+        let (a, b) = crate::net::transport::memory::duplex(4096);
+
+        let server = server.clone();
+        let accept = smol::spawn(async move { server.accept(b).await.unwrap() });
+        let client_stream = client.upgrade_dialer_noise(a).await.unwrap();
+        let server_stream = accept.await;
+
+        (
+            client_stream.remote_static().unwrap().to_vec(),
+            server_stream.remote_static().unwrap().to_vec(),
+        )
+    }
+
+    #[test]
+    fn noise_static_keys() {
+        smol::block_on(async {
+            let keypair = Keypair::random(&mut OsRng);
+            let server = NoiseUpgrade::new(Some(&keypair));
+            let client = NoiseUpgrade::new(Some(&Keypair::random(&mut OsRng)));
+
+            // The static key is derived from the keypair, so it's stable
+            // across connections and upgrades built from the same keypair.
+            let (server_key, client_key) = handshake(&client, &server).await;
+            let (server_key_, _) = handshake(&client, &NoiseUpgrade::new(Some(&keypair))).await;
+            assert_eq!(server_key, server_key_);
+            assert_ne!(server_key, client_key);
+
+            // Without a keypair, each upgrade gets a random identity
+            let (a, _) = handshake(&client, &NoiseUpgrade::new(None)).await;
+            let (b, _) = handshake(&client, &NoiseUpgrade::new(None)).await;
+            assert_ne!(a, b);
+        });
+    }
+
+    #[test]
+    fn noise_pinned_remote_keys() {
+        smol::block_on(async {
+            let server = NoiseUpgrade::new(Some(&Keypair::random(&mut OsRng)));
+            let client = NoiseUpgrade::new(Some(&Keypair::random(&mut OsRng)));
+
+            // The advertised public key is the one peers see
+            let (server_key, client_key) = handshake(&client, &server).await;
+            assert_eq!(server_key, server.public_key());
+            assert_eq!(client_key, client.public_key());
+
+            // Pinning the right keys on both ends lets the handshake through
+            let pinned_client = client.clone().with_remote_keys(vec![server.public_key()]);
+            let pinned_server = server.clone().with_remote_keys(vec![client.public_key()]);
+            handshake(&pinned_client, &pinned_server).await;
+
+            // A dialer rejects a responder it didn't pin
+            let stranger = NoiseUpgrade::new(None);
+            let (a, b) = crate::net::transport::memory::duplex(4096);
+            let accept = smol::spawn(async move { stranger.accept(b).await });
+            let err = pinned_client.upgrade_dialer_noise(a).await.err().unwrap();
+            assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+            assert!(accept.await.is_err());
+
+            // A listener rejects an initiator it didn't pin
+            let (a, b) = crate::net::transport::memory::duplex(4096);
+            let server = pinned_server.clone();
+            let accept = smol::spawn(async move { server.accept(b).await });
+            let stranger = NoiseUpgrade::new(None);
+            let _ = stranger.upgrade_dialer_noise(a).await;
+            let err = accept.await.err().unwrap();
+            assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+        });
+    }
+}
@@ -32,7 +32,7 @@ use smol::{
 use socket2::{Domain, Socket, TcpKeepalive, Type};
 use url::Url;
 
-use super::{PtListener, PtStream};
+use super::{parse_endpoint, PtListener, PtStream};
 
 /// TCP Dialer implementation
 #[derive(Debug, Clone)]
@@ -174,7 +174,7 @@ impl PtListener for SmolTcpListener {
             Err(e) => return Err(e),
         };
 
-        let url = Url::parse(&format!("tcp://{}", peer_addr)).unwrap();
+        let url = parse_endpoint(&format!("tcp://{}", peer_addr)).unwrap();
         Ok((Box::new(stream), url))
     }
 }
@@ -192,8 +192,28 @@ impl PtListener for (TlsAcceptor, SmolTcpListener) {
             Err(e) => return Err(e),
         };
 
-        let url = Url::parse(&format!("tcp+tls://{}", peer_addr)).unwrap();
+        let url = parse_endpoint(&format!("tcp+tls://{}", peer_addr)).unwrap();
 
         Ok((Box::new(TlsStream::Server(stream)), url))
     }
 }
+
+#[cfg(feature = "p2p-noise")]
+#[async_trait]
+impl PtListener for (super::noise::NoiseUpgrade, SmolTcpListener) {
+    async fn next(&self) -> io::Result<(Box<dyn PtStream>, Url)> {
+        let (stream, peer_addr) = match self.1.accept().await {
+            Ok((s, a)) => (s, a),
+            Err(e) => return Err(e),
+        };
+
+        let stream = match self.0.accept(stream).await {
+            Ok(v) => v,
+            Err(e) => return Err(e),
+        };
+
+        let url = parse_endpoint(&format!("tcp+noise://{}", peer_addr)).unwrap();
+
+        Ok((Box::new(stream), url))
+    }
+}
@@ -17,24 +17,33 @@
  */
 
 use std::{
+    collections::BTreeMap,
+    fmt,
     io::{self, ErrorKind},
+    net::SocketAddr,
     pin::Pin,
-    sync::Arc,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
     time::Duration,
 };
 
 use arti_client::{
-    config::{onion_service::OnionServiceConfigBuilder, BoolOrAuto, TorClientConfigBuilder},
-    DataStream, StreamPrefs, TorClient,
+    config::{
+        onion_service::OnionServiceConfigBuilder, pt::TransportConfigBuilder, BoolOrAuto,
+        BridgeConfigBuilder, CfgPath, TorClientConfigBuilder,
+    },
+    DataStream, StreamPrefs, TorClient, TorClientConfig,
 };
 use async_trait::async_trait;
 use futures::{
-    future::{select, Either},
+    future::{select, BoxFuture, Either},
     pin_mut,
-    stream::StreamExt,
+    stream::{FuturesUnordered, StreamExt},
     Stream,
 };
-use log::{debug, error, info, warn};
+use log::{debug, error, warn, Level, Log, Record};
 use smol::{
     lock::{Mutex, OnceCell},
     Timer,
@@ -46,22 +55,309 @@ use tor_proto::stream::IncomingStreamRequest;
 use tor_rtcompat::PreferredRuntime;
 use url::Url;
 
-use super::{PtListener, PtStream};
+use super::{PtListener, PtStream, TorBridgeConfig, TransportHealth};
 use crate::util::path::expand_path;
 
-/// A static for `TorClient` reusability
-static TOR_CLIENT: OnceCell<TorClient<PreferredRuntime>> = OnceCell::new();
+/// Log target of the Tor client bootstrapping messages
+pub const TOR_BOOTSTRAP_LOG_TARGET: &str = "net::tor::bootstrap";
+
+/// Log target of the onion service launch messages
+pub const TOR_ONION_LOG_TARGET: &str = "net::tor::onion";
+
+/// Level overriding the defaults of the Tor lifecycle messages, stored as
+/// `Level as usize`. Zero keeps the defaults.
+static TOR_LOG_LEVEL: AtomicUsize = AtomicUsize::new(0);
+
+/// Override the level at which Tor bootstrapping and onion service launch
+/// messages are logged, e.g. to quiet them when embedding darkfi. `None`
+/// restores the defaults (debug for bootstrapping, info for launches).
+/// The messages can also be filtered by their own log targets.
+pub fn set_tor_log_level(level: Option<Level>) {
+    TOR_LOG_LEVEL.store(level.map_or(0, |l| l as usize), Ordering::Relaxed);
+}
+
+/// Level override set with [`set_tor_log_level()`], if any
+fn tor_log_level() -> Option<Level> {
+    Level::iter().nth(TOR_LOG_LEVEL.load(Ordering::Relaxed).checked_sub(1)?)
+}
+
+/// Log a Tor lifecycle message on `target` through `logger`
+fn tor_log(logger: &dyn Log, target: &str, level: Level, args: fmt::Arguments) {
+    if level <= log::max_level() {
+        logger.log(&Record::builder().args(args).level(level).target(target).build());
+    }
+}
+
+/// Log the start of a Tor client bootstrap
+fn log_bootstrapping(logger: &dyn Log, level: Option<Level>) {
+    tor_log(
+        logger,
+        TOR_BOOTSTRAP_LOG_TARGET,
+        level.unwrap_or(Level::Debug),
+        format_args!("Bootstrapping..."),
+    );
+}
+
+/// Log an established onion service listener
+fn log_onion_launched(logger: &dyn Log, level: Option<Level>, nickname: &str, endpoint: &Url) {
+    tor_log(
+        logger,
+        TOR_ONION_LOG_TARGET,
+        level.unwrap_or(Level::Info),
+        format_args!("[P2P] Established Tor listener ({}) on {}", nickname, endpoint),
+    );
+}
+
+/// Identifies a distinct `TorClient` configuration
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct TorClientKey {
+    datastore: Option<String>,
+    bridges: TorBridgeConfig,
+}
+
+/// A lazily bootstrapped `TorClient` shared by everyone using its config
+#[derive(Default)]
+struct TorClientSlot {
+    client: OnceCell<TorClient<PreferredRuntime>>,
+    /// Why the last bootstrap failed, cleared when a new one starts
+    error: std::sync::Mutex<Option<String>>,
+}
+
+type TorClientCell = Arc<TorClientSlot>;
+
+/// Registry of `TorClient` instances for reusability. Dialers and listeners
+/// with identical configurations share a client, while distinct ones get
+/// their own, allowing separate Tor identities within one process.
+/// Note that distinct configurations should also use distinct datastores,
+/// since Arti locks its state directory.
+static TOR_CLIENTS: Mutex<BTreeMap<TorClientKey, TorClientCell>> = Mutex::new(BTreeMap::new());
+
+/// Fetch the registry cell for the given configuration, creating an
+/// empty one if it doesn't exist yet.
+async fn tor_client_cell(key: TorClientKey) -> TorClientCell {
+    TOR_CLIENTS.lock().await.entry(key).or_default().clone()
+}
+
+/// Health of a `TorClient`, according to its bootstrap progress
+pub(crate) fn client_health(client: &TorClient<PreferredRuntime>) -> TransportHealth {
+    let status = client.bootstrap_status();
+    if status.ready_for_traffic() {
+        TransportHealth::Available
+    } else if status.as_frac() > 0.0 {
+        TransportHealth::Bootstrapping
+    } else {
+        TransportHealth::NotBootstrapped
+    }
+}
+
+/// Health of the registry client for the given configuration. It's not
+/// bootstrapped until a Tor dialer or listener using it requests it.
+pub(crate) async fn config_health(
+    datastore: &Option<String>,
+    bridges: &TorBridgeConfig,
+) -> TransportHealth {
+    let key = TorClientKey { datastore: datastore.clone(), bridges: bridges.clone() };
+    match TOR_CLIENTS.lock().await.get(&key) {
+        Some(cell) => cell_health(cell),
+        None => TransportHealth::NotBootstrapped,
+    }
+}
+
+fn cell_health(cell: &TorClientSlot) -> TransportHealth {
+    match cell.client.get() {
+        Some(client) => client_health(client),
+        None if cell.error.lock().unwrap().is_some() => TransportHealth::Failed,
+        // Clients are registered as soon as they're requested, so an
+        // empty cell without an error is still being bootstrapped
+        None => TransportHealth::Bootstrapping,
+    }
+}
+
+/// Build the `TorClient` configuration using the given datastore and
+/// pluggable transport bridges, if any.
+fn client_config(
+    datastore: &Option<String>,
+    bridges: &TorBridgeConfig,
+) -> io::Result<TorClientConfig> {
+    let mut builder = match datastore {
+        Some(datadir) => {
+            let datadir = match expand_path(datadir) {
+                Ok(v) => v,
+                Err(e) => {
+                    error!(
+                        target: "net::tor::client_config",
+                        "[P2P] Invalid Tor datastore path \"{}\": {}", datadir, e,
+                    );
+                    return Err(io::Error::new(
+                        ErrorKind::InvalidInput,
+                        "Invalid Tor datastore path",
+                    ))
+                }
+            };
+            TorClientConfigBuilder::from_directories(datadir.clone(), datadir)
+        }
+        None => TorClientConfigBuilder::default(),
+    };
+
+    for line in &bridges.bridges {
+        let bridge: BridgeConfigBuilder = match line.parse() {
+            Ok(v) => v,
+            Err(e) => {
+                error!(
+                    target: "net::tor::client_config",
+                    "[P2P] Invalid Tor bridge line \"{}\": {}", line, e,
+                );
+                return Err(io::Error::new(ErrorKind::InvalidInput, "Invalid Tor bridge line"))
+            }
+        };
+        builder.bridges().bridges().push(bridge);
+    }
+
+    if let Some(pt_path) = &bridges.pt_path {
+        let mut protocols = vec![];
+        for protocol in bridges.transports() {
+            match protocol.parse() {
+                Ok(v) => protocols.push(v),
+                Err(e) => {
+                    error!(
+                        target: "net::tor::client_config",
+                        "[P2P] Invalid pluggable transport \"{}\": {}", protocol, e,
+                    );
+                    return Err(io::Error::new(
+                        ErrorKind::InvalidInput,
+                        "Invalid pluggable transport",
+                    ))
+                }
+            }
+        }
+
+        let mut transport = TransportConfigBuilder::default();
+        transport.protocols(protocols).path(CfgPath::new(pt_path.clone())).run_on_startup(true);
+        builder.bridges().transports().push(transport);
+    }
+
+    match builder.build() {
+        Ok(config) => Ok(config),
+        Err(e) => {
+            error!(
+                target: "net::tor::client_config",
+                "[P2P] Failed to build TorClientConfig: {}", e,
+            );
+            Err(io::Error::new(ErrorKind::Other, "Internal Tor error"))
+        }
+    }
+}
+
+impl TorBridgeConfig {
+    /// Returns the pluggable transport names used by the configured
+    /// bridge lines, e.g. `obfs4`. Bridge lines starting with an address
+    /// are plain bridges and don't need a pluggable transport.
+    fn transports(&self) -> Vec<&str> {
+        let mut transports = vec![];
+        for line in &self.bridges {
+            let Some(first) = line.split_whitespace().next() else { continue };
+            if first.parse::<SocketAddr>().is_err() && !transports.contains(&first) {
+                transports.push(first);
+            }
+        }
+        transports
+    }
+}
+
+/// Initialize or fetch the `TorClient` for the given configuration from
+/// the registry shared by the Tor dialers and listeners
+async fn tor_client(
+    datastore: &Option<String>,
+    bridges: &TorBridgeConfig,
+) -> io::Result<TorClient<PreferredRuntime>> {
+    let config = client_config(datastore, bridges)?;
+    let key = TorClientKey { datastore: datastore.clone(), bridges: bridges.clone() };
+    let cell = tor_client_cell(key).await;
+
+    let client = cell
+        .client
+        .get_or_try_init(|| async {
+            // This attempt supersedes the last failed one
+            *cell.error.lock().unwrap() = None;
+            log_bootstrapping(log::logger(), tor_log_level());
+            TorClient::create_bootstrapped(config).await
+        })
+        .await;
+
+    match client {
+        Ok(client) => Ok(client.clone()),
+        Err(e) => {
+            let report = e.report().to_string();
+            warn!(target: TOR_BOOTSTRAP_LOG_TARGET, "{}", report);
+            *cell.error.lock().unwrap() = Some(report);
+            Err(io::Error::new(ErrorKind::Other, "Internal Tor error, see logged warning"))
+        }
+    }
+}
+
+/// A `TorClient` run by the embedding application, used by Tor dialers
+/// and listeners instead of one from the registry
+#[derive(Clone)]
+struct ProvidedTorClient(TorClient<PreferredRuntime>);
+
+impl fmt::Debug for ProvidedTorClient {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ProvidedTorClient")
+    }
+}
+
+/// Use the provided `TorClient` if there is one, otherwise initialize or
+/// fetch the one for the given configuration from the registry
+async fn provided_or_registry_client(
+    provided: &Option<ProvidedTorClient>,
+    datastore: &Option<String>,
+    bridges: &TorBridgeConfig,
+) -> io::Result<TorClient<PreferredRuntime>> {
+    match provided {
+        Some(client) => Ok(client.0.clone()),
+        None => tor_client(datastore, bridges).await,
+    }
+}
 
 /// Tor Dialer implementation
 #[derive(Debug, Clone)]
 pub struct TorDialer {
     datastore: Option<String>,
+    bridges: TorBridgeConfig,
+    /// Client provided by the embedder, bypassing the registry
+    client: Option<ProvidedTorClient>,
 }
 
 impl TorDialer {
-    /// Instantiate a new [`TorDialer`] object
-    pub(crate) async fn new(datastore: Option<String>) -> io::Result<Self> {
-        Ok(Self { datastore })
+    /// Instantiate a new [`TorDialer`] object. If `bridges` contains any
+    /// bridge lines, Tor will only connect through those bridges.
+    pub(crate) async fn new(
+        datastore: Option<String>,
+        bridges: TorBridgeConfig,
+    ) -> io::Result<Self> {
+        Ok(Self { datastore, bridges, client: None })
+    }
+
+    /// Instantiate a [`TorDialer`] dialing through an existing `TorClient`,
+    /// so the process shares a single Tor instance. The client is used
+    /// as-is, so it should already be bootstrapped or configured to
+    /// bootstrap on demand.
+    pub fn with_client(client: TorClient<PreferredRuntime>) -> Self {
+        Self {
+            datastore: None,
+            bridges: TorBridgeConfig::default(),
+            client: Some(ProvidedTorClient(client)),
+        }
+    }
+
+    /// Dial through the given `TorClient` instead of one from the registry
+    pub fn set_client(&mut self, client: TorClient<PreferredRuntime>) {
+        self.client = Some(ProvidedTorClient(client));
+    }
+
+    /// Fetch the `TorClient` this dialer uses
+    async fn client(&self) -> io::Result<TorClient<PreferredRuntime>> {
+        provided_or_registry_client(&self.client, &self.datastore, &self.bridges).await
     }
 
     /// Internal dial function
@@ -73,34 +369,9 @@ impl TorDialer {
     ) -> io::Result<DataStream> {
         debug!(target: "net::tor::do_dial", "Dialing {}:{} with Tor...", host, port);
 
-        // Initialize or fetch the static TOR_CLIENT that should be reused in
-        // the Tor dialer
-        let client = match TOR_CLIENT
-            .get_or_try_init(|| async {
-                debug!(target: "net::tor::do_dial", "Bootstrapping...");
-                if let Some(datadir) = &self.datastore {
-                    let datadir = expand_path(datadir).unwrap();
-
-                    let config = TorClientConfigBuilder::from_directories(datadir.clone(), datadir)
-                        .build()
-                        .unwrap();
-
-                    TorClient::create_bootstrapped(config).await
-                } else {
-                    TorClient::builder().create_bootstrapped().await
-                }
-            })
-            .await
-        {
-            Ok(client) => client,
-            Err(e) => {
-                warn!("{}", e.report());
-                return Err(io::Error::new(
-                    ErrorKind::Other,
-                    "Internal Tor error, see logged warning",
-                ))
-            }
-        };
+        // Initialize or fetch the TorClient for our configuration that should
+        // be reused in the Tor dialer
+        let client = self.client().await?;
 
         let mut stream_prefs = StreamPrefs::new();
         stream_prefs.connect_to_onion_services(BoolOrAuto::Explicit(true));
@@ -150,98 +421,313 @@ impl TorDialer {
     }
 }
 
+/// Default amount of inbound rendezvous handshakes negotiated at once
+pub const DEFAULT_TOR_HANDSHAKE_LIMIT: usize = 8;
+
+/// Nickname of the onion service launched if none are configured
+pub const DEFAULT_ONION_SERVICE: &str = "darkfi_tor";
+
 /// Tor Listener implementation
 #[derive(Clone, Debug)]
 pub struct TorListener {
     datastore: Option<String>,
+    bridges: TorBridgeConfig,
+    /// Maximum amount of rendezvous handshakes negotiated concurrently
+    handshake_limit: usize,
+    /// Nicknames of the onion services to launch
+    nicknames: Vec<String>,
+    /// Client provided by the embedder, bypassing the registry
+    client: Option<ProvidedTorClient>,
+    /// Address of the first onion service
     pub endpoint: Arc<Mutex<Option<Url>>>,
+    /// Nickname and address of every launched onion service
+    pub services: Arc<Mutex<Vec<(String, Url)>>>,
 }
 
 impl TorListener {
-    /// Instantiate a new [`TorListener`]
-    pub async fn new(datastore: Option<String>) -> io::Result<Self> {
-        Ok(Self { datastore, endpoint: Arc::new(Mutex::new(None)) })
+    /// Instantiate a new [`TorListener`]. If `bridges` contains any
+    /// bridge lines, Tor will only connect through those bridges.
+    pub async fn new(datastore: Option<String>, bridges: TorBridgeConfig) -> io::Result<Self> {
+        Ok(Self {
+            datastore,
+            bridges,
+            handshake_limit: DEFAULT_TOR_HANDSHAKE_LIMIT,
+            nicknames: vec![DEFAULT_ONION_SERVICE.to_string()],
+            client: None,
+            endpoint: Arc::new(Mutex::new(None)),
+            services: Arc::new(Mutex::new(vec![])),
+        })
+    }
+
+    /// Instantiate a [`TorListener`] launching its onion services on an
+    /// existing `TorClient`, so the process shares a single Tor instance.
+    pub async fn with_client(client: TorClient<PreferredRuntime>) -> io::Result<Self> {
+        let mut listener = Self::new(None, TorBridgeConfig::default()).await?;
+        listener.set_client(client);
+        Ok(listener)
+    }
+
+    /// Launch onion services on the given `TorClient` instead of one
+    /// from the registry
+    pub fn set_client(&mut self, client: TorClient<PreferredRuntime>) {
+        self.client = Some(ProvidedTorClient(client));
+    }
+
+    /// Fetch the `TorClient` this listener uses
+    async fn client(&self) -> io::Result<TorClient<PreferredRuntime>> {
+        provided_or_registry_client(&self.client, &self.datastore, &self.bridges).await
+    }
+
+    /// Launch an onion service for each of the given nicknames instead
+    /// of the default one. Connections to all of them are accepted by
+    /// the same listener, and the nickname of the accepting service is
+    /// set as the fragment of the returned URL.
+    pub fn set_onion_services(&mut self, nicknames: Vec<String>) {
+        if !nicknames.is_empty() {
+            self.nicknames = nicknames;
+        }
+    }
+
+    /// Returns the nickname of the onion service which accepted a
+    /// connection, given the URL returned along with it.
+    pub fn accepting_service(url: &Url) -> Option<&str> {
+        url.fragment()
+    }
+
+    /// Set the maximum amount of rendezvous handshakes negotiated
+    /// concurrently. A limit of zero is treated as one.
+    pub fn set_handshake_limit(&mut self, limit: usize) {
+        self.handshake_limit = limit.max(1);
     }
 
     /// Internal listen function
     pub(crate) async fn do_listen(&self, port: u16) -> io::Result<TorListenerIntern> {
-        // Initialize or fetch the static TOR_CLIENT that should be reused in
-        // the Tor dialer
-        let client = match TOR_CLIENT
-            .get_or_try_init(|| async {
-                debug!(target: "net::tor::do_listen", "Bootstrapping...");
-                if let Some(datadir) = &self.datastore {
-                    let datadir = expand_path(datadir).unwrap();
-
-                    let config = TorClientConfigBuilder::from_directories(datadir.clone(), datadir)
-                        .build()
-                        .unwrap();
-
-                    TorClient::create_bootstrapped(config).await
-                } else {
-                    TorClient::builder().create_bootstrapped().await
-                }
-            })
-            .await
-        {
-            Ok(client) => client,
-            Err(e) => {
-                warn!("{}", e.report());
-                return Err(io::Error::new(
-                    ErrorKind::Other,
-                    "Internal Tor error, see logged warning",
-                ))
-            }
-        };
+        // Initialize or fetch the TorClient for our configuration that should
+        // be reused in the Tor listener
+        let client = self.client().await?;
 
-        let hs_nick = HsNickname::new("darkfi_tor".to_string()).unwrap();
+        let mut onion_services = vec![];
+        let mut rendreq_streams: Vec<ServiceStream<RendRequest>> = vec![];
+        let mut services = vec![];
 
-        let hs_config = match OnionServiceConfigBuilder::default().nickname(hs_nick).build() {
-            Ok(v) => v,
-            Err(e) => {
+        for nickname in &self.nicknames {
+            let Ok(hs_nick) = HsNickname::new(nickname.clone()) else {
                 error!(
                     target: "net::tor::do_listen",
-                    "[P2P] Failed to create OnionServiceConfig: {}", e,
+                    "[P2P] Invalid Onion Service nickname: {}", nickname,
                 );
-                return Err(io::Error::new(ErrorKind::Other, "Internal Tor error"))
-            }
-        };
+                return Err(io::Error::new(
+                    ErrorKind::InvalidInput,
+                    "Invalid Onion Service nickname",
+                ))
+            };
+
+            let hs_config = match OnionServiceConfigBuilder::default().nickname(hs_nick).build() {
+                Ok(v) => v,
+                Err(e) => {
+                    error!(
+                        target: "net::tor::do_listen",
+                        "[P2P] Failed to create OnionServiceConfig: {}", e,
+                    );
+                    return Err(io::Error::new(ErrorKind::Other, "Internal Tor error"))
+                }
+            };
+
+            let (onion_service, rendreq_stream) = match client.launch_onion_service(hs_config) {
+                Ok(v) => v,
+                Err(e) => {
+                    error!(
+                        target: "net::tor::do_listen",
+                        "[P2P] Failed to launch Onion Service: {}", e,
+                    );
+                    return Err(io::Error::new(ErrorKind::Other, "Internal Tor error"))
+                }
+            };
 
-        let (onion_service, rendreq_stream) = match client.launch_onion_service(hs_config) {
-            Ok(v) => v,
-            Err(e) => {
-                error!(
-                    target: "net::tor::do_listen",
-                    "[P2P] Failed to launch Onion Service: {}", e,
-                );
-                return Err(io::Error::new(ErrorKind::Other, "Internal Tor error"))
-            }
-        };
+            let endpoint =
+                Url::parse(&format!("tor://{}:{}", onion_service.onion_name().unwrap(), port))
+                    .unwrap();
 
-        info!(
-            target: "net::tor::do_listen",
-            "[P2P] Established Tor listener on tor://{}:{}",
-            onion_service.onion_name().unwrap(), port,
-        );
+            log_onion_launched(log::logger(), tor_log_level(), nickname, &endpoint);
 
-        *self.endpoint.lock().await = Some(
-            Url::parse(&format!("tor://{}:{}", onion_service.onion_name().unwrap(), port)).unwrap(),
-        );
+            services.push((nickname.clone(), endpoint));
+            onion_services.push(onion_service);
+            rendreq_streams.push((nickname.clone(), Box::pin(rendreq_stream) as _));
+        }
+
+        *self.endpoint.lock().await = services.first().map(|(_, endpoint)| endpoint.clone());
+        *self.services.lock().await = services;
+
+        let handshakes = merge_services(rendreq_streams).map(move |(nickname, rendrequest)| {
+            Box::pin(accept_rend_request(rendrequest, port, nickname))
+                as BoxFuture<'static, TorAccept>
+        });
 
         Ok(TorListenerIntern {
-            port,
-            _onion_service: onion_service,
-            rendreq_stream: Mutex::new(Box::pin(rendreq_stream)),
+            _onion_services: onion_services,
+            handshakes: Mutex::new(HandshakePool::new(Box::pin(handshakes), self.handshake_limit)),
         })
     }
 }
 
+/// Reasons for rejecting an incoming Tor stream request. These are kept
+/// distinct so scanning or a misconfigured hidden service can be told
+/// apart from normal disconnects.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum StreamRequestError {
+    /// A `BEGIN` request for a port we aren't listening on
+    WrongPort { requested: u16, listening: u16 },
+    /// A request other than `BEGIN`, e.g. `BEGIN_DIR` or `RESOLVE`
+    Unsupported(String),
+}
+
+impl fmt::Display for StreamRequestError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::WrongPort { requested, listening } => write!(
+                f,
+                "Tor stream requested port {}, but we're listening on port {}",
+                requested, listening
+            ),
+            Self::Unsupported(request) => write!(f, "Unsupported Tor stream request: {}", request),
+        }
+    }
+}
+
+impl std::error::Error for StreamRequestError {}
+
+/// Check that an incoming stream request is a `BEGIN` for the port
+/// we're listening on.
+fn validate_stream_request(
+    request: &IncomingStreamRequest,
+    port: u16,
+) -> Result<(), StreamRequestError> {
+    match request {
+        IncomingStreamRequest::Begin(begin) if begin.port() == port => Ok(()),
+        IncomingStreamRequest::Begin(begin) => {
+            Err(StreamRequestError::WrongPort { requested: begin.port(), listening: port })
+        }
+        other => Err(StreamRequestError::Unsupported(format!("{:?}", other))),
+    }
+}
+
+/// Result of an inbound Tor handshake, as yielded by `PtListener`
+type TorAccept = io::Result<(Box<dyn PtStream>, Url)>;
+
+/// Runs the handshakes of incoming requests concurrently, up to `limit`
+/// at a time, and yields their results in completion order. A slow peer
+/// therefore can't hold up the connections arriving after it.
+struct HandshakePool<T> {
+    /// Incoming requests, each mapped to its handshake future
+    requests: Pin<Box<dyn Stream<Item = BoxFuture<'static, T>> + Send>>,
+    /// Handshakes currently being negotiated
+    in_flight: FuturesUnordered<BoxFuture<'static, T>>,
+    /// Maximum amount of handshakes in flight
+    limit: usize,
+    /// Marks that the request stream has ended
+    exhausted: bool,
+}
+
+impl<T> HandshakePool<T> {
+    fn new(
+        requests: Pin<Box<dyn Stream<Item = BoxFuture<'static, T>> + Send>>,
+        limit: usize,
+    ) -> Self {
+        Self { requests, in_flight: FuturesUnordered::new(), limit, exhausted: false }
+    }
+
+    /// Wait for the next completed handshake. Returns `None` once the
+    /// requests have ended and every handshake has completed. This is
+    /// cancel-safe, as handshakes in flight are kept in the pool.
+    async fn next(&mut self) -> Option<T> {
+        loop {
+            let can_start = !self.exhausted && self.in_flight.len() < self.limit;
+
+            if !can_start {
+                // Returns `None` if nothing is in flight either
+                return self.in_flight.next().await
+            }
+
+            if self.in_flight.is_empty() {
+                match self.requests.next().await {
+                    Some(handshake) => self.in_flight.push(handshake),
+                    None => self.exhausted = true,
+                }
+                continue
+            }
+
+            let next = match select(self.requests.next(), self.in_flight.next()).await {
+                Either::Left((request, _)) => Either::Left(request),
+                Either::Right((result, _)) => Either::Right(result),
+            };
+
+            match next {
+                Either::Left(Some(handshake)) => self.in_flight.push(handshake),
+                Either::Left(None) => self.exhausted = true,
+                Either::Right(result) => return result,
+            }
+        }
+    }
+}
+
+/// Incoming requests of a single onion service, keyed by its nickname
+type ServiceStream<R> = (String, Pin<Box<dyn Stream<Item = R> + Send>>);
+
+/// Merge the incoming requests of several onion services into a single
+/// stream, tagging each request with the nickname of its service.
+fn merge_services<R: Send + 'static>(
+    services: Vec<ServiceStream<R>>,
+) -> impl Stream<Item = (String, R)> + Send {
+    futures::stream::select_all(services.into_iter().map(|(nickname, requests)| {
+        requests.map(move |request| (nickname.clone(), request)).boxed()
+    }))
+}
+
+/// Negotiate an inbound connection: accept the rendezvous request, then
+/// the stream request for our port. The returned URL carries the
+/// nickname of the accepting onion service as its fragment.
+async fn accept_rend_request(rendrequest: RendRequest, port: u16, nickname: String) -> TorAccept {
+    let mut streamreq_stream = match rendrequest.accept().await {
+        Ok(v) => v,
+        Err(e) => {
+            error!(
+                target: "net::tor::PtListener::next",
+                "[P2P] Failed accepting Tor RendRequest: {}", e,
+            );
+            return Err(io::Error::new(ErrorKind::ConnectionAborted, "Connection Aborted"))
+        }
+    };
+
+    let Some(streamrequest) = streamreq_stream.next().await else {
+        return Err(io::Error::new(ErrorKind::ConnectionAborted, "Connection Aborted"))
+    };
+
+    // Validate port correctness
+    if let Err(e) = validate_stream_request(streamrequest.request(), port) {
+        return Err(io::Error::new(ErrorKind::ConnectionAborted, e))
+    }
+
+    let stream = match streamrequest.accept(Connected::new_empty()).await {
+        Ok(v) => v,
+        Err(e) => {
+            error!(
+                target: "net::tor::PtListener::next",
+                "[P2P] Failed accepting Tor StreamRequest: {}", e,
+            );
+            return Err(io::Error::new(ErrorKind::Other, "Internal Tor error"))
+        }
+    };
+
+    let mut url = Url::parse(&format!("tor://127.0.0.1:{}", port)).unwrap();
+    url.set_fragment(Some(&nickname));
+
+    Ok((Box::new(stream), url))
+}
+
 /// Internal Tor Listener implementation, used with `PtListener`
 pub struct TorListenerIntern {
-    port: u16,
-    _onion_service: Arc<RunningOnionService>,
-    //rendreq_stream: Mutex<BoxStream<'a, RendRequest>>,
-    rendreq_stream: Mutex<Pin<Box<dyn Stream<Item = RendRequest> + Send>>>,
+    _onion_services: Vec<Arc<RunningOnionService>>,
+    handshakes: Mutex<HandshakePool<TorAccept>>,
 }
 
 unsafe impl Sync for TorListenerIntern {}
@@ -249,50 +735,274 @@ unsafe impl Sync for TorListenerIntern {}
 #[async_trait]
 impl PtListener for TorListenerIntern {
     async fn next(&self) -> io::Result<(Box<dyn PtStream>, Url)> {
-        let mut rendreq_stream = self.rendreq_stream.lock().await;
+        match self.handshakes.lock().await.next().await {
+            Some(result) => result,
+            None => Err(io::Error::new(ErrorKind::ConnectionAborted, "Connection Aborted")),
+        }
+    }
+}
 
-        let Some(rendrequest) = rendreq_stream.next().await else {
-            return Err(io::Error::new(ErrorKind::ConnectionAborted, "Connection Aborted"))
-        };
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::{channel::oneshot, FutureExt};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tor_cell::relaycell::msg::{Begin, BeginDir, BeginFlags};
 
-        drop(rendreq_stream);
+    const OBFS4_BRIDGE: &str = "obfs4 192.0.2.1:443 0123456789ABCDEF0123456789ABCDEF01234567 cert=AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA iat-mode=0";
 
-        let mut streamreq_stream = match rendrequest.accept().await {
-            Ok(v) => v,
-            Err(e) => {
-                error!(
-                    target: "net::tor::PtListener::next",
-                    "[P2P] Failed accepting Tor RendRequest: {}", e,
-                );
-                return Err(io::Error::new(ErrorKind::ConnectionAborted, "Connection Aborted"))
-            }
+    #[test]
+    fn tor_client_config_with_bridges() {
+        let config = client_config(&None, &TorBridgeConfig::default()).unwrap();
+        assert!(!format!("{:?}", config).contains("192.0.2.1:443"));
+
+        let bridges = TorBridgeConfig {
+            bridges: vec![OBFS4_BRIDGE.to_string(), "198.51.100.1:9001".to_string()],
+            pt_path: Some("/usr/bin/lyrebird".to_string()),
         };
+        assert_eq!(bridges.transports(), vec!["obfs4"]);
 
-        let Some(streamrequest) = streamreq_stream.next().await else {
-            return Err(io::Error::new(ErrorKind::ConnectionAborted, "Connection Aborted"))
+        let config = format!("{:?}", client_config(&None, &bridges).unwrap());
+        assert!(config.contains("192.0.2.1:443"));
+        assert!(config.contains("obfs4"));
+        assert!(config.contains("/usr/bin/lyrebird"));
+
+        let bridges =
+            TorBridgeConfig { bridges: vec!["not a bridge line".to_string()], pt_path: None };
+        assert!(client_config(&None, &bridges).is_err());
+    }
+
+    #[test]
+    fn tor_stream_request_validation() {
+        let begin = Begin::new("127.0.0.1", 26661, BeginFlags::empty()).unwrap();
+        let request = IncomingStreamRequest::Begin(begin);
+        assert_eq!(validate_stream_request(&request, 26661), Ok(()));
+
+        // A begin request for the wrong port is reported as such
+        let begin = Begin::new("127.0.0.1", 80, BeginFlags::empty()).unwrap();
+        let request = IncomingStreamRequest::Begin(begin);
+        let err = validate_stream_request(&request, 26661).unwrap_err();
+        assert_eq!(err, StreamRequestError::WrongPort { requested: 80, listening: 26661 });
+
+        // And it can be told apart from the resulting io::Error
+        let err = io::Error::new(ErrorKind::ConnectionAborted, err);
+        let inner = err.get_ref().unwrap().downcast_ref::<StreamRequestError>().unwrap();
+        assert!(matches!(inner, StreamRequestError::WrongPort { .. }));
+
+        let request = IncomingStreamRequest::BeginDir(BeginDir::default());
+        assert!(matches!(
+            validate_stream_request(&request, 26661),
+            Err(StreamRequestError::Unsupported(_))
+        ));
+    }
+
+    #[test]
+    fn tor_handshakes_run_concurrently() {
+        // Handshakes which complete once their gate is opened, as a
+        // mocked stream of rendezvous requests
+        let started = Arc::new(AtomicUsize::new(0));
+        let handshake = |id: u32, gate: oneshot::Receiver<()>| -> BoxFuture<'static, u32> {
+            let started = started.clone();
+            Box::pin(async move {
+                started.fetch_add(1, Ordering::SeqCst);
+                gate.await.unwrap();
+                id
+            })
         };
 
-        // Validate port correctness
-        match streamrequest.request() {
-            IncomingStreamRequest::Begin(begin) => {
-                if begin.port() != self.port {
-                    return Err(io::Error::new(ErrorKind::ConnectionAborted, "Connection Aborted"))
-                }
+        // Both are in flight, so the second one completes first
+        let (open_1, gate_1) = oneshot::channel();
+        let (open_2, gate_2) = oneshot::channel();
+        let requests = futures::stream::iter(vec![handshake(1, gate_1), handshake(2, gate_2)]);
+        let mut pool = HandshakePool::new(Box::pin(requests), 2);
+        open_2.send(()).unwrap();
+        assert_eq!(pool.next().now_or_never(), Some(Some(2)));
+        assert_eq!(started.load(Ordering::SeqCst), 2);
+        assert_eq!(pool.next().now_or_never(), None);
+        open_1.send(()).unwrap();
+        assert_eq!(pool.next().now_or_never(), Some(Some(1)));
+        assert_eq!(pool.next().now_or_never(), Some(None));
+
+        // With a limit of one they're negotiated serially
+        started.store(0, Ordering::SeqCst);
+        let (open_1, gate_1) = oneshot::channel();
+        let (open_2, gate_2) = oneshot::channel();
+        let requests = futures::stream::iter(vec![handshake(1, gate_1), handshake(2, gate_2)]);
+        let mut pool = HandshakePool::new(Box::pin(requests), 1);
+        open_2.send(()).unwrap();
+        assert_eq!(pool.next().now_or_never(), None);
+        assert_eq!(started.load(Ordering::SeqCst), 1);
+        open_1.send(()).unwrap();
+        assert_eq!(pool.next().now_or_never(), Some(Some(1)));
+        assert_eq!(pool.next().now_or_never(), Some(Some(2)));
+        assert_eq!(pool.next().now_or_never(), Some(None));
+    }
+
+    #[test]
+    fn tor_multiple_onion_services() {
+        smol::block_on(async {
+            // Two services, each with mocked rendezvous requests
+            let requests = |ids: Vec<u32>| {
+                Box::pin(futures::stream::iter(ids)) as Pin<Box<dyn Stream<Item = u32> + Send>>
+            };
+            let services = vec![
+                ("darkfi_a".to_string(), requests(vec![1, 2])),
+                ("darkfi_b".to_string(), requests(vec![3])),
+            ];
+
+            let handshakes = merge_services(services).map(|(nickname, id)| {
+                Box::pin(async move {
+                    let mut url = Url::parse("tor://127.0.0.1:26661").unwrap();
+                    url.set_fragment(Some(&nickname));
+                    (url, id)
+                }) as BoxFuture<'static, (Url, u32)>
+            });
+
+            let mut pool = HandshakePool::new(Box::pin(handshakes), 2);
+            let mut accepted = vec![];
+            while let Some((url, id)) = pool.next().await {
+                accepted.push((TorListener::accepting_service(&url).unwrap().to_string(), id));
             }
-            &_ => return Err(io::Error::new(ErrorKind::ConnectionAborted, "Connection Aborted")),
+            accepted.sort_by_key(|(_, id)| *id);
+
+            assert_eq!(
+                accepted,
+                vec![
+                    ("darkfi_a".to_string(), 1),
+                    ("darkfi_a".to_string(), 2),
+                    ("darkfi_b".to_string(), 3),
+                ]
+            );
+        })
+    }
+
+    /// Logger keeping the target and level of every record
+    #[derive(Default)]
+    struct CaptureLogger(std::sync::Mutex<Vec<(String, Level)>>);
+
+    impl Log for CaptureLogger {
+        fn enabled(&self, _: &log::Metadata) -> bool {
+            true
         }
 
-        let stream = match streamrequest.accept(Connected::new_empty()).await {
-            Ok(v) => v,
-            Err(e) => {
-                error!(
-                    target: "net::tor::PtListener::next",
-                    "[P2P] Failed accepting Tor StreamRequest: {}", e,
-                );
-                return Err(io::Error::new(ErrorKind::Other, "Internal Tor error"))
+        fn log(&self, record: &Record) {
+            self.0.lock().unwrap().push((record.target().to_string(), record.level()));
+        }
+
+        fn flush(&self) {}
+    }
+
+    #[test]
+    fn tor_log_targets() {
+        let logger = CaptureLogger::default();
+        let endpoint = Url::parse("tor://abcdefghij.onion:25551").unwrap();
+
+        // Let every level through for the duration of the test
+        let max_level = log::max_level();
+        log::set_max_level(log::LevelFilter::Trace);
+
+        log_bootstrapping(&logger, None);
+        log_onion_launched(&logger, None, DEFAULT_ONION_SERVICE, &endpoint);
+
+        // A host embedding darkfi quiets both down to trace
+        log_bootstrapping(&logger, Some(Level::Trace));
+        log_onion_launched(&logger, Some(Level::Trace), DEFAULT_ONION_SERVICE, &endpoint);
+
+        log::set_max_level(max_level);
+
+        assert_eq!(
+            *logger.0.lock().unwrap(),
+            vec![
+                (TOR_BOOTSTRAP_LOG_TARGET.to_string(), Level::Debug),
+                (TOR_ONION_LOG_TARGET.to_string(), Level::Info),
+                (TOR_BOOTSTRAP_LOG_TARGET.to_string(), Level::Trace),
+                (TOR_ONION_LOG_TARGET.to_string(), Level::Trace),
+            ]
+        );
+    }
+
+    #[test]
+    fn tor_client_registry() {
+        smol::block_on(async {
+            let key_a = TorClientKey {
+                datastore: Some("/tmp/darkfi_tor_a".to_string()),
+                bridges: TorBridgeConfig::default(),
+            };
+            let key_b = TorClientKey {
+                datastore: Some("/tmp/darkfi_tor_b".to_string()),
+                bridges: TorBridgeConfig::default(),
+            };
+            let key_c = TorClientKey {
+                datastore: Some("/tmp/darkfi_tor_a".to_string()),
+                bridges: TorBridgeConfig {
+                    bridges: vec![OBFS4_BRIDGE.to_string()],
+                    pt_path: Some("/usr/bin/lyrebird".to_string()),
+                },
+            };
+
+            // Identical configurations share a client
+            let cell_a = tor_client_cell(key_a.clone()).await;
+            assert!(Arc::ptr_eq(&cell_a, &tor_client_cell(key_a.clone()).await));
+
+            // Distinct configurations get their own
+            let cell_b = tor_client_cell(key_b.clone()).await;
+            let cell_c = tor_client_cell(key_c.clone()).await;
+            assert!(!Arc::ptr_eq(&cell_a, &cell_b));
+            assert!(!Arc::ptr_eq(&cell_a, &cell_c));
+            assert!(!Arc::ptr_eq(&cell_b, &cell_c));
+
+            // Don't leak the entries into other tests
+            let mut clients = TOR_CLIENTS.lock().await;
+            for key in [key_a, key_b, key_c] {
+                clients.remove(&key);
             }
-        };
+        })
+    }
 
-        Ok((Box::new(stream), Url::parse(&format!("tor://127.0.0.1:{}", self.port)).unwrap()))
+    #[test]
+    fn tor_failed_bootstrap_health() {
+        smol::block_on(async {
+            let bridges = TorBridgeConfig::default();
+            let failed = Some("/tmp/darkfi_tor_failed_health".to_string());
+            let pending = Some("/tmp/darkfi_tor_pending_health".to_string());
+            assert_eq!(config_health(&failed, &bridges).await, TransportHealth::NotBootstrapped);
+
+            // A failed bootstrap isn't reported as still in progress
+            let key = TorClientKey { datastore: failed.clone(), bridges: bridges.clone() };
+            let cell = tor_client_cell(key.clone()).await;
+            *cell.error.lock().unwrap() = Some("No route to the Tor network".to_string());
+            assert_eq!(config_health(&failed, &bridges).await, TransportHealth::Failed);
+
+            // Only the client for the requested configuration counts
+            let pending_key = TorClientKey { datastore: pending.clone(), bridges: bridges.clone() };
+            tor_client_cell(pending_key.clone()).await;
+            assert_eq!(config_health(&pending, &bridges).await, TransportHealth::Bootstrapping);
+            assert_eq!(config_health(&failed, &bridges).await, TransportHealth::Failed);
+
+            // Don't leak the entries into other tests
+            let mut clients = TOR_CLIENTS.lock().await;
+            clients.remove(&key);
+            clients.remove(&pending_key);
+        })
+    }
+
+    #[test]
+    fn tor_provided_client() {
+        smol::block_on(async {
+            let datastore = Some("/tmp/darkfi_tor_provided".to_string());
+            let config = client_config(&datastore, &TorBridgeConfig::default()).unwrap();
+            let stub = TorClient::builder().config(config).create_unbootstrapped().unwrap();
+
+            // The provided client is handed out as-is, without bootstrapping
+            let dialer = TorDialer::with_client(stub.clone());
+            assert!(!dialer.client().await.unwrap().bootstrap_status().ready_for_traffic());
+
+            let listener = TorListener::with_client(stub).await.unwrap();
+            assert!(!listener.client().await.unwrap().bootstrap_status().ready_for_traffic());
+
+            // and nothing was added to the registry
+            let key = TorClientKey { datastore, bridges: TorBridgeConfig::default() };
+            assert!(!TOR_CLIENTS.lock().await.contains_key(&key));
+        })
     }
 }
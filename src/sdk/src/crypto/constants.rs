@@ -27,6 +27,10 @@ pub use fixed_bases::{
 /// Domain prefix used for Schnorr signatures, with `hash_to_scalar`.
 pub const DRK_SCHNORR_DOMAIN: &[u8] = b"DarkFi:Schnorr";
 
+/// Prefix of off-chain messages signed with `message_signature`, keeping
+/// them apart from raw Schnorr signatures such as transaction ones.
+pub const DRK_MESSAGE_SIGNATURE_PREFIX: &[u8] = b"DarkFi:MessageSignature";
+
 /// Domain prefix used for block hashes, with `hash_to_curve`.
 pub const BLOCK_HASH_DOMAIN: &str = "DarkFi:Block";
 
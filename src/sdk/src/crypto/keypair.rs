@@ -17,10 +17,11 @@
  */
 
 use core::str::FromStr;
+use std::io::{Error, ErrorKind, Read};
 
 #[cfg(feature = "async")]
 use darkfi_serial::async_trait;
-use darkfi_serial::{SerialDecodable, SerialEncodable};
+use darkfi_serial::{Decodable, SerialDecodable, SerialEncodable};
 use halo2_gadgets::ecc::chip::FixedPoint;
 use pasta_curves::{
     arithmetic::CurveAffine,
@@ -52,6 +53,30 @@ impl Keypair {
     pub fn random(rng: &mut (impl CryptoRng + RngCore)) -> Self {
         Self::new(SecretKey::random(rng))
     }
+
+    /// Generate a new `Keypair` on the blocking thread pool, so bulk key
+    /// generation doesn't stall the async executor with scalar mults.
+    #[cfg(feature = "async")]
+    pub async fn random_async(mut rng: impl CryptoRng + RngCore + Send + 'static) -> Self {
+        smol::unblock(move || Self::random(&mut rng)).await
+    }
+
+    /// Check that the stored `PublicKey` is the one derived from the
+    /// stored `SecretKey`.
+    pub fn validate(&self) -> bool {
+        PublicKey::from_secret(self.secret) == self.public
+    }
+
+    /// Decode a `Keypair` and check it with [`Keypair::validate()`], so
+    /// keypairs read from corrupted storage are rejected.
+    pub fn checked_decode<D: Read>(d: &mut D) -> Result<Self, Error> {
+        let keypair = Self::decode(d)?;
+        if !keypair.validate() {
+            return Err(Error::new(ErrorKind::InvalidData, "Keypair public key mismatch"))
+        }
+
+        Ok(keypair)
+    }
 }
 
 impl Default for Keypair {
@@ -86,6 +111,32 @@ impl SecretKey {
             None => Err(ContractError::IoError("Could not convert bytes to SecretKey".to_string())),
         }
     }
+
+    /// Instantiate a `SecretKey` given its 32 bytes in little-endian
+    /// order. This is the same as [`SecretKey::from_bytes()`].
+    pub fn from_le_bytes(bytes: [u8; 32]) -> Result<Self, ContractError> {
+        Self::from_bytes(bytes)
+    }
+
+    /// Instantiate a `SecretKey` given its 32 bytes in big-endian order.
+    /// Returns an error if the representation is noncanonical.
+    pub fn from_be_bytes(mut bytes: [u8; 32]) -> Result<Self, ContractError> {
+        bytes.reverse();
+        Self::from_bytes(bytes)
+    }
+
+    /// Encode the `SecretKey` as 32 bytes in little-endian order, the
+    /// same as the canonical `to_repr()` encoding used for serialization.
+    pub fn to_le_bytes(&self) -> [u8; 32] {
+        self.0.to_repr()
+    }
+
+    /// Encode the `SecretKey` as 32 bytes in big-endian order
+    pub fn to_be_bytes(&self) -> [u8; 32] {
+        let mut bytes = self.to_le_bytes();
+        bytes.reverse();
+        bytes
+    }
 }
 
 impl From<pallas::Base> for SecretKey {
@@ -157,6 +208,36 @@ impl PublicKey {
         self.0.to_bytes()
     }
 
+    /// Instantiate a `PublicKey` given its compressed encoding in
+    /// little-endian order: the `x` coordinate with the sign of `y` in
+    /// the top bit of the last byte. This is the same as
+    /// [`PublicKey::from_bytes()`].
+    pub fn from_le_bytes(bytes: [u8; 32]) -> Result<Self, ContractError> {
+        Self::from_bytes(bytes)
+    }
+
+    /// Instantiate a `PublicKey` given its compressed encoding in
+    /// big-endian order: the `x` coordinate with the sign of `y` in
+    /// the top bit of the first byte.
+    pub fn from_be_bytes(mut bytes: [u8; 32]) -> Result<Self, ContractError> {
+        bytes.reverse();
+        Self::from_bytes(bytes)
+    }
+
+    /// Encode the `PublicKey` in little-endian order, the same as
+    /// [`PublicKey::to_bytes()`]. See [`PublicKey::from_le_bytes()`].
+    pub fn to_le_bytes(&self) -> [u8; 32] {
+        self.to_bytes()
+    }
+
+    /// Encode the `PublicKey` in big-endian order.
+    /// See [`PublicKey::from_be_bytes()`].
+    pub fn to_be_bytes(&self) -> [u8; 32] {
+        let mut bytes = self.to_bytes();
+        bytes.reverse();
+        bytes
+    }
+
     /// Fetch the `x` coordinate of this `PublicKey`
     pub fn x(&self) -> pallas::Base {
         *self.0.to_affine().coordinates().unwrap().x()
@@ -210,3 +291,78 @@ impl core::fmt::Display for PublicKey {
         write!(f, "{}", disp)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use darkfi_serial::serialize;
+    use rand::rngs::OsRng;
+
+    use super::*;
+
+    #[test]
+    fn keypair_validation() {
+        let keypair = Keypair::random(&mut OsRng);
+        assert!(keypair.validate());
+
+        let decoded = Keypair::checked_decode(&mut &serialize(&keypair)[..]).unwrap();
+        assert_eq!(decoded, keypair);
+
+        // Swap in the public key of another secret
+        let tampered = Keypair { public: Keypair::random(&mut OsRng).public, ..keypair };
+        assert!(!tampered.validate());
+
+        let err = Keypair::checked_decode(&mut &serialize(&tampered)[..]).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn key_byte_order() {
+        let mut le = [0u8; 32];
+        le[0] = 0x01;
+        le[1] = 0x02;
+        let mut be = [0u8; 32];
+        be[31] = 0x01;
+        be[30] = 0x02;
+
+        // 0x0201 is stored least significant byte first in LE form
+        let secret = SecretKey::from(pallas::Base::from(0x0201));
+        assert_eq!(secret.to_le_bytes(), le);
+        assert_eq!(secret.to_be_bytes(), be);
+        assert_eq!(SecretKey::from_le_bytes(le).unwrap(), secret);
+        assert_eq!(SecretKey::from_be_bytes(be).unwrap(), secret);
+        assert_eq!(SecretKey::from_bytes(le).unwrap(), secret);
+
+        // Noncanonical values are rejected in both forms
+        assert!(SecretKey::from_le_bytes([0xff; 32]).is_err());
+        assert!(SecretKey::from_be_bytes([0xff; 32]).is_err());
+
+        let public = PublicKey::from_secret(secret);
+        let (le, be) = (public.to_le_bytes(), public.to_be_bytes());
+        assert_eq!(le, public.to_bytes());
+        assert_eq!(le[..31], public.x().to_repr()[..31]);
+        // The sign of y is kept in the most significant bit
+        assert_eq!(le[31] >> 7, be[0] >> 7);
+        assert_eq!(be[1..], public.x().to_repr().iter().rev().copied().collect::<Vec<_>>()[1..]);
+        assert_eq!(PublicKey::from_le_bytes(le).unwrap(), public);
+        assert_eq!(PublicKey::from_be_bytes(be).unwrap(), public);
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn keypair_random_async() {
+        use std::{cell::Cell, rc::Rc};
+
+        let executor = smol::LocalExecutor::new();
+        smol::block_on(executor.run(async {
+            // This task can only run if the executor thread is free
+            // while the keypair is being generated
+            let ticked = Rc::new(Cell::new(false));
+            let ticked_ = ticked.clone();
+            executor.spawn(async move { ticked_.set(true) }).detach();
+
+            let keypair = Keypair::random_async(OsRng).await;
+            assert!(keypair.validate());
+            assert!(ticked.get());
+        }));
+    }
+}
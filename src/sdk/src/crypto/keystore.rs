@@ -0,0 +1,210 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2024 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::{
+    fs::{self, OpenOptions},
+    io::Write,
+    path::Path,
+};
+
+use argon2::Argon2;
+use chacha20poly1305::{aead::Aead, ChaCha20Poly1305, KeyInit};
+use darkfi_serial::{deserialize, serialize};
+use rand_core::{CryptoRng, RngCore};
+use zeroize::Zeroizing;
+
+#[cfg(unix)]
+use std::{
+    fs::Permissions,
+    os::unix::fs::{OpenOptionsExt, PermissionsExt},
+};
+
+use super::Keypair;
+use crate::error::{KeystoreError, KeystoreResult};
+
+/// Magic bytes identifying a keystore file
+const KEYSTORE_MAGIC: [u8; 4] = *b"DKKS";
+
+/// Current keystore format version
+const KEYSTORE_VERSION: u8 = 1;
+
+/// Length of the random salt fed to Argon2
+const SALT_SIZE: usize = 16;
+
+/// Length of the ChaCha20Poly1305 nonce
+const NONCE_SIZE: usize = 12;
+
+/// Length of the header preceding the ciphertext
+const HEADER_SIZE: usize = KEYSTORE_MAGIC.len() + 1 + SALT_SIZE + NONCE_SIZE;
+
+/// A set of keypairs stored encrypted at rest.
+///
+/// The encryption key is derived from a passphrase with Argon2id and
+/// the serialized keypairs are sealed with ChaCha20Poly1305. The file
+/// layout is `magic || version || salt || nonce || ciphertext`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Keystore {
+    pub keypairs: Vec<Keypair>,
+}
+
+impl Keystore {
+    /// Create a keystore holding the given keypairs
+    pub fn new(keypairs: Vec<Keypair>) -> Self {
+        Self { keypairs }
+    }
+
+    /// Encrypt the keystore with `passphrase`, using fresh randomness
+    /// for the salt and the nonce.
+    pub fn encrypt(
+        &self,
+        passphrase: &str,
+        rng: &mut (impl CryptoRng + RngCore),
+    ) -> KeystoreResult<Vec<u8>> {
+        let mut salt = [0u8; SALT_SIZE];
+        let mut nonce = [0u8; NONCE_SIZE];
+        rng.fill_bytes(&mut salt);
+        rng.fill_bytes(&mut nonce);
+
+        let key = derive_key(passphrase, &salt)?;
+        let plaintext = Zeroizing::new(serialize(&self.keypairs));
+        let ciphertext = ChaCha20Poly1305::new(key[..].into())
+            .encrypt(nonce[..].into(), &plaintext[..])
+            .map_err(|_| KeystoreError::Encrypt)?;
+
+        let mut bytes = Vec::with_capacity(HEADER_SIZE + ciphertext.len());
+        bytes.extend_from_slice(&KEYSTORE_MAGIC);
+        bytes.push(KEYSTORE_VERSION);
+        bytes.extend_from_slice(&salt);
+        bytes.extend_from_slice(&nonce);
+        bytes.extend_from_slice(&ciphertext);
+        Ok(bytes)
+    }
+
+    /// Decrypt a keystore created with [`Keystore::encrypt()`]. Returns
+    /// [`KeystoreError::Decrypt`] if the passphrase is wrong or the data
+    /// was tampered with, and [`KeystoreError::InvalidKeypair`] if any
+    /// of the stored keypairs doesn't validate.
+    pub fn decrypt(bytes: &[u8], passphrase: &str) -> KeystoreResult<Self> {
+        if bytes.len() < HEADER_SIZE || bytes[..KEYSTORE_MAGIC.len()] != KEYSTORE_MAGIC {
+            return Err(KeystoreError::InvalidMagic)
+        }
+
+        let (version, rest) = (bytes[KEYSTORE_MAGIC.len()], &bytes[KEYSTORE_MAGIC.len() + 1..]);
+        if version != KEYSTORE_VERSION {
+            return Err(KeystoreError::UnsupportedVersion(version))
+        }
+
+        let (salt, rest) = rest.split_at(SALT_SIZE);
+        let (nonce, ciphertext) = rest.split_at(NONCE_SIZE);
+
+        let key = derive_key(passphrase, salt)?;
+        let plaintext = ChaCha20Poly1305::new(key[..].into())
+            .decrypt(nonce.into(), ciphertext)
+            .map(Zeroizing::new)
+            .map_err(|_| KeystoreError::Decrypt)?;
+
+        let keypairs: Vec<Keypair> = deserialize(&plaintext).map_err(|_| KeystoreError::Decrypt)?;
+        if !keypairs.iter().all(Keypair::validate) {
+            return Err(KeystoreError::InvalidKeypair)
+        }
+
+        Ok(Self { keypairs })
+    }
+
+    /// Encrypt the keystore with `passphrase` and write it to `path`.
+    /// On Unix the file is only readable and writable by its owner.
+    pub fn save(
+        &self,
+        path: &Path,
+        passphrase: &str,
+        rng: &mut (impl CryptoRng + RngCore),
+    ) -> KeystoreResult<()> {
+        let bytes = self.encrypt(passphrase, rng)?;
+
+        let mut options = OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        options.mode(0o600);
+
+        let mut file = options.open(path)?;
+        // The mode only applies to newly created files
+        #[cfg(unix)]
+        file.set_permissions(Permissions::from_mode(0o600))?;
+        file.write_all(&bytes)?;
+        Ok(())
+    }
+
+    /// Read the keystore at `path` and decrypt it with `passphrase`
+    pub fn load(path: &Path, passphrase: &str) -> KeystoreResult<Self> {
+        Self::decrypt(&fs::read(path)?, passphrase)
+    }
+}
+
+/// Derive the symmetric encryption key from the passphrase and salt
+fn derive_key(passphrase: &str, salt: &[u8]) -> KeystoreResult<Zeroizing<[u8; 32]>> {
+    let mut key = Zeroizing::new([0u8; 32]);
+    Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key[..])
+        .map_err(|e| KeystoreError::KeyDerivation(e.to_string()))?;
+    Ok(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::OsRng;
+
+    use super::*;
+
+    #[test]
+    fn keystore_roundtrip() {
+        let keystore =
+            Keystore::new(vec![Keypair::random(&mut OsRng), Keypair::random(&mut OsRng)]);
+
+        let path = std::env::temp_dir().join(format!("darkfi_keystore_{}", OsRng.next_u64()));
+        keystore.save(&path, "correct horse", &mut OsRng).unwrap();
+        #[cfg(unix)]
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        let loaded = Keystore::load(&path, "correct horse");
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.unwrap(), keystore);
+        #[cfg(unix)]
+        assert_eq!(mode & 0o777, 0o600);
+
+        // Every encryption uses a fresh salt and nonce
+        let a = keystore.encrypt("correct horse", &mut OsRng).unwrap();
+        let b = keystore.encrypt("correct horse", &mut OsRng).unwrap();
+        assert_ne!(a, b);
+    }
+
+    #[test]
+    fn keystore_wrong_passphrase() {
+        let keystore = Keystore::new(vec![Keypair::random(&mut OsRng)]);
+        let mut bytes = keystore.encrypt("correct horse", &mut OsRng).unwrap();
+
+        assert!(matches!(Keystore::decrypt(&bytes, "battery staple"), Err(KeystoreError::Decrypt)));
+
+        // Tampering with the ciphertext is caught by the AEAD tag
+        *bytes.last_mut().unwrap() ^= 1;
+        assert!(matches!(Keystore::decrypt(&bytes, "correct horse"), Err(KeystoreError::Decrypt)));
+
+        assert!(matches!(
+            Keystore::decrypt(b"not a keystore", "correct horse"),
+            Err(KeystoreError::InvalidMagic)
+        ));
+    }
+}
@@ -19,7 +19,7 @@
 use core::{fmt, str::FromStr};
 use std::{io, iter};
 
-use bridgetree::{BridgeTree, Hashable, Level};
+use bridgetree::{BridgeTree, Hashable, Level, Position};
 use darkfi_serial::{SerialDecodable, SerialEncodable};
 use halo2_gadgets::sinsemilla::primitives::HashDomain;
 use lazy_static::lazy_static;
@@ -42,6 +42,20 @@ use crate::crypto::{
 
 pub type MerkleTree = BridgeTree<MerkleNode, usize, { MERKLE_DEPTH }>;
 
+/// Locate `leaf` in `tree` and return its position together with the
+/// Merkle path to the current root, as consumed by the burn circuits.
+/// The tree only keeps paths for marked leaves, so `None` is returned
+/// if `leaf` wasn't marked when it was appended, or isn't in the tree.
+pub fn membership_witness(
+    tree: &MerkleTree,
+    leaf: &MerkleNode,
+) -> Option<(Position, Vec<MerkleNode>)> {
+    let position =
+        tree.marked_positions().into_iter().find(|pos| tree.get_marked_leaf(*pos) == Some(leaf))?;
+    let path = tree.witness(position, 0).ok()?;
+    Some((position, path))
+}
+
 lazy_static! {
     static ref UNCOMMITTED_ORCHARD: pallas::Base = pallas::Base::from(2);
     static ref EMPTY_ROOTS: Vec<MerkleNode> = {
@@ -189,4 +203,34 @@ mod tests {
             assert!(root == &tree.root(0).unwrap());
         }
     }
+
+    #[test]
+    fn membership_witness_marked_leaves() {
+        let mut tree = MerkleTree::new(1);
+        let mut marked = vec![];
+        let mut unmarked = vec![];
+
+        for i in 0..8 {
+            let leaf = MerkleNode::from(pallas::Base::random(&mut OsRng));
+            tree.append(leaf);
+            if i % 2 == 0 {
+                marked.push((leaf, tree.mark().unwrap()));
+            } else {
+                unmarked.push(leaf);
+            }
+        }
+
+        for (leaf, position) in &marked {
+            let (witness_pos, path) = membership_witness(&tree, leaf).unwrap();
+            assert_eq!(witness_pos, *position);
+            assert_eq!(path, tree.witness(*position, 0).unwrap());
+        }
+
+        // Unmarked and unknown leaves can't be witnessed
+        for leaf in &unmarked {
+            assert!(membership_witness(&tree, leaf).is_none());
+        }
+        let unknown = MerkleNode::from(pallas::Base::random(&mut OsRng));
+        assert!(membership_witness(&tree, &unknown).is_none());
+    }
 }
@@ -28,7 +28,7 @@ pub mod diffie_hellman;
 
 /// Miscellaneous utilities
 pub mod util;
-pub use util::poseidon_hash;
+pub use util::{poseidon_hash, poseidon_hash_domain, PoseidonDomain};
 
 /// Keypairs, secret keys, and public keys
 pub mod keypair;
@@ -44,18 +44,31 @@ pub use func_ref::{FuncId, FuncRef};
 
 /// Merkle node definitions
 pub mod merkle_node;
-pub use merkle_node::{MerkleNode, MerkleTree};
+pub use merkle_node::{membership_witness, MerkleNode, MerkleTree};
 
 /// Note encryption
 pub mod note;
 
+/// Passphrase-encrypted keypair storage
+#[cfg(feature = "keystore")]
+pub mod keystore;
+#[cfg(feature = "keystore")]
+pub use keystore::Keystore;
+
 /// Pedersen commitment utilities
 pub mod pedersen;
-pub use pedersen::{pedersen_commitment_base, pedersen_commitment_u64};
+pub use pedersen::{
+    pedersen_commitment_base, pedersen_commitment_base_opens, pedersen_commitment_u64,
+    pedersen_commitment_u64_opens, PedersenGenerators,
+};
 
 /// Schnorr signature traits
 pub mod schnorr;
 
+/// Domain-separated signatures over off-chain messages
+pub mod message_signature;
+pub use message_signature::{sign_message, verify_message};
+
 /// MiMC VDF
 pub mod mimc_vdf;
 
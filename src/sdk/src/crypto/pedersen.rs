@@ -17,6 +17,7 @@
  */
 
 use halo2_gadgets::ecc::chip::FixedPoint;
+use lazy_static::lazy_static;
 use pasta_curves::{arithmetic::CurveExt, pallas};
 
 use super::{
@@ -30,24 +31,113 @@ use super::{
     util::fp_mod_fv,
 };
 
+/// Token generator domain byte used by [`PedersenGenerators::from_personalization()`]
+const TOKEN_GENERATOR_BYTES: [u8; 1] = *b"t";
+
+/// Generator points used to create Pedersen commitments.
+///
+/// The default generators are the fixed bases the zk circuits commit
+/// with, so only those produce commitments the circuits agree with.
+/// Custom generators are meant for test networks and for separating
+/// commitment domains outside of the circuits.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct PedersenGenerators {
+    /// Generator for 64-bit values
+    pub value: pallas::Point,
+    /// Generator for full-width base field elements, e.g. token IDs
+    pub token: pallas::Point,
+    /// Generator for the blinding factor
+    pub blind: pallas::Point,
+}
+
+lazy_static! {
+    /// Generators matching the zk circuit fixed bases, derived once
+    static ref DEFAULT_GENERATORS: PedersenGenerators = {
+        let hasher = pallas::Point::hash_to_curve(VALUE_COMMITMENT_PERSONALIZATION);
+        PedersenGenerators {
+            value: hasher(&VALUE_COMMITMENT_V_BYTES),
+            token: NullifierK.generator().into(),
+            blind: hasher(&VALUE_COMMITMENT_R_BYTES),
+        }
+    };
+}
+
+impl Default for PedersenGenerators {
+    fn default() -> Self {
+        *DEFAULT_GENERATORS
+    }
+}
+
+impl PedersenGenerators {
+    /// Derive an independent set of generators by hashing to the curve
+    /// with the given domain personalization.
+    pub fn from_personalization(personalization: &str) -> Self {
+        let hasher = pallas::Point::hash_to_curve(personalization);
+        Self {
+            value: hasher(&VALUE_COMMITMENT_V_BYTES),
+            token: hasher(&TOKEN_GENERATOR_BYTES),
+            blind: hasher(&VALUE_COMMITMENT_R_BYTES),
+        }
+    }
+
+    /// Pedersen commitment for a full-width base field element.
+    pub fn commit_base(&self, value: pallas::Base, blind: ScalarBlind) -> pallas::Point {
+        self.token * fp_mod_fv(value) + self.blind * blind.inner()
+    }
+
+    /// Pedersen commitment for a 64-bit value, in the base field.
+    pub fn commit_u64(&self, value: u64, blind: ScalarBlind) -> pallas::Point {
+        self.value * fp_mod_fv(pallas::Base::from(value)) + self.blind * blind.inner()
+    }
+
+    /// Check that `commitment` opens to the given full-width base field
+    /// element and blind under these generators.
+    pub fn base_opens(
+        &self,
+        commitment: &pallas::Point,
+        value: pallas::Base,
+        blind: ScalarBlind,
+    ) -> bool {
+        self.commit_base(value, blind) == *commitment
+    }
+
+    /// Check that `commitment` opens to the given 64-bit value and blind
+    /// under these generators.
+    pub fn u64_opens(&self, commitment: &pallas::Point, value: u64, blind: ScalarBlind) -> bool {
+        self.commit_u64(value, blind) == *commitment
+    }
+}
+
 /// Pedersen commitment for a full-width base field element.
-#[allow(non_snake_case)]
 pub fn pedersen_commitment_base(value: pallas::Base, blind: ScalarBlind) -> pallas::Point {
-    let hasher = pallas::Point::hash_to_curve(VALUE_COMMITMENT_PERSONALIZATION);
-    let V = NullifierK.generator();
-    let R = hasher(&VALUE_COMMITMENT_R_BYTES);
-
-    V * fp_mod_fv(value) + R * blind.inner()
+    DEFAULT_GENERATORS.commit_base(value, blind)
 }
 
 /// Pedersen commitment for a 64-bit value, in the base field.
-#[allow(non_snake_case)]
 pub fn pedersen_commitment_u64(value: u64, blind: ScalarBlind) -> pallas::Point {
-    let hasher = pallas::Point::hash_to_curve(VALUE_COMMITMENT_PERSONALIZATION);
-    let V = hasher(&VALUE_COMMITMENT_V_BYTES);
-    let R = hasher(&VALUE_COMMITMENT_R_BYTES);
+    DEFAULT_GENERATORS.commit_u64(value, blind)
+}
 
-    V * fp_mod_fv(pallas::Base::from(value)) + R * blind.inner()
+/// Check that `commitment` opens to the given full-width base field
+/// element and blind. This is a non-circuit sanity check, useful for
+/// catching value/blind corruption before creating a proof.
+pub fn pedersen_commitment_base_opens(
+    commitment: &pallas::Point,
+    value: pallas::Base,
+    blind: ScalarBlind,
+) -> bool {
+    pedersen_commitment_base(value, blind) == *commitment
+}
+
+/// Check that `commitment` opens to the given 64-bit value and blind.
+/// This is a non-circuit sanity check, useful for catching value/blind
+/// corruption before creating a proof.
+pub fn pedersen_commitment_u64_opens(
+    commitment: &pallas::Point,
+    value: u64,
+    blind: ScalarBlind,
+) -> bool {
+    pedersen_commitment_u64(value, blind) == *commitment
 }
 
 #[cfg(test)]
@@ -74,4 +164,56 @@ mod tests {
             pedersen_commitment_u64(a_value + b_value, &a_blind + &b_blind)
         );
     }
+
+    #[test]
+    fn pedersen_commitment_opening() {
+        let value = 42;
+        let blind = ScalarBlind::from(1337);
+
+        let commit = pedersen_commitment_u64(value, blind);
+        assert!(pedersen_commitment_u64_opens(&commit, value, blind));
+        // Tampered value or blind must not open the commitment
+        assert!(!pedersen_commitment_u64_opens(&commit, value + 1, blind));
+        assert!(!pedersen_commitment_u64_opens(&commit, value, ScalarBlind::from(1338)));
+
+        let value = pallas::Base::from(42);
+        let commit = pedersen_commitment_base(value, blind);
+        assert!(pedersen_commitment_base_opens(&commit, value, blind));
+        assert!(!pedersen_commitment_base_opens(&commit, pallas::Base::from(43), blind));
+        assert!(!pedersen_commitment_base_opens(&commit, value, ScalarBlind::from(1338)));
+    }
+
+    #[test]
+    fn pedersen_custom_generators() {
+        let default = PedersenGenerators::default();
+        let custom = PedersenGenerators::from_personalization("darkfi:testnet-cv");
+        assert_ne!(default, custom);
+
+        let value = 42;
+        let blind = ScalarBlind::from(1337);
+
+        // The default generators match the free functions
+        assert_eq!(default.commit_u64(value, blind), pedersen_commitment_u64(value, blind));
+
+        let commit = custom.commit_u64(value, blind);
+        assert!(custom.u64_opens(&commit, value, blind));
+        assert!(!default.u64_opens(&commit, value, blind));
+        assert!(!pedersen_commitment_u64_opens(&commit, value, blind));
+
+        let value = pallas::Base::from(42);
+        assert_eq!(default.commit_base(value, blind), pedersen_commitment_base(value, blind));
+
+        let commit = custom.commit_base(value, blind);
+        assert!(custom.base_opens(&commit, value, blind));
+        assert!(!default.base_opens(&commit, value, blind));
+        assert!(!pedersen_commitment_base_opens(&commit, value, blind));
+
+        // Homomorphism holds under custom generators too
+        let a_blind = ScalarBlind::from(11);
+        let b_blind = ScalarBlind::from(21);
+        assert_eq!(
+            custom.commit_u64(10, a_blind) + custom.commit_u64(20, b_blind),
+            custom.commit_u64(30, &a_blind + &b_blind)
+        );
+    }
 }
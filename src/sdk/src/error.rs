@@ -231,3 +231,40 @@ pub enum DarkTreeError {
     #[error("DarkTree max capacity has been exceeded")]
     MaxCapacityExceeded,
 }
+
+/// Main result type used by the keystore.
+#[cfg(feature = "keystore")]
+pub type KeystoreResult<T> = ResultGeneric<T, KeystoreError>;
+
+/// Errors returned when reading or writing an encrypted keystore.
+#[cfg(feature = "keystore")]
+#[derive(Debug, Clone, thiserror::Error)]
+pub enum KeystoreError {
+    #[error("Keystore IO error: {0}")]
+    Io(String),
+
+    #[error("Not a keystore file")]
+    InvalidMagic,
+
+    #[error("Unsupported keystore version: {0}")]
+    UnsupportedVersion(u8),
+
+    #[error("Keystore key derivation failed: {0}")]
+    KeyDerivation(String),
+
+    #[error("Keystore encryption failed")]
+    Encrypt,
+
+    #[error("Wrong passphrase or corrupted keystore")]
+    Decrypt,
+
+    #[error("Keystore contains a keypair with a mismatched public key")]
+    InvalidKeypair,
+}
+
+#[cfg(feature = "keystore")]
+impl From<std::io::Error> for KeystoreError {
+    fn from(err: std::io::Error) -> Self {
+        Self::Io(format!("{}", err))
+    }
+}
@@ -0,0 +1,226 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2024 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Lightweight logging spans, tagging every log line emitted while an
+//! instrumented future is polled with the span's fields.
+//!
+//! Spans only apply to the polls of the future they instrument, so tasks
+//! spawned from within it need to be instrumented themselves. Log lines
+//! are only tagged if the global logger is wrapped in a [`SpanLogger`].
+
+use std::{
+    cell::RefCell,
+    fmt::Display,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use log::{LevelFilter, Log, Metadata, Record, SetLoggerError};
+use pin_project_lite::pin_project;
+use smol::future::Future;
+
+thread_local! {
+    /// Spans entered on this thread, innermost last
+    static SPANS: RefCell<Vec<Arc<str>>> = const { RefCell::new(Vec::new()) };
+}
+
+/// A named set of fields attached to log lines, e.g. `channel{id=1}`
+#[derive(Clone, Debug)]
+pub struct LogSpan(Arc<str>);
+
+impl LogSpan {
+    /// Create a span called `name`, with the given fields
+    pub fn new(name: &str, fields: &[(&str, &dyn Display)]) -> Self {
+        let fields: Vec<String> =
+            fields.iter().map(|(key, value)| format!("{}={}", key, value)).collect();
+        Self(format!("{}{{{}}}", name, fields.join(" ")).into())
+    }
+
+    /// Enter the span until the returned guard is dropped
+    fn enter(&self) -> SpanGuard {
+        SPANS.with(|spans| spans.borrow_mut().push(self.0.clone()));
+        SpanGuard
+    }
+}
+
+impl Display for LogSpan {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// Exits the entered span on drop, so it's also exited if polling panics
+struct SpanGuard;
+
+impl Drop for SpanGuard {
+    fn drop(&mut self) {
+        SPANS.with(|spans| spans.borrow_mut().pop());
+    }
+}
+
+/// Returns the spans entered on this thread, outermost first and joined
+/// by `:`, or `None` if there are none.
+pub fn current_span() -> Option<String> {
+    SPANS.with(|spans| {
+        let spans = spans.borrow();
+        if spans.is_empty() {
+            return None
+        }
+        Some(spans.join(":"))
+    })
+}
+
+pin_project! {
+    /// A future entering a [`LogSpan`] whenever it's polled
+    pub struct Instrumented<F> {
+        #[pin]
+        future: F,
+        span: LogSpan,
+    }
+}
+
+impl<F: Future> Future for Instrumented<F> {
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let _guard = this.span.enter();
+        this.future.poll(cx)
+    }
+}
+
+/// Attach a [`LogSpan`] to a future
+pub trait Instrument: Future + Sized {
+    /// Enter `span` whenever the future is polled
+    fn instrument(self, span: LogSpan) -> Instrumented<Self> {
+        Instrumented { future: self, span }
+    }
+}
+
+impl<F: Future> Instrument for F {}
+
+/// Logger prefixing records with the current spans before passing them
+/// on to the wrapped logger.
+pub struct SpanLogger<L: Log> {
+    inner: L,
+}
+
+impl<L: Log> SpanLogger<L> {
+    /// Wrap `inner`, which keeps deciding what gets logged
+    pub fn new(inner: L) -> Self {
+        Self { inner }
+    }
+
+    /// Wrap `inner` and set it as the global logger
+    pub fn init(inner: L, max_level: LevelFilter) -> Result<(), SetLoggerError>
+    where
+        L: 'static,
+    {
+        log::set_boxed_logger(Box::new(Self::new(inner)))?;
+        log::set_max_level(max_level);
+        Ok(())
+    }
+}
+
+impl<L: Log> Log for SpanLogger<L> {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.inner.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        let Some(span) = current_span() else {
+            self.inner.log(record);
+            return
+        };
+
+        self.inner.log(
+            &Record::builder()
+                .args(format_args!("[{}] {}", span, record.args()))
+                .metadata(record.metadata().clone())
+                .module_path(record.module_path())
+                .file(record.file())
+                .line(record.line())
+                .build(),
+        );
+    }
+
+    fn flush(&self) {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use log::Level;
+
+    use super::*;
+
+    /// Logger keeping the message of every record
+    #[derive(Default)]
+    struct CaptureLogger(Mutex<Vec<String>>);
+
+    impl Log for CaptureLogger {
+        fn enabled(&self, _: &Metadata) -> bool {
+            true
+        }
+
+        fn log(&self, record: &Record) {
+            self.0.lock().unwrap().push(record.args().to_string());
+        }
+
+        fn flush(&self) {}
+    }
+
+    fn log_info(logger: &impl Log, msg: &str) {
+        logger.log(&Record::builder().args(format_args!("{}", msg)).level(Level::Info).build());
+    }
+
+    #[test]
+    fn log_span_tags_records() {
+        let capture = CaptureLogger::default();
+        let logger = SpanLogger::new(&capture);
+
+        let outer = LogSpan::new("channel", &[("id", &42), ("addr", &"tcp://127.0.0.1:26661")]);
+        let inner = LogSpan::new("handshake", &[]);
+
+        smol::block_on(
+            async {
+                log_info(&logger, "outer");
+                async { log_info(&logger, "inner") }.instrument(inner).await;
+                smol::future::yield_now().await;
+                log_info(&logger, "after yield");
+            }
+            .instrument(outer),
+        );
+        log_info(&logger, "untagged");
+
+        assert_eq!(
+            *capture.0.lock().unwrap(),
+            vec![
+                "[channel{id=42 addr=tcp://127.0.0.1:26661}] outer",
+                "[channel{id=42 addr=tcp://127.0.0.1:26661}:handshake{}] inner",
+                "[channel{id=42 addr=tcp://127.0.0.1:26661}] after yield",
+                "untagged",
+            ]
+        );
+        assert!(current_span().is_none());
+    }
+}
@@ -37,6 +37,10 @@ pub use publisher::{Publisher, PublisherPtr, Subscription};
 pub mod timeout;
 pub use timeout::io_timeout;
 
+/// Logging spans tagging the log lines of a future
+pub mod log_span;
+pub use log_span::{Instrument, LogSpan};
+
 pub type ExecutorPtr = Arc<Executor<'static>>;
 
 /// Sleep for any number of seconds.
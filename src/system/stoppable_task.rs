@@ -37,6 +37,9 @@ pub struct StoppableTask {
 
     /// Used so we can keep StoppableTask in HashMap/HashSet
     pub task_id: u32,
+    /// Human readable label identifying the task in logs and diagnostics.
+    /// Defaults to the `task_id`.
+    name: String,
 }
 
 /// A task that can be prematurely stopped at any time.
@@ -53,8 +56,25 @@ pub struct StoppableTask {
 ///
 /// Then at any time we can call `task.stop()` to close the task.
 impl StoppableTask {
+    /// Create a task named after its `task_id`
     pub fn new() -> Arc<Self> {
-        Arc::new(Self { signal: CondVar::new(), barrier: CondVar::new(), task_id: OsRng.gen() })
+        let task_id = OsRng.gen();
+        Self::with_name(task_id, task_id.to_string())
+    }
+
+    /// Same as `new()`, but labels the task with the given name so it
+    /// can be identified in logs and task dumps.
+    pub fn new_named(name: String) -> Arc<Self> {
+        Self::with_name(OsRng.gen(), name)
+    }
+
+    fn with_name(task_id: u32, name: String) -> Arc<Self> {
+        Arc::new(Self { signal: CondVar::new(), barrier: CondVar::new(), task_id, name })
+    }
+
+    /// Returns the name this task was labeled with
+    pub fn name(&self) -> &str {
+        &self.name
     }
 
     /// Starts the task.
@@ -75,7 +95,7 @@ impl StoppableTask {
         Error: std::error::Error + Send + 'a,
     {
         // NOTE: we could send the error code from stop() instead of having it specified in start()
-        trace!(target: "system::StoppableTask", "Starting task {}", self.task_id);
+        trace!(target: "system::StoppableTask", "Starting task {}", self.name);
         // Allow stopping and starting task again.
         // NOTE: maybe we should disallow this with a panic?
         self.signal.reset();
@@ -89,7 +109,7 @@ impl StoppableTask {
                     trace!(
                         target: "system::StoppableTask",
                         "Stop signal received for task {}",
-                        self.task_id
+                        self.name,
                     );
                     Err(stop_value)
                 };
@@ -100,8 +120,7 @@ impl StoppableTask {
                 trace!(
                     target: "system::StoppableTask",
                     "Closing task {} with result: {:?}",
-                    self.task_id,
-                    result
+                    self.name, result,
                 );
 
                 stop_handler(result).await;
@@ -114,16 +133,16 @@ impl StoppableTask {
     /// Stops the task. On completion, guarantees the process has stopped.
     /// Can be called multiple times. After the first call, this does nothing.
     pub async fn stop(&self) {
-        trace!(target: "system::StoppableTask", "Stopping task {}", self.task_id);
+        trace!(target: "system::StoppableTask", "Stopping task {}", self.name);
         self.signal.notify();
         self.barrier.wait().await;
-        trace!(target: "system::StoppableTask", "Stopped task {}", self.task_id);
+        trace!(target: "system::StoppableTask", "Stopped task {}", self.name);
     }
 
     /// Sends a stop signal and returns immediately. Doesn't guarantee the task
     /// stopped on completion.
     pub fn stop_nowait(&self) {
-        trace!(target: "system::StoppableTask", "Stopping task (nowait) {}", self.task_id);
+        trace!(target: "system::StoppableTask", "Stopping task (nowait) {}", self.name);
         self.signal.notify();
     }
 }
@@ -198,4 +217,14 @@ mod tests {
             task.stop().await;
         }))
     }
+
+    #[test]
+    fn stoppable_task_names() {
+        // Unnamed tasks go by their id, like in the logs
+        let task = StoppableTask::new();
+        assert_eq!(task.name(), task.task_id.to_string());
+
+        let task = StoppableTask::new_named("channel-1".to_string());
+        assert_eq!(task.name(), "channel-1");
+    }
 }
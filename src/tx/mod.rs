@@ -38,7 +38,7 @@ use log::{debug, error};
 
 use crate::{
     error::TxVerifyFailed,
-    zk::{proof::VerifyingKey, Proof},
+    zk::{proof::VerifyingKey, Proof, VerificationCache, VerificationLimit},
     Error, Result,
 };
 
@@ -67,10 +67,16 @@ pub struct Transaction {
 
 impl Transaction {
     /// Verify ZK proofs for the entire transaction.
+    /// Results are kept in the given [`VerificationCache`], so proofs
+    /// that were already verified aren't verified again.
+    /// Proofs that aren't cached are verified on the blocking thread pool,
+    /// at most as many at once as the given [`VerificationLimit`] allows.
     pub async fn verify_zkps(
         &self,
         verifying_keys: &HashMap<[u8; 32], HashMap<String, VerifyingKey>>,
         zkp_table: Vec<Vec<(String, Vec<pallas::Base>)>>,
+        verification_cache: &VerificationCache,
+        verification_limit: &VerificationLimit,
     ) -> Result<()> {
         // TODO: Are we sure we should assert here?
         assert_eq!(self.calls.len(), self.proofs.len());
@@ -92,7 +98,9 @@ impl Transaction {
                 if let Some(vk) = contract_map.get(zk_ns) {
                     // We have a verifying key for this
                     debug!(target: "tx::verify_zkps", "[TX] public inputs: {:#?}", public_vals);
-                    if let Err(e) = proof.verify(vk, public_vals) {
+                    if let Err(e) =
+                        verification_cache.verify(proof, vk, public_vals, verification_limit).await
+                    {
                         error!(
                             target: "tx::verify_zkps",
                             "[TX] Failed verifying {}::{} ZK proof: {:#?}",
@@ -218,7 +226,7 @@ impl std::fmt::Debug for Transaction {
 use crate::net::Message;
 
 #[cfg(feature = "net")]
-crate::impl_p2p_message!(Transaction, "tx");
+crate::impl_p2p_message!(Transaction, "tx", dedup);
 
 /// Calls tree bounds definitions
 // TODO: increase min to 2 when fees are implement
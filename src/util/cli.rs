@@ -192,10 +192,16 @@ macro_rules! async_daemonize {
                     let log_path = darkfi::util::path::expand_path(log_path)?;
                     let log_file = std::fs::File::create(log_path)?;
                     let write_logger = simplelog::WriteLogger::new(log_level, log_config, log_file);
-                    simplelog::CombinedLogger::init(vec![term_logger, write_logger])?;
+                    darkfi::system::log_span::SpanLogger::init(
+                        simplelog::CombinedLogger::new(vec![term_logger, write_logger]),
+                        log_level,
+                    )?;
                 }
                 None => {
-                    simplelog::CombinedLogger::init(vec![term_logger])?;
+                    darkfi::system::log_span::SpanLogger::init(
+                        simplelog::CombinedLogger::new(vec![term_logger]),
+                        log_level,
+                    )?;
                 }
             }
 
@@ -36,7 +36,7 @@ use crate::{
         utils::{best_fork_index, block_rank, find_extended_fork_index},
         verification::{verify_proposal, verify_transaction},
     },
-    zk::VerifyingKey,
+    zk::{VerificationCache, VerificationLimit, VerifyingKey},
     Error, Result,
 };
 
@@ -100,7 +100,13 @@ impl Consensus {
 
     /// Given a proposal, the node verifys it and finds which fork it extends.
     /// If the proposal extends the canonical blockchain, a new fork chain is created.
-    pub async fn append_proposal(&self, proposal: &Proposal, verify_fees: bool) -> Result<()> {
+    pub async fn append_proposal(
+        &self,
+        proposal: &Proposal,
+        verify_fees: bool,
+        verification_cache: &VerificationCache,
+        verification_limit: &VerificationLimit,
+    ) -> Result<()> {
         debug!(target: "validator::consensus::append_proposal", "Appending proposal {}", proposal.hash);
 
         // Check if proposal already exists
@@ -117,7 +123,9 @@ impl Consensus {
         drop(lock);
 
         // Verify proposal and grab corresponding fork
-        let (mut fork, index) = verify_proposal(self, proposal, verify_fees).await?;
+        let (mut fork, index) =
+            verify_proposal(self, proposal, verify_fees, verification_cache, verification_limit)
+                .await?;
 
         // Append proposal to the fork
         fork.append_proposal(proposal).await?;
@@ -696,6 +704,8 @@ impl Fork {
         verifying_block_height: u32,
         block_target: u32,
         verify_fees: bool,
+        verification_cache: &VerificationCache,
+        verification_limit: &VerificationLimit,
     ) -> Result<(Vec<Transaction>, u64, u64)> {
         // Check if our mempool is not empty
         if self.mempool.is_empty() {
@@ -745,6 +755,8 @@ impl Fork {
                 &mut tree,
                 &mut vks,
                 verify_fees,
+                verification_cache,
+                verification_limit,
             )
             .await
             {
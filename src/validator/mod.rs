@@ -31,7 +31,7 @@ use crate::{
     },
     error::TxVerifyFailed,
     tx::Transaction,
-    zk::VerifyingKey,
+    zk::{VerificationCache, VerificationLimit, VerifyingKey},
     Error, Result,
 };
 
@@ -70,6 +70,10 @@ pub struct ValidatorConfig {
     pub genesis_block: BlockInfo,
     /// Flag to enable tx fee verification
     pub verify_fees: bool,
+    /// Amount of ZK proof verification results to cache, 0 disables it
+    pub verification_cache_size: usize,
+    /// Amount of ZK proofs verified concurrently, 0 disables the limit
+    pub max_concurrent_verifications: usize,
 }
 
 /// Atomic pointer to validator.
@@ -85,6 +89,10 @@ pub struct Validator {
     pub synced: RwLock<bool>,
     /// Flag to enable tx fee verification
     pub verify_fees: bool,
+    /// Cached ZK proof verification results
+    pub verification_cache: VerificationCache,
+    /// Bound on the amount of ZK proofs verified concurrently
+    pub verification_limit: VerificationLimit,
 }
 
 impl Validator {
@@ -94,6 +102,9 @@ impl Validator {
         info!(target: "validator::new", "Initializing Blockchain");
         let blockchain = Blockchain::new(db)?;
 
+        let verification_cache = VerificationCache::new(config.verification_cache_size);
+        let verification_limit = VerificationLimit::new(config.max_concurrent_verifications);
+
         // Create an overlay over whole blockchain so we can write stuff
         let overlay = BlockchainOverlay::new(&blockchain)?;
 
@@ -103,7 +114,14 @@ impl Validator {
         // Add genesis block if blockchain is empty
         if blockchain.genesis().is_err() {
             info!(target: "validator::new", "Appending genesis block");
-            verify_genesis_block(&overlay, &config.genesis_block, config.pow_target).await?;
+            verify_genesis_block(
+                &overlay,
+                &config.genesis_block,
+                config.pow_target,
+                &verification_cache,
+                &verification_limit,
+            )
+            .await?;
         };
 
         // Write the changes to the actual chain db
@@ -123,6 +141,8 @@ impl Validator {
             consensus,
             synced: RwLock::new(false),
             verify_fees: config.verify_fees,
+            verification_cache,
+            verification_limit,
         });
 
         info!(target: "validator::new", "Finished initializing validator");
@@ -157,6 +177,8 @@ impl Validator {
             &mut MerkleTree::new(1),
             &mut vks,
             verify_fee,
+            &self.verification_cache,
+            &self.verification_limit,
         )
         .await;
 
@@ -204,6 +226,8 @@ impl Validator {
                 &tx_vec,
                 &mut MerkleTree::new(1),
                 self.verify_fees,
+                &self.verification_cache,
+                &self.verification_limit,
             )
             .await;
 
@@ -278,6 +302,8 @@ impl Validator {
                     &tx_vec,
                     &mut MerkleTree::new(1),
                     self.verify_fees,
+                    &self.verification_cache,
+                    &self.verification_limit,
                 )
                 .await;
 
@@ -323,7 +349,15 @@ impl Validator {
         let append_lock = self.consensus.append_lock.write().await;
 
         // Execute append
-        let result = self.consensus.append_proposal(proposal, self.verify_fees).await;
+        let result = self
+            .consensus
+            .append_proposal(
+                proposal,
+                self.verify_fees,
+                &self.verification_cache,
+                &self.verification_limit,
+            )
+            .await;
 
         // Release append lock
         drop(append_lock);
@@ -518,7 +552,17 @@ impl Validator {
         // Validate and insert each block
         for block in blocks {
             // Verify block
-            match verify_block(&overlay, &module, block, previous, self.verify_fees).await {
+            match verify_block(
+                &overlay,
+                &module,
+                block,
+                previous,
+                self.verify_fees,
+                &self.verification_cache,
+                &self.verification_limit,
+            )
+            .await
+            {
                 Ok(()) => { /* Do nothing */ }
                 // Skip already existing block
                 Err(Error::BlockAlreadyExists(_)) => {
@@ -609,6 +653,8 @@ impl Validator {
             txs,
             &mut MerkleTree::new(1),
             verify_fees,
+            &self.verification_cache,
+            &self.verification_limit,
         )
         .await;
 
@@ -656,6 +702,8 @@ impl Validator {
             block_target,
             tx,
             &mut MerkleTree::new(1),
+            &self.verification_cache,
+            &self.verification_limit,
         )
         .await
         {
@@ -709,7 +757,14 @@ impl Validator {
         deploy_native_contracts(&overlay, pow_target).await?;
 
         // Validate genesis block
-        verify_genesis_block(&overlay, previous, pow_target).await?;
+        verify_genesis_block(
+            &overlay,
+            previous,
+            pow_target,
+            &self.verification_cache,
+            &self.verification_limit,
+        )
+        .await?;
 
         // Write the changes to the in memory db
         overlay.lock().unwrap().overlay.lock().unwrap().apply()?;
@@ -720,7 +775,18 @@ impl Validator {
         // Validate and insert each block
         for block in &blocks[1..] {
             // Verify block
-            if verify_block(&overlay, &module, block, previous, self.verify_fees).await.is_err() {
+            if verify_block(
+                &overlay,
+                &module,
+                block,
+                previous,
+                self.verify_fees,
+                &self.verification_cache,
+                &self.verification_limit,
+            )
+            .await
+            .is_err()
+            {
                 error!(target: "validator::validate_blockchain", "Erroneous block found in set");
                 overlay.lock().unwrap().overlay.lock().unwrap().purge_new_trees()?;
                 return Err(Error::BlockIsInvalid(block.hash().as_string()))
@@ -46,7 +46,7 @@ use crate::{
         fees::{circuit_gas_use, PALLAS_SCHNORR_SIGNATURE_FEE},
         pow::PoWModule,
     },
-    zk::VerifyingKey,
+    zk::{VerificationCache, VerificationLimit, VerifyingKey},
     Error, Result,
 };
 
@@ -55,6 +55,8 @@ pub async fn verify_genesis_block(
     overlay: &BlockchainOverlayPtr,
     block: &BlockInfo,
     block_target: u32,
+    verification_cache: &VerificationCache,
+    verification_limit: &VerificationLimit,
 ) -> Result<()> {
     let block_hash = block.hash().as_string();
     debug!(target: "validator::verification::verify_genesis_block", "Validating genesis block {}", block_hash);
@@ -90,8 +92,17 @@ pub async fn verify_genesis_block(
     // Genesis block doesn't check for fees
     let mut tree = MerkleTree::new(1);
     let txs = &block.txs[..block.txs.len() - 1];
-    if let Err(e) =
-        verify_transactions(overlay, block.header.height, block_target, txs, &mut tree, false).await
+    if let Err(e) = verify_transactions(
+        overlay,
+        block.header.height,
+        block_target,
+        txs,
+        &mut tree,
+        false,
+        verification_cache,
+        verification_limit,
+    )
+    .await
     {
         warn!(
             target: "validator::verification::verify_genesis_block",
@@ -181,6 +192,8 @@ pub async fn verify_block(
     block: &BlockInfo,
     previous: &BlockInfo,
     verify_fees: bool,
+    verification_cache: &VerificationCache,
+    verification_limit: &VerificationLimit,
 ) -> Result<()> {
     let block_hash = block.hash();
     debug!(target: "validator::verification::verify_block", "Validating block {}", block_hash);
@@ -208,6 +221,8 @@ pub async fn verify_block(
         txs,
         &mut tree,
         verify_fees,
+        verification_cache,
+        verification_limit,
     )
     .await;
     if let Err(e) = e {
@@ -226,6 +241,8 @@ pub async fn verify_block(
         module.target,
         block.txs.last().unwrap(),
         &mut tree,
+        verification_cache,
+        verification_limit,
     )
     .await?;
 
@@ -332,6 +349,8 @@ pub async fn verify_producer_transaction(
     block_target: u32,
     tx: &Transaction,
     tree: &mut MerkleTree,
+    verification_cache: &VerificationCache,
+    verification_limit: &VerificationLimit,
 ) -> Result<PublicKey> {
     let tx_hash = tx.hash();
     debug!(target: "validator::verification::verify_producer_transaction", "Validating producer transaction {}", tx_hash);
@@ -447,7 +466,9 @@ pub async fn verify_producer_transaction(
     debug!(target: "validator::verification::verify_producer_transaction", "Signature verification successful");
 
     debug!(target: "validator::verification::verify_producer_transaction", "Verifying ZK proofs for transaction {}", tx_hash);
-    if let Err(e) = tx.verify_zkps(&verifying_keys, zkp_table).await {
+    if let Err(e) =
+        tx.verify_zkps(&verifying_keys, zkp_table, verification_cache, verification_limit).await
+    {
         error!(target: "validator::verification::verify_producer_transaction", "ZK proof verification for tx {} failed: {}", tx_hash, e);
         return Err(TxVerifyFailed::InvalidZkProof.into())
     }
@@ -539,6 +560,7 @@ async fn apply_producer_transaction(
 /// Verify WASM execution, signatures, and ZK proofs for a given [`Transaction`],
 /// and apply it to the provided overlay. Additionally, append its hash to the
 /// provided Merkle tree.
+#[allow(clippy::too_many_arguments)]
 pub async fn verify_transaction(
     overlay: &BlockchainOverlayPtr,
     verifying_block_height: u32,
@@ -547,6 +569,8 @@ pub async fn verify_transaction(
     tree: &mut MerkleTree,
     verifying_keys: &mut HashMap<[u8; 32], HashMap<String, VerifyingKey>>,
     verify_fee: bool,
+    verification_cache: &VerificationCache,
+    verification_limit: &VerificationLimit,
 ) -> Result<(u64, u64)> {
     let tx_hash = tx.hash();
     debug!(target: "validator::verification::verify_transaction", "Validating transaction {}", tx_hash);
@@ -787,7 +811,9 @@ pub async fn verify_transaction(
     debug!(target: "validator::verification::verify_transaction", "Signature verification successful");
 
     debug!(target: "validator::verification::verify_transaction", "Verifying ZK proofs for transaction {}", tx_hash);
-    if let Err(e) = tx.verify_zkps(verifying_keys, zkp_table).await {
+    if let Err(e) =
+        tx.verify_zkps(verifying_keys, zkp_table, verification_cache, verification_limit).await
+    {
         error!(
             target: "validator::verification::verify_transaction",
             "[VALIDATOR] ZK proof verification for tx {} failed: {}", tx_hash, e,
@@ -891,6 +917,8 @@ pub async fn verify_transactions(
     txs: &[Transaction],
     tree: &mut MerkleTree,
     verify_fees: bool,
+    verification_cache: &VerificationCache,
+    verification_limit: &VerificationLimit,
 ) -> Result<(u64, u64)> {
     debug!(target: "validator::verification::verify_transactions", "Verifying {} transactions", txs.len());
     if txs.is_empty() {
@@ -925,6 +953,8 @@ pub async fn verify_transactions(
             tree,
             &mut vks,
             verify_fees,
+            verification_cache,
+            verification_limit,
         )
         .await
         {
@@ -1008,6 +1038,8 @@ pub async fn verify_proposal(
     consensus: &Consensus,
     proposal: &Proposal,
     verify_fees: bool,
+    verification_cache: &VerificationCache,
+    verification_limit: &VerificationLimit,
 ) -> Result<(Fork, Option<usize>)> {
     // Check if proposal hash matches actual one (1)
     let proposal_hash = proposal.block.hash();
@@ -1026,9 +1058,17 @@ pub async fn verify_proposal(
     let previous = fork.overlay.lock().unwrap().last_block()?;
 
     // Verify proposal block (3)
-    if verify_block(&fork.overlay, &fork.module, &proposal.block, &previous, verify_fees)
-        .await
-        .is_err()
+    if verify_block(
+        &fork.overlay,
+        &fork.module,
+        &proposal.block,
+        &previous,
+        verify_fees,
+        verification_cache,
+        verification_limit,
+    )
+    .await
+    .is_err()
     {
         error!(target: "validator::verification::verify_pow_proposal", "Erroneous proposal block found");
         fork.overlay.lock().unwrap().overlay.lock().unwrap().purge_new_trees()?;
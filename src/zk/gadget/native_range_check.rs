@@ -35,6 +35,13 @@ pub struct NativeRangeCheckConfig<const WINDOW_SIZE: usize, const NUM_BITS: usiz
     pub k_values_table: TableColumn,
 }
 
+/// Proves a value fits in `NUM_BITS` bits by decomposing it into
+/// `WINDOW_SIZE`-bit chunks looked up in a shared table.
+///
+/// To embed it in a larger circuit, call `configure()` with a dedicated
+/// advice column and a table column, `construct()` the chip during
+/// synthesis, and load the table once with `load_k_table()`. Any number
+/// of chips with the same `WINDOW_SIZE` can share one table column.
 #[derive(Clone, Debug)]
 pub struct NativeRangeCheckChip<const WINDOW_SIZE: usize, const NUM_BITS: usize> {
     config: NativeRangeCheckConfig<WINDOW_SIZE, NUM_BITS>,
@@ -407,6 +414,68 @@ mod tests {
         };
     }
 
+    /// Two range checks of different widths sharing one lookup table
+    #[derive(Default)]
+    struct SharedTableCircuit {
+        a: Value<pallas::Base>,
+        b: Value<pallas::Base>,
+    }
+
+    impl Circuit<pallas::Base> for SharedTableCircuit {
+        type Config = (NativeRangeCheckConfig<3, 64>, NativeRangeCheckConfig<3, 253>);
+        type FloorPlanner = floor_planner::V1;
+        type Params = ();
+
+        fn without_witnesses(&self) -> Self {
+            Self::default()
+        }
+
+        fn configure(meta: &mut ConstraintSystem<pallas::Base>) -> Self::Config {
+            let z_64 = meta.advice_column();
+            let z_253 = meta.advice_column();
+            let table_column = meta.lookup_table_column();
+
+            let constants = meta.fixed_column();
+            meta.enable_constant(constants);
+            (
+                NativeRangeCheckChip::<3, 64>::configure(meta, z_64, table_column),
+                NativeRangeCheckChip::<3, 253>::configure(meta, z_253, table_column),
+            )
+        }
+
+        fn synthesize(
+            &self,
+            config: Self::Config,
+            mut layouter: impl Layouter<pallas::Base>,
+        ) -> Result<(), plonk::Error> {
+            // The table is loaded once, for both chips
+            NativeRangeCheckChip::<3, 64>::load_k_table(&mut layouter, config.0.k_values_table)?;
+
+            let chip_64 = NativeRangeCheckChip::<3, 64>::construct(config.0);
+            let chip_253 = NativeRangeCheckChip::<3, 253>::construct(config.1);
+            chip_64.witness_range_check(layouter.namespace(|| "64-bit check"), self.a)?;
+            chip_253.witness_range_check(layouter.namespace(|| "253-bit check"), self.b)?;
+
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn native_range_check_shared_table() {
+        let k = 8;
+        let a = pallas::Base::from(u64::MAX);
+        let b = pallas::Base::from_u128(u128::MAX);
+
+        let circuit = SharedTableCircuit { a: Value::known(a), b: Value::known(b) };
+        MockProver::run(k, &circuit, vec![]).unwrap().assert_satisfied();
+
+        // Either decomposition failing is caught
+        let circuit = SharedTableCircuit { a: Value::known(b), b: Value::known(b) };
+        assert!(MockProver::run(k, &circuit, vec![]).unwrap().verify().is_err());
+        let circuit = SharedTableCircuit { a: Value::known(a), b: Value::known(-a) };
+        assert!(MockProver::run(k, &circuit, vec![]).unwrap().verify().is_err());
+    }
+
     // cargo test --release --all-features --lib native_range_check -- --nocapture
     #[test]
     fn native_range_check_2() {
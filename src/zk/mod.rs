@@ -29,7 +29,20 @@ pub mod gadget;
 
 /// Proof creation API
 pub mod proof;
-pub use proof::{Proof, ProvingKey, VerifyingKey};
+pub use proof::{key_param_hash, Proof, ProvingKey, VerifyingKey};
+
+/// Cache of proof verification results, so proofs seen more than once
+/// (e.g. in the mempool and then in a block) are only verified once
+pub mod verification_cache;
+pub use verification_cache::VerificationCache;
+
+/// Bound on the amount of proofs verified concurrently
+pub mod verification_limit;
+pub use verification_limit::VerificationLimit;
+
+/// Circuit size measurement
+pub mod stats;
+pub use stats::{circuit_stats, CircuitStats};
 
 /// Trace computation of intermediate values in circuit
 mod tracer;
@@ -23,6 +23,7 @@ use darkfi_serial::async_trait;
 use darkfi_sdk::pasta::{pallas, vesta};
 use darkfi_serial::{SerialDecodable, SerialEncodable};
 use halo2_proofs::{
+    dev::CircuitCost,
     helpers::SerdeFormat,
     plonk,
     plonk::{Circuit, SingleVerifier},
@@ -31,17 +32,64 @@ use halo2_proofs::{
 };
 use rand::RngCore;
 
+/// Length of the parameter hash header prepended by `to_bytes()`
+pub const KEY_PARAM_HASH_LEN: usize = 32;
+
+/// Hash identifying what a proving or verifying key was built for: the
+/// circuit size `k` and a description of the circuit, usually its zkas
+/// bincode. Keys cached with `to_bytes()` only load if this matches.
+pub fn key_param_hash(k: u32, circuit_id: &[u8]) -> [u8; KEY_PARAM_HASH_LEN] {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(b"darkfi:zk:key_param_hash");
+    hasher.update(&k.to_le_bytes());
+    hasher.update(circuit_id);
+    *hasher.finalize().as_bytes()
+}
+
+/// Strip the parameter hash header off `bytes`, rejecting them unless
+/// it matches `param_hash`.
+fn check_param_hash<'a>(
+    bytes: &'a [u8],
+    param_hash: &[u8; KEY_PARAM_HASH_LEN],
+) -> io::Result<&'a [u8]> {
+    if bytes.len() < KEY_PARAM_HASH_LEN {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Missing parameter hash"))
+    }
+
+    let (header, key) = bytes.split_at(KEY_PARAM_HASH_LEN);
+    if header != param_hash {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "Parameter hash mismatch"))
+    }
+
+    Ok(key)
+}
+
+/// Hash of a serialized `plonk::VerifyingKey`
+fn vk_hash(vk_bytes: &[u8]) -> [u8; 32] {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(b"darkfi:zk:vk_hash");
+    hasher.update(vk_bytes);
+    *hasher.finalize().as_bytes()
+}
+
 #[derive(Clone, Debug)]
 pub struct VerifyingKey {
     pub params: Params<vesta::Affine>,
     pub vk: plonk::VerifyingKey<vesta::Affine>,
+    /// Hash of the serialized `vk`, computed once when the key is built or read
+    hash: [u8; 32],
 }
 
 impl VerifyingKey {
     pub fn build(k: u32, c: &impl Circuit<pallas::Base>) -> Self {
         let params = Params::new(k);
         let vk = plonk::keygen_vk(&params, c).unwrap();
-        VerifyingKey { params, vk }
+
+        let mut vk_bytes = vec![];
+        vk.write(&mut vk_bytes, SerdeFormat::RawBytes).expect("writing to a Vec can't fail");
+        let hash = vk_hash(&vk_bytes);
+
+        VerifyingKey { params, vk, hash }
     }
 
     pub fn write<W: io::Write>(&self, writer: &mut W) -> io::Result<()> {
@@ -87,6 +135,7 @@ impl VerifyingKey {
         let mut params_c = Cursor::new(params_buf);
         let params: Params<vesta::Affine> = Params::read(&mut params_c)?;
 
+        let hash = vk_hash(&vk_buf);
         let mut vk_c = Cursor::new(vk_buf);
         let vk: plonk::VerifyingKey<vesta::Affine> =
             plonk::VerifyingKey::read::<Cursor<Vec<u8>>, ConcreteCircuit>(
@@ -95,7 +144,39 @@ impl VerifyingKey {
                 circuit.params(),
             )?;
 
-        Ok(Self { params, vk })
+        Ok(Self { params, vk, hash })
+    }
+
+    /// Hash of the serialized verifying key, identifying the circuit it
+    /// verifies proofs for
+    pub fn hash(&self) -> &[u8; 32] {
+        &self.hash
+    }
+
+    /// Size in bytes of the key as serialized by `write()`
+    pub fn serialized_size(&self) -> usize {
+        let mut buf = vec![];
+        self.write(&mut buf).expect("writing to a Vec can't fail");
+        buf.len()
+    }
+
+    /// Serialize the key for caching, prefixed with `param_hash`
+    /// (see [`key_param_hash()`]).
+    pub fn to_bytes(&self, param_hash: &[u8; KEY_PARAM_HASH_LEN]) -> io::Result<Vec<u8>> {
+        let mut buf = param_hash.to_vec();
+        self.write(&mut buf)?;
+        Ok(buf)
+    }
+
+    /// Load a key serialized with `to_bytes()`. Fails with
+    /// `InvalidData` if it was built for a different `param_hash`.
+    pub fn from_bytes<ConcreteCircuit: Circuit<pallas::Base>>(
+        bytes: &[u8],
+        param_hash: &[u8; KEY_PARAM_HASH_LEN],
+        circuit: ConcreteCircuit,
+    ) -> io::Result<Self> {
+        let mut key = check_param_hash(bytes, param_hash)?;
+        Self::read(&mut key, circuit)
     }
 }
 
@@ -163,6 +244,32 @@ impl ProvingKey {
 
         Ok(Self { params, pk })
     }
+
+    /// Size in bytes of the key as serialized by `write()`
+    pub fn serialized_size(&self) -> usize {
+        let mut buf = vec![];
+        self.write(&mut buf).expect("writing to a Vec can't fail");
+        buf.len()
+    }
+
+    /// Serialize the key for caching, prefixed with `param_hash`
+    /// (see [`key_param_hash()`]).
+    pub fn to_bytes(&self, param_hash: &[u8; KEY_PARAM_HASH_LEN]) -> io::Result<Vec<u8>> {
+        let mut buf = param_hash.to_vec();
+        self.write(&mut buf)?;
+        Ok(buf)
+    }
+
+    /// Load a key serialized with `to_bytes()`. Fails with
+    /// `InvalidData` if it was built for a different `param_hash`.
+    pub fn from_bytes<ConcreteCircuit: Circuit<pallas::Base>>(
+        bytes: &[u8],
+        param_hash: &[u8; KEY_PARAM_HASH_LEN],
+        circuit: ConcreteCircuit,
+    ) -> io::Result<Self> {
+        let mut key = check_param_hash(bytes, param_hash)?;
+        Self::read(&mut key, circuit)
+    }
 }
 
 #[derive(Clone, Default, PartialEq, Eq, SerialEncodable, SerialDecodable)]
@@ -181,34 +288,73 @@ impl core::fmt::Debug for Proof {
 }
 
 impl Proof {
+    /// Size in bytes of a proof of `circuit` with `2^k` rows, as returned
+    /// by `as_ref()` (serializing the proof adds a length prefix). The size
+    /// only depends on the circuit layout, so it can be used for fee and
+    /// storage planning without creating a proof. `circuit` can be built
+    /// from empty witnesses.
+    pub fn expected_size(k: u32, circuit: &impl Circuit<pallas::Base>) -> usize {
+        CircuitCost::<vesta::Point, _>::measure(k, circuit).proof_size(1).into()
+    }
+
     pub fn create(
         pk: &ProvingKey,
         circuits: &[impl Circuit<pallas::Base>],
         instances: &[pallas::Base],
+        rng: impl RngCore,
+    ) -> std::result::Result<Self, plonk::Error> {
+        Self::create_batch(pk, circuits, &[instances], rng)
+    }
+
+    /// Verify the proof against a raw public input vector, in the order
+    /// the circuit constrains them. Callers receiving the public inputs
+    /// over the wire can pass them as-is, without rebuilding the revealed
+    /// values struct they were derived from.
+    pub fn verify(
+        &self,
+        vk: &VerifyingKey,
+        instances: &[pallas::Base],
+    ) -> std::result::Result<(), plonk::Error> {
+        self.verify_batch(vk, &[instances])
+    }
+
+    /// Create a single proof covering several circuits built from the
+    /// same zkas code, e.g. all outputs of a transfer.
+    ///
+    /// `instances[i]` holds the public inputs of `circuits[i]`, and the
+    /// proof only verifies if `verify_batch()` is given them in the same
+    /// order. A proof is bound to one proving key, so circuits of
+    /// different types (e.g. mint and burn) still need separate proofs.
+    pub fn create_batch(
+        pk: &ProvingKey,
+        circuits: &[impl Circuit<pallas::Base>],
+        instances: &[&[pallas::Base]],
         mut rng: impl RngCore,
     ) -> std::result::Result<Self, plonk::Error> {
+        // Our circuits have a single instance column
+        let instances: Vec<[&[pallas::Base]; 1]> = instances.iter().map(|i| [*i]).collect();
+        let instances: Vec<&[&[pallas::Base]]> = instances.iter().map(|i| &i[..]).collect();
+
         let mut transcript = Blake2bWrite::<_, vesta::Affine, _>::init(vec![]);
-        plonk::create_proof(
-            &pk.params,
-            &pk.pk,
-            circuits,
-            &[&[instances]],
-            &mut rng,
-            &mut transcript,
-        )?;
+        plonk::create_proof(&pk.params, &pk.pk, circuits, &instances, &mut rng, &mut transcript)?;
 
         Ok(Proof(transcript.finalize()))
     }
 
-    pub fn verify(
+    /// Verify a proof created with `create_batch()`, with the public
+    /// inputs of each circuit in the order they were proven.
+    pub fn verify_batch(
         &self,
         vk: &VerifyingKey,
-        instances: &[pallas::Base],
+        instances: &[&[pallas::Base]],
     ) -> std::result::Result<(), plonk::Error> {
+        let instances: Vec<[&[pallas::Base]; 1]> = instances.iter().map(|i| [*i]).collect();
+        let instances: Vec<&[&[pallas::Base]]> = instances.iter().map(|i| &i[..]).collect();
+
         let strategy = SingleVerifier::new(&vk.params);
         let mut transcript = Blake2bRead::init(&self.0[..]);
 
-        plonk::verify_proof(&vk.params, &vk.vk, strategy, &[&[instances]], &mut transcript)
+        plonk::verify_proof(&vk.params, &vk.vk, strategy, &instances, &mut transcript)
     }
 
     pub fn new(bytes: Vec<u8>) -> Self {
@@ -0,0 +1,172 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2024 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use darkfi_sdk::pasta::pallas;
+use halo2_proofs::{
+    circuit::Value,
+    plonk::{
+        Advice, Any, Assigned, Assignment, Circuit, Column, ConstraintSystem, Error as PlonkError,
+        Fixed, FloorPlanner, Instance, Selector,
+    },
+};
+
+use super::{empty_witnesses, ZkCircuit};
+use crate::{zkas::ZkBinary, Result};
+
+/// Size of a zkas circuit, as laid out by the halo2 floor planner
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CircuitStats {
+    /// Amount of advice columns used by the circuit
+    pub advice_columns: usize,
+    /// Amount of rows assigned by the circuit, excluding blinding rows
+    pub rows_used: usize,
+    /// Smallest `k` (with `2^k` rows) the circuit can be proven with
+    pub k: u32,
+}
+
+/// Assignment backend which only records the highest row touched
+#[derive(Default)]
+struct RowCounter {
+    rows: usize,
+}
+
+impl RowCounter {
+    fn touch(&mut self, row: usize) {
+        self.rows = self.rows.max(row + 1);
+    }
+}
+
+impl Assignment<pallas::Base> for RowCounter {
+    fn enter_region<NR, N>(&mut self, _: N)
+    where
+        NR: Into<String>,
+        N: FnOnce() -> NR,
+    {
+    }
+
+    fn exit_region(&mut self) {}
+
+    fn enable_selector<A, AR>(
+        &mut self,
+        _: A,
+        _: &Selector,
+        row: usize,
+    ) -> std::result::Result<(), PlonkError>
+    where
+        A: FnOnce() -> AR,
+        AR: Into<String>,
+    {
+        self.touch(row);
+        Ok(())
+    }
+
+    fn query_instance(
+        &self,
+        _: Column<Instance>,
+        _: usize,
+    ) -> std::result::Result<Value<pallas::Base>, PlonkError> {
+        Ok(Value::unknown())
+    }
+
+    fn assign_advice<V, VR, A, AR>(
+        &mut self,
+        _: A,
+        _: Column<Advice>,
+        row: usize,
+        _: V,
+    ) -> std::result::Result<(), PlonkError>
+    where
+        V: FnOnce() -> Value<VR>,
+        VR: Into<Assigned<pallas::Base>>,
+        A: FnOnce() -> AR,
+        AR: Into<String>,
+    {
+        self.touch(row);
+        Ok(())
+    }
+
+    fn assign_fixed<V, VR, A, AR>(
+        &mut self,
+        _: A,
+        _: Column<Fixed>,
+        row: usize,
+        _: V,
+    ) -> std::result::Result<(), PlonkError>
+    where
+        V: FnOnce() -> Value<VR>,
+        VR: Into<Assigned<pallas::Base>>,
+        A: FnOnce() -> AR,
+        AR: Into<String>,
+    {
+        self.touch(row);
+        Ok(())
+    }
+
+    fn copy(
+        &mut self,
+        _: Column<Any>,
+        _: usize,
+        _: Column<Any>,
+        _: usize,
+    ) -> std::result::Result<(), PlonkError> {
+        Ok(())
+    }
+
+    fn fill_from_row(
+        &mut self,
+        _: Column<Fixed>,
+        row: usize,
+        _: Value<Assigned<pallas::Base>>,
+    ) -> std::result::Result<(), PlonkError> {
+        self.touch(row);
+        Ok(())
+    }
+
+    fn push_namespace<NR, N>(&mut self, _: N)
+    where
+        NR: Into<String>,
+        N: FnOnce() -> NR,
+    {
+    }
+
+    fn pop_namespace(&mut self, _: Option<String>) {}
+}
+
+/// Lay out the given zkas circuit and report how many columns and rows
+/// it uses, along with the smallest `k` it fits in. This can be used to
+/// size the `k` declared in zkas code, and to catch circuits outgrowing it.
+pub fn circuit_stats(zkbin: &ZkBinary) -> Result<CircuitStats> {
+    let circuit = ZkCircuit::new(empty_witnesses(zkbin)?, zkbin);
+
+    let mut cs = ConstraintSystem::default();
+    let config = ZkCircuit::configure_with_params(&mut cs, circuit.params());
+
+    let mut counter = RowCounter::default();
+    <ZkCircuit as Circuit<pallas::Base>>::FloorPlanner::synthesize(
+        &mut counter,
+        &circuit,
+        config,
+        cs.constants().clone(),
+    )?;
+
+    // halo2 reserves the last rows of every column for blinding factors
+    let rows_needed = (counter.rows + cs.blinding_factors() + 1).max(cs.minimum_rows());
+    let k = rows_needed.next_power_of_two().trailing_zeros();
+
+    Ok(CircuitStats { advice_columns: cs.num_advice_columns(), rows_used: counter.rows, k })
+}
@@ -0,0 +1,149 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2024 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{
+        atomic::{AtomicU64, Ordering::SeqCst},
+        Mutex,
+    },
+};
+
+use darkfi_sdk::pasta::{group::ff::PrimeField, pallas};
+use halo2_proofs::plonk;
+
+use super::{Proof, VerificationLimit, VerifyingKey};
+
+/// Amount of results the validator caches unless configured otherwise
+pub const DEFAULT_VERIFICATION_CACHE_SIZE: usize = 4096;
+
+/// Cached verification results, keyed by a hash of the verifying key,
+/// the proof and its public inputs. Both passing and failing results are
+/// cached. The oldest entry is evicted when it's full.
+pub struct VerificationCache {
+    capacity: usize,
+    entries: Mutex<CacheEntries>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+#[derive(Default)]
+struct CacheEntries {
+    /// Verification results by entry key
+    results: HashMap<[u8; 32], bool>,
+    /// Entry keys in insertion order, oldest first
+    order: VecDeque<[u8; 32]>,
+}
+
+impl VerificationCache {
+    /// Create a cache keeping up to `capacity` results. A capacity of
+    /// zero disables caching.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: Mutex::new(CacheEntries::default()),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    /// Verify `proof` with [`VerificationLimit::verify()`], unless the
+    /// result for the same key, proof and public inputs is cached. A cached
+    /// failure is reported as `plonk::Error::ConstraintSystemFailure`, since
+    /// the original error isn't kept.
+    pub async fn verify(
+        &self,
+        proof: &Proof,
+        vk: &VerifyingKey,
+        instances: &[pallas::Base],
+        limit: &VerificationLimit,
+    ) -> std::result::Result<(), plonk::Error> {
+        if self.capacity == 0 {
+            return limit.verify(proof, vk, instances).await
+        }
+
+        let key = Self::key(proof, vk, instances);
+
+        let cached = self.entries.lock().unwrap().results.get(&key).copied();
+        if let Some(valid) = cached {
+            self.hits.fetch_add(1, SeqCst);
+            return if valid { Ok(()) } else { Err(plonk::Error::ConstraintSystemFailure) }
+        }
+        self.misses.fetch_add(1, SeqCst);
+
+        // Verify without holding the lock, this is the slow part
+        let result = limit.verify(proof, vk, instances).await;
+
+        let mut entries = self.entries.lock().unwrap();
+        if !entries.results.contains_key(&key) {
+            if entries.results.len() >= self.capacity {
+                if let Some(oldest) = entries.order.pop_front() {
+                    entries.results.remove(&oldest);
+                }
+            }
+            entries.order.push_back(key);
+        }
+        entries.results.insert(key, result.is_ok());
+
+        result
+    }
+
+    /// Amount of verifications served from the cache
+    pub fn hits(&self) -> u64 {
+        self.hits.load(SeqCst)
+    }
+
+    /// Amount of verifications that had to run the verifier
+    pub fn misses(&self) -> u64 {
+        self.misses.load(SeqCst)
+    }
+
+    /// Amount of cached results
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().results.len()
+    }
+
+    /// Returns `true` if no results are cached
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Drop all cached results
+    pub fn clear(&self) {
+        let mut entries = self.entries.lock().unwrap();
+        entries.results.clear();
+        entries.order.clear();
+    }
+
+    /// Hash identifying a verification. The verifying key is included
+    /// so a proof checked against the wrong circuit can't poison the
+    /// result for the right one.
+    fn key(proof: &Proof, vk: &VerifyingKey, instances: &[pallas::Base]) -> [u8; 32] {
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(b"darkfi:zk:verification_cache");
+        hasher.update(&vk.params.k().to_le_bytes());
+        hasher.update(vk.hash());
+        hasher.update(&(proof.as_ref().len() as u64).to_le_bytes());
+        hasher.update(proof.as_ref());
+        hasher.update(&(instances.len() as u64).to_le_bytes());
+        for instance in instances {
+            hasher.update(&instance.to_repr());
+        }
+        *hasher.finalize().as_bytes()
+    }
+}
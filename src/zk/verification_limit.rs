@@ -0,0 +1,71 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2024 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use darkfi_sdk::pasta::pallas;
+use halo2_proofs::plonk;
+use smol::lock::{Semaphore, SemaphoreGuard};
+
+use super::{Proof, VerifyingKey};
+
+/// Amount of concurrent verifications the validator allows unless
+/// configured otherwise
+pub const DEFAULT_MAX_CONCURRENT_VERIFICATIONS: usize = 8;
+
+/// Bounds how many ZK proofs are verified at the same time, so validating
+/// a large block can't exhaust memory by verifying all of its proofs in
+/// parallel.
+pub struct VerificationLimit {
+    /// Permits for the limit, `None` if verification is unbounded
+    permits: Option<Semaphore>,
+}
+
+impl VerificationLimit {
+    /// Allow up to `limit` concurrent verifications. A limit of zero
+    /// disables the bound.
+    pub fn new(limit: usize) -> Self {
+        Self { permits: (limit > 0).then(|| Semaphore::new(limit)) }
+    }
+
+    /// Wait until another verification is allowed to run. It's counted
+    /// against the limit until the returned permit is dropped.
+    pub async fn acquire(&self) -> VerificationPermit<'_> {
+        match &self.permits {
+            Some(permits) => VerificationPermit { _guard: Some(permits.acquire().await) },
+            None => VerificationPermit { _guard: None },
+        }
+    }
+
+    /// Verify `proof` like [`Proof::verify()`] once a permit is available,
+    /// holding it while the verifier runs on the blocking thread pool.
+    pub async fn verify(
+        &self,
+        proof: &Proof,
+        vk: &VerifyingKey,
+        instances: &[pallas::Base],
+    ) -> std::result::Result<(), plonk::Error> {
+        let _permit = self.acquire().await;
+
+        let (proof, vk, instances) = (proof.clone(), vk.clone(), instances.to_vec());
+        smol::unblock(move || proof.verify(&vk, &instances)).await
+    }
+}
+
+/// Permit to run a verification, given back to the limit on drop
+pub struct VerificationPermit<'a> {
+    _guard: Option<SemaphoreGuard<'a>>,
+}
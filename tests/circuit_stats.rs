@@ -0,0 +1,53 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2024 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use darkfi_sdk::pasta::vesta;
+use halo2_proofs::{
+    plonk::{keygen_vk, Error},
+    poly::commitment::Params,
+};
+
+use darkfi::{
+    zk::{circuit_stats, empty_witnesses, ZkCircuit},
+    zkas::ZkBinary,
+    Result,
+};
+
+#[test]
+fn circuit_stats_k() -> Result<()> {
+    let bincodes: [&[u8]; 2] =
+        [include_bytes!("../proof/mint.zk.bin"), include_bytes!("../proof/burn.zk.bin")];
+
+    for bincode in bincodes {
+        let zkbin = ZkBinary::decode(bincode)?;
+        let stats = circuit_stats(&zkbin)?;
+        assert!(stats.advice_columns > 0);
+        assert!(stats.rows_used > 0);
+        assert!(stats.k <= zkbin.k);
+
+        // The reported k is enough for key generation, and one less is not
+        let circuit = ZkCircuit::new(empty_witnesses(&zkbin)?, &zkbin);
+        assert!(keygen_vk(&Params::<vesta::Affine>::new(stats.k), &circuit).is_ok());
+        assert!(matches!(
+            keygen_vk(&Params::<vesta::Affine>::new(stats.k - 1), &circuit),
+            Err(Error::NotEnoughRowsAvailable { .. })
+        ));
+    }
+
+    Ok(())
+}
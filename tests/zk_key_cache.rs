@@ -0,0 +1,77 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2024 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use halo2_proofs::{circuit::Value, pasta::pallas};
+use rand::rngs::OsRng;
+
+use darkfi::{
+    zk::{empty_witnesses, key_param_hash, Proof, ProvingKey, VerifyingKey, Witness, ZkCircuit},
+    zkas::ZkBinary,
+    Result,
+};
+
+#[test]
+fn zk_key_cache() -> Result<()> {
+    let bincode = include_bytes!("../proof/arithmetic.zk.bin");
+    let zkbin = ZkBinary::decode(bincode)?;
+    let param_hash = key_param_hash(zkbin.k, bincode);
+
+    let circuit = ZkCircuit::new(empty_witnesses(&zkbin)?, &zkbin);
+    let pk = ProvingKey::build(zkbin.k, &circuit);
+    let vk = VerifyingKey::build(zkbin.k, &circuit);
+
+    // Persist both keys to disk and load them back
+    let dir = std::env::temp_dir().join(format!("darkfi-zk-key-cache-{}", std::process::id()));
+    std::fs::create_dir_all(&dir)?;
+    let pk_path = dir.join("arithmetic.pk");
+    let vk_path = dir.join("arithmetic.vk");
+
+    let pk_bytes = pk.to_bytes(&param_hash)?;
+    let vk_bytes = vk.to_bytes(&param_hash)?;
+    assert_eq!(pk_bytes.len(), pk.serialized_size() + 32);
+    assert_eq!(vk_bytes.len(), vk.serialized_size() + 32);
+    std::fs::write(&pk_path, &pk_bytes)?;
+    std::fs::write(&vk_path, &vk_bytes)?;
+
+    let circuit = ZkCircuit::new(empty_witnesses(&zkbin)?, &zkbin);
+    let pk = ProvingKey::from_bytes(&std::fs::read(&pk_path)?, &param_hash, circuit)?;
+    let circuit = ZkCircuit::new(empty_witnesses(&zkbin)?, &zkbin);
+    let vk = VerifyingKey::from_bytes(&std::fs::read(&vk_path)?, &param_hash, circuit)?;
+
+    // The loaded keys still prove and verify
+    let (a, b) = (pallas::Base::from(4), pallas::Base::from(110));
+    let witnesses = vec![Witness::Base(Value::known(a)), Witness::Base(Value::known(b))];
+    let circuit = ZkCircuit::new(witnesses, &zkbin);
+    let public_inputs = vec![a + b, a * b, a - b];
+    let proof = Proof::create(&pk, &[circuit], &public_inputs, &mut OsRng)?;
+    proof.verify(&vk, &public_inputs)?;
+
+    // Keys cached for another circuit or size are rejected
+    let other_hash = key_param_hash(zkbin.k + 1, bincode);
+    let circuit = ZkCircuit::new(empty_witnesses(&zkbin)?, &zkbin);
+    let err = VerifyingKey::from_bytes(&vk_bytes, &other_hash, circuit).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+
+    let other_hash = key_param_hash(zkbin.k, include_bytes!("../proof/opcodes.zk.bin"));
+    let circuit = ZkCircuit::new(empty_witnesses(&zkbin)?, &zkbin);
+    let err = ProvingKey::from_bytes(&pk_bytes, &other_hash, circuit).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
@@ -0,0 +1,79 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2024 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use halo2_proofs::{circuit::Value, pasta::pallas};
+use rand::rngs::OsRng;
+
+use darkfi::{
+    zk::{empty_witnesses, Proof, ProvingKey, VerifyingKey, Witness, ZkCircuit},
+    zkas::ZkBinary,
+    Result,
+};
+
+#[test]
+fn zk_proof_batch() -> Result<()> {
+    let zkbin = ZkBinary::decode(include_bytes!("../proof/arithmetic.zk.bin"))?;
+    let circuit = ZkCircuit::new(empty_witnesses(&zkbin)?, &zkbin);
+    let pk = ProvingKey::build(zkbin.k, &circuit);
+    let vk = VerifyingKey::build(zkbin.k, &circuit);
+
+    // The arithmetic circuit reveals a + b, a * b and a - b
+    let arith = |a: u64, b: u64| {
+        let (a, b) = (pallas::Base::from(a), pallas::Base::from(b));
+        let witnesses = vec![Witness::Base(Value::known(a)), Witness::Base(Value::known(b))];
+        (ZkCircuit::new(witnesses, &zkbin), vec![a + b, a * b, a - b])
+    };
+
+    let (circuit_0, public_0) = arith(4, 110);
+    let (circuit_1, public_1) = arith(7, 3);
+
+    let proof =
+        Proof::create_batch(&pk, &[circuit_0, circuit_1], &[&public_0, &public_1], &mut OsRng)?;
+    proof.verify_batch(&vk, &[&public_0, &public_1])?;
+
+    // The public inputs are bound to the circuit order
+    assert!(proof.verify_batch(&vk, &[&public_1, &public_0]).is_err());
+    assert!(proof.verify_batch(&vk, &[&public_0]).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn zk_proof_raw_inputs() -> Result<()> {
+    let zkbin = ZkBinary::decode(include_bytes!("../proof/arithmetic.zk.bin"))?;
+    let circuit = ZkCircuit::new(empty_witnesses(&zkbin)?, &zkbin);
+    let pk = ProvingKey::build(zkbin.k, &circuit);
+    let vk = VerifyingKey::build(zkbin.k, &circuit);
+
+    let (a, b) = (pallas::Base::from(4), pallas::Base::from(110));
+    let witnesses = vec![Witness::Base(Value::known(a)), Witness::Base(Value::known(b))];
+    let circuit = ZkCircuit::new(witnesses, &zkbin);
+    let public_inputs = vec![a + b, a * b, a - b];
+    let proof = Proof::create(&pk, &[circuit], &public_inputs, &mut OsRng)?;
+
+    // Verify against the raw inputs, without a revealed values struct
+    proof.verify(&vk, &public_inputs)?;
+
+    for i in 0..public_inputs.len() {
+        let mut flipped = public_inputs.clone();
+        flipped[i] += pallas::Base::from(1);
+        assert!(proof.verify(&vk, &flipped).is_err());
+    }
+
+    Ok(())
+}
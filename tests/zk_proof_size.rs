@@ -0,0 +1,79 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2024 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use darkfi_sdk::crypto::{constants::MERKLE_DEPTH_ORCHARD, MerkleNode};
+use halo2_proofs::{arithmetic::Field, circuit::Value, pasta::pallas};
+use rand::rngs::OsRng;
+
+use darkfi::{
+    zk::{empty_witnesses, Proof, ProvingKey, Witness, ZkCircuit},
+    zkas::ZkBinary,
+    Result,
+};
+
+/// Create a proof for `zkbin` and check its size against the estimate.
+/// The proof doesn't have to verify, so any known witnesses will do.
+fn check_expected_size(zkbin: &ZkBinary, witnesses: Vec<Witness>, n_public: usize) -> Result<()> {
+    let circuit = ZkCircuit::new(empty_witnesses(zkbin)?, zkbin);
+    let expected = Proof::expected_size(zkbin.k, &circuit);
+    let pk = ProvingKey::build(zkbin.k, &circuit);
+
+    let circuit = ZkCircuit::new(witnesses, zkbin);
+    let public_inputs = vec![pallas::Base::ZERO; n_public];
+    let proof = Proof::create(&pk, &[circuit], &public_inputs, &mut OsRng)?;
+
+    assert_eq!(proof.as_ref().len(), expected);
+    Ok(())
+}
+
+#[test]
+fn zk_proof_expected_size_mint() -> Result<()> {
+    let zkbin = ZkBinary::decode(include_bytes!("../proof/mint.zk.bin"))?;
+
+    let witnesses = vec![
+        Witness::Base(Value::known(pallas::Base::random(&mut OsRng))),
+        Witness::Base(Value::known(pallas::Base::random(&mut OsRng))),
+        Witness::Base(Value::known(pallas::Base::from(42))),
+        Witness::Base(Value::known(pallas::Base::random(&mut OsRng))),
+        Witness::Base(Value::known(pallas::Base::random(&mut OsRng))),
+        Witness::Scalar(Value::known(pallas::Scalar::random(&mut OsRng))),
+        Witness::Scalar(Value::known(pallas::Scalar::random(&mut OsRng))),
+    ];
+
+    check_expected_size(&zkbin, witnesses, 5)
+}
+
+#[test]
+fn zk_proof_expected_size_burn() -> Result<()> {
+    let zkbin = ZkBinary::decode(include_bytes!("../proof/burn.zk.bin"))?;
+
+    let path = [MerkleNode::from(pallas::Base::ZERO); MERKLE_DEPTH_ORCHARD];
+    let witnesses = vec![
+        Witness::Base(Value::known(pallas::Base::random(&mut OsRng))),
+        Witness::Base(Value::known(pallas::Base::random(&mut OsRng))),
+        Witness::Base(Value::known(pallas::Base::from(42))),
+        Witness::Base(Value::known(pallas::Base::random(&mut OsRng))),
+        Witness::Scalar(Value::known(pallas::Scalar::random(&mut OsRng))),
+        Witness::Scalar(Value::known(pallas::Scalar::random(&mut OsRng))),
+        Witness::Uint32(Value::known(0)),
+        Witness::MerklePath(Value::known(path)),
+        Witness::Base(Value::known(pallas::Base::random(&mut OsRng))),
+    ];
+
+    check_expected_size(&zkbin, witnesses, 8)
+}
@@ -0,0 +1,93 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2024 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use halo2_proofs::{circuit::Value, pasta::pallas};
+use rand::rngs::OsRng;
+
+use darkfi::{
+    zk::{
+        empty_witnesses, Proof, ProvingKey, VerificationCache, VerificationLimit, VerifyingKey,
+        Witness, ZkCircuit,
+    },
+    zkas::ZkBinary,
+    Result,
+};
+
+#[test]
+fn zk_verification_cache() -> Result<()> {
+    let zkbin = ZkBinary::decode(include_bytes!("../proof/arithmetic.zk.bin"))?;
+    let circuit = ZkCircuit::new(empty_witnesses(&zkbin)?, &zkbin);
+    let pk = ProvingKey::build(zkbin.k, &circuit);
+    let vk = VerifyingKey::build(zkbin.k, &circuit);
+
+    let (a, b) = (pallas::Base::from(4), pallas::Base::from(110));
+    let witnesses = vec![Witness::Base(Value::known(a)), Witness::Base(Value::known(b))];
+    let circuit = ZkCircuit::new(witnesses, &zkbin);
+    let public_inputs = vec![a + b, a * b, a - b];
+    let proof = Proof::create(&pk, &[circuit], &public_inputs, &mut OsRng)?;
+
+    let limit = VerificationLimit::new(1);
+
+    smol::block_on(async {
+        let cache = VerificationCache::new(2);
+
+        // The first verification runs the verifier, the second is cached
+        cache.verify(&proof, &vk, &public_inputs, &limit).await?;
+        assert_eq!((cache.hits(), cache.misses()), (0, 1));
+        cache.verify(&proof, &vk, &public_inputs, &limit).await?;
+        assert_eq!((cache.hits(), cache.misses()), (1, 1));
+
+        // A modified proof misses and fails, also when served from the cache
+        let mut bytes = proof.as_ref().to_vec();
+        bytes[0] ^= 1;
+        let modified = Proof::new(bytes);
+        assert!(cache.verify(&modified, &vk, &public_inputs, &limit).await.is_err());
+        assert_eq!((cache.hits(), cache.misses()), (1, 2));
+        assert!(cache.verify(&modified, &vk, &public_inputs, &limit).await.is_err());
+        assert_eq!((cache.hits(), cache.misses()), (2, 2));
+
+        // So do different public inputs
+        let mut flipped = public_inputs.clone();
+        flipped[0] += pallas::Base::from(1);
+        assert!(cache.verify(&proof, &vk, &flipped, &limit).await.is_err());
+        assert_eq!((cache.hits(), cache.misses()), (2, 3));
+
+        // The cache is full, so the oldest entry was evicted
+        assert_eq!(cache.len(), 2);
+        cache.verify(&proof, &vk, &public_inputs, &limit).await?;
+        assert_eq!((cache.hits(), cache.misses()), (2, 4));
+
+        // A key read back from its serialization hits the same entries
+        let mut vk_bytes = vec![];
+        vk.write(&mut vk_bytes)?;
+        let empty_circuit = ZkCircuit::new(empty_witnesses(&zkbin)?, &zkbin);
+        let read_vk = VerifyingKey::read(&mut &vk_bytes[..], empty_circuit)?;
+        assert_eq!(read_vk.hash(), vk.hash());
+        cache.verify(&proof, &read_vk, &public_inputs, &limit).await?;
+        assert_eq!((cache.hits(), cache.misses()), (3, 4));
+
+        // Without capacity nothing is cached
+        let cache = VerificationCache::new(0);
+        cache.verify(&proof, &vk, &public_inputs, &limit).await?;
+        cache.verify(&proof, &vk, &public_inputs, &limit).await?;
+        assert!(cache.is_empty());
+        assert_eq!((cache.hits(), cache.misses()), (0, 0));
+
+        Ok(())
+    })
+}
@@ -0,0 +1,134 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2024 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::{
+    collections::HashMap,
+    sync::atomic::{AtomicUsize, Ordering::SeqCst},
+    time::{Duration, Instant},
+};
+
+use darkfi::{
+    system::msleep,
+    tx::Transaction,
+    zk::{
+        empty_witnesses, Proof, ProvingKey, VerificationCache, VerificationLimit, VerifyingKey,
+        Witness, ZkCircuit,
+    },
+    zkas::ZkBinary,
+    Result,
+};
+use darkfi_sdk::{crypto::MONEY_CONTRACT_ID, dark_tree::DarkLeaf, pasta::pallas, tx::ContractCall};
+use halo2_proofs::circuit::Value;
+use rand::rngs::OsRng;
+use smol::future::poll_once;
+
+/// Run two verifications at the same time and return how many of
+/// them ran concurrently at most.
+fn max_concurrent_verifications(limit: &VerificationLimit) -> usize {
+    let active = AtomicUsize::new(0);
+    let max_active = AtomicUsize::new(0);
+
+    let verify = || async {
+        let _permit = limit.acquire().await;
+        let now_active = active.fetch_add(1, SeqCst) + 1;
+        max_active.fetch_max(now_active, SeqCst);
+        // Stand-in for the verifier
+        msleep(100).await;
+        active.fetch_sub(1, SeqCst);
+    };
+
+    smol::block_on(smol::future::zip(verify(), verify()));
+
+    max_active.load(SeqCst)
+}
+
+#[test]
+fn zk_verification_limit() {
+    // With a limit of 1 the verifications serialize
+    let limit = VerificationLimit::new(1);
+    let start = Instant::now();
+    assert_eq!(max_concurrent_verifications(&limit), 1);
+    assert!(start.elapsed() >= Duration::from_millis(200));
+
+    // Otherwise they overlap
+    let limit = VerificationLimit::new(2);
+    assert_eq!(max_concurrent_verifications(&limit), 2);
+
+    // Zero disables the limit
+    let limit = VerificationLimit::new(0);
+    assert_eq!(max_concurrent_verifications(&limit), 2);
+}
+
+#[test]
+fn zk_verification_limit_verify_zkps() -> Result<()> {
+    let zkbin = ZkBinary::decode(include_bytes!("../proof/arithmetic.zk.bin"))?;
+    let circuit = ZkCircuit::new(empty_witnesses(&zkbin)?, &zkbin);
+    let pk = ProvingKey::build(zkbin.k, &circuit);
+    let vk = VerifyingKey::build(zkbin.k, &circuit);
+
+    let (a, b) = (pallas::Base::from(4), pallas::Base::from(110));
+    let witnesses = vec![Witness::Base(Value::known(a)), Witness::Base(Value::known(b))];
+    let circuit = ZkCircuit::new(witnesses, &zkbin);
+    let public_inputs = vec![a + b, a * b, a - b];
+    let proof = Proof::create(&pk, &[circuit], &public_inputs, &mut OsRng)?;
+
+    // A transaction with a single call carrying the proof
+    let call = ContractCall { contract_id: *MONEY_CONTRACT_ID, data: vec![] };
+    let tx = Transaction {
+        calls: vec![DarkLeaf { data: call, parent_index: None, children_indexes: vec![] }],
+        proofs: vec![vec![proof]],
+        signatures: vec![vec![]],
+    };
+    let vks = HashMap::from([(zkbin.namespace.clone(), vk)]);
+    let verifying_keys = HashMap::from([(MONEY_CONTRACT_ID.to_bytes(), vks)]);
+    let zkp_table = vec![vec![(zkbin.namespace.clone(), public_inputs)]];
+
+    // Without caching, every verification needs a permit
+    let no_cache = VerificationCache::new(0);
+    let limit = VerificationLimit::new(1);
+
+    smol::block_on(async {
+        // While the only permit is taken, the proof isn't verified
+        let permit = limit.acquire().await;
+        let mut verify =
+            Box::pin(tx.verify_zkps(&verifying_keys, zkp_table.clone(), &no_cache, &limit));
+        assert!(poll_once(&mut verify).await.is_none());
+        msleep(100).await;
+        assert!(poll_once(&mut verify).await.is_none());
+
+        // and it goes through once the permit is given back
+        drop(permit);
+        verify.await?;
+
+        // Concurrent verifications share the single permit and both finish
+        let first = tx.verify_zkps(&verifying_keys, zkp_table.clone(), &no_cache, &limit);
+        let second = tx.verify_zkps(&verifying_keys, zkp_table.clone(), &no_cache, &limit);
+        let (first, second) = smol::future::zip(first, second).await;
+        first?;
+        second?;
+
+        // Cached results are served without waiting for a permit
+        let cache = VerificationCache::new(1);
+        tx.verify_zkps(&verifying_keys, zkp_table.clone(), &cache, &limit).await?;
+        let _permit = limit.acquire().await;
+        let cached = poll_once(tx.verify_zkps(&verifying_keys, zkp_table, &cache, &limit)).await;
+        assert!(matches!(cached, Some(Ok(()))));
+
+        Ok(())
+    })
+}